@@ -11,9 +11,55 @@ import sys
 import tempfile
 from pathlib import Path
 
-from pmgfal._pmgfal import __version__, generate, hash_lexicons
+from pmgfal._pmgfal import (
+    DuplicateNsidError,
+    GenerateResult,
+    Generator,
+    InvalidLexiconError,
+    LexiconDiff,
+    LexiconError,
+    LexiconProblem,
+    PropertyChange,
+    __version__,
+    build_info,
+    builtin_nsids,
+    check_lexicons,
+    diff_lexicons,
+    generate,
+    generate_targets,
+    generate_with_hash,
+    hash_lexicons,
+    to_openapi,
+)
+
+__all__ = [
+    "DuplicateNsidError",
+    "GenerateResult",
+    "Generator",
+    "InvalidLexiconError",
+    "LexiconDiff",
+    "LexiconError",
+    "LexiconProblem",
+    "PropertyChange",
+    "__version__",
+    "build_info",
+    "builtin_nsids",
+    "check_lexicons",
+    "diff_lexicons",
+    "generate",
+    "generate_targets",
+    "generate_with_hash",
+    "get_cache_dir",
+    "hash_lexicons",
+    "main",
+    "to_openapi",
+]
+
+# the graphql export is compiled in only with the experimental `graphql` feature
+if "graphql" in build_info()["features"]:
+    from pmgfal._pmgfal import to_graphql
 
-__all__ = ["__version__", "generate", "get_cache_dir", "hash_lexicons", "main"]
+    __all__ += ["to_graphql"]
 
 # ansi color codes
 _RESET = "\033[0m"
@@ -29,6 +75,9 @@ GIT_PROVIDERS = [
     ("tangled", "https://tangled.org/{}.git"),
 ]
 
+# lexicon archive extensions read directly by the rust parser
+ARCHIVE_SUFFIXES = (".zip", ".tar.gz", ".tgz")
+
 
 def _supports_color() -> bool:
     """check if terminal supports color."""
@@ -83,6 +132,11 @@ def is_git_url(path: str) -> bool:
     return path.startswith(("https://", "git@", "ssh://", "git://"))
 
 
+def is_archive(path: str) -> bool:
+    """check if path names a lexicon archive."""
+    return path.lower().endswith(ARCHIVE_SUFFIXES)
+
+
 def is_shorthand(path: str) -> bool:
     """check if path looks like owner/repo shorthand."""
     return bool(re.match(r"^[\w.-]+/[\w.-]+$", path))
@@ -134,7 +188,10 @@ def main(args: list[str] | None = None) -> int:
     parser.add_argument(
         "lexicon_source",
         nargs="?",
-        help="directory, git url, or owner/repo shorthand (default: ./lexicons or .)",
+        help=(
+            "directory, .zip/.tar.gz archive, git url, or owner/repo shorthand "
+            "(default: ./lexicons or .)"
+        ),
     )
     parser.add_argument(
         "-o",
@@ -178,7 +235,8 @@ def main(args: list[str] | None = None) -> int:
         source = parsed.lexicon_source
 
         # handle git urls or shorthand by cloning to temp dir
-        if source and (is_git_url(source) or is_shorthand(source)):
+        is_remote = is_git_url(source or "") or is_shorthand(source or "")
+        if source and is_remote and not is_archive(source):
             temp_dir = tempfile.mkdtemp(prefix="pmgfal-")
             success, _ = clone_repo(source, temp_dir)
             if not success:
@@ -197,7 +255,11 @@ def main(args: list[str] | None = None) -> int:
         else:
             lexicon_dir = Path(source)
 
-        if not lexicon_dir.is_dir():
+        if is_archive(str(lexicon_dir)):
+            if not lexicon_dir.is_file():
+                _log_error(f"archive not found: {lexicon_dir}")
+                return 1
+        elif not lexicon_dir.is_dir():
             _log_error(f"not a directory: {lexicon_dir}")
             return 1
         # compute hash of lexicons (in rust)
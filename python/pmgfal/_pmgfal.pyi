@@ -1,19 +1,561 @@
 """type stubs for rust bindings."""
 
+from collections.abc import Callable, Iterator
+from typing import Any, TypedDict
+
 __version__: str
 
+class LexiconError(ValueError):
+    """a lexicon directory or file could not be read."""
+
+class InvalidLexiconError(LexiconError):
+    """a file declares a lexicon but isn't a valid one."""
+
+class DuplicateNsidError(LexiconError):
+    """two lexicon files declare the same nsid."""
+
+class GenerateResult:
+    """result of a generate call.
+
+    behaves like the list of generated file paths for backwards compatibility.
+    """
+
+    @property
+    def files(self) -> list[str]:
+        """paths of the generated files, under output_dir unless
+        `relative_paths=True`."""
+    @property
+    def shadowed_builtins(self) -> list[str]:
+        """builtin nsids overridden by a user lexicon with the same id."""
+    @property
+    def warnings(self) -> list[str]:
+        """non-fatal problems found during generation."""
+    @property
+    def removed(self) -> list[str]:
+        """stale generated files deleted because of `clean=True`."""
+    def __len__(self) -> int: ...
+    def __getitem__(self, index: int) -> str: ...
+    def __iter__(self) -> Iterator[str]: ...
+
+class Generator:
+    """runs `generate`, keeping lexicon parses across calls.
+
+    a file whose modification time and size haven't changed since an earlier
+    call isn't read or parsed again, which suits a long-running process
+    regenerating on every change.
+    """
+
+    def __init__(self) -> None: ...
+    def generate(
+        self,
+        lexicon_dir: str,
+        output_dir: str | list[tuple[str, str]],
+        **kwargs: Any,
+    ) -> GenerateResult | list[GenerateResult]:
+        """`generate`, with the same arguments, reusing unchanged parses.
+
+        `output_dir` may also be `generate_targets`' list of
+        `(prefix, output_dir)` targets, returning one result per target.
+        """
+    @property
+    def parses(self) -> int:
+        """how many lexicon files were parsed so far."""
+
+class PropertyChange:
+    """a property added, removed or retyped between two lexicon versions."""
+
+    def_ref: str
+    property: str
+    kind: str
+    old_type: str | None
+    new_type: str | None
+    breaking: bool
+
+class LexiconDiff:
+    """model-level differences between two lexicon sets."""
+
+    @property
+    def added_nsids(self) -> list[str]: ...
+    @property
+    def removed_nsids(self) -> list[str]: ...
+    @property
+    def added_defs(self) -> list[str]:
+        """defs added to nsids present on both sides (`nsid#defName`)."""
+    @property
+    def removed_defs(self) -> list[str]:
+        """defs removed from nsids present on both sides (`nsid#defName`)."""
+    @property
+    def changes(self) -> list[PropertyChange]:
+        """property changes in defs present on both sides."""
+    @property
+    def breaking(self) -> bool:
+        """whether any change removes or narrows something."""
+
+class LexiconProblem:
+    """a problem `check_lexicons` found in one lexicon file."""
+
+    path: str
+    nsid: str | None
+    message: str
+
+class BuildInfo(TypedDict):
+    version: str
+    features: list[str]
+    builtin_lexicon_count: int
+
+def build_info() -> BuildInfo:
+    """version and optional features of this build.
+
+    features are "archive", "graphql", "jsonc" and "tracing".
+    """
+
+def builtin_nsids() -> list[str]:
+    """list the nsids of the bundled com.atproto lexicons, sorted."""
+
 def generate(
     lexicon_dir: str,
     output_dir: str,
     namespace_prefix: str | None = None,
-) -> list[str]:
+    log_level: str | None = None,
+    use_builtins: bool = True,
+    strict_refs: bool = False,
+    builtins_dir: str | None = None,
+    embed_metadata: bool = False,
+    layout: str = "single",
+    split_defs: bool = False,
+    small_int_ranges_as_literal: bool = False,
+    max_literal_range: int = 16,
+    keep_acronyms: bool = False,
+    mark_deprecated: bool = False,
+    target: str = "pydantic",
+    records_only: bool = False,
+    seeds: list[str] | None = None,
+    emit_client: bool = False,
+    transport_base: str = "XrpcTransport",
+    skip_nsids: list[str] | None = None,
+    emit_invariant_validators: bool = False,
+    compact_repr: bool = False,
+    identity_equality: bool = False,
+    identity_fields: list[str] | None = None,
+    emit_example_tests: bool = False,
+    name_hook: Callable[[str, str], str | None] | None = None,
+    compat_map: str | None = None,
+    file_hook: Callable[[str, str], str] | None = None,
+    emit_union_adapters: bool = False,
+    constraints_in_schema: bool = False,
+    discriminator_field: str = "$type",
+    field_metadata_keys: list[str] | None = None,
+    emit_nsid_constant: bool = False,
+    emit_rkey_info: bool = False,
+    emit_lexicon_hash: bool = False,
+    clean: bool = False,
+    relative_paths: bool = False,
+    warnings_file: str | None = None,
+    write_threads: int = 8,
+    lazy_imports: bool = False,
+    syntax: str | None = None,
+    target_python: str = "3.10",
+    indent: str = "spaces",
+    quote_style: str = "double",
+    docstring_style: str = "none",
+    max_annotation_width: int | None = None,
+    emit_parse_record: bool = False,
+    emit_collections: bool = False,
+    emit_fixtures: bool = False,
+    value_object_defs: list[str] | None = None,
+    class_members: dict[str, str] | None = None,
+    exclude: list[str] | None = None,
+    alias_repeated_unions: bool = False,
+    record_union_for_unknown: bool = False,
+    top_level_as: str = "adapter",
+    on_invalid_field: str = "skip",
+    on_invalid_default: str = "error",
+    emit_titles: bool = False,
+    schema_ids: bool = False,
+    open_maps: bool = False,
+    typed_datetimes: bool = False,
+    manifest_path: str | None = None,
+    strict_keys: bool = False,
+    merge_split_nsids: bool = False,
+    max_file_size: int | None = None,
+    max_depth: int | None = None,
+) -> GenerateResult:
     """generate pydantic models from lexicon files.
 
+    user lexicons take precedence over the bundled com.atproto builtins when
+    they share an nsid.
+
     Args:
-        lexicon_dir: directory containing lexicon json files
+        lexicon_dir: directory containing lexicon json files, or a `.zip`,
+            `.tar.gz` or `.tgz` archive of them
         output_dir: directory to write generated python files
+        namespace_prefix: optional filter for specific nsid prefix, matched by
+            whole segments (`fm.plyr` takes `fm.plyr.track`, not `fm.plyrx.track`)
+        log_level: forward codegen diagnostics at this level (e.g. "debug")
+            to the `pmgfal` python logger
+        use_builtins: resolve refs against the bundled com.atproto lexicons
+        strict_refs: raise on refs to unknown lexicons instead of warning
+        builtins_dir: directory of lexicons that replaces the bundled
+            com.atproto set for this call (e.g. a fresh upstream checkout)
+        embed_metadata: wrap field types in `Annotated[..., LexiconMeta(...)]`
+            carrying the source lexicon ref or string format
+        layout: "single" writes one models.py; "package" writes a python
+            package with one module per nsid (fm.plyr.track ->
+            fm/plyr/track.py), a root __init__.py re-exporting every model
+            and a py.typed marker
+        split_defs: write each def to its own module under a per-nsid
+            package whose __init__.py aggregates them (implies "package")
+        small_int_ranges_as_literal: type integers with both `minimum` and
+            `maximum` as a `Literal` of every value in the range
+        max_literal_range: widest range (number of values) enumerated by
+            `small_int_ranges_as_literal`; wider ranges stay `int`
+        keep_acronyms: pascal-case nsid segments and def names by upper-casing
+            each word's first letter only, so a def named `URLPreview` is
+            `...URLPreview` rather than `...UrlPreview`
+        mark_deprecated: emit `Field(deprecated=True)` for fields whose
+            description starts with "DEPRECATED", so access warns.
+            requires pydantic 2.7 or later, which is why it's off by default
+        target: "pydantic" for BaseModel classes, or "protocol" for
+            typing.Protocol classes exposing each field as a read-only property
+        records_only: emit only record defs and the objects, tokens and
+            other defs they reference (transitively)
+        seeds: defs (`nsid` for main, `nsid#defName` otherwise) to generate
+            along with everything they reach through refs and unions, across
+            documents and builtins, and nothing else. a seed naming no known
+            def raises `ValueError`; takes the place of `records_only`
+        emit_client: also write client.py with an async `XrpcClient` method
+            per query and procedure, each forwarding to `_call(nsid, params,
+            input)` on the transport base class
+        transport_base: name of the transport base class `XrpcClient`
+            inherits from; subclass it and implement `_call` to send requests
+        skip_nsids: nsids whose models are hand-written. they still resolve
+            refs, but instead of generating them, pmgfal imports their classes
+            (same names as generated) from the module package layout would
+            write: output_dir/fm/plyr/get_tracks.py for fm.plyr.getTracks, or
+            output_dir/fm/plyr/get_tracks/<def>.py with split_defs
+        emit_invariant_validators: add a before-mode `model_validator` to
+            models with closed-union fields, rejecting union objects whose
+            `$type` is missing or not one of the union's refs (the only
+            invariant enforced so far)
+        compact_repr: give record models a short `__repr__`/`__str__` like
+            `fm.plyr.track(uri='at://...', cid='bafy...')`, listing whichever
+            of `uri`, `cid` and `rkey` the record has; pydantic's repr is
+            kept by default
+        identity_equality: give record models an `__eq__` and `__hash__`
+            comparing only their identity fields, so two copies of a record
+            whose server-populated fields differ are still equal and dedupe
+            in a set. records with none of the fields keep pydantic's
+            equality. pydantic target only
+        identity_fields: the fields `identity_equality` compares, in order,
+            when a record has them (default `["uri", "cid"]`)
+        emit_example_tests: also write test_examples.py, a pytest module that
+            validates every example (see below) against its model
+        name_hook: called as `name_hook(nsid, def_name)` for every def
+            generated or referenced; a returned string replaces the generated
+            class name everywhere (definitions and refs), and None keeps the
+            default. exceptions it raises propagate out of `generate`, and a
+            name that isn't an identifier, is a keyword, or is another
+            class's name raises ValueError
+        compat_map: a json file matching an existing layout, mapping defs
+            (`"fm.plyr.track"` for main, or `"fm.plyr.track#artist"`) to
+            `{"module": "music.tracks", "class": "Track"}`. the class name
+            replaces the generated one everywhere, ahead of name_hook; the
+            module, a dotted path within the package, applies to package
+            layout. either key may be left out, as may any def, to keep the
+            default. raises ValueError if the file can't be read
+        file_hook: called as `file_hook(path, content)` for every python
+            file generated, once styled; the string it returns is written
+            instead, so returning `content` is a no-op. an exception it
+            raises aborts `generate` as a RuntimeError naming the file, with
+            the hook's exception as its `__cause__`
+        emit_union_adapters: also emit a `TypeAdapter` per closed-union
+            field (`FmPlyrPostEmbedAdapter`) that picks the member model by
+            `$type`; reuse it to decode many raw members quickly
+        constraints_in_schema: add string constraints pydantic can't enforce
+            (`minLength`/`maxLength` in utf-8 bytes, `minGraphemes`/
+            `maxGraphemes`) to the field's `json_schema_extra`, so they show
+            in `model_json_schema()`
+        discriminator_field: the raw-input key holding a union member's type,
+            read by union adapters and invariant validators. members are
+            models without a field for it, so no alias is involved
+        field_metadata_keys: extra lexicon property keys (custom hints like
+            `"searchable"`) to copy into each field's `json_schema_extra`.
+            values are passed through verbatim, unvalidated
+        emit_nsid_constant: give record models a class-level
+            `NSID: ClassVar[str]` holding their nsid, for dispatch tables
+            keyed by model class without instantiating one
+        emit_rkey_info: give record models an `RKEY_TYPE: ClassVar[str]`
+            holding the lexicon's record `key` (`"tid"`, `"any"`,
+            `"literal:self"`, ...), and for a `literal:` key an `RKEY`
+            holding the one record key it allows (`"self"`), so tooling
+            creating records knows how to form their rkey
+        emit_lexicon_hash: expose `__lexicon_hash__` (what `hash_lexicons`
+            returns for the same input) and `__pmgfal_version__` from the
+            package root, or the module in single layout, so an app can
+            check its installed models against the lexicons
+        clean: delete generated files in output_dir that this run didn't
+            write, e.g. modules of a lexicon that was removed. only files
+            carrying the pmgfal banner are deleted, never those of
+            skip_nsids, and directories left empty are pruned. only what
+            namespace_prefix could have produced is considered: its subtree
+            in package layout, its module in single layout
+        relative_paths: report `files` and `removed` relative to output_dir
+            (`fm_plyr.py`, `fm/plyr/track.py`). by default they're joined
+            onto output_dir as given, so they're absolute when it is
+        warnings_file: write the warnings as a json array to this path,
+            relative to output_dir, each entry with the `kind` of problem
+            and the `nsid`, `path` (lexicon file) and `field` it concerns
+            next to its `message`. with no warnings, nothing is written and
+            a report left by an earlier run is removed
+        write_threads: most files package layout writes concurrently; 1
+            writes them one after another. output and the order of `files`
+            are the same either way
+        lazy_imports: in package layout, make `__init__` modules import each
+            re-exported name on first access (pep 562 `__getattr__`), so
+            `import pkg` stays cheap however many lexicons there are
+        syntax: "pep604" spells unions `A | B` and `X | None`; "typing"
+            spells them `Union[A, B]` and `Optional[X]`, imported from
+            typing. defaults to what target_python can evaluate
+        target_python: oldest python the models run on: "3.8" (as 3.9,
+            with `Annotated` from typing_extensions), "3.9" (typing unions,
+            `List`/`Dict`), "3.10" or "3.12" (pep 604 unions, builtin
+            generics). every version gets `from __future__ import
+            annotations`, which forward refs between models rely on
+        indent: "spaces" (4 per level, the default) or "tabs"
+        quote_style: "double" (the default) or "single" quotes for string
+            literals. docstrings and strings holding a single quote or a
+            backslash keep double quotes
+        docstring_style: also document each field with a description in
+            its class docstring, for sphinx autodoc: "napoleon" adds a
+            numpy-style `Attributes` section, "google" an `Attributes:`
+            section, both read by `sphinx.ext.napoleon`. "none" (the
+            default) keeps just the def's description
+        max_annotation_width: widest line a field annotation or type alias
+            may make; a wider one is parenthesized with one union member per
+            line (or one subscript argument, as in `Annotated[...]`), black
+            style. None (the default) never breaks them
+        emit_parse_record: also emit `RECORD_TYPES`, mapping each record's
+            `$type` to its model, and `parse_record(data)`, which validates
+            a raw record into the model its `$type` names (ValueError for
+            unknown types). importable from the package root in package
+            layout
+        emit_collections: also emit `COLLECTIONS`, mapping each collection
+            (the nsid of a record's main def) to its model, and
+            `validate_collection(nsid, records)`, which validates a list of
+            that collection's raw records, as a repo export holds them
+            (ValueError for unknown collections). importable from the
+            package root in package layout
+        emit_fixtures: also write `fixtures.py`, with a factory per record
+            model (`make_fm_plyr_track(**overrides)`) returning a minimal
+            valid instance: required fields get placeholders of their type
+            (nested objects filled the same way, unions their first member)
+            and `overrides`, keyed by lexicon field name, replace them.
+            imports the models relatively, so output_dir must be a package
+        value_object_defs: defs (`"com.atproto.repo.strongRef"` for a main
+            def, `"nsid#defName"` otherwise) to emit as frozen models, which
+            are immutable and hashable so they work as set members and dict
+            keys. pydantic fields can't live in `__slots__`, so they aren't
+            slotted
+        class_members: defs (`nsid` for main, `nsid#defName` otherwise)
+            mapped to python source appended to their class body, dedented
+            and re-indented, e.g. `@computed_field` properties deriving
+            values from the fields. `computed_field` is imported when used;
+            anything else the source names must be importable by it
+        exclude: nsid or `nsid#defName` patterns (`*` matches any run of
+            characters, as in `"app.bsky.embed.*"`) for defs to leave out.
+            union members naming an excluded def are dropped, so a union
+            left with one member becomes that bare type, and one left with
+            none becomes `Any` with a warning
+        alias_repeated_unions: emit each union shape (set of members) that
+            more than one field uses once, as a module-level alias named
+            after the first such field by nsid (`FmPlyrPostEmbedUnion`),
+            and annotate every one of those fields with it
+        record_union_for_unknown: type `unknown` fields named `record` or
+            `value` (`com.atproto.repo.listRecords#record.value`) as
+            `AnyRecord`, a union of every generated record model that
+            validates the member its `$type` names, instead of `Any`.
+            pydantic target only
+        top_level_as: how defs that aren't objects (top-level arrays,
+            unions and primitives) are emitted: "adapter" (a type alias plus
+            a `TypeAdapter`) or "rootmodel" (a `RootModel` subclass, whose
+            value is `.root`). pydantic target only
+        on_invalid_field: what to do with a record or object property whose
+            name can't be a python identifier (empty, whitespace, or starting
+            with a digit): "skip" leaves it out with a warning, "error"
+            raises ValueError
+        on_invalid_default: what to do with an integer `default` its own
+            `enum`, `const`, `minimum` or `maximum` rules out: "error"
+            raises ValueError naming the property, "warn" generates anyway
+            with a warning
+        emit_titles: give each field `Field(title=...)`, from the property's
+            `"title"` or its humanized name ("durationMs" is "Duration ms"),
+            plus `examples=[...]` when the property lists `"examples"`, so
+            `model_json_schema()` carries both
+        schema_ids: give each record and object model's json schema an
+            `$id` naming its def as the lexicon does: the nsid for `main`
+            (`"fm.plyr.track"`), `nsid#defName` otherwise. pydantic target
+            only
+        open_maps: emit an object def with no properties and an
+            `additionalProperties` schema (or `true`, for any value) as
+            `dict[str, V]`, V typed as an array item would be. a value
+            schema an array item can't be leaves the def a model, with a
+            warning. off by default, which emits such defs as empty models
+        typed_datetimes: type strings with `format: datetime` as
+            `datetime.datetime` instead of `str`. the pydantic target's
+            `Datetime` dumps them to json as atproto writes them: rfc 3339
+            with `Z` for utc (an offset otherwise), in milliseconds unless
+            the value has microseconds
+        manifest_path: a file listing the lexicon files to read, relative
+            to `lexicon_dir`, instead of every json file under it: a json
+            array of paths, an object with an `"imports"` array, or text with
+            one path per line (`#` comments allowed). a listed file that
+            doesn't exist raises LexiconError. `generate_with_hash` then
+            hashes only the listed files
+        strict_keys: raise InvalidLexiconError for a lexicon holding keys
+            the lexicon language doesn't define, such as a misspelt `defss`
+            or `maxLenght`, naming each by its path
+            (`defs.main.record.properties.title.maxLenght`). pmgfal's own
+            `examples`, `title` and `additionalProperties` and the
+            field_metadata_keys are accepted. off by default, which ignores
+            such keys
+        merge_split_nsids: merge lexicon files declaring the same nsid
+            (a record's `main` in one file, its supporting defs in siblings)
+            into one lexicon instead of raising DuplicateNsidError. a def
+            declared by more than one of the files still raises it
+        max_file_size: largest lexicon file (or archive entry) read, in
+            bytes. defaults to 16 MiB
+        max_depth: deepest json object/array nesting accepted in a lexicon
+            file. defaults to 64
+
+    record and object defs may list sample values in a def-level `"examples"`
+    array (a pmgfal extension; atproto lexicons define no such field). each
+    example is shown as json in the model's docstring.
+
+    Returns:
+        the generated file paths and any builtins shadowed by user lexicons
+
+    Raises:
+        InvalidLexiconError: a file with a `lexicon` key failed to deserialize,
+            holds unknown keys under `strict_keys`, or has ref aliases that
+            form a cycle
+        DuplicateNsidError: two files declare the same nsid, or under
+            `merge_split_nsids` the same def of one nsid
+        LexiconError: `lexicon_dir` isn't a directory or readable archive, or
+            a file exceeds `max_file_size` or `max_depth`
+        NotADirectoryError: `output_dir` exists but is a file; otherwise it's
+            created, parents included
+    """
+
+def generate_with_hash(
+    lexicon_dir: str, output_dir: str, **kwargs: Any
+) -> tuple[str, GenerateResult]:
+    """generate models and hash their lexicons in one pass over the files.
+
+    takes the same arguments as `generate`. the hash is the one
+    `hash_lexicons(lexicon_dir, namespace_prefix)` returns.
+
+    Returns:
+        the lexicon hash and the `generate` result
+
+    Raises:
+        ValueError: `output_dir` is a list of targets; use `generate_targets`
+    """
+
+def generate_targets(
+    lexicon_dir: str, targets: list[tuple[str, str]], **kwargs: Any
+) -> list[GenerateResult]:
+    """generate several nsid prefixes into their own packages from one parse.
+
+    takes the same keyword arguments as `generate`, except
+    `namespace_prefix`. each `(prefix, output_dir)` target gets what
+    `generate(lexicon_dir, output_dir, namespace_prefix=prefix)` would write,
+    but a ref into an nsid another target generates imports that model from
+    the other package, named after its directory, instead of emitting a copy.
+    the output directories' parent must be importable.
+
+    Returns:
+        one result per target, in order
+    """
+
+def to_openapi(lexicon_dir: str, namespace_prefix: str | None = None) -> str:
+    """export xrpc methods as an openapi 3.1 document.
+
+    queries become GET and procedures POST operations under `/xrpc/{nsid}`.
+    every def reachable from the selected lexicons is emitted as a json schema
+    component named like the generated pydantic class.
+
+    Args:
+        lexicon_dir: directory containing lexicon json files
+        namespace_prefix: optional filter for specific nsid prefix
+
+    Returns:
+        the openapi document as a json string
+    """
+
+# only defined when built with the `graphql` feature
+# (`"graphql" in build_info()["features"]`)
+def to_graphql(lexicon_dir: str, namespace_prefix: str | None = None) -> str:
+    """export record and object defs as a graphql schema (experimental).
+
+    only available when built with the `graphql` feature. records and objects
+    become object types named like the generated pydantic classes, unions
+    become union types, and `cid`, `at-uri` and `datetime` strings, bytes,
+    blobs and cid links become custom scalars. there is no `Query` type.
+
+    Args:
+        lexicon_dir: directory containing lexicon json files
         namespace_prefix: optional filter for specific nsid prefix
 
     Returns:
-        list of generated file paths
+        the schema as graphql sdl
+    """
+
+def diff_lexicons(old_dir: str, new_dir: str) -> LexiconDiff:
+    """compare two lexicon directories at the generated-model level.
+
+    property types are compared by their rendered python annotation, so a type
+    that gains union members or becomes optional is widened (additive) and one
+    that loses them is narrowed (breaking). removed nsids, defs and required
+    properties, and new required properties, are also breaking.
+
+    Args:
+        old_dir: directory (or archive) with the current lexicons
+        new_dir: directory (or archive) with the upgraded lexicons
+
+    Returns:
+        the added/removed nsids and defs and per-property changes
+    """
+
+def check_lexicons(
+    lexicon_dir: str,
+    use_builtins: bool = True,
+    builtins_dir: str | None = None,
+    strict_keys: bool = False,
+    strict_paths: bool = False,
+) -> list[LexiconProblem]:
+    """lint lexicon files without generating anything.
+
+    every file is checked, so one bad file doesn't hide the next: malformed
+    json, lexicons that don't deserialize, duplicate nsids, invalid nsid
+    strings, unions without members, and refs that resolve neither within
+    `lexicon_dir` nor against the builtins.
+
+    Args:
+        lexicon_dir: directory containing lexicon json files, or an archive
+        use_builtins: resolve refs against the bundled com.atproto lexicons
+        builtins_dir: directory of lexicons replacing the bundled builtins
+        strict_keys: also report each key the lexicon language doesn't
+            define (`unknown key defs.main.record.propertiess`), as
+            `generate(..., strict_keys=True)` would reject. pmgfal's own
+            `examples`, `title` and `additionalProperties` are accepted
+        strict_paths: also report files whose path doesn't spell their id,
+            as atproto lays lexicons out (`app/bsky/feed/post.json` for
+            `app.bsky.feed.post`). `lexicon_dir` may start anywhere along
+            the nsid, so a flat directory of `post.json` files passes
+
+    Returns:
+        the problems found, ordered by file; empty when all is well
+
+    Raises:
+        LexiconError: if the directory can't be read at all
     """
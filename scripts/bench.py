@@ -1,7 +1,12 @@
 #!/usr/bin/env -S uv run python
-"""benchmark pmgfal on real lexicons."""
+"""benchmark pmgfal on real lexicons, generated union adapters, or file writes."""
 
+import functools
+import importlib.util
+import json
+import operator
 import subprocess
+import sys
 import tempfile
 import time
 from pathlib import Path
@@ -65,5 +70,137 @@ def bench_atproto():
         print(f"  cache hit: {cache_time:.3f}s")
 
 
+EMBED_TYPES = ["images", "video", "external", "record"]
+
+
+def embed_lexicon() -> dict:
+    """a post with a closed union of embed types."""
+    defs = {
+        "main": {
+            "type": "record",
+            "record": {
+                "type": "object",
+                "properties": {
+                    "embed": {
+                        "type": "union",
+                        "refs": [f"#{name}" for name in EMBED_TYPES],
+                        "closed": True,
+                    },
+                },
+                "required": ["embed"],
+            },
+        },
+    }
+    for name in EMBED_TYPES:
+        defs[name] = {
+            "type": "object",
+            "properties": {"uri": {"type": "string"}, "alt": {"type": "string"}},
+            "required": ["uri"],
+        }
+    return {"lexicon": 1, "id": "fm.plyr.post", "defs": defs}
+
+
+def bench_union_adapters(count: int = 10_000):
+    """decode raw embeds with a reused adapter vs one built per call."""
+    from pydantic import TypeAdapter
+
+    from pmgfal import generate
+
+    with tempfile.TemporaryDirectory() as tmp:
+        lexicon_dir = Path(tmp) / "lexicons"
+        lexicon_dir.mkdir()
+        (lexicon_dir / "post.json").write_text(json.dumps(embed_lexicon()))
+        files = generate(str(lexicon_dir), tmp, emit_union_adapters=True)
+
+        spec = importlib.util.spec_from_file_location("models", files[0])
+        assert spec is not None and spec.loader is not None
+        models = importlib.util.module_from_spec(spec)
+        spec.loader.exec_module(models)
+
+        raw = [
+            {
+                "$type": f"fm.plyr.post#{EMBED_TYPES[i % len(EMBED_TYPES)]}",
+                "uri": f"at://{i}",
+            }
+            for i in range(count)
+        ]
+        adapter = models.FmPlyrPostEmbedAdapter
+        members = [getattr(models, f"FmPlyrPost{n.title()}") for n in EMBED_TYPES]
+        plain = functools.reduce(operator.or_, members)
+
+        start = time.perf_counter()
+        for item in raw:
+            adapter.validate_python(item)
+        reused = time.perf_counter() - start
+
+        start = time.perf_counter()
+        for item in raw:
+            TypeAdapter(plain).validate_python(item)
+        per_call = time.perf_counter() - start
+
+        print(f"\nresults ({count} embeds):")
+        print(f"  reused adapter: {reused:.3f}s")
+        print(f"  adapter per call: {per_call:.3f}s ({per_call / reused:.1f}x)")
+
+
+def bench_writes(count: int = 500):
+    """write a large package one file at a time vs on several threads."""
+    from pmgfal import generate
+
+    with tempfile.TemporaryDirectory() as tmp:
+        lexicon_dir = Path(tmp) / "lexicons"
+        lexicon_dir.mkdir()
+        for i in range(count):
+            nsid = f"fm.plyr.gen{i // 50}.record{i}"
+            lexicon = {
+                "lexicon": 1,
+                "id": nsid,
+                "defs": {
+                    "main": {
+                        "type": "record",
+                        "record": {
+                            "type": "object",
+                            "properties": {
+                                "title": {"type": "string"},
+                                "next": {
+                                    "type": "ref",
+                                    "ref": f"fm.plyr.gen{(i + 1) % count // 50}"
+                                    f".record{(i + 1) % count}",
+                                },
+                            },
+                        },
+                    },
+                },
+            }
+            (lexicon_dir / f"{nsid}.json").write_text(json.dumps(lexicon))
+
+        outputs = {}
+        for threads in (1, 8):
+            output_dir = Path(tmp) / f"threads{threads}"
+            start = time.perf_counter()
+            result = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                write_threads=threads,
+                relative_paths=True,
+            )
+            elapsed = time.perf_counter() - start
+            outputs[threads] = {
+                path: (output_dir / path).read_bytes() for path in result.files
+            }
+            assert list(outputs[threads]) == result.files
+            print(f"  write_threads={threads}: {elapsed:.3f}s")
+
+        assert list(outputs[1]) == list(outputs[8]), "file order differs"
+        assert outputs[1] == outputs[8], "file contents differ"
+        print(f"\nresults ({len(outputs[1])} files): identical output")
+
+
 if __name__ == "__main__":
-    bench_atproto()
+    if sys.argv[1:] == ["unions"]:
+        bench_union_adapters()
+    elif sys.argv[1:] == ["writes"]:
+        bench_writes()
+    else:
+        bench_atproto()
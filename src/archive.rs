@@ -0,0 +1,114 @@
+//! reading lexicons from `.zip` and `.tar.gz` archives
+
+use std::path::Path;
+
+use crate::parser::ParseError;
+
+/// a json file inside an archive
+pub struct Entry {
+    /// path of the entry within the archive
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+/// whether `path` is a file that should be read as an archive
+pub fn is_archive(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    path.is_file()
+        && [".zip", ".tar.gz", ".tgz"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+}
+
+/// read every `.json` entry of an archive, sorted by entry path
+///
+/// an entry over `max_file_size` bytes is an error.
+#[cfg(feature = "archive")]
+pub fn json_entries(path: &Path, max_file_size: u64) -> Result<Vec<Entry>, ParseError> {
+    let name = path.to_string_lossy().to_lowercase();
+    let mut entries = if name.ends_with(".zip") {
+        zip_entries(path, max_file_size)?
+    } else {
+        tar_entries(path, max_file_size)?
+    };
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+#[cfg(not(feature = "archive"))]
+pub fn json_entries(path: &Path, _max_file_size: u64) -> Result<Vec<Entry>, ParseError> {
+    Err(ParseError::ArchiveUnsupported(path.display().to_string()))
+}
+
+/// an entry's content, read no further than `max_file_size` bytes
+///
+/// the size an archive declares is checked first, but a compressed stream
+/// can disagree with its header, so reading stops past the limit regardless.
+#[cfg(feature = "archive")]
+fn read_entry(
+    reader: impl std::io::Read,
+    path: &str,
+    declared_size: u64,
+    max_file_size: u64,
+) -> Result<Vec<u8>, ParseError> {
+    use std::io::Read;
+
+    let too_large = |size| ParseError::TooLarge {
+        path: path.to_string(),
+        size,
+        limit: max_file_size,
+    };
+    if declared_size > max_file_size {
+        return Err(too_large(declared_size));
+    }
+    let mut content = Vec::new();
+    reader.take(max_file_size + 1).read_to_end(&mut content)?;
+    if content.len() as u64 > max_file_size {
+        return Err(too_large(content.len() as u64));
+    }
+    Ok(content)
+}
+
+#[cfg(feature = "archive")]
+fn zip_entries(path: &Path, max_file_size: u64) -> Result<Vec<Entry>, ParseError> {
+    use std::io;
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?).map_err(io::Error::other)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(io::Error::other)?;
+        if !file.is_file() || !file.name().ends_with(".json") {
+            continue;
+        }
+        let name = file.name().to_string();
+        let size = file.size();
+        entries.push(Entry {
+            content: read_entry(file, &name, size, max_file_size)?,
+            path: name,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(feature = "archive")]
+fn tar_entries(path: &Path, max_file_size: u64) -> Result<Vec<Entry>, ParseError> {
+    let gz = flate2::read::GzDecoder::new(std::fs::File::open(path)?);
+    let mut archive = tar::Archive::new(gz);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+        if !entry.header().entry_type().is_file() || !entry_path.ends_with(".json") {
+            continue;
+        }
+        let size = entry.size();
+        entries.push(Entry {
+            content: read_entry(entry, &entry_path, size, max_file_size)?,
+            path: entry_path,
+        });
+    }
+    Ok(entries)
+}
@@ -1,8 +1,12 @@
 //! built-in atproto lexicons for resolving external refs
 
 use atrium_lex::LexiconDoc;
+use std::borrow::Cow;
 use std::sync::LazyLock;
 
+use crate::config::Config;
+use crate::parser::{parse_lexicons_with_limits, ParseError};
+
 /// all bundled com.atproto.* lexicons
 static LEXICONS: LazyLock<Vec<LexiconDoc>> = LazyLock::new(|| {
     let mut docs = Vec::new();
@@ -22,6 +26,22 @@ pub fn builtin_lexicons() -> &'static [LexiconDoc] {
     &LEXICONS
 }
 
+/// resolve the builtin set for a generation run
+///
+/// `builtins_dir` replaces the bundled snapshot entirely, and disabling
+/// builtins yields an empty set.
+pub fn select_builtins(config: &Config) -> Result<Cow<'static, [LexiconDoc]>, ParseError> {
+    if !config.use_builtins {
+        return Ok(Cow::Borrowed(&[]));
+    }
+    match &config.builtins_dir {
+        Some(dir) => Ok(Cow::Owned(
+            parse_lexicons_with_limits(dir, &config.limits())?.0,
+        )),
+        None => Ok(Cow::Borrowed(builtin_lexicons())),
+    }
+}
+
 const LEXICON_JSON: &[&str] = &[
     include_str!("../lexicons/com/atproto/admin/defs.json"),
     include_str!("../lexicons/com/atproto/admin/deleteAccount.json"),
@@ -0,0 +1,174 @@
+//! lexicon parses kept across `generate` calls, for long-running processes
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use crate::archive;
+use crate::parser::{self, Lexicons, Limits, ParseError, Parsed, ParsedDocument};
+
+/// parsed lexicon files, reused while they don't change
+///
+/// set as `Config::parse_cache`, so a process regenerating models over and
+/// over (a dev server watching its lexicons) only reads the files whose
+/// modification time or size changed since the last call, and only
+/// deserializes those, or every file when the ref aliases between documents
+/// changed. archives are read in full each time, but their unchanged
+/// entries aren't deserialized again. clones share one cache.
+#[derive(Clone, Default)]
+pub struct ParseCache(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    /// the modification time and size each file had when last read
+    stats: HashMap<PathBuf, (SystemTime, u64)>,
+    /// by path, as `read_json_files` names files
+    files: HashMap<String, CachedFile>,
+    /// the alias targets the cached documents were deserialized with
+    aliases: HashMap<String, String>,
+    /// lexicons deserialized so far
+    parses: usize,
+}
+
+struct CachedFile {
+    content: Vec<u8>,
+    /// the file as `read_document` read it, `None` for json that isn't a
+    /// lexicon
+    value: Option<serde_json::Value>,
+    parsed: Option<ParsedDocument>,
+}
+
+impl ParseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// how many lexicon files were deserialized through this cache
+    pub fn parses(&self) -> usize {
+        self.lock().parses
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // a panic mid-update leaves nothing a later call can't redo
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// `parser::read_json_files`, reusing the content of files whose
+    /// modification time and size are unchanged
+    pub(crate) fn read_json_files(
+        &self,
+        dir: &Path,
+        limits: &Limits,
+    ) -> Result<Vec<archive::Entry>, ParseError> {
+        if archive::is_archive(dir) || !dir.is_dir() {
+            return parser::read_json_files(dir, limits);
+        }
+        let mut paths = Vec::new();
+        parser::visit_dir(dir, &mut paths)?;
+        paths.sort();
+
+        let mut state = self.lock();
+        let state = &mut *state;
+        // forget files deleted since the last call
+        let listed: HashSet<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        state.stats.retain(|path, _| {
+            !path.starts_with(dir) || listed.contains(&path.display().to_string())
+        });
+        state
+            .files
+            .retain(|name, _| !Path::new(name).starts_with(dir) || listed.contains(name));
+
+        let mut entries = Vec::with_capacity(paths.len());
+        for path in paths {
+            let metadata = fs::metadata(&path)?;
+            let stat = (metadata.modified()?, metadata.len());
+            let name = path.display().to_string();
+            let cached = state
+                .files
+                .get(&name)
+                .filter(|_| state.stats.get(&path) == Some(&stat));
+            let entry = match cached {
+                Some(file) => archive::Entry {
+                    content: file.content.clone(),
+                    path: name,
+                },
+                None => parser::read_file(&path, limits)?,
+            };
+            state.stats.insert(path, stat);
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// `parser::parse_files`, deserializing only files whose content
+    /// changed since they were last parsed
+    pub(crate) fn parse_files(
+        &self,
+        files: &[archive::Entry],
+        limits: &Limits,
+        strict_keys: Option<&[String]>,
+        merge_split: bool,
+    ) -> Result<Lexicons, ParseError> {
+        let mut state = self.lock();
+        let state = &mut *state;
+        for file in files {
+            parser::check_depth(file, limits)?;
+            let changed = state
+                .files
+                .get(&file.path)
+                .is_none_or(|cached| cached.content != file.content);
+            if changed {
+                let value = parser::read_document(&file.content, &file.path)?;
+                let cached = CachedFile {
+                    content: file.content.clone(),
+                    value,
+                    parsed: None,
+                };
+                state.files.insert(file.path.clone(), cached);
+            }
+            if let (Some(allowed), Some(value)) = (strict_keys, &state.files[&file.path].value) {
+                parser::check_unknown_keys(value, &file.path, allowed)?;
+            }
+        }
+
+        let values = files
+            .iter()
+            .filter_map(|file| state.files[&file.path].value.as_ref());
+        let resolved = parser::resolve_ref_aliases(values)?;
+        if resolved != state.aliases {
+            for cached in state.files.values_mut() {
+                cached.parsed = None;
+            }
+            state.aliases = resolved;
+        }
+
+        let mut parsed = Parsed::new(merge_split);
+        for file in files {
+            let cached = state
+                .files
+                .get_mut(&file.path)
+                .expect("every file is cached above");
+            let Some(value) = &cached.value else {
+                continue;
+            };
+            if cached.parsed.is_none() {
+                let mut value = value.clone();
+                parser::drop_ref_aliases(&mut value, &state.aliases);
+                cached.parsed = Some(parser::parse_document(value, &file.path)?);
+                state.parses += 1;
+            }
+            let document = cached.parsed.clone().expect("parsed above");
+            parsed.add(&file.path, document)?;
+        }
+        Ok(parsed.into_lexicons())
+    }
+}
+
+impl fmt::Debug for ParseCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ParseCache({} files)", self.lock().files.len())
+    }
+}
@@ -0,0 +1,225 @@
+//! lexicon linting: the checks `generate` relies on, without writing output
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use atrium_lex::lexicon::{LexArrayItem, LexObject, LexObjectProperty, LexRecord, LexUserType};
+use atrium_lex::LexiconDoc;
+
+use crate::codegen::invalid_defaults;
+use crate::parser::{self, Limits, ParseError};
+use crate::types::{collect_def_refs, RefContext};
+
+/// a problem found in one lexicon file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexiconProblem {
+    /// the file, as `read_json_files` names it
+    pub path: String,
+    /// the document's nsid, when the file got far enough to declare one
+    pub nsid: Option<String>,
+    pub message: String,
+}
+
+/// check every lexicon under `dir` (a directory or archive)
+///
+/// unlike `parse_lexicons`, a bad file doesn't stop the run: malformed json,
+/// lexicons that don't deserialize, duplicate nsids, invalid nsids, empty
+/// unions, integer defaults their own constraints rule out and refs that
+/// resolve neither within `dir` nor against `builtins` are all collected,
+/// ordered by file, along with keys the lexicon language doesn't define when
+/// `strict_keys` is set. with `strict_paths`, so are files whose path doesn't
+/// spell their declared nsid (see `matches_path`). only failing to read the
+/// tree at all (a missing directory, an oversized file) is an error.
+pub fn check_lexicons(
+    dir: &Path,
+    builtins: &[LexiconDoc],
+    limits: &Limits,
+    strict_keys: bool,
+    strict_paths: bool,
+) -> Result<Vec<LexiconProblem>, ParseError> {
+    let files = parser::read_json_files(dir, limits)?;
+    let mut problems = Vec::new();
+    // nsid -> (path, document), for the first file declaring each nsid
+    let mut docs: BTreeMap<String, (String, LexiconDoc)> = BTreeMap::new();
+    // alias defs, which parsing drops once refs within their own file
+    // point past them; refs from other files still resolve through them
+    let mut aliases = HashSet::new();
+
+    for file in &files {
+        let problem = |nsid: Option<String>, message: String| LexiconProblem {
+            path: file.path.clone(),
+            nsid,
+            message,
+        };
+        let doc = match parser::parse_files(std::slice::from_ref(file), limits, None, false) {
+            Ok(lexicons) => lexicons.docs.into_iter().next(),
+            Err(e) => {
+                problems.push(problem(document_id(&file.content), parse_message(e)));
+                continue;
+            }
+        };
+        let Some(doc) = doc else {
+            // other json is skipped, but only if it is json
+            if let Err(e) = parser::read_json(&file.content) {
+                problems.push(problem(None, format!("malformed json: {e}")));
+            }
+            continue;
+        };
+        if strict_keys {
+            for key in parser::file_unknown_keys(&file.content, &[]) {
+                problems.push(problem(Some(doc.id.clone()), format!("unknown key {key}")));
+            }
+        }
+        if strict_paths && !matches_path(&doc.id, dir, &file.path) {
+            problems.push(problem(
+                Some(doc.id.clone()),
+                format!("id {} doesn't match its path {}", doc.id, file.path),
+            ));
+        }
+        if let Some((first, _)) = docs.get(&doc.id) {
+            problems.push(problem(
+                Some(doc.id.clone()),
+                format!("duplicate nsid, first declared in {first}"),
+            ));
+            continue;
+        }
+        aliases.extend(parser::file_ref_aliases(&file.content));
+        docs.insert(doc.id.clone(), (file.path.clone(), doc));
+    }
+
+    let builtins: HashMap<&str, &LexiconDoc> =
+        builtins.iter().map(|doc| (doc.id.as_str(), doc)).collect();
+    let resolves = |def_ref: &str| {
+        let (nsid, def_name) = def_ref.split_once('#').unwrap_or((def_ref, "main"));
+        aliases.contains(&format!("{nsid}#{def_name}"))
+            || docs
+                .get(nsid)
+                .map(|(_, doc)| doc)
+                .or_else(|| builtins.get(nsid).copied())
+                .is_some_and(|doc| doc.defs.contains_key(def_name))
+    };
+
+    for (nsid, (path, doc)) in &docs {
+        let mut messages = Vec::new();
+        if !is_valid_nsid(nsid) {
+            messages.push(format!("invalid nsid {nsid:?}"));
+        }
+        let ctx = RefContext::new(nsid);
+        let mut defs: Vec<_> = doc.defs.iter().collect();
+        defs.sort_by_key(|(name, _)| *name);
+        for (def_name, def) in defs {
+            for field in empty_unions(def) {
+                messages.push(format!("{nsid}#{def_name}{field}: union has no members"));
+            }
+            let mut refs: Vec<String> = collect_def_refs(def).into_iter().collect();
+            refs.sort();
+            for r in refs {
+                if !resolves(&ctx.qualify_ref(&r)) {
+                    messages.push(format!("{nsid}#{def_name}: unresolved ref {r}"));
+                }
+            }
+        }
+        for (field, reason) in invalid_defaults(doc) {
+            messages.push(format!("{field}: {reason}"));
+        }
+        problems.extend(messages.into_iter().map(|message| LexiconProblem {
+            path: path.clone(),
+            nsid: Some(nsid.clone()),
+            message,
+        }));
+    }
+
+    problems.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(problems)
+}
+
+/// whether a file sits where atproto lays out a lexicon with this `id`
+///
+/// directories are the nsid's leading segments and the file name its last,
+/// so `app.bsky.feed.post` lives at `app/bsky/feed/post.json`. `dir` may
+/// start anywhere along the nsid (a flat directory of `post.json` files is
+/// fine) and archives may nest the tree under directories of their own, so
+/// it's enough for the shorter of the two to end the other.
+fn matches_path(id: &str, dir: &Path, path: &str) -> bool {
+    let path = Path::new(path);
+    let relative = path.strip_prefix(dir).unwrap_or(path).with_extension("");
+    let from_path: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let from_id: Vec<_> = id.split('.').collect();
+    from_path
+        .iter()
+        .rev()
+        .zip(from_id.iter().rev())
+        .all(|(a, b)| a == b)
+}
+
+/// the `id` a file declares, read without deserializing the lexicon
+fn document_id(content: &[u8]) -> Option<String> {
+    let value = parser::read_json(content).ok()?;
+    value.get("id")?.as_str().map(str::to_string)
+}
+
+/// a parse error without the path `LexiconProblem` already carries
+fn parse_message(e: ParseError) -> String {
+    match e {
+        ParseError::InvalidLexicon { source, .. } => format!("invalid lexicon: {source}"),
+        ParseError::TooDeep { limit, .. } => format!("nests deeper than the limit of {limit}"),
+        other => other.to_string(),
+    }
+}
+
+/// where a def has a union without refs: `""` for the def itself (an array
+/// of unions), `.field` for a property
+fn empty_unions(def: &LexUserType) -> Vec<String> {
+    let is_empty =
+        |item: &LexArrayItem| matches!(item, LexArrayItem::Union(u) if u.refs.is_empty());
+    let obj: &LexObject = match def {
+        LexUserType::Record(LexRecord { record, .. }) => {
+            let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+            obj
+        }
+        LexUserType::Object(obj) => obj,
+        LexUserType::Array(arr) if is_empty(&arr.items) => return vec![String::new()],
+        _ => return Vec::new(),
+    };
+    let mut fields: Vec<String> = obj
+        .properties
+        .iter()
+        .filter(|(_, prop)| match prop {
+            LexObjectProperty::Union(u) => u.refs.is_empty(),
+            LexObjectProperty::Array(arr) => is_empty(&arr.items),
+            _ => false,
+        })
+        .map(|(name, _)| format!(".{name}"))
+        .collect();
+    fields.sort();
+    fields
+}
+
+/// whether `nsid` is a valid atproto nsid
+///
+/// at least three dot-separated segments and at most 317 characters. the
+/// domain segments are 1-63 letters, digits and inner hyphens, the first not
+/// starting with a digit; the name is 1-63 letters and digits, starting with
+/// a letter.
+fn is_valid_nsid(nsid: &str) -> bool {
+    let segments: Vec<&str> = nsid.split('.').collect();
+    let Some((name, domain)) = segments.split_last() else {
+        return false;
+    };
+    let domain_ok = domain.iter().enumerate().all(|(i, segment)| {
+        (1..=63).contains(&segment.len())
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !segment.starts_with('-')
+            && !segment.ends_with('-')
+            && !(i == 0 && segment.starts_with(|c: char| c.is_ascii_digit()))
+    });
+    let name_ok = (1..=63).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric());
+    nsid.len() <= 317 && segments.len() >= 3 && domain_ok && name_ok
+}
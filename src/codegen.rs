@@ -1,26 +1,239 @@
 //! python code generation from lexicon documents
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
 
-use atrium_lex::lexicon::{LexObject, LexRecord, LexUserType};
+use atrium_lex::lexicon::{
+    LexArrayItem, LexObject, LexObjectProperty, LexRecord, LexRefUnion, LexUserType,
+};
 use atrium_lex::LexiconDoc;
-use heck::ToSnakeCase;
+use heck::{ToPascalCase, ToSnakeCase};
+use serde::Deserialize;
+use thiserror::Error;
 
-use crate::builtin::builtin_lexicons;
-use crate::types::{collect_external_refs, property_to_python, to_class_name, RefContext};
+use crate::config::{
+    in_namespace, Config, DocstringStyle, Layout, OnInvalidDefault, OnInvalidField, Syntax, Target,
+    TopLevel,
+};
+use crate::fixtures;
+use crate::parser::{Examples, Extras};
+use crate::style::restyle;
+use crate::types::{
+    array_item_to_python, blob_mime_constraint, bounded_integer, closed_union_members,
+    closed_union_types, collect_def_refs, collect_external_refs, def_as_property,
+    disambiguate_class_names, first_group, integer_default_problem, integer_literal, is_deprecated,
+    optional_type, property_description, property_meta, property_to_python, schema_constraints,
+    split_top_level, typing_generics, typing_syntax, union_members, union_shape, unquoted_chars,
+    ClassNames, MimeConstraint, RefContext, UnionAliases,
+};
+
+/// first line of every generated python file, unless a `file_hook` puts
+/// comments ahead of it; `clean` only deletes these
+const BANNER: &str = "# auto-generated by pmgfal - do not edit";
 
 const HEADER: &str = r#"# auto-generated by pmgfal - do not edit
 
 from __future__ import annotations
+"#;
+
+/// delimit the generated region of a hand-written package `__init__.py`
+const INIT_BEGIN: &str = "# BEGIN pmgfal";
+const INIT_END: &str = "# END pmgfal";
+
+/// module holding helper definitions in package layout
+const HELPERS_MODULE: &str = "_helpers";
+
+/// longest import line emitted before wrapping in parentheses
+const MAX_IMPORT_WIDTH: usize = 88;
+
+/// field provenance marker emitted when `embed_metadata` is on
+const LEXICON_META: &str = r#"
+
+@dataclass(frozen=True)
+class LexiconMeta:
+    """provenance of a generated field in its source lexicon"""
+
+    ref: str | None = None
+    refs: tuple[str, ...] = ()
+    format: str | None = None
+"#;
+
+/// typed blob reference (`{"$type": "blob", "ref": {"$link": ...}, ...}`)
+const BLOB_REF: &str = r#"
+
+class BlobRef(BaseModel):
+    """reference to a blob uploaded to a pds"""
+
+    model_config = ConfigDict(populate_by_name=True)
+
+    type_: Literal["blob"] = Field(default="blob", alias="$type")
+    ref: dict[str, str]
+    mime_type: str = Field(alias="mimeType")
+    size: int
+"#;
+
+/// `cid-link` type, accepting the dag-json `{"$link": cid}` form too
+const CID_LINK: &str = r#"
+
+def unwrap_cid_link(value: Any) -> Any:
+    """the cid of a dag-json link (`{"$link": cid}`); anything else as is"""
+    if isinstance(value, dict) and list(value) == ["$link"]:
+        link: dict[str, Any] = value
+        return link["$link"]
+    return value
+
+
+CidLink = Annotated[str, BeforeValidator(unwrap_cid_link)]
+"#;
+
+/// `bytes` type, accepting the dag-json `{"$bytes": base64}` form too
+const BYTES: &str = r#"
+
+def decode_dag_json_bytes(value: Any) -> Any:
+    """the bytes of a dag-json `{"$bytes": base64}`; anything else as is"""
+    if isinstance(value, dict) and list(value) == ["$bytes"]:
+        wrapper: dict[str, Any] = value
+        encoded: Any = wrapper["$bytes"]
+        if isinstance(encoded, str):
+            # dag-json leaves out the padding
+            padding = "=" * (-len(encoded) % 4)
+            return base64.b64decode(encoded + padding, validate=True)
+    return value
+
+
+Bytes = Annotated[bytes, BeforeValidator(decode_dag_json_bytes)]
+"#;
+
+/// `datetime` type, dumped to json the way atproto writes timestamps
+const DATETIME: &str = r#"
+
+def format_datetime(value: datetime) -> str:
+    """rfc 3339 with `Z` for utc, in milliseconds unless finer precision is set"""
+    precise = value.microsecond % 1000
+    text = value.isoformat(timespec="microseconds" if precise else "milliseconds")
+    if text.endswith("+00:00"):
+        text = text[: -len("+00:00")] + "Z"
+    return text
+
+
+Datetime = Annotated[datetime, PlainSerializer(format_datetime, when_used="json")]
+"#;
+
+/// transport hook and client class of `client.py`; methods are appended
+const CLIENT: &str = r#"
+
+class {base}:
+    """sends xrpc requests; subclass and implement `_call`"""
+
+    async def _call(self, nsid: str, params: Any, input: Any) -> Any:
+        raise NotImplementedError
 
-from typing import Any
 
-from pydantic import BaseModel, Field
+class XrpcClient({base}):
+    """async client with a method per xrpc query and procedure"""
 "#;
 
+/// record dispatch behind `emit_parse_record`, after `RECORD_TYPES`
+const PARSE_RECORD: &str = r#"
+
+def parse_record(data: dict[str, Any]) -> BaseModel:
+    """validate a raw record into the model its `$type` names"""
+    type_: Any = data.get("$type")
+    model = RECORD_TYPES.get(type_) if isinstance(type_, str) else None
+    if model is None:
+        raise ValueError(f"unknown record $type: {type_!r}")
+    return model.model_validate(data)
+"#;
+
+/// collection validation behind `emit_collections`, after `COLLECTIONS`
+const VALIDATE_COLLECTION: &str = r#"
+
+def validate_collection(nsid: str, records: list[dict[str, Any]]) -> list[BaseModel]:
+    """validate the raw records of a collection into its record model"""
+    model = COLLECTIONS.get(nsid)
+    if model is None:
+        raise ValueError(f"unknown collection: {nsid!r}")
+    return [model.model_validate(record) for record in records]
+"#;
+
+/// binds a module's cycle-deferred refs, followed by `global` and imports
+const RESOLVE_IMPORT_CYCLE: &str = r#"def resolve_import_cycle() -> None:
+    """bind the refs deferred to break an import cycle"""
+"#;
+
+/// module holding the record registries in package layout
+const RECORDS_MODULE: &str = "_records";
+
+/// module `__getattr__`/`__dir__` of a lazy package `__init__`
+const LAZY_GETATTR: &str = r#"
+
+def __getattr__(name: str) -> Any:
+    """import the module defining `name` on first access (pep 562)"""
+    module = _LAZY_EXPORTS.get(name)
+    if module is None:
+        raise AttributeError(f"module {__name__!r} has no attribute {name!r}")
+    value = getattr(import_module(module, __name__), name)
+    globals()[name] = value
+    return value
+
+
+def __dir__() -> list[str]:
+    return sorted({*globals(), *__all__})
+"#;
+
+/// raw-input check behind `emit_invariant_validators`
+const CHECK_UNION_TYPE: &str = r#"
+
+def check_union_type(
+    data: Any, field: str, allowed: tuple[str, ...], discriminator: str = "$type"
+) -> None:
+    """require each object in a closed-union field to carry a listed `$type`"""
+    raw: dict[str, Any] = data if isinstance(data, dict) else {}
+    value: Any = raw.get(field)
+    items: list[Any] = value if isinstance(value, list) else [value]
+    for item in items:
+        if not isinstance(item, dict):
+            continue
+        member: dict[str, Any] = item
+        if member.get(discriminator) not in allowed:
+            raise ValueError(
+                f"{field}: {discriminator} must be one of {', '.join(allowed)}"
+            )
+"#;
+
+/// discriminator of the adapters behind `emit_union_adapters`
+const UNION_TAG: &str = r#"
+
+def union_tag(value: Any, discriminator: str = "$type") -> str | None:
+    """`$type` of a raw union member, tagging it for a discriminated union"""
+    if isinstance(value, dict):
+        member: dict[str, Any] = value
+        return member.get(discriminator)
+    return None
+"#;
+
+/// definitions shared by generated modules, emitted only where referenced
+const HELPERS: &[(&str, &str)] = &[
+    ("BlobRef", BLOB_REF),
+    ("Bytes", BYTES),
+    ("CidLink", CID_LINK),
+    ("Datetime", DATETIME),
+    ("LexiconMeta", LEXICON_META),
+    ("check_union_type", CHECK_UNION_TYPE),
+    ("union_tag", UNION_TAG),
+];
+
+/// `unknown` fields `record_union_for_unknown` types as a record
+const RECORD_SLOTS: &[&str] = &["record", "value"];
+
+/// the alias `record_union_for_unknown` types record slots as
+const RECORD_UNION: &str = "AnyRecord";
+
 /// python keywords that need escaping as field names
 const PYTHON_KEYWORDS: &[&str] = &[
     "type", "class", "import", "from", "global", "lambda", "def", "return", "yield", "raise",
@@ -28,178 +241,3565 @@ const PYTHON_KEYWORDS: &[&str] = &[
     "continue", "pass", "and", "or", "not", "in", "is", "None", "True", "False", "async", "await",
 ];
 
+#[derive(Error, Debug)]
+pub enum CodegenError {
+    #[error("unresolved refs: {}", .0.join(", "))]
+    UnresolvedRefs(Vec<String>),
+
+    #[error("class name hook failed: {0}")]
+    NameHook(String),
+
+    #[error("output path {} exists and is not a directory", .0.display())]
+    NotADirectory(PathBuf),
+
+    #[error("{def}: property name {name:?} is not a valid python identifier")]
+    InvalidFieldName { def: String, name: String },
+
+    #[error("{field}: {reason}")]
+    InvalidDefault { field: String, reason: String },
+
+    #[error("seeds name no known def: {}", .0.join(", "))]
+    UnknownSeeds(Vec<String>),
+
+    #[error("compat map {}: {reason}", .path.display())]
+    CompatMap { path: PathBuf, reason: String },
+
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// outcome of a codegen run
+#[derive(Debug, Default)]
+pub struct GenerateResult {
+    /// paths of the files written, joined onto the output directory or, with
+    /// `relative_paths`, relative to it
+    pub files: Vec<String>,
+    /// builtin nsids replaced by a user-provided lexicon with the same id
+    pub shadowed_builtins: Vec<String>,
+    /// non-fatal problems found during generation
+    pub warnings: Vec<GenerateWarning>,
+    /// stale generated files deleted because of `clean`
+    pub removed: Vec<String>,
+}
+
+/// a non-fatal problem found during generation
+///
+/// it displays as its message; `warnings_file` also records where the
+/// problem was found, so it can be triaged without parsing messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenerateWarning {
+    /// `unresolved_ref`, `unknown_format`, `invalid_field`,
+    /// `invalid_default`, `excluded_union` or `open_map`
+    pub kind: &'static str,
+    /// the document concerned; for an unresolved ref, the one not found
+    pub nsid: Option<String>,
+    /// the lexicon file `nsid` was read from, as `read_json_files` names it
+    pub path: Option<String>,
+    /// the def (`nsid#defName`) or property (`nsid#defName.field`) concerned
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl GenerateWarning {
+    pub(crate) fn new(kind: &'static str, nsid: &str, message: String) -> Self {
+        Self {
+            kind,
+            nsid: Some(nsid.to_string()),
+            path: None,
+            field: None,
+            message,
+        }
+    }
+
+    /// this warning, about the def or property at `field`
+    pub(crate) fn at(self, field: String) -> Self {
+        Self {
+            field: Some(field),
+            ..self
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "kind": self.kind,
+            "nsid": self.nsid,
+            "path": self.path,
+            "field": self.field,
+            "message": self.message,
+        })
+    }
+}
+
+impl std::fmt::Display for GenerateWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// write `warnings` as a json array to `config.warnings_file`
+///
+/// the path is relative to `output_dir`. with no warnings nothing is
+/// written, and a report an earlier run left is removed, so a stale one
+/// can't fail ci.
+pub(crate) fn write_warnings_file(
+    warnings: &[GenerateWarning],
+    output_dir: &Path,
+    config: &Config,
+) -> io::Result<()> {
+    let Some(file) = &config.warnings_file else {
+        return Ok(());
+    };
+    let path = output_dir.join(file);
+    if warnings.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let report: Vec<serde_json::Value> = warnings.iter().map(GenerateWarning::to_json).collect();
+    let mut json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+    json.push('\n');
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, json)
+}
+
 /// generate pydantic models for all documents
+///
+/// user documents take precedence over builtins: when a user lexicon shares an
+/// nsid with a builtin one, the user's copy is used for ref resolution and
+/// emission, and the builtin is reported in `shadowed_builtins`.
 pub fn generate_models(
     docs: &[LexiconDoc],
+    builtins: &[LexiconDoc],
     output_dir: &Path,
-    namespace_prefix: Option<&str>,
-) -> Result<Vec<String>, io::Error> {
+    config: &Config,
+) -> Result<GenerateResult, CodegenError> {
+    generate_models_with_examples(docs, builtins, &Examples::new(), output_dir, config)
+}
+
+/// generate models like `generate_models`, documenting defs with examples
+///
+/// examples (see `parse_lexicons_with_examples`) are shown in model
+/// docstrings, and back the smoke tests written with `emit_example_tests`.
+pub fn generate_models_with_examples(
+    docs: &[LexiconDoc],
+    builtins: &[LexiconDoc],
+    examples: &Examples,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<GenerateResult, CodegenError> {
+    let extras = Extras {
+        examples: examples.clone(),
+        ..Default::default()
+    };
+    generate_models_with_extras(docs, builtins, &extras, output_dir, config)
+}
+
+/// generate models like `generate_models`, using what atrium-lex drops
+///
+/// besides examples, the raw def json (see `parse_lexicons_with_extras`)
+/// supplies the property keys harvested by `field_metadata_keys`.
+pub fn generate_models_with_extras(
+    docs: &[LexiconDoc],
+    builtins: &[LexiconDoc],
+    extras: &Extras,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<GenerateResult, CodegenError> {
+    generate_models_with_hash(docs, builtins, extras, None, output_dir, config)
+}
+
+/// `generate_models_with_extras`, with the `hash_lexicons` hash of the files
+/// the documents came from for `emit_lexicon_hash`, which only a caller that
+/// read them knows
+pub(crate) fn generate_models_with_hash(
+    docs: &[LexiconDoc],
+    builtins: &[LexiconDoc],
+    extras: &Extras,
+    lexicon_hash: Option<String>,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<GenerateResult, CodegenError> {
+    let namespace_prefix = config.namespace_prefix.as_deref();
+
+    // excluded defs are gone before anything refs them, so unions collapse
+    // (or fall back to `Any`) over the members that remain
+    let mut warnings = Vec::new();
+    let (docs, builtins) = if config.exclude.is_empty() {
+        (Cow::Borrowed(docs), Cow::Borrowed(builtins))
+    } else {
+        (
+            Cow::Owned(exclude_defs(docs, &config.exclude, &mut warnings)),
+            Cow::Owned(exclude_defs(builtins, &config.exclude, &mut warnings)),
+        )
+    };
+    // a property whose name can't become a python attribute would make the
+    // whole module fail to import
+    let docs = drop_invalid_fields(docs, config, &mut warnings)?;
+    let docs = rewrite_open_maps(docs, extras, config, &mut warnings);
+
+    // build lookup of all available lexicons (user first, then builtin)
+    let mut all_docs: HashMap<&str, &LexiconDoc> = HashMap::new();
+    for doc in docs.iter() {
+        all_docs.insert(&doc.id, doc);
+    }
+    let mut shadowed_builtins = Vec::new();
+    for doc in builtins.iter() {
+        if all_docs.contains_key(doc.id.as_str()) {
+            info!("user lexicon {} shadows builtin", doc.id);
+            shadowed_builtins.push(doc.id.clone());
+        } else {
+            all_docs.insert(&doc.id, doc);
+        }
+    }
+
+    // pascal-casing can map sibling defs to one class name
+    let mut class_names = ClassNames::new();
+    for doc in all_docs.values() {
+        class_names.extend(disambiguate_class_names(
+            &doc.id,
+            doc.defs.keys().map(String::as_str),
+            config.keep_acronyms,
+        ));
+    }
+    // names (and modules) an earlier generator gave defs win over the hook,
+    // which runs once the emitted defs are known
+    let compat = match &config.compat_map {
+        Some(path) => read_compat_map(path)?,
+        None => HashMap::new(),
+    };
+    let mut module_paths = HashMap::new();
+    let mut compat_classes = HashSet::new();
+    for (def_ref, entry) in compat {
+        if let Some(class_name) = entry.class {
+            compat_classes.insert(def_ref.clone());
+            class_names.insert(def_ref.clone(), class_name);
+        }
+        if let Some(module) = entry.module {
+            module_paths.insert(def_ref, module);
+        }
+    }
+    let mut selection = Selection {
+        class_names,
+        module_paths,
+        keep: None,
+        extras: extras.clone(),
+        union_aliases: UnionAliases::new(),
+        repeated_unions: Vec::new(),
+        record_union: None,
+        dag_json: config.target == Target::Pydantic,
+        datetimes: config.typed_datetimes,
+        lexicon_hash: lexicon_hash.filter(|_| config.emit_lexicon_hash),
+    };
+
+    let mut result = GenerateResult {
+        shadowed_builtins,
+        warnings,
+        ..Default::default()
+    };
+
+    // with seeds, only the defs they reach are emitted
+    let seed_closure = if config.seeds.is_empty() {
+        None
+    } else {
+        let roots: Vec<String> = config
+            .seeds
+            .iter()
+            .map(|seed| {
+                if seed.contains('#') {
+                    seed.clone()
+                } else {
+                    format!("{seed}#main")
+                }
+            })
+            .collect();
+        let unknown: Vec<String> = roots
+            .iter()
+            .filter(|r| !def_exists(r, &all_docs))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(CodegenError::UnknownSeeds(unknown));
+        }
+        Some(def_closure(roots, &all_docs))
+    };
+
     let filtered: Vec<_> = docs
         .iter()
         .filter(|doc| {
             namespace_prefix
-                .map(|p| doc.id.starts_with(p))
+                .map(|p| in_namespace(&doc.id, p))
                 .unwrap_or(true)
         })
+        .filter(|doc| {
+            seed_closure.as_ref().is_none_or(|keep| {
+                doc.defs
+                    .keys()
+                    .any(|name| keep.contains(&format!("{}#{name}", doc.id)))
+            })
+        })
         .collect();
 
-    if filtered.is_empty() {
-        return Ok(vec![]);
+    if filtered.is_empty() {
+        return Ok(result);
+    }
+
+    // collect external refs from user documents
+    let mut external_refs: HashSet<String> = HashSet::new();
+    let keep = seed_closure.or_else(|| {
+        config
+            .records_only
+            .then(|| record_closure(&filtered, &all_docs))
+    });
+    if let Some(keep) = keep {
+        external_refs.extend(
+            keep.iter()
+                .filter_map(|r| r.split_once('#'))
+                .map(|(nsid, _)| nsid.to_string())
+                .filter(|nsid| !filtered.iter().any(|d| d.id == *nsid)),
+        );
+        selection.keep = Some(keep);
+    } else {
+        for doc in &filtered {
+            external_refs.extend(collect_external_refs(doc));
+        }
+    }
+    let mut external_refs: Vec<_> = external_refs.into_iter().collect();
+    external_refs.sort();
+
+    // find which external refs we can resolve from user docs or builtins
+    let mut resolved_externals: Vec<&LexiconDoc> = Vec::new();
+    let mut unresolved = Vec::new();
+    for ref_nsid in external_refs {
+        if let Some(doc) = all_docs.get(ref_nsid.as_str()) {
+            // only include if not already in user docs
+            if !filtered.iter().any(|d| d.id == ref_nsid) {
+                debug!("including external {ref_nsid}");
+                resolved_externals.push(doc);
+            }
+        } else {
+            warn!("unresolved external ref {ref_nsid}");
+            unresolved.push(ref_nsid);
+        }
+    }
+
+    if config.strict_refs && !unresolved.is_empty() {
+        return Err(CodegenError::UnresolvedRefs(unresolved));
+    }
+    result.warnings.extend(
+        unresolved
+            .iter()
+            .map(|r| GenerateWarning::new("unresolved_ref", r, format!("unresolved ref: {r}"))),
+    );
+
+    // created up front (parents included) so a bad path fails once, clearly,
+    // instead of as an io error on the first file written
+    if output_dir.exists() && !output_dir.is_dir() {
+        return Err(CodegenError::NotADirectory(output_dir.to_path_buf()));
+    }
+    fs::create_dir_all(output_dir)?;
+
+    if config.layout == Layout::Package || config.split_defs {
+        let mut emitted = resolved_externals;
+        emitted.extend(filtered.iter().copied());
+        apply_name_hook(&emitted, &mut selection, &compat_classes, config)?;
+        check_defaults(&emitted, &selection, config, &mut result.warnings)?;
+        if config.alias_repeated_unions {
+            alias_repeated_unions(&emitted, &mut selection, config);
+        }
+        selection.record_union = record_union(&emitted, &selection, config);
+        result.files = write_package(&emitted, &selection, output_dir, config)?;
+        if config.emit_client {
+            result.files.extend(write_client(
+                &filtered, &selection, ".", output_dir, config,
+            )?);
+        }
+        if config.emit_example_tests && config.target == Target::Pydantic {
+            result.files.extend(write_example_tests(
+                &filtered, &selection, ".", output_dir, config,
+            )?);
+        }
+        if config.emit_fixtures && config.target == Target::Pydantic {
+            result.files.extend(write_fixtures(
+                &filtered, &all_docs, &selection, ".", output_dir, config,
+            )?);
+        }
+        if config.clean {
+            let scope = clean_scope(output_dir, namespace_prefix, config);
+            result.removed = remove_stale_files(output_dir, &scope, &result.files, config)?;
+        }
+        return Ok(relative_paths(result, output_dir, config));
+    }
+
+    let mut body = String::new();
+    let mut skipped = String::new();
+    // module -> class names, for nsids another target generates
+    let mut imported: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+    let mut adapters = String::new();
+    let mut models = Vec::new();
+    let mut records = BTreeMap::new();
+
+    // generate external deps first, and every doc after those it refs
+    let emitted: Vec<&LexiconDoc> = resolved_externals
+        .iter()
+        .chain(&filtered)
+        .copied()
+        .collect();
+    let emitted = dependency_order(&emitted);
+    apply_name_hook(&emitted, &mut selection, &compat_classes, config)?;
+    check_defaults(&emitted, &selection, config, &mut result.warnings)?;
+    disambiguate_across_documents(&emitted, &mut selection, config);
+    if config.alias_repeated_unions {
+        alias_repeated_unions(&emitted, &mut selection, config);
+    }
+    selection.record_union = record_union(&emitted, &selection, config);
+    for doc in emitted {
+        if selection.defs(doc).is_empty() {
+            continue;
+        }
+        if let Some(module) = config.imported_nsids.get(&doc.id) {
+            let classes = imported.entry(module.as_str()).or_default();
+            classes.extend(selection.class_names_of(doc));
+            continue;
+        }
+        let mut exports = Vec::new();
+        let code = generate_document(doc, &selection, config, &mut exports);
+        records.extend(record_classes(doc, &selection));
+
+        // skipped nsids are hand-written where package layout would put them
+        if config.skip_nsids.contains(&doc.id) {
+            let path = def_module_path(&doc.id, "main", false);
+            let names: Vec<&str> = exports.iter().map(String::as_str).collect();
+            skipped.push_str(&import_line(&format!(".{}", path.join(".")), &names));
+            continue;
+        }
+
+        let origin = if filtered.iter().any(|d| d.id == doc.id) {
+            String::new()
+        } else if docs.iter().any(|d| d.id == doc.id) {
+            " (external)".to_string()
+        } else {
+            " (builtin)".to_string()
+        };
+        body.push_str(&format!("\n# {}{origin}\n", doc.id));
+        body.push_str(&code);
+        models.extend(model_classes(doc, &selection, config));
+        adapters.push_str(&document_union_adapters(
+            doc,
+            &selection,
+            config,
+            &mut exports,
+        ));
+    }
+    if !selection.repeated_unions.is_empty() {
+        body.push_str("\n# repeated unions\n");
+        for alias in &selection.repeated_unions {
+            body.push_str(&alias.definition(config));
+        }
+    }
+    if let Some(union) = &selection.record_union {
+        body.push_str("\n# record union\n");
+        body.push_str(&union.definition(config));
+    }
+    // resolve every forward ref now, so a broken one fails at import
+    if !models.is_empty() {
+        body.push_str("\n# resolve forward refs\n");
+        for model in &models {
+            body.push_str(&format!("{model}.model_rebuild()\n"));
+        }
+    }
+    if !adapters.is_empty() {
+        body.push_str("\n# discriminated union adapters\n");
+        body.push_str(&adapters);
+    }
+    if emits_record_registry(config) {
+        body.push_str("\n# record registry\n");
+        body.push_str(&record_registry(&records, config).0);
+    }
+
+    let mut output = header(true, &body, config);
+    output.push('\n');
+    let imports = imported_classes(&imported, &body);
+    if !imports.is_empty() {
+        output.push_str(&imports);
+        output.push('\n');
+    }
+    output.push_str(&skipped);
+    if let Some(hash) = &selection.lexicon_hash {
+        output.push_str(&version_constants(hash));
+    }
+    output.push_str(&body);
+
+    let output_file = single_module_file(output_dir, namespace_prefix);
+
+    write_hooked(&output_file, &restyle(&output, config), config)?;
+
+    result.files.push(output_file.to_string_lossy().to_string());
+    if config.emit_client {
+        let models_module = format!(".{module_name}");
+        result.files.extend(write_client(
+            &filtered,
+            &selection,
+            &models_module,
+            output_dir,
+            config,
+        )?);
+    }
+    if config.emit_example_tests && config.target == Target::Pydantic {
+        result.files.extend(write_example_tests(
+            &filtered,
+            &selection,
+            &module_name,
+            output_dir,
+            config,
+        )?);
+    }
+    if config.emit_fixtures && config.target == Target::Pydantic {
+        result.files.extend(write_fixtures(
+            &filtered,
+            &all_docs,
+            &selection,
+            &format!(".{module_name}"),
+            output_dir,
+            config,
+        )?);
+    }
+    if config.clean {
+        let scope = clean_scope(output_dir, namespace_prefix, config);
+        result.removed = remove_stale_files(output_dir, &scope, &result.files, config)?;
+    }
+    Ok(relative_paths(result, output_dir, config))
+}
+
+/// `result` with its paths relative to `output_dir`, for `relative_paths`
+fn relative_paths(
+    mut result: GenerateResult,
+    output_dir: &Path,
+    config: &Config,
+) -> GenerateResult {
+    if config.relative_paths {
+        for path in result.files.iter_mut().chain(&mut result.removed) {
+            if let Ok(relative) = Path::new(path.as_str()).strip_prefix(output_dir) {
+                *path = relative.to_string_lossy().into_owned();
+            }
+        }
+    }
+    result
+}
+
+/// the single-layout module for `namespace_prefix`, `fm_plyr.py` for
+/// `fm.plyr` or `models.py` without one
+fn single_module_file(output_dir: &Path, namespace_prefix: Option<&str>) -> PathBuf {
+    let module_name = match namespace_prefix {
+        Some(prefix) => prefix.trim_end_matches('.').replace('.', "_"),
+        None => "models".to_string(),
+    };
+    output_dir.join(format!("{module_name}.py"))
+}
+
+/// the files and directories under `output_dir` a run for
+/// `namespace_prefix` could have written, which `clean` may remove from
+///
+/// in single layout that's the prefix's own module, and in package layout
+/// the prefix's subtree, so runs for other prefixes can share the directory
+/// without removing each other's modules.
+pub(crate) fn clean_scope(
+    output_dir: &Path,
+    namespace_prefix: Option<&str>,
+    config: &Config,
+) -> Vec<PathBuf> {
+    if config.layout != Layout::Package && !config.split_defs {
+        return vec![single_module_file(output_dir, namespace_prefix)];
+    }
+    match namespace_prefix {
+        Some(prefix) => {
+            let path: Vec<String> = prefix
+                .trim_end_matches('.')
+                .split('.')
+                .map(to_field_name)
+                .collect();
+            let package = path
+                .iter()
+                .fold(output_dir.to_path_buf(), |dir, part| dir.join(part));
+            vec![module_file(output_dir, &path), package]
+        }
+        None => vec![output_dir.to_path_buf()],
+    }
+}
+
+/// delete generated python files within `scope` (see `clean_scope`) under
+/// `output_dir` that this run didn't write
+///
+/// only files carrying the pmgfal banner are candidates, and the modules of
+/// `skip_nsids` are left alone even if they still carry it, so hand-written
+/// code is never touched. directories the removal empties are pruned too.
+pub(crate) fn remove_stale_files(
+    output_dir: &Path,
+    scope: &[PathBuf],
+    written: &[String],
+    config: &Config,
+) -> io::Result<Vec<String>> {
+    let written: HashSet<PathBuf> = written.iter().map(PathBuf::from).collect();
+    let skipped: Vec<PathBuf> = config
+        .skip_nsids
+        .iter()
+        .map(|nsid| {
+            def_module_path(nsid, "main", false)
+                .iter()
+                .fold(output_dir.to_path_buf(), |path, part| path.join(part))
+        })
+        .collect();
+    let keep = |path: &Path| {
+        !scope.iter().any(|root| path.starts_with(root))
+            || written.contains(path)
+            || skipped
+                .iter()
+                .any(|module| path.starts_with(module) || path == module.with_extension("py"))
+    };
+
+    let mut stale = Vec::new();
+    collect_stale(output_dir, &keep, &mut stale)?;
+    stale.sort();
+    for path in &stale {
+        debug!("removing stale {}", path.display());
+        fs::remove_file(path)?;
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|d| *d != output_dir) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+    }
+    Ok(stale
+        .iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .collect())
+}
+
+/// generated python files under `dir` that `keep` doesn't claim
+fn collect_stale(
+    dir: &Path,
+    keep: &dyn Fn(&Path) -> bool,
+    stale: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_stale(&path, keep, stale)?;
+        } else if file_type.is_file()
+            && path.extension().is_some_and(|ext| ext == "py")
+            && !keep(&path)
+            && fs::read_to_string(&path).is_ok_and(|source| is_generated(&source))
+        {
+            stale.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// rename the defs `emitted` selects as `config.name_hook` says
+///
+/// the hook is only asked about defs that are generated or referenced, and
+/// not about those `compat_map` (`renamed`) already names. a name it returns
+/// must be a python identifier and no keyword, and no other def may end up
+/// with the same class name, which in a single file would shadow one of them.
+fn apply_name_hook(
+    emitted: &[&LexiconDoc],
+    selection: &mut Selection,
+    renamed: &HashSet<String>,
+    config: &Config,
+) -> Result<(), CodegenError> {
+    let Some(hook) = &config.name_hook else {
+        return Ok(());
+    };
+    let mut hooked = Vec::new();
+    for doc in emitted {
+        for (def_name, _) in selection.defs(doc) {
+            let def_ref = format!("{}#{def_name}", doc.id);
+            if renamed.contains(&def_ref) {
+                continue;
+            }
+            let Some(name) = hook
+                .call(&doc.id, def_name)
+                .map_err(CodegenError::NameHook)?
+            else {
+                continue;
+            };
+            if !is_identifier(&name) || PYTHON_KEYWORDS.contains(&name.as_str()) {
+                return Err(CodegenError::NameHook(format!(
+                    "{def_ref}: {name:?} is not a valid class name"
+                )));
+            }
+            selection.class_names.insert(def_ref.clone(), name.clone());
+            hooked.push((def_ref, name));
+        }
+    }
+
+    let mut owners: HashMap<String, Vec<String>> = HashMap::new();
+    for doc in emitted {
+        let ctx = selection.ctx(&doc.id);
+        for (def_name, _) in selection.defs(doc) {
+            owners
+                .entry(ctx.class_name(&doc.id, def_name))
+                .or_default()
+                .push(format!("{}#{def_name}", doc.id));
+        }
+    }
+    for (def_ref, name) in hooked {
+        let mut others = owners.get(&name).into_iter().flatten();
+        if let Some(other) = others.find(|owner| **owner != def_ref) {
+            return Err(CodegenError::NameHook(format!(
+                "{def_ref} and {other} are both named {name}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// rename classes whose names collide across documents of one file
+///
+/// per-document collisions are settled by `disambiguate_class_names`, and
+/// package layout gives each nsid its own module. a single file is one
+/// namespace, though: `fm.plyr.trackItem` and `fm.plyr.track#item` are both
+/// `FmPlyrTrackItem`. the first document keeps the name and later ones get
+/// their snake-cased nsid appended (`FmPlyrTrackItem_fm_plyr_track_item`).
+/// skipped and imported nsids keep their names, since code elsewhere
+/// defines them.
+fn disambiguate_across_documents(docs: &[&LexiconDoc], selection: &mut Selection, config: &Config) {
+    let mut taken = HashSet::new();
+    for doc in docs {
+        let mut renamed = ClassNames::new();
+        let ctx = RefContext::with_class_names(&doc.id, &selection.class_names);
+        for (def_name, _) in selection.defs(doc) {
+            let class_name = ctx.class_name(&doc.id, def_name);
+            if !taken.insert(class_name.clone()) && !config.defined_elsewhere(&doc.id) {
+                let unique = format!("{class_name}_{}", doc.id.replace('.', "_").to_snake_case());
+                info!(
+                    "{}: def {def_name} collides with {class_name}, using {unique}",
+                    doc.id
+                );
+                renamed.insert(format!("{}#{def_name}", doc.id), unique);
+            }
+        }
+        selection.class_names.extend(renamed);
+    }
+}
+
+/// a union shape several fields share, emitted once under one name
+struct UnionAlias {
+    name: String,
+    /// member class names, in the order the first field lists them
+    members: Vec<String>,
+    /// def (`nsid`, def name) whose module defines the alias in package layout
+    owner: (String, String),
+}
+
+impl UnionAlias {
+    /// the alias assignment, which must follow every member's class
+    fn definition(&self, config: &Config) -> String {
+        let py_type = annotation(&self.members.join(" | "), config);
+        let prefix = format!("{} = ", self.name);
+        format!("{prefix}{}\n", wrap_annotation(&py_type, &prefix, config))
+    }
+}
+
+/// name every union shape (member set) that more than one field uses
+///
+/// the alias is named after the first field with the shape, ordered by nsid,
+/// def name and field name (`FmPlyrPostEmbedUnion`), so names don't depend
+/// on which documents happen to be emitted first. single-member unions are
+/// already a bare class and get none.
+fn alias_repeated_unions(docs: &[&LexiconDoc], selection: &mut Selection, config: &Config) {
+    let mut docs = docs.to_vec();
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut shapes: BTreeMap<String, Vec<UnionAlias>> = BTreeMap::new();
+    for doc in docs {
+        if config.defined_elsewhere(&doc.id) {
+            continue;
+        }
+        let ctx = RefContext::with_class_names(&doc.id, &selection.class_names);
+        let mut defs = selection.defs(doc);
+        defs.sort_by_key(|(def_name, _)| *def_name);
+        for (def_name, def) in defs {
+            for (field, union) in def_unions(def) {
+                let members = union_members(union, &ctx);
+                if members.len() < 2 {
+                    continue;
+                }
+                shapes
+                    .entry(union_shape(&members))
+                    .or_default()
+                    .push(UnionAlias {
+                        name: format!(
+                            "{}{}Union",
+                            ctx.class_name(&doc.id, def_name),
+                            field.to_pascal_case()
+                        ),
+                        members,
+                        owner: (doc.id.clone(), def_name.clone()),
+                    });
+            }
+        }
+    }
+    let mut aliases: Vec<UnionAlias> = shapes
+        .into_values()
+        .filter(|fields| fields.len() > 1)
+        .filter_map(|fields| fields.into_iter().next())
+        .collect();
+    aliases.sort_by(|a, b| a.name.cmp(&b.name));
+    for alias in &aliases {
+        debug!("aliasing {} as {}", alias.members.join(" | "), alias.name);
+        selection
+            .union_aliases
+            .insert(union_shape(&alias.members), alias.name.clone());
+    }
+    selection.repeated_unions = aliases;
+}
+
+/// the union properties (or arrays of one) of a record or object, by name
+fn def_unions(def: &LexUserType) -> Vec<(&String, &LexRefUnion)> {
+    let obj = match def {
+        LexUserType::Record(LexRecord { record, .. }) => {
+            let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+            obj
+        }
+        LexUserType::Object(obj) => obj,
+        _ => return Vec::new(),
+    };
+    let mut unions: Vec<_> = obj
+        .properties
+        .iter()
+        .filter_map(|(name, prop)| match prop {
+            LexObjectProperty::Union(u) => Some((name, u)),
+            LexObjectProperty::Array(arr) => match &arr.items {
+                LexArrayItem::Union(u) => Some((name, u)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    unions.sort_by_key(|(name, _)| *name);
+    unions
+}
+
+/// `$type` and class name of each record a document emits
+fn record_classes(doc: &LexiconDoc, selection: &Selection) -> Vec<(String, String)> {
+    let ctx = RefContext::with_class_names(&doc.id, &selection.class_names);
+    selection
+        .defs(doc)
+        .into_iter()
+        .filter(|(_, def)| matches!(def, LexUserType::Record(_)))
+        .map(|(def_name, _)| {
+            let type_ = match def_name.as_str() {
+                "main" => doc.id.clone(),
+                _ => format!("{}#{def_name}", doc.id),
+            };
+            (type_, ctx.class_name(&doc.id, def_name))
+        })
+        .collect()
+}
+
+/// whether `emit_parse_record` or `emit_collections` asks for a registry
+fn emits_record_registry(config: &Config) -> bool {
+    (config.emit_parse_record || config.emit_collections) && config.target == Target::Pydantic
+}
+
+/// the record registries `config` asks for, and the names they define
+///
+/// `emit_parse_record` gives `RECORD_TYPES`, mapping record `$type`s to
+/// models, and `parse_record`; `emit_collections` gives `COLLECTIONS`,
+/// mapping each collection (the nsid of a main record) to its model, and
+/// `validate_collection`.
+fn record_registry(records: &BTreeMap<String, String>, config: &Config) -> (String, Vec<String>) {
+    let mut sections = Vec::new();
+    let mut names = Vec::new();
+    if config.emit_parse_record {
+        sections.push(model_dict("RECORD_TYPES", records.iter()) + PARSE_RECORD);
+        names.extend(["RECORD_TYPES".to_string(), "parse_record".to_string()]);
+    }
+    if config.emit_collections {
+        let collections = records.iter().filter(|(type_, _)| !type_.contains('#'));
+        sections.push(model_dict("COLLECTIONS", collections) + VALIDATE_COLLECTION);
+        names.extend(["COLLECTIONS".to_string(), "validate_collection".to_string()]);
+    }
+    (sections.join("\n\n"), names)
+}
+
+/// a `dict[str, type[BaseModel]]` constant of model classes
+fn model_dict<'r>(name: &str, entries: impl Iterator<Item = (&'r String, &'r String)>) -> String {
+    let mut source = format!("{name}: dict[str, type[BaseModel]] = {{\n");
+    for (key, class_name) in entries {
+        source.push_str(&format!("    {}: {class_name},\n", python_string(key)));
+    }
+    source.push_str("}\n");
+    source
+}
+
+/// pydantic model classes a document's emitted defs define
+fn model_classes(doc: &LexiconDoc, selection: &Selection, config: &Config) -> Vec<String> {
+    if config.target != Target::Pydantic {
+        return Vec::new();
+    }
+    let ctx = RefContext::with_class_names(&doc.id, &selection.class_names);
+    selection
+        .defs(doc)
+        .into_iter()
+        .filter_map(|(def_name, def)| def_model_class(&doc.id, def_name, def, &ctx, config))
+        .collect()
+}
+
+/// the pydantic model class a def defines, if any
+fn def_model_class(
+    nsid: &str,
+    def_name: &str,
+    def: &LexUserType,
+    ctx: &RefContext,
+    config: &Config,
+) -> Option<String> {
+    match def {
+        LexUserType::Record(_) | LexUserType::Object(_) => Some(ctx.class_name(nsid, def_name)),
+        LexUserType::XrpcQuery(_) | LexUserType::XrpcProcedure(_) => {
+            params_object(def).map(|_| format!("{}Params", ctx.class_name(nsid, def_name)))
+        }
+        other if root_models(config) && def_as_property(other).is_some() => {
+            Some(ctx.class_name(nsid, def_name))
+        }
+        _ => None,
+    }
+}
+
+/// documents reordered so each follows the documents it refs
+///
+/// a single file evaluates type aliases at import, so the classes an alias
+/// names must come first, whichever nsid sorts earlier. documents otherwise
+/// keep their order, as do documents whose refs form a cycle.
+fn dependency_order<'d>(docs: &[&'d LexiconDoc]) -> Vec<&'d LexiconDoc> {
+    fn visit<'d>(
+        i: usize,
+        docs: &[&'d LexiconDoc],
+        index: &HashMap<&str, usize>,
+        visited: &mut [bool],
+        ordered: &mut Vec<&'d LexiconDoc>,
+    ) {
+        if visited[i] {
+            return;
+        }
+        visited[i] = true;
+        let mut deps: Vec<usize> = collect_external_refs(docs[i])
+            .iter()
+            .filter_map(|nsid| index.get(nsid.as_str()).copied())
+            .collect();
+        deps.sort_unstable();
+        for dep in deps {
+            visit(dep, docs, index, visited, ordered);
+        }
+        ordered.push(docs[i]);
+    }
+
+    let index: HashMap<&str, usize> = docs
+        .iter()
+        .enumerate()
+        .map(|(i, doc)| (doc.id.as_str(), i))
+        .collect();
+    let mut visited = vec![false; docs.len()];
+    let mut ordered = Vec::with_capacity(docs.len());
+    for i in 0..docs.len() {
+        visit(i, docs, &index, &mut visited, &mut ordered);
+    }
+    ordered
+}
+
+/// write `test_examples.py`, validating each example against its model
+///
+/// one pytest function per example of a record or object def. models are
+/// imported from `models_module`: the sibling module by name in single-file
+/// layout (pytest puts a non-package test file's directory on `sys.path`),
+/// or `.` in package layout. nothing is written when there are no examples.
+fn write_example_tests(
+    docs: &[&LexiconDoc],
+    selection: &Selection,
+    models_module: &str,
+    output_dir: &Path,
+    config: &Config,
+) -> io::Result<Option<String>> {
+    let mut tests = Vec::new();
+    let mut imports = BTreeSet::new();
+    for doc in docs {
+        let ctx = RefContext::with_class_names(&doc.id, &selection.class_names);
+        for (def_name, def) in selection.defs(doc) {
+            if !matches!(def, LexUserType::Record(_) | LexUserType::Object(_)) {
+                continue;
+            }
+            let Some(examples) = selection
+                .extras
+                .examples
+                .get(&format!("{}#{def_name}", doc.id))
+            else {
+                continue;
+            };
+            let class_name = ctx.class_name(&doc.id, def_name);
+            for (i, example) in examples.iter().enumerate() {
+                let literal = python_string(&serde_json::to_string(example)?);
+                tests.push(format!(
+                    "def test_{}_example_{i}() -> None:\n    \
+                     {class_name}.model_validate_json({literal})\n",
+                    class_name.to_snake_case()
+                ));
+            }
+            imports.insert(class_name);
+        }
+    }
+    if tests.is_empty() {
+        return Ok(None);
+    }
+
+    let names: Vec<&str> = imports.iter().map(String::as_str).collect();
+    let mut source = String::from(HEADER);
+    source.push('\n');
+    source.push_str(&import_line(models_module, &names));
+    source.push_str("\n\n");
+    source.push_str(&tests.join("\n\n"));
+
+    let path = output_dir.join("test_examples.py");
+    write_hooked(&path, &restyle(&source, config), config)?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+/// write `fixtures.py`, with a factory per record model
+///
+/// `make_<record>(**overrides)` validates a minimal instance: the record's
+/// required fields filled with placeholders (see `fixtures`), updated by
+/// `overrides`, keyed by lexicon field name. refs resolve against
+/// `all_docs`. models are imported from `models_module`, relative to the
+/// output directory. nothing is written when there are no records.
+fn write_fixtures(
+    docs: &[&LexiconDoc],
+    all_docs: &HashMap<&str, &LexiconDoc>,
+    selection: &Selection,
+    models_module: &str,
+    output_dir: &Path,
+    config: &Config,
+) -> io::Result<Option<String>> {
+    let mut body = String::new();
+    let mut imports = BTreeSet::new();
+    for doc in docs {
+        let ctx = selection.ctx(&doc.id);
+        for (def_name, def) in selection.defs(doc) {
+            if !matches!(def, LexUserType::Record(_)) {
+                continue;
+            }
+            let class_name = ctx.class_name(&doc.id, def_name);
+            let data = fixtures::record_placeholder(doc, def_name, all_docs);
+            body.push_str(&fixture_factory(&class_name, &data));
+            imports.insert(class_name);
+        }
+    }
+    if imports.is_empty() {
+        return Ok(None);
+    }
+
+    let names: Vec<&str> = imports.iter().map(String::as_str).collect();
+    let mut source = header(false, &body, config);
+    source.push('\n');
+    source.push_str(&import_line(models_module, &names));
+    source.push_str(&body);
+
+    let path = output_dir.join("fixtures.py");
+    write_hooked(&path, &restyle(&source, config), config)?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+/// a `make_<record>` factory validating `data` updated by `overrides`
+fn fixture_factory(class_name: &str, data: &serde_json::Value) -> String {
+    let mut source = format!(
+        "\n\ndef make_{}(**overrides: Any) -> {class_name}:\n    \
+         \"\"\"a minimal valid `{class_name}`, with fields set by `overrides`\"\"\"\n",
+        class_name.to_snake_case()
+    );
+    let fields: Vec<String> = data
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, value)| {
+            format!(
+                "        {}: {},\n",
+                python_string(name),
+                python_literal(value)
+            )
+        })
+        .collect();
+    if fields.is_empty() {
+        source.push_str("    data: dict[str, Any] = {}\n");
+    } else {
+        source.push_str("    data: dict[str, Any] = {\n");
+        source.push_str(&fields.concat());
+        source.push_str("    }\n");
+    }
+    source.push_str(&format!(
+        "    return {class_name}.model_validate({{**data, **overrides}})\n"
+    ));
+    source
+}
+
+/// write `client.py` with an async method per query and procedure
+///
+/// methods forward to a `_call(nsid, params, input)` hook on the transport
+/// base class, which raises `NotImplementedError` until a subclass provides
+/// one. model names are imported from `models_module`, relative to the
+/// output directory. nothing is written when there are no xrpc methods.
+fn write_client(
+    docs: &[&LexiconDoc],
+    selection: &Selection,
+    models_module: &str,
+    output_dir: &Path,
+    config: &Config,
+) -> io::Result<Option<String>> {
+    let mut methods = Vec::new();
+    let mut imports = BTreeSet::new();
+    for doc in docs {
+        let ctx = RefContext::with_class_names(&doc.id, &selection.class_names);
+        for (def_name, def) in selection.defs(doc) {
+            if matches!(
+                def,
+                LexUserType::XrpcQuery(_) | LexUserType::XrpcProcedure(_)
+            ) {
+                methods.push(client_method(
+                    doc,
+                    def_name,
+                    def,
+                    &ctx,
+                    config,
+                    &mut imports,
+                ));
+            }
+        }
+    }
+    if methods.is_empty() {
+        return Ok(None);
+    }
+
+    let mut body = CLIENT.replace("{base}", &config.transport_base);
+    for method in methods {
+        body.push('\n');
+        body.push_str(&method);
+    }
+
+    let mut source = header(false, &body, config);
+    if !imports.is_empty() {
+        let names: Vec<&str> = imports.iter().map(String::as_str).collect();
+        source.push('\n');
+        source.push_str(&import_line(models_module, &names));
+    }
+    source.push_str(&body);
+
+    let path = output_dir.join("client.py");
+    write_hooked(&path, &restyle(&source, config), config)?;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+/// one async client method for a query or procedure def
+///
+/// the method is named after the nsid (`fm.plyr.getTracks` ->
+/// `fm_plyr_get_tracks`), plus the def name for non-main defs. model classes
+/// it references are added to `imports`.
+fn client_method(
+    doc: &LexiconDoc,
+    def_name: &str,
+    def: &LexUserType,
+    ctx: &RefContext,
+    config: &Config,
+    imports: &mut BTreeSet<String>,
+) -> String {
+    let value = serde_json::to_value(def).unwrap_or_default();
+    let mut name = doc.id.replace('.', "_").to_snake_case();
+    if def_name != "main" {
+        name = format!("{name}_{}", def_name.to_snake_case());
+    }
+    let nsid = if def_name == "main" {
+        doc.id.clone()
+    } else {
+        format!("{}#{def_name}", doc.id)
+    };
+
+    let mut args = vec!["self".to_string()];
+    let mut call_args = vec![python_string(&nsid)];
+    match params_object(def) {
+        Some(params) => {
+            let class_name = format!("{}Params", ctx.class_name(&doc.id, def_name));
+            if params.required.as_ref().is_some_and(|r| !r.is_empty()) {
+                args.push(format!("params: {class_name}"));
+            } else {
+                args.push(format!(
+                    "params: {} = None",
+                    annotation(&optional_type(&class_name), config)
+                ));
+            }
+            imports.insert(class_name);
+            call_args.push("params".into());
+        }
+        None => call_args.push("None".into()),
+    }
+    match value.get("input").filter(|i| !i.is_null()) {
+        Some(input) => {
+            let (py_type, _) = body_type(input, ctx, imports);
+            args.push(format!("input: {}", annotation(&py_type, config)));
+            call_args.push("input".into());
+        }
+        None => call_args.push("None".into()),
+    }
+    let (returns, model) = match value.get("output").filter(|o| !o.is_null()) {
+        Some(output) => body_type(output, ctx, imports),
+        None => ("None".to_string(), None),
+    };
+
+    let mut lines = vec![format!(
+        "    async def {name}({}) -> {}:",
+        args.join(", "),
+        annotation(&returns, config)
+    )];
+    if let Some(desc) = value.get("description").and_then(|d| d.as_str()) {
+        lines.push(format!("        {}", docstring(desc)));
+    }
+    let call = format!("await self._call({})", call_args.join(", "));
+    lines.push(match model {
+        _ if returns == "None" => format!("        {call}"),
+        Some(model) if config.target == Target::Pydantic => {
+            format!("        return {model}.model_validate({call})")
+        }
+        _ => format!("        return {call}"),
+    });
+    let mut method = lines.join("\n");
+    method.push('\n');
+    method
+}
+
+/// python type of an xrpc input/output body, and the model class that
+/// validates it when the schema is a single ref
+fn body_type(
+    body: &serde_json::Value,
+    ctx: &RefContext,
+    imports: &mut BTreeSet<String>,
+) -> (String, Option<String>) {
+    let schema = body.get("schema").filter(|s| !s.is_null());
+    let Some(schema) = schema else {
+        let encoding = body.get("encoding").and_then(|e| e.as_str());
+        return match encoding {
+            Some("application/json") | None => ("Any".into(), None),
+            Some(_) => ("bytes".into(), None),
+        };
+    };
+    match schema.get("type").and_then(|t| t.as_str()) {
+        Some("ref") => {
+            let target = schema
+                .get("ref")
+                .and_then(|r| r.as_str())
+                .unwrap_or_default();
+            let class_name = ctx.resolve_ref(target);
+            imports.insert(class_name.clone());
+            (class_name.clone(), Some(class_name))
+        }
+        Some("union") => {
+            let refs: Vec<String> = schema
+                .get("refs")
+                .and_then(|r| r.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|r| r.as_str())
+                .map(|r| ctx.resolve_ref(r))
+                .collect();
+            if refs.is_empty() {
+                return ("Any".into(), None);
+            }
+            imports.extend(refs.iter().cloned());
+            (refs.join(" | "), None)
+        }
+        _ => ("dict[str, Any]".into(), None),
+    }
+}
+
+/// a python module in package layout
+#[derive(Default)]
+struct Module {
+    /// class and constant definitions
+    body: String,
+    /// names defined by this module
+    exports: Vec<String>,
+    /// class names referenced by this module's definitions
+    refs: BTreeSet<String>,
+    /// refs evaluated when the module is imported (type aliases), which an
+    /// import cycle can't defer
+    runtime_refs: BTreeSet<String>,
+    /// pydantic model classes defined by this module
+    models: Vec<String>,
+    /// union adapters, emitted after every definition they may reference
+    adapters: String,
+}
+
+/// write one module per nsid (or per def with `split_defs`) as a package
+///
+/// module paths mirror the nsid (`fm.plyr.track` -> `fm/plyr/track.py`), refs
+/// to other modules become relative imports, and the root `__init__.py`
+/// re-exports every generated name. an empty `py.typed` marks the package as
+/// typed.
+fn write_package(
+    docs: &[&LexiconDoc],
+    selection: &Selection,
+    output_dir: &Path,
+    config: &Config,
+) -> io::Result<Vec<String>> {
+    let mut modules: BTreeMap<Vec<String>, Module> = BTreeMap::new();
+    let mut locations: HashMap<String, Vec<String>> = HashMap::new();
+    // modules of skipped nsids, left to hand-written code
+    let mut skipped: HashSet<Vec<String>> = HashSet::new();
+    // module -> class names, for nsids another target generates
+    let mut imported: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+
+    for doc in docs {
+        let ctx = selection.ctx(&doc.id);
+        if let Some(module) = config.imported_nsids.get(&doc.id) {
+            let classes = imported.entry(module.as_str()).or_default();
+            classes.extend(selection.class_names_of(doc));
+            continue;
+        }
+        for (def_name, def) in selection.defs(doc) {
+            let path = selection.module_path(&doc.id, def_name, config.split_defs);
+            if config.skip_nsids.contains(&doc.id) {
+                skipped.insert(path.clone());
+                if config.split_defs {
+                    skipped.insert(path[..path.len() - 1].to_vec());
+                }
+            }
+            let module = modules.entry(path.clone()).or_default();
+
+            let mut exports = Vec::new();
+            module.body.push_str(&generate_def(
+                doc,
+                def_name,
+                def,
+                &ctx,
+                &selection.extras,
+                config,
+                &mut exports,
+            ));
+            if !config.skip_nsids.contains(&doc.id) {
+                module.adapters.push_str(&union_adapters(
+                    doc,
+                    def_name,
+                    def,
+                    &ctx,
+                    config,
+                    &mut exports,
+                ));
+            }
+            let refs: Vec<String> = collect_def_refs(def)
+                .iter()
+                .map(|r| ctx.resolve_ref(r))
+                .collect();
+            if def_as_property(def).is_some() && !root_models(config) {
+                module.runtime_refs.extend(refs.iter().cloned());
+            }
+            module.refs.extend(refs);
+            for (_, union) in def_unions(def) {
+                let shape = union_shape(&union_members(union, &ctx));
+                if let Some(alias) = selection.union_aliases.get(&shape) {
+                    module.refs.insert(alias.clone());
+                }
+            }
+            if selection.record_union.is_some() && has_record_slot(def) {
+                module.refs.insert(RECORD_UNION.to_string());
+            }
+            if config.target == Target::Pydantic {
+                module
+                    .models
+                    .extend(def_model_class(&doc.id, def_name, def, &ctx, config));
+            }
+
+            for name in &exports {
+                locations.insert(name.clone(), path.clone());
+            }
+            module.exports.extend(exports);
+        }
+    }
+
+    // each alias follows the classes of its first field's module
+    for alias in &selection.repeated_unions {
+        let (nsid, def_name) = &alias.owner;
+        let path = selection.module_path(nsid, def_name, config.split_defs);
+        let module = modules.entry(path.clone()).or_default();
+        module.body.push_str(&alias.definition(config));
+        module.body.push('\n');
+        module.exports.push(alias.name.clone());
+        module.refs.extend(alias.members.iter().cloned());
+        module.runtime_refs.extend(alias.members.iter().cloned());
+        locations.insert(alias.name.clone(), path);
+    }
+    if let Some(union) = &selection.record_union {
+        let (nsid, def_name) = &union.owner;
+        let path = selection.module_path(nsid, def_name, config.split_defs);
+        let module = modules.entry(path.clone()).or_default();
+        module.body.push_str(&union.definition(config));
+        module.body.push('\n');
+        module.exports.push(RECORD_UNION.to_string());
+        let members = union
+            .members
+            .iter()
+            .map(|(_, class_name)| class_name.clone());
+        module.refs.extend(members.clone());
+        module.runtime_refs.extend(members);
+        locations.insert(RECORD_UNION.to_string(), path);
+    }
+
+    if emits_record_registry(config) {
+        let records: BTreeMap<String, String> = docs
+            .iter()
+            .filter(|doc| !config.imported_nsids.contains_key(&doc.id))
+            .flat_map(|doc| record_classes(doc, selection))
+            .collect();
+        let (body, exports) = record_registry(&records, config);
+        let used = used_names(&body);
+        let refs = records
+            .into_values()
+            .filter(|class_name| used.contains(class_name.as_str()))
+            .collect();
+        modules.insert(
+            vec![RECORDS_MODULE.to_string()],
+            Module {
+                body,
+                exports,
+                refs,
+                ..Default::default()
+            },
+        );
+    }
+
+    let mut files: BTreeMap<PathBuf, String> = BTreeMap::new();
+
+    for module in modules.values_mut() {
+        let adapters = std::mem::take(&mut module.adapters);
+        module.body.push_str(&adapters);
+    }
+
+    let mut used_anywhere = HashSet::new();
+    for module in modules.values() {
+        used_anywhere.extend(used_names(&module.body));
+    }
+    if let Some(source) = helpers_source(&used_anywhere, config) {
+        let source = restyle(&source, config).into_owned();
+        files.insert(output_dir.join(format!("{HELPERS_MODULE}.py")), source);
+    }
+
+    // modules each module imports from, to find the imports that form cycles
+    let graph: HashMap<&[String], BTreeSet<&[String]>> = modules
+        .iter()
+        .map(|(path, module)| {
+            let targets = module
+                .refs
+                .iter()
+                .filter_map(|class_name| locations.get(class_name))
+                .filter(|target| *target != path)
+                .map(Vec::as_slice)
+                .collect();
+            (path.as_slice(), targets)
+        })
+        .collect();
+    // modules binding refs deferred to break a cycle, with the models to rebuild
+    let mut cyclic: Vec<(&[String], &[String])> = Vec::new();
+
+    for (path, module) in &modules {
+        if skipped.contains(path) {
+            continue;
+        }
+        let mut imports: BTreeMap<&[String], Vec<&str>> = BTreeMap::new();
+        // refs into a module that imports this one back, bound once every
+        // module has loaded; annotations are strings, so only aliases need
+        // their refs at import
+        let mut deferred: BTreeMap<&[String], Vec<&str>> = BTreeMap::new();
+        // a union rendered by its alias no longer names its members
+        let used = used_names(&module.body);
+        for class_name in &module.refs {
+            if let Some(target) = locations.get(class_name) {
+                if target == path || !used.contains(class_name.as_str()) {
+                    continue;
+                }
+                if !module.runtime_refs.contains(class_name) && reaches(&graph, target, path) {
+                    deferred.entry(target).or_default().push(class_name);
+                } else {
+                    imports.entry(target).or_default().push(class_name);
+                }
+            }
+        }
+
+        let mut body = module.body.clone();
+        let mut type_checking = String::new();
+        if !deferred.is_empty() {
+            type_checking.push_str("\nif TYPE_CHECKING:\n");
+            let mut bind = format!("\n\n{RESOLVE_IMPORT_CYCLE}");
+            let names: Vec<&str> = deferred.values().flatten().copied().collect();
+            bind.push_str(&format!("    global {}\n", names.join(", ")));
+            for (target, names) in &deferred {
+                let line = import_line(&relative_module(path, target), names);
+                for line in line.lines() {
+                    type_checking.push_str(&format!("    {line}\n"));
+                    bind.push_str(&format!("    {line}\n"));
+                }
+            }
+            body.push_str(&bind);
+            cyclic.push((path, &module.models));
+        }
+
+        let mut source = header(false, &format!("{type_checking}{body}"), config);
+        let imports = imported_classes(&imported, &module.body);
+        if !imports.is_empty() {
+            source.push('\n');
+            source.push_str(&imports);
+        }
+        let mut local = Vec::new();
+        let helpers_used: Vec<&str> = used_helpers(&used_names(&module.body))
+            .into_iter()
+            .map(|(name, _)| *name)
+            .collect();
+        if !helpers_used.is_empty() {
+            let helpers_path = [HELPERS_MODULE.to_string()];
+            local.push(import_line(
+                &relative_module(path, &helpers_path),
+                &helpers_used,
+            ));
+        }
+        for (target, names) in imports {
+            local.push(import_line(&relative_module(path, target), &names));
+        }
+        if !local.is_empty() {
+            source.push('\n');
+            source.push_str(&local.concat());
+        }
+        source.push_str(&type_checking);
+        source.push_str("\n\n");
+        source.push_str(&body);
+        let source = restyle(&source, config).into_owned();
+        files.insert(module_file(output_dir, path), source);
+    }
+
+    // every ancestor of a module is a package; split nsids aggregate their defs
+    let mut packages: BTreeMap<Vec<String>, BTreeMap<Vec<String>, Vec<&str>>> = BTreeMap::new();
+    packages.entry(vec![]).or_default();
+    for (path, module) in &modules {
+        for depth in 1..path.len() {
+            packages.entry(path[..depth].to_vec()).or_default();
+        }
+        let exports: Vec<&str> = module.exports.iter().map(String::as_str).collect();
+        if config.split_defs {
+            let nsid_package = path[..path.len() - 1].to_vec();
+            packages
+                .entry(nsid_package)
+                .or_default()
+                .insert(path.clone(), exports.clone());
+        }
+        packages
+            .entry(vec![])
+            .or_default()
+            .insert(path.clone(), exports);
+    }
+
+    for (package, reexports) in &packages {
+        if skipped.contains(package) {
+            continue;
+        }
+        let init_path: Vec<String> = package
+            .iter()
+            .cloned()
+            .chain(["__init__".to_string()])
+            .collect();
+        let mut source = String::from(HEADER);
+        if !reexports.is_empty() {
+            source.push('\n');
+            if config.lazy_imports {
+                source.push_str(&lazy_package_init(&init_path, reexports));
+            } else {
+                for (module, names) in reexports {
+                    source.push_str(&import_line(&relative_module(&init_path, module), names));
+                }
+                source.push_str(&dunder_all(reexports));
+            }
+        }
+        if let Some(hash) = selection
+            .lexicon_hash
+            .as_ref()
+            .filter(|_| package.is_empty())
+        {
+            source.push_str(&version_constants(hash));
+        }
+        if package.is_empty() && !cyclic.is_empty() {
+            source.push_str(&resolve_import_cycles(&init_path, &cyclic));
+        }
+        let init_file = module_file(output_dir, &init_path);
+        // restyled before a hand-written part is spliced in, which stays as is
+        let source = keep_hand_written(&init_file, restyle(&source, config).into_owned())?;
+        files.insert(init_file, source);
+    }
+
+    let mut written = write_files(&files, config)?;
+
+    // pep 561: installed copies of the package are typed
+    let marker = output_dir.join("py.typed");
+    fs::write(&marker, "")?;
+    written.push(marker.to_string_lossy().to_string());
+    Ok(written)
+}
+
+/// write every file of `files`, at most `config.write_threads` at a time
+///
+/// directories are created up front, so the writes are independent. the
+/// `file_hook` runs on each file first, on this thread, so a hook calling
+/// back into python never waits on the interpreter the caller holds. the
+/// paths come back in `files`' order however the writes interleave.
+fn write_files(files: &BTreeMap<PathBuf, String>, config: &Config) -> io::Result<Vec<String>> {
+    let parents: BTreeSet<&Path> = files.keys().filter_map(|path| path.parent()).collect();
+    for parent in parents {
+        fs::create_dir_all(parent)?;
+    }
+
+    let files = files
+        .iter()
+        .map(|(path, source)| Ok((path, hooked(path, source, config)?)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let threads = config.write_threads.clamp(1, files.len().max(1));
+    if threads == 1 {
+        for (path, source) in &files {
+            write_file(path, source)?;
+        }
+    } else {
+        // each thread takes the next unwritten file until none are left, or
+        // one fails
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let write_next = || -> io::Result<()> {
+            while !failed.load(Ordering::Relaxed) {
+                let Some((path, source)) = files.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                    break;
+                };
+                if let Err(e) = write_file(path, source) {
+                    failed.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+            Ok(())
+        };
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|_| scope.spawn(write_next)).collect();
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().expect("file writer panicked"))
+        })?;
+    }
+    Ok(files
+        .iter()
+        .map(|(path, _)| path.to_string_lossy().to_string())
+        .collect())
+}
+
+/// whether pmgfal wrote `source`: the banner opens it, or follows the
+/// comments a `file_hook` put ahead of it (a license header)
+fn is_generated(source: &str) -> bool {
+    source
+        .lines()
+        .take_while(|line| line.trim().is_empty() || line.starts_with('#'))
+        .any(|line| line.starts_with(BANNER))
+}
+
+/// a package `__init__` source that keeps hand-written content in `path`
+///
+/// an existing `__init__.py` not written by pmgfal gets the generated code
+/// between `# BEGIN pmgfal` / `# END pmgfal` markers, replacing the previous
+/// region or, without markers, appended. everything outside is left as is.
+/// the region has no `from __future__` import, which must open a file.
+fn keep_hand_written(path: &Path, source: String) -> io::Result<String> {
+    let existing = match fs::read_to_string(path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(source),
+        Err(e) => return Err(e),
+    };
+    if is_generated(&existing) {
+        return Ok(source);
+    }
+    let generated = source.strip_prefix(HEADER).unwrap_or(&source);
+    let region = format!(
+        "{INIT_BEGIN}
+{}{INIT_END}
+",
+        generated.trim_start_matches('\n')
+    );
+    let begin = existing.find(INIT_BEGIN);
+    let end = existing.find(INIT_END);
+    match (begin, end) {
+        (Some(begin), Some(end)) if begin < end => {
+            let after = &existing[end + INIT_END.len()..];
+            let after = after.strip_prefix('\n').unwrap_or(after);
+            Ok(format!("{}{region}{after}", &existing[..begin]))
+        }
+        _ => match existing.trim_end() {
+            "" => Ok(region),
+            before => Ok(format!("{before}\n\n{region}")),
+        },
+    }
+}
+
+/// whether `to` is reachable from `from` along module imports
+fn reaches(
+    graph: &HashMap<&[String], BTreeSet<&[String]>>,
+    from: &[String],
+    to: &[String],
+) -> bool {
+    let mut seen = HashSet::new();
+    let mut pending = vec![from];
+    while let Some(path) = pending.pop() {
+        if path == to {
+            return true;
+        }
+        if seen.insert(path) {
+            pending.extend(graph.get(path).into_iter().flatten().copied());
+        }
+    }
+    false
+}
+
+/// root `__init__` code binding every module's deferred cycle refs, then
+/// rebuilding those modules' models
+///
+/// every binding runs before any rebuild, since a model's schema takes in
+/// the models it refs, whose own refs must resolve by then.
+fn resolve_import_cycles(init_path: &[String], cyclic: &[(&[String], &[String])]) -> String {
+    let mut source = String::from(
+        "\n# refs deferred to break import cycles, bound once every module has loaded\n",
+    );
+    let aliases: Vec<String> = cyclic
+        .iter()
+        .map(|(path, _)| format!("_{}", path.join("_")))
+        .collect();
+    for ((path, _), alias) in cyclic.iter().zip(&aliases) {
+        let (name, parent) = path.split_last().expect("module paths are non-empty");
+        let package = relative_module(init_path, parent);
+        source.push_str(&format!("from {package} import {name} as {alias}\n"));
+    }
+    source.push('\n');
+    for alias in &aliases {
+        source.push_str(&format!("{alias}.resolve_import_cycle()\n"));
+    }
+    for ((_, models), alias) in cyclic.iter().zip(&aliases) {
+        for model in *models {
+            source.push_str(&format!("{alias}.{model}.model_rebuild()\n"));
+        }
+    }
+    source
+}
+
+/// `__all__` listing every re-exported name, sorted
+fn dunder_all(reexports: &BTreeMap<Vec<String>, Vec<&str>>) -> String {
+    let mut all: Vec<&str> = reexports.values().flatten().copied().collect();
+    all.sort_unstable();
+    let mut source = String::from("\n__all__ = [\n");
+    for name in all {
+        source.push_str(&format!("    \"{name}\",\n"));
+    }
+    source.push_str("]\n");
+    source
+}
+
+/// package `__init__` body importing re-exported names on first access
+///
+/// the name-to-module table drives a pep 562 `__getattr__`, so importing the
+/// package imports none of its modules. the eager imports are kept under
+/// `TYPE_CHECKING` for type checkers and editors.
+fn lazy_package_init(init_path: &[String], reexports: &BTreeMap<Vec<String>, Vec<&str>>) -> String {
+    let mut source = String::from(
+        "from importlib import import_module\nfrom typing import TYPE_CHECKING, Any\n\n\
+         if TYPE_CHECKING:\n",
+    );
+    let mut table = String::from("\n_LAZY_EXPORTS: dict[str, str] = {\n");
+    for (module, names) in reexports {
+        let module = relative_module(init_path, module);
+        for line in import_line(&module, names).lines() {
+            source.push_str(&format!("    {line}\n"));
+        }
+        for name in names {
+            table.push_str(&format!("    \"{name}\": {},\n", python_string(&module)));
+        }
+    }
+    table.push_str("}\n");
+    source.push_str(&table);
+    source.push_str(&dunder_all(reexports));
+    source.push_str(LAZY_GETATTR);
+    source
+}
+
+/// dotted module path for a def (`fm.plyr.track` -> `fm.plyr.track`, or
+/// `fm.plyr.track.main` when each def gets its own module)
+fn def_module_path(nsid: &str, def_name: &str, split_defs: bool) -> Vec<String> {
+    let mut path: Vec<String> = nsid.split('.').map(to_field_name).collect();
+    if split_defs {
+        path.push(to_field_name(def_name));
+    }
+    path
+}
+
+/// file holding the module at `path`
+fn module_file(output_dir: &Path, path: &[String]) -> PathBuf {
+    let mut file = output_dir.to_path_buf();
+    if let Some((last, parents)) = path.split_last() {
+        file.extend(parents);
+        file.push(format!("{last}.py"));
+    }
+    file
+}
+
+/// relative import path from the module at `from` to the module at `to`
+fn relative_module(from: &[String], to: &[String]) -> String {
+    let package = &from[..from.len().saturating_sub(1)];
+    let common = package.iter().zip(to).take_while(|(a, b)| a == b).count();
+    let dots = ".".repeat(package.len() - common + 1);
+    format!("{dots}{}", to[common..].join("."))
+}
+
+/// a `from module import names` line, wrapped when too long
+fn import_line(module: &str, names: &[&str]) -> String {
+    let line = format!("from {module} import {}\n", names.join(", "));
+    if line.len() <= MAX_IMPORT_WIDTH + 1 {
+        return line;
+    }
+    let mut wrapped = format!("from {module} import (\n");
+    for name in names {
+        wrapped.push_str(&format!("    {name},\n"));
+    }
+    wrapped.push_str(")\n");
+    wrapped
+}
+
+/// `content` as `config.file_hook` rewrites it for `path`
+fn hooked<'c>(path: &Path, content: &'c str, config: &Config) -> io::Result<Cow<'c, str>> {
+    let Some(hook) = &config.file_hook else {
+        return Ok(Cow::Borrowed(content));
+    };
+    hook.call(path, content)
+        .map(Cow::Owned)
+        .map_err(|e| io::Error::other(format!("file hook failed for {}: {e}", path.display())))
+}
+
+/// `write_file` of `content` as `config.file_hook` rewrites it
+fn write_hooked(path: &Path, content: &str, config: &Config) -> io::Result<()> {
+    write_file(path, &hooked(path, content, config)?)
+}
+
+/// write generated source with stable bytes
+///
+/// output is utf-8 without a bom, uses lf line endings and ends with exactly
+/// one newline, so regenerating never churns otherwise identical files.
+fn write_file(path: &Path, content: &str) -> io::Result<()> {
+    let mut normalized = content.replace("\r\n", "\n").replace('\r', "\n");
+    let trimmed_len = normalized.trim_end_matches('\n').len();
+    normalized.truncate(trimmed_len);
+    normalized.push('\n');
+    fs::write(path, normalized)
+}
+
+/// module header: banner and the imports `body` actually uses
+///
+/// helper definitions referenced by `body` are appended when `inline_helpers`
+/// is set; otherwise the caller imports them from the helpers module.
+fn header(inline_helpers: bool, body: &str, config: &Config) -> String {
+    let helpers = if inline_helpers {
+        helper_code(&used_names(body), config)
+    } else {
+        String::new()
+    };
+    let code = format!("{helpers}{body}");
+    let used = used_names(&code);
+
+    let mut stdlib = Vec::new();
+    if used.contains("base64") {
+        stdlib.push("import base64\n".to_string());
+    }
+    if used.contains("dataclass") {
+        stdlib.push("from dataclasses import dataclass\n".to_string());
+    }
+    if used.contains("datetime") {
+        stdlib.push("from datetime import datetime\n".to_string());
+    }
+    let typing: Vec<&str> = [
+        "Annotated",
+        "Any",
+        "ClassVar",
+        "Dict",
+        "List",
+        "Literal",
+        "Optional",
+        "Protocol",
+        "TYPE_CHECKING",
+        "Tuple",
+        "Union",
+    ]
+    .into_iter()
+    .filter(|name| used.contains(name))
+    // `typing.Annotated` is new in 3.9, as are builtin generics
+    .filter(|name| *name != "Annotated" || config.target_python.runtime_generics())
+    .collect();
+    if !typing.is_empty() {
+        stdlib.push(format!("from typing import {}\n", typing.join(", ")));
+    }
+    let pydantic: Vec<&str> = [
+        "BaseModel",
+        "BeforeValidator",
+        "ConfigDict",
+        "Discriminator",
+        "Field",
+        "PlainSerializer",
+        "RootModel",
+        "Tag",
+        "TypeAdapter",
+        "computed_field",
+        "model_validator",
+    ]
+    .into_iter()
+    .filter(|name| used.contains(name))
+    .collect();
+
+    let mut third_party = Vec::new();
+    if !pydantic.is_empty() {
+        third_party.push(format!("from pydantic import {}\n", pydantic.join(", ")));
+    }
+    if used.contains("Annotated") && !config.target_python.runtime_generics() {
+        third_party.push("from typing_extensions import Annotated\n".to_string());
+    }
+
+    let mut groups = Vec::new();
+    if !stdlib.is_empty() {
+        groups.push(stdlib.concat());
+    }
+    if !third_party.is_empty() {
+        groups.push(third_party.concat());
+    }
+
+    let mut output = String::from(HEADER);
+    if !groups.is_empty() {
+        output.push('\n');
+        output.push_str(&groups.join("\n"));
+    }
+    output.push_str(&helpers);
+    output
+}
+
+/// the pmgfal version and lexicon hash a package was generated with, so an
+/// app can tell when it's out of date
+fn version_constants(lexicon_hash: &str) -> String {
+    format!(
+        "\n__pmgfal_version__ = \"{}\"\n__lexicon_hash__ = \"{lexicon_hash}\"\n",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// import lines for the classes of `imported` (module -> class names) that
+/// `body` uses
+fn imported_classes(imported: &BTreeMap<&str, BTreeSet<String>>, body: &str) -> String {
+    let used = used_names(body);
+    let mut lines = String::new();
+    for (module, names) in imported {
+        let names: Vec<&str> = names
+            .iter()
+            .map(String::as_str)
+            .filter(|name| used.contains(name))
+            .collect();
+        if !names.is_empty() {
+            lines.push_str(&import_line(module, &names));
+        }
+    }
+    lines
+}
+
+/// identifiers referenced by generated code, ignoring docstrings, comments
+/// and string literals (`LexiconMeta(format="datetime")`, `title="Any"`)
+fn used_names(body: &str) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    let mut in_docstring = false;
+    for line in body.lines() {
+        let quotes = line.matches("\"\"\"").count();
+        let skip = in_docstring || quotes > 0 || line.trim_start().starts_with('#');
+        if quotes % 2 == 1 {
+            in_docstring = !in_docstring;
+        }
+        if skip {
+            continue;
+        }
+        // a word ends at any other character, or where a string was skipped
+        let mut start = None;
+        let mut end = 0;
+        for (i, c, _) in unquoted_chars(line) {
+            let is_word = c.is_alphanumeric() || c == '_';
+            if let Some(word_start) = start.filter(|_| !(is_word && i == end)) {
+                names.insert(&line[word_start..end]);
+                start = None;
+            }
+            if is_word {
+                start.get_or_insert(i);
+                end = i + c.len_utf8();
+            }
+        }
+        if let Some(word_start) = start {
+            names.insert(&line[word_start..end]);
+        }
+    }
+    names
+}
+
+/// helper definitions referenced by a set of names
+fn used_helpers(used: &HashSet<&str>) -> Vec<&'static (&'static str, &'static str)> {
+    HELPERS
+        .iter()
+        .filter(|(name, _)| used.contains(name))
+        .collect()
+}
+
+/// the definitions of the helpers `used` names
+///
+/// they're written with builtin generics, which a python without them
+/// (`runtime_generics`) gets spelled from `typing`, as `BlobRef`'s fields
+/// are evaluated by pydantic.
+fn helper_code(used: &HashSet<&str>, config: &Config) -> String {
+    let code: String = used_helpers(used)
+        .into_iter()
+        .map(|(_, source)| *source)
+        .collect();
+    if config.target_python.runtime_generics() {
+        code
+    } else {
+        typing_generics(&code)
+    }
+}
+
+/// source of the standalone helpers module, if any helpers are referenced
+fn helpers_source(used: &HashSet<&str>, config: &Config) -> Option<String> {
+    let code = helper_code(used, config);
+    if code.is_empty() {
+        return None;
+    }
+    Some(header(false, &code, config) + &code)
+}
+
+/// generate python code for a single lexicon document
+fn generate_document(
+    doc: &LexiconDoc,
+    selection: &Selection,
+    config: &Config,
+    exports: &mut Vec<String>,
+) -> String {
+    let ctx = selection.ctx(&doc.id);
+    let mut output = String::new();
+
+    for (def_name, def) in selection.defs(doc) {
+        output.push_str(&generate_def(
+            doc,
+            def_name,
+            def,
+            &ctx,
+            &selection.extras,
+            config,
+            exports,
+        ));
+    }
+
+    output
+}
+
+/// union adapters of a document's emitted defs, recording their names
+fn document_union_adapters(
+    doc: &LexiconDoc,
+    selection: &Selection,
+    config: &Config,
+    exports: &mut Vec<String>,
+) -> String {
+    let ctx = RefContext::with_class_names(&doc.id, &selection.class_names);
+    selection
+        .defs(doc)
+        .into_iter()
+        .map(|(def_name, def)| union_adapters(doc, def_name, def, &ctx, config, exports))
+        .collect()
+}
+
+/// the union of every emitted record, which `record_union_for_unknown` types
+/// record slots as
+struct RecordUnion {
+    /// `$type` and class name of each record, by `$type`
+    members: Vec<(String, String)>,
+    /// def (`nsid`, def name) whose module defines the union in package
+    /// layout: the first with a record slot
+    owner: (String, String),
+}
+
+impl RecordUnion {
+    /// the `AnyRecord` assignment, which must follow every record's class
+    ///
+    /// members are tagged with their `$type`, like the union adapters', so a
+    /// slot validates the record its data names; a lone record is the bare
+    /// class.
+    fn definition(&self, config: &Config) -> String {
+        if let [(_, class_name)] = self.members.as_slice() {
+            return format!("{RECORD_UNION} = {class_name}\n");
+        }
+        let tagged: Vec<String> = self
+            .members
+            .iter()
+            .map(|(type_, class)| format!("Annotated[{class}, Tag({})]", python_string(type_)))
+            .collect();
+        let tagged = match config.syntax {
+            Syntax::Pep604 => tagged.join("\n    | "),
+            Syntax::Typing => format!("Union[\n        {},\n    ]", tagged.join(",\n        ")),
+        };
+        format!(
+            "{RECORD_UNION} = Annotated[\n    {tagged},\n    Discriminator({}),\n]\n",
+            union_discriminator(config)
+        )
+    }
+}
+
+/// the record union `config` asks for, if any field would use it
+///
+/// records of nsids another target generates are left out, as they are from
+/// the record registry.
+fn record_union(
+    docs: &[&LexiconDoc],
+    selection: &Selection,
+    config: &Config,
+) -> Option<RecordUnion> {
+    if !config.record_union_for_unknown || config.target != Target::Pydantic {
+        return None;
+    }
+    let mut docs = docs.to_vec();
+    docs.sort_by(|a, b| a.id.cmp(&b.id));
+    let owner = docs
+        .iter()
+        .filter(|doc| !config.defined_elsewhere(&doc.id))
+        .find_map(|doc| {
+            let mut defs = selection.defs(doc);
+            defs.sort_by_key(|(def_name, _)| *def_name);
+            defs.into_iter()
+                .find(|(_, def)| has_record_slot(def))
+                .map(|(def_name, _)| (doc.id.clone(), def_name.clone()))
+        })?;
+    let members: BTreeMap<String, String> = docs
+        .iter()
+        .filter(|doc| !config.imported_nsids.contains_key(&doc.id))
+        .flat_map(|doc| record_classes(doc, selection))
+        .collect();
+    if members.is_empty() {
+        return None;
+    }
+    Some(RecordUnion {
+        members: members.into_iter().collect(),
+        owner,
+    })
+}
+
+/// whether a record or object has an `unknown` field named as a record slot
+fn has_record_slot(def: &LexUserType) -> bool {
+    let obj = match def {
+        LexUserType::Record(LexRecord { record, .. }) => {
+            let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+            obj
+        }
+        LexUserType::Object(obj) => obj,
+        _ => return false,
+    };
+    obj.properties
+        .iter()
+        .any(|(name, prop)| is_record_slot(name, prop))
+}
+
+/// whether a property is an `unknown` named as a record slot
+fn is_record_slot(name: &str, prop: &LexObjectProperty) -> bool {
+    matches!(prop, LexObjectProperty::Unknown(_)) && RECORD_SLOTS.contains(&name)
+}
+
+/// the `Discriminator` callable reading a raw union member's tag
+fn union_discriminator(config: &Config) -> String {
+    match config.discriminator_field.as_str() {
+        "$type" => "union_tag".to_string(),
+        field => format!("lambda value: union_tag(value, {})", python_string(field)),
+    }
+}
+
+/// a discriminated `TypeAdapter` per closed-union field of a record or object
+///
+/// members are tagged with their `$type`, so validation goes straight to the
+/// matching model instead of trying each in turn. building an adapter is the
+/// expensive part, hence one per field at import. open unions (whose `$type`
+/// may be anything) and single-member unions get none. the adapter is named
+/// after the class and field (`FmPlyrPostEmbedAdapter`) and validates one
+/// member, also for arrays of a union.
+fn union_adapters(
+    doc: &LexiconDoc,
+    def_name: &str,
+    def: &LexUserType,
+    ctx: &RefContext,
+    config: &Config,
+    exports: &mut Vec<String>,
+) -> String {
+    if !config.emit_union_adapters || config.target != Target::Pydantic {
+        return String::new();
+    }
+    let obj = match def {
+        LexUserType::Record(LexRecord { record, .. }) => {
+            let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+            obj
+        }
+        LexUserType::Object(obj) => obj,
+        _ => return String::new(),
+    };
+    let class_name = ctx.class_name(&doc.id, def_name);
+
+    let mut fields: Vec<_> = obj.properties.iter().collect();
+    fields.sort_by_key(|(name, _)| *name);
+    let discriminator = union_discriminator(config);
+
+    let mut output = String::new();
+    for (name, prop) in fields {
+        let Some(members) = closed_union_members(prop, ctx) else {
+            continue;
+        };
+        if members.len() < 2 {
+            continue;
+        }
+        let adapter = format!("{class_name}{}Adapter", name.to_pascal_case());
+        let union: Vec<&str> = members.iter().map(|(_, class)| class.as_str()).collect();
+        let tagged: Vec<String> = members
+            .iter()
+            .map(|(type_, class)| format!("Annotated[{class}, Tag({})]", python_string(type_)))
+            .collect();
+        // the tagged union is evaluated at import, so it follows `syntax` too
+        let tagged = match config.syntax {
+            Syntax::Pep604 => tagged.join("\n        | "),
+            Syntax::Typing => format!(
+                "Union[\n            {},\n        ]",
+                tagged.join(",\n            ")
+            ),
+        };
+        output.push_str(&format!(
+            "{adapter}: TypeAdapter[{}] = TypeAdapter(\n    Annotated[\n        {tagged},\n        \
+             Discriminator({discriminator}),\n    ]\n)\n\n",
+            annotation(&union.join(" | "), config),
+        ));
+        exports.push(adapter);
+    }
+    output
+}
+
+/// where `compat_map` puts a def, either part falling back to the default
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CompatEntry {
+    /// dotted module path within the package (`music.tracks`)
+    module: Option<String>,
+    /// class name, for definitions and refs alike
+    class: Option<String>,
+}
+
+/// the `compat_map` file, keyed by absolute def ref (`nsid#defName`)
+///
+/// keys are an nsid for its `main` def or `nsid#defName`, as in
+/// `class_members`. a module path must be dotted python identifiers.
+fn read_compat_map(path: &Path) -> Result<HashMap<String, CompatEntry>, CodegenError> {
+    let error = |reason: String| CodegenError::CompatMap {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let content = fs::read(path).map_err(|e| error(e.to_string()))?;
+    let entries: HashMap<String, CompatEntry> =
+        serde_json::from_slice(&content).map_err(|e| error(e.to_string()))?;
+    let mut compat = HashMap::new();
+    for (key, entry) in entries {
+        if let Some(module) = &entry.module {
+            if !module.split('.').all(is_identifier) {
+                return Err(error(format!("{key}: {module:?} is not a module path")));
+            }
+        }
+        let def_ref = if key.contains('#') {
+            key
+        } else {
+            format!("{key}#main")
+        };
+        compat.insert(def_ref, entry);
+    }
+    Ok(compat)
+}
+
+/// whether `name` can be a python identifier
+fn is_identifier(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// which defs are emitted, under what class names, with what extras
+struct Selection {
+    class_names: ClassNames,
+    /// package modules `compat_map` moves defs to, by `nsid#defName`
+    module_paths: HashMap<String, String>,
+    /// when set, only these defs (`nsid#defName`) are emitted
+    keep: Option<HashSet<String>>,
+    extras: Extras,
+    /// names of union shapes several fields share, by `union_shape`
+    union_aliases: UnionAliases,
+    /// the aliases in `union_aliases`, by name
+    repeated_unions: Vec<UnionAlias>,
+    /// the union record slots are typed as, for `record_union_for_unknown`
+    record_union: Option<RecordUnion>,
+    /// type `cid-link` and `bytes` as the dag-json aware helpers, which
+    /// validating targets want and protocols have no use for
+    dag_json: bool,
+    /// type `datetime`-format strings as datetimes, for `typed_datetimes`
+    datetimes: bool,
+    /// exposed from the package root as `__lexicon_hash__`
+    lexicon_hash: Option<String>,
+}
+
+impl Selection {
+    /// context for emitting a document's models
+    fn ctx<'s>(&'s self, nsid: &'s str) -> RefContext<'s> {
+        RefContext::with_class_names(nsid, &self.class_names)
+            .with_union_aliases(&self.union_aliases)
+            .with_dag_json(self.dag_json)
+            .with_datetimes(self.datetimes)
+            .with_record_union(self.record_union.as_ref().map(|_| RECORD_UNION))
+    }
+
+    /// the emitted defs of a document, in name order
+    fn defs<'d>(&self, doc: &'d LexiconDoc) -> Vec<(&'d String, &'d LexUserType)> {
+        let mut defs = sorted_defs(doc);
+        if let Some(keep) = &self.keep {
+            defs.retain(|(name, _)| keep.contains(&format!("{}#{name}", doc.id)));
+        }
+        defs
+    }
+
+    /// the package module defining a def: where `compat_map` puts it, or
+    /// else the one its nsid (and def name, with `split_defs`) spells
+    fn module_path(&self, nsid: &str, def_name: &str, split_defs: bool) -> Vec<String> {
+        match self.module_paths.get(&format!("{nsid}#{def_name}")) {
+            Some(module) => module.split('.').map(str::to_string).collect(),
+            None => def_module_path(nsid, def_name, split_defs),
+        }
+    }
+
+    /// class names of a document's emitted defs
+    fn class_names_of(&self, doc: &LexiconDoc) -> Vec<String> {
+        let ctx = self.ctx(&doc.id);
+        self.defs(doc)
+            .into_iter()
+            .map(|(def_name, _)| ctx.class_name(&doc.id, def_name))
+            .collect()
+    }
+}
+
+/// record defs of `roots` plus every def they reach through refs
+fn record_closure(roots: &[&LexiconDoc], all_docs: &HashMap<&str, &LexiconDoc>) -> HashSet<String> {
+    let records = roots
+        .iter()
+        .flat_map(|doc| {
+            doc.defs
+                .iter()
+                .filter(|(_, def)| matches!(def, LexUserType::Record(_)))
+                .map(|(name, _)| format!("{}#{name}", doc.id))
+        })
+        .collect();
+    def_closure(records, all_docs)
+}
+
+/// whether the absolute ref `def_ref` (`nsid#defName`) names a known def
+fn def_exists(def_ref: &str, all_docs: &HashMap<&str, &LexiconDoc>) -> bool {
+    def_ref.split_once('#').is_some_and(|(nsid, def_name)| {
+        all_docs
+            .get(nsid)
+            .is_some_and(|doc| doc.defs.contains_key(def_name))
+    })
+}
+
+/// the absolute refs `roots` plus every def they reach through refs
+///
+/// entries are absolute refs (`nsid#defName`); refs to unknown documents are
+/// kept so they surface as unresolved.
+fn def_closure(mut pending: Vec<String>, all_docs: &HashMap<&str, &LexiconDoc>) -> HashSet<String> {
+    let mut keep = HashSet::new();
+    while let Some(def_ref) = pending.pop() {
+        if !keep.insert(def_ref.clone()) {
+            continue;
+        }
+        let Some((nsid, def_name)) = def_ref.split_once('#') else {
+            continue;
+        };
+        let Some(def) = all_docs.get(nsid).and_then(|doc| doc.defs.get(def_name)) else {
+            continue;
+        };
+        let ctx = RefContext::new(nsid);
+        for r in collect_def_refs(def) {
+            let absolute = ctx.qualify_ref(&r);
+            if absolute.contains('#') {
+                pending.push(absolute);
+            } else {
+                pending.push(format!("{absolute}#main"));
+            }
+        }
     }
+    keep
+}
 
-    // build lookup of all available lexicons (user + builtin)
-    let mut all_docs: HashMap<&str, &LexiconDoc> = HashMap::new();
-    for doc in docs {
-        all_docs.insert(&doc.id, doc);
+/// documents with each open map def as an array def of its values, for
+/// `open_maps`
+///
+/// an object def is a map when it has no properties but an
+/// `additionalProperties` schema (or `true`, for `Any` values), as json
+/// schema spells a string-keyed dict. the value schema takes the place of an
+/// array's `items`, so refs, imports and ordering work as for an array
+/// alias; `def_type` spells it as a dict. a value an array can't hold (an
+/// object or a nested array) leaves the def a model, with a warning.
+fn rewrite_open_maps<'d>(
+    docs: Cow<'d, [LexiconDoc]>,
+    extras: &Extras,
+    config: &Config,
+    warnings: &mut Vec<GenerateWarning>,
+) -> Cow<'d, [LexiconDoc]> {
+    if !config.open_maps {
+        return docs;
     }
-    for doc in builtin_lexicons() {
-        all_docs.entry(&doc.id).or_insert(doc);
+    // (document index, def name, the def as an array)
+    let mut maps = Vec::new();
+    for (i, doc) in docs.iter().enumerate() {
+        for (def_name, def) in sorted_defs(doc) {
+            let LexUserType::Object(obj) = def else {
+                continue;
+            };
+            if !obj.properties.is_empty() {
+                continue;
+            }
+            let def_ref = format!("{}#{def_name}", doc.id);
+            let values = match extras
+                .raw_defs
+                .get(&def_ref)
+                .and_then(|raw| raw.get("additionalProperties"))
+            {
+                Some(serde_json::Value::Bool(true)) => serde_json::json!({"type": "unknown"}),
+                Some(values @ serde_json::Value::Object(_)) => values.clone(),
+                _ => continue,
+            };
+            let array = serde_json::json!({
+                "type": "array",
+                "description": obj.description,
+                "items": values,
+            });
+            match serde_json::from_value(array) {
+                Ok(array) => maps.push((i, def_name.clone(), array)),
+                Err(_) => warnings.push(
+                    GenerateWarning::new(
+                        "open_map",
+                        &doc.id,
+                        format!(
+                            "{def_ref}: additionalProperties can't be a map value, kept as a model"
+                        ),
+                    )
+                    .at(def_ref),
+                ),
+            }
+        }
     }
-
-    // collect external refs from user documents
-    let mut external_refs: HashSet<String> = HashSet::new();
-    for doc in &filtered {
-        external_refs.extend(collect_external_refs(doc));
+    if maps.is_empty() {
+        return docs;
+    }
+    let mut docs = docs.into_owned();
+    for (i, def_name, array) in maps {
+        docs[i].defs.insert(def_name, array);
     }
+    Cow::Owned(docs)
+}
 
-    // find which external refs we can resolve from builtins
-    let mut resolved_externals: Vec<&LexiconDoc> = Vec::new();
-    for ref_nsid in &external_refs {
-        if let Some(doc) = all_docs.get(ref_nsid.as_str()) {
-            // only include if not already in user docs
-            if !filtered.iter().any(|d| d.id == *ref_nsid) {
-                resolved_externals.push(doc);
+/// documents without the defs `patterns` exclude, nor union members naming
+/// them
+///
+/// a pattern matches a def by its nsid or its `nsid#defName`, with `*`
+/// standing for any run of characters (`app.bsky.embed.*`). documents left
+/// without defs are dropped, and a union whose every member is excluded
+/// becomes an open, empty union (typed `Any`) with a warning.
+fn exclude_defs(
+    docs: &[LexiconDoc],
+    patterns: &[String],
+    warnings: &mut Vec<GenerateWarning>,
+) -> Vec<LexiconDoc> {
+    let excluded = |def_ref: &str| {
+        let nsid = def_ref.split_once('#').map_or(def_ref, |(nsid, _)| nsid);
+        patterns
+            .iter()
+            .any(|p| glob_match(p, nsid) || glob_match(p, def_ref))
+    };
+
+    let mut kept = Vec::new();
+    for doc in docs {
+        let mut doc = doc.clone();
+        doc.defs
+            .retain(|def_name, _| !excluded(&format!("{}#{def_name}", doc.id)));
+        if doc.defs.is_empty() {
+            debug!("excluding {}", doc.id);
+            continue;
+        }
+        let ctx = RefContext::new(&doc.id);
+        for (def_name, def) in doc.defs.iter_mut() {
+            let location = format!("{}#{def_name}", ctx.nsid);
+            let unions = match def {
+                LexUserType::Record(LexRecord { record, .. }) => {
+                    let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+                    object_unions(&location, obj)
+                }
+                LexUserType::Object(obj) => object_unions(&location, obj),
+                LexUserType::Array(arr) => match &mut arr.items {
+                    LexArrayItem::Union(u) => vec![(location, u)],
+                    _ => Vec::new(),
+                },
+                _ => Vec::new(),
+            };
+            for (location, union) in unions {
+                if union.refs.is_empty() {
+                    continue;
+                }
+                union.refs.retain(|r| !excluded(&ctx.qualify_ref(r)));
+                if union.refs.is_empty() {
+                    warn!("{location}: every union member is excluded");
+                    warnings.push(
+                        GenerateWarning::new(
+                            "excluded_union",
+                            ctx.nsid,
+                            format!("{location}: every union member is excluded, typed as Any"),
+                        )
+                        .at(location),
+                    );
+                    union.closed = None;
+                }
             }
         }
+        kept.push(doc);
     }
-    resolved_externals.sort_by(|a, b| a.id.cmp(&b.id));
+    kept
+}
 
-    fs::create_dir_all(output_dir)?;
+/// `docs` without the record and object properties whose names don't make a
+/// python identifier
+///
+/// a name is invalid when its field name (`to_field_name`) is empty, as for
+/// `""`, `"  "` or `"🎵"`, or starts with a digit. each one dropped is a
+/// warning, or with `OnInvalidField::Error` the first is an error. `docs` is
+/// only cloned when there is something to drop.
+fn drop_invalid_fields<'d>(
+    docs: Cow<'d, [LexiconDoc]>,
+    config: &Config,
+    warnings: &mut Vec<GenerateWarning>,
+) -> Result<Cow<'d, [LexiconDoc]>, CodegenError> {
+    // (document index, def name, property name)
+    let mut invalid = Vec::new();
+    for (i, doc) in docs.iter().enumerate() {
+        for (def_name, def) in sorted_defs(doc) {
+            let obj = match def {
+                LexUserType::Record(LexRecord { record, .. }) => {
+                    let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+                    obj
+                }
+                LexUserType::Object(obj) => obj,
+                _ => continue,
+            };
+            let mut names: Vec<&String> = obj
+                .properties
+                .keys()
+                .filter(|name| {
+                    let field = to_field_name(name);
+                    field.is_empty() || field.starts_with(|c: char| c.is_ascii_digit())
+                })
+                .collect();
+            names.sort();
+            invalid.extend(
+                names
+                    .into_iter()
+                    .map(|name| (i, def_name.clone(), name.clone())),
+            );
+        }
+    }
+    if invalid.is_empty() {
+        return Ok(docs);
+    }
 
-    let mut output = String::from(HEADER);
-    output.push('\n');
+    let mut docs = docs.into_owned();
+    for (i, def_name, name) in invalid {
+        let doc = &mut docs[i];
+        let location = format!("{}#{def_name}", doc.id);
+        if config.on_invalid_field == OnInvalidField::Error {
+            return Err(CodegenError::InvalidFieldName {
+                def: location,
+                name,
+            });
+        }
+        warn!("{location}: skipping property {name:?}");
+        warnings.push(
+            GenerateWarning::new(
+                "invalid_field",
+                &doc.id,
+                format!("{location}: property {name:?} is not a valid python identifier, skipped"),
+            )
+            .at(format!("{location}.{name}")),
+        );
+        let obj = match doc.defs.get_mut(&def_name) {
+            Some(LexUserType::Record(LexRecord { record, .. })) => {
+                let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+                obj
+            }
+            Some(LexUserType::Object(obj)) => obj,
+            _ => continue,
+        };
+        obj.properties.remove(&name);
+        if let Some(required) = &mut obj.required {
+            required.retain(|r| *r != name);
+        }
+    }
+    Ok(Cow::Owned(docs))
+}
 
-    // generate external deps first (so they're defined before use)
-    for doc in &resolved_externals {
-        output.push_str(&format!("\n# {} (builtin)\n", doc.id));
-        output.push_str(&generate_document(doc));
+/// fail on the first integer `default` its own constraints rule out, or with
+/// `OnInvalidDefault::Warn` record each as a warning
+///
+/// only defs generated here are checked, so a bad default outside the prefix,
+/// cut by the selection or in a `skip_nsids` module can't stop the run.
+fn check_defaults(
+    emitted: &[&LexiconDoc],
+    selection: &Selection,
+    config: &Config,
+    warnings: &mut Vec<GenerateWarning>,
+) -> Result<(), CodegenError> {
+    for doc in emitted {
+        if config.defined_elsewhere(&doc.id) {
+            continue;
+        }
+        let kept: HashSet<&str> = selection
+            .defs(doc)
+            .into_iter()
+            .map(|(def_name, _)| def_name.as_str())
+            .collect();
+        for (field, reason) in invalid_defaults(doc) {
+            // `nsid#defName` or `nsid#defName.field`
+            let def_name = field
+                .split_once('#')
+                .and_then(|(_, rest)| rest.split('.').next())
+                .unwrap_or_default();
+            if !kept.contains(def_name) {
+                continue;
+            }
+            if config.on_invalid_default == OnInvalidDefault::Error {
+                return Err(CodegenError::InvalidDefault { field, reason });
+            }
+            warn!("{field}: {reason}");
+            warnings.push(
+                GenerateWarning::new("invalid_default", &doc.id, format!("{field}: {reason}"))
+                    .at(field),
+            );
+        }
     }
+    Ok(())
+}
 
-    // generate user documents
-    for doc in &filtered {
-        output.push_str(&format!("\n# {}\n", doc.id));
-        output.push_str(&generate_document(doc));
+/// the integer defaults in a document that break their own `enum`, `const`,
+/// `minimum` or `maximum`, as (`nsid#defName` or `nsid#defName.field`, why)
+///
+/// record, object and xrpc params properties are covered, as are top-level
+/// integer (and integer array) defs.
+pub(crate) fn invalid_defaults(doc: &LexiconDoc) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    for (def_name, def) in sorted_defs(doc) {
+        let location = format!("{}#{def_name}", doc.id);
+        let obj = match def {
+            LexUserType::Record(LexRecord { record, .. }) => {
+                let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+                Some(obj.clone())
+            }
+            LexUserType::Object(obj) => Some(obj.clone()),
+            LexUserType::XrpcQuery(_) | LexUserType::XrpcProcedure(_) => params_object(def),
+            _ => {
+                let reason = def_as_property(def)
+                    .as_ref()
+                    .and_then(integer_default_problem);
+                problems.extend(reason.map(|reason| (location.clone(), reason)));
+                None
+            }
+        };
+        let Some(obj) = obj else {
+            continue;
+        };
+        let mut names: Vec<&String> = obj.properties.keys().collect();
+        names.sort();
+        for name in names {
+            if let Some(reason) = integer_default_problem(&obj.properties[name]) {
+                problems.push((format!("{location}.{name}"), reason));
+            }
+        }
     }
+    problems
+}
 
-    let output_file = match namespace_prefix {
-        Some(prefix) => output_dir.join(format!("{}.py", prefix.replace('.', "_"))),
-        None => output_dir.join("models.py"),
-    };
+/// the union properties (or arrays of one) of an object, by `def.property`
+fn object_unions<'o>(def: &str, obj: &'o mut LexObject) -> Vec<(String, &'o mut LexRefUnion)> {
+    obj.properties
+        .iter_mut()
+        .filter_map(|(name, prop)| {
+            let union = match prop {
+                LexObjectProperty::Union(u) => u,
+                LexObjectProperty::Array(arr) => match &mut arr.items {
+                    LexArrayItem::Union(u) => u,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            Some((format!("{def}.{name}"), union))
+        })
+        .collect()
+}
 
-    fs::write(&output_file, &output)?;
+/// whether `text` matches `pattern`, where `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let Some(text) = text.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=text.len())
+                .filter(|&i| text.is_char_boundary(i))
+                .any(|i| glob_match(rest, &text[i..]))
+        }
+    }
+}
 
-    Ok(vec![output_file.to_string_lossy().to_string()])
+/// defs of a document: `main` first, then by name, type aliases last
+///
+/// the primary def leads the file; forward refs from it are fine since
+/// annotations are lazy. aliases (`def_as_property`) are plain assignments
+/// evaluated at import, so they follow the classes they may reference.
+fn sorted_defs(doc: &LexiconDoc) -> Vec<(&String, &LexUserType)> {
+    let mut defs: Vec<_> = doc.defs.iter().collect();
+    defs.sort_by_key(|(name, def)| (def_as_property(def).is_some(), *name != "main", *name));
+    defs
 }
 
-/// generate python code for a single lexicon document
-fn generate_document(doc: &LexiconDoc) -> String {
-    let ctx = RefContext::new(&doc.id);
+/// generate python code for a single def, recording the names it defines
+fn generate_def(
+    doc: &LexiconDoc,
+    def_name: &str,
+    def: &LexUserType,
+    ctx: &RefContext,
+    extras: &Extras,
+    config: &Config,
+    exports: &mut Vec<String>,
+) -> String {
+    let class_name = ctx.class_name(&doc.id, def_name);
+    let def_ref = format!("{}#{def_name}", doc.id);
+    let examples = extras.examples.get(&def_ref);
+    let raw_def = extras.raw_defs.get(&def_ref);
+    // the def's lexicon ref, as a record's `$type` spells it
+    let schema_id = config.schema_ids.then(|| match def_name {
+        "main" => doc.id.as_str(),
+        _ => def_ref.as_str(),
+    });
     let mut output = String::new();
 
-    for (def_name, def) in &doc.defs {
-        let class_name = to_class_name(&doc.id, def_name);
-
-        match def {
-            LexUserType::Record(LexRecord {
-                record,
-                description,
-                ..
-            }) => {
-                let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
-                let desc = description.as_deref().unwrap_or(&doc.id);
-                output.push_str(&generate_class(&class_name, obj, Some(desc), &ctx));
-                output.push_str("\n\n");
+    match def {
+        LexUserType::Record(LexRecord {
+            record,
+            description,
+            key,
+        }) => {
+            let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+            let desc = description.as_deref().unwrap_or(&doc.id);
+            output.push_str(&generate_class(
+                &class_name,
+                obj,
+                with_examples(Some(desc), examples).as_deref(),
+                raw_properties(raw_def.and_then(|d| d.get("record"))),
+                schema_id,
+                ctx,
+                config,
+                false,
+            ));
+            if config.emit_nsid_constant && config.target == Target::Pydantic {
+                output.push_str(&format!(
+                    "\n\n    NSID: ClassVar[str] = {}",
+                    python_string(&doc.id)
+                ));
+            }
+            if let Some(key) = key
+                .as_deref()
+                .filter(|_| config.emit_rkey_info && config.target == Target::Pydantic)
+            {
+                output.push_str(&rkey_info(key));
+            }
+            if config.compact_repr && config.target == Target::Pydantic {
+                output.push_str(&compact_repr(&doc.id, obj));
+            }
+            if let Some(methods) = identity_equality(obj, &config.identity_fields)
+                .filter(|_| config.identity_equality && config.target == Target::Pydantic)
+            {
+                output.push_str(&methods);
             }
-            LexUserType::Object(obj) => {
+            if let Some(members) = config.class_members(&doc.id, def_name) {
+                output.push_str(&class_members(members));
+            }
+            output.push_str("\n\n");
+            exports.push(class_name);
+        }
+        LexUserType::Object(obj) => {
+            output.push_str(&generate_class(
+                &class_name,
+                obj,
+                with_examples(obj.description.as_deref(), examples).as_deref(),
+                raw_properties(raw_def),
+                schema_id,
+                ctx,
+                config,
+                false,
+            ));
+            if let Some(members) = config.class_members(&doc.id, def_name) {
+                output.push_str(&class_members(members));
+            }
+            output.push_str("\n\n");
+            exports.push(class_name);
+        }
+        LexUserType::XrpcQuery(_) | LexUserType::XrpcProcedure(_) => {
+            if let Some(params) = params_object(def) {
+                let params_name = format!("{class_name}Params");
+                let desc = params
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| format!("{} parameters", doc.id));
                 output.push_str(&generate_class(
-                    &class_name,
-                    obj,
-                    obj.description.as_deref(),
-                    &ctx,
+                    &params_name,
+                    &params,
+                    Some(&desc),
+                    raw_properties(raw_def.and_then(|d| d.get("parameters"))),
+                    None,
+                    ctx,
+                    config,
+                    true,
                 ));
                 output.push_str("\n\n");
+                exports.push(params_name);
             }
-            LexUserType::Token(_) => {
-                output.push_str(&format!(
-                    "# token: {}\n{} = \"{}#{}\"\n\n",
-                    class_name,
-                    class_name.to_uppercase(),
-                    doc.id,
-                    def_name
-                ));
+        }
+        LexUserType::Token(_) => {
+            let constant = class_name.to_uppercase();
+            output.push_str(&format!(
+                "# token: {}\n{} = {}\n\n",
+                class_name,
+                constant,
+                python_string(&format!("{}#{def_name}", doc.id))
+            ));
+            exports.push(constant);
+        }
+        other => match def_as_property(other) {
+            Some(prop) if root_models(config) => {
+                let py_type = def_type(&prop, raw_def, ctx, config);
+                output.push_str(&root_model(&class_name, &py_type, config));
+                output.push_str("\n\n");
+                exports.push(class_name);
             }
-            _ => {}
+            Some(prop) => {
+                // evaluated at import, before repeated unions are defined
+                let ctx = RefContext {
+                    union_aliases: None,
+                    ..*ctx
+                };
+                let py_type = def_type(&prop, raw_def, &ctx, config);
+                output.push_str(&type_alias(&class_name, &py_type, config));
+                exports.push(class_name.clone());
+                if config.target == Target::Pydantic {
+                    exports.push(format!("{class_name}Adapter"));
+                }
+            }
+            None => {}
+        },
+    }
+
+    output
+}
+
+/// `__eq__`/`__hash__` over the identity `fields` a record has, or `None`
+/// when it has none of them
+fn identity_equality(obj: &LexObject, fields: &[String]) -> Option<String> {
+    let names: Vec<String> = fields
+        .iter()
+        .filter(|name| obj.properties.contains_key(name.as_str()))
+        .map(|name| to_field_name(name))
+        .collect();
+    if names.is_empty() {
+        return None;
+    }
+    let key = |instance: &str| {
+        let values: Vec<String> = names.iter().map(|n| format!("{instance}.{n}")).collect();
+        match values.as_slice() {
+            [value] => format!("({value},)"),
+            _ => format!("({})", values.join(", ")),
+        }
+    };
+    Some(format!(
+        "\n\n    def __eq__(self, other: object) -> bool:\n        \
+         if not isinstance(other, type(self)):\n            return NotImplemented\n        \
+         return {} == {}\n\n    def __hash__(self) -> int:\n        return hash({})",
+        key("self"),
+        key("other"),
+        key("self"),
+    ))
+}
+
+/// `RKEY_TYPE` (and, for a `literal:` key, the fixed `RKEY`) class constants
+fn rkey_info(key: &str) -> String {
+    let mut output = format!("\n\n    RKEY_TYPE: ClassVar[str] = {}", python_string(key));
+    if let Some(rkey) = key.strip_prefix("literal:") {
+        output.push_str(&format!(
+            "\n    RKEY: ClassVar[str] = {}",
+            python_string(rkey)
+        ));
+    }
+    output
+}
+
+/// user `class_members` source, re-indented into a class body
+///
+/// the source is dedented first, so it may be written at any indentation.
+fn class_members(source: &str) -> String {
+    let indent = source
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut output = String::from("\n");
+    let lines = source.trim_end().lines();
+    for line in lines.skip_while(|line| line.trim().is_empty()) {
+        output.push('\n');
+        if !line.trim().is_empty() {
+            output.push_str("    ");
+            output.push_str(line[indent..].trim_end());
+        }
+    }
+    output
+}
+
+/// record fields shown by `compact_repr`, in display order
+const IDENTIFYING_FIELDS: &[&str] = &["uri", "cid", "rkey"];
+
+/// `__repr__`/`__str__` showing a record's nsid and identifying fields
+fn compact_repr(nsid: &str, obj: &LexObject) -> String {
+    let fields: Vec<String> = IDENTIFYING_FIELDS
+        .iter()
+        .filter(|name| obj.properties.contains_key(**name))
+        .map(|name| format!("{name}={{self.{}!r}}", to_field_name(name)))
+        .collect();
+    format!(
+        "\n\n    def __repr__(self) -> str:\n        return f\"{nsid}({})\"\n\n    \
+         def __str__(self) -> str:\n        return repr(self)",
+        fields.join(", ")
+    )
+}
+
+/// whether defs that aren't objects are emitted as `RootModel` subclasses
+fn root_models(config: &Config) -> bool {
+    config.target == Target::Pydantic && config.top_level_as == TopLevel::RootModel
+}
+
+/// the python type a non-model def stands for
+///
+/// an open map arrives as an array of its values (see `rewrite_open_maps`),
+/// told apart by the `"object"` type its raw def still has.
+fn def_type(
+    prop: &LexObjectProperty,
+    raw_def: Option<&serde_json::Value>,
+    ctx: &RefContext,
+    config: &Config,
+) -> String {
+    if let LexObjectProperty::Array(arr) = prop {
+        let raw_type = raw_def.and_then(|raw| raw.get("type"));
+        if raw_type.and_then(serde_json::Value::as_str) == Some("object") {
+            let value = array_item_to_python(&arr.items, ctx);
+            return annotation(&format!("dict[str, {value}]"), config);
+        }
+    }
+    if config.small_int_ranges_as_literal {
+        if let Some(literal) = integer_literal(prop, config.max_literal_range) {
+            return annotation(&literal, config);
         }
     }
+    annotation(&property_to_python(prop, ctx), config)
+}
 
+/// a type alias for a non-model def, plus a `TypeAdapter` to validate it
+fn type_alias(alias: &str, py_type: &str, config: &Config) -> String {
+    let prefix = format!("{alias} = ");
+    let py_type = wrap_annotation(py_type, &prefix, config);
+    let mut output = format!("{prefix}{py_type}\n");
+    if config.target == Target::Pydantic {
+        output.push_str(&format!(
+            "{alias}Adapter: TypeAdapter[{alias}] = TypeAdapter({alias})\n"
+        ));
+    }
+    output.push('\n');
     output
 }
 
+/// a `RootModel` subclass for a non-model def
+///
+/// the type is the `root` annotation rather than a `RootModel[...]`
+/// parameter, so like any field it may name classes defined further down.
+fn root_model(name: &str, py_type: &str, config: &Config) -> String {
+    let py_type = wrap_annotation(py_type, "    root: ", config);
+    format!("class {name}(RootModel):\n    root: {py_type}")
+}
+
+/// a docstring body: the description followed by any examples as json
+///
+/// the text is indented for a class docstring, so examples render as an
+/// indented block under an `example:` heading.
+fn with_examples(
+    description: Option<&str>,
+    examples: Option<&Vec<serde_json::Value>>,
+) -> Option<String> {
+    let examples = match examples {
+        Some(examples) if !examples.is_empty() => examples,
+        _ => return description.map(str::to_string),
+    };
+    let mut text = description.unwrap_or_default().to_string();
+    for example in examples {
+        let json = serde_json::to_string(example).unwrap_or_default();
+        text.push_str(&format!("\n\n    example:\n\n        {json}"));
+    }
+    text.push_str("\n    ");
+    Some(text)
+}
+
+/// read xrpc `parameters` as an object
+///
+/// params allow a subset of object properties (primitives and arrays of
+/// primitives), so they can reuse the object codegen path.
+fn params_object(def: &LexUserType) -> Option<LexObject> {
+    let value = serde_json::to_value(def).ok()?;
+    let params = value.get("parameters").filter(|p| !p.is_null())?;
+    serde_json::from_value(params.clone()).ok()
+}
+
 /// generate a pydantic model class
+///
+/// for xrpc params (`is_params`), array fields are marked `explode` since
+/// they serialize as a repeated query key (`?uri=a&uri=b`).
+#[allow(clippy::too_many_arguments)]
 fn generate_class(
     class_name: &str,
     obj: &LexObject,
     description: Option<&str>,
+    raw_properties: Option<&RawObject>,
+    schema_id: Option<&str>,
     ctx: &RefContext,
+    config: &Config,
+    is_params: bool,
 ) -> String {
+    if config.target == Target::Protocol {
+        return generate_protocol(class_name, obj, description, ctx, config);
+    }
+
     let mut lines = vec![format!("class {class_name}(BaseModel):")];
 
-    if let Some(desc) = description {
-        lines.push(format!("    \"\"\"{desc}\"\"\""));
+    if let Some(doc) = class_docstring(description, obj, config) {
+        lines.push(format!("    {doc}"));
     }
 
+    // `model_json_schema()` names the def by its lexicon ref
+    let schema_extra = schema_id.map(|id| {
+        format!(
+            "json_schema_extra={{{}: {}}}",
+            python_string("$id"),
+            python_string(id)
+        )
+    });
     if obj.properties.is_empty() {
-        lines.push("    pass".into());
+        match schema_extra {
+            Some(extra) => lines.push(format!("    model_config = ConfigDict({extra})")),
+            None => lines.push("    pass".into()),
+        }
         return lines.join("\n");
     }
 
+    let mut model_config = Vec::new();
+    // aliased fields also accept their python name, whatever renamed them
+    if obj
+        .properties
+        .keys()
+        .any(|name| to_field_name(name) != *name)
+    {
+        model_config.push("populate_by_name=True".to_string());
+    }
+    // frozen pydantic models hash by their field values
+    if config
+        .value_object_defs
+        .iter()
+        .any(|def_ref| ctx.resolve_ref(def_ref) == class_name)
+    {
+        model_config.push("frozen=True".to_string());
+    }
+    model_config.extend(schema_extra);
+    if !model_config.is_empty() {
+        lines.push(format!(
+            "    model_config = ConfigDict({})",
+            model_config.join(", ")
+        ));
+        lines.push(String::new());
+    }
+
     let required: HashSet<_> = obj
         .required
         .as_ref()
         .map(|r| r.iter().map(String::as_str).collect())
         .unwrap_or_default();
 
-    // generate required fields first, then optional
+    // generate required fields first, then optional, each by name
     let mut fields: Vec<_> = obj.properties.iter().collect();
-    fields.sort_by_key(|(name, _)| !required.contains(name.as_str()));
+    fields.sort_by_key(|(name, _)| (!required.contains(name.as_str()), *name));
+
+    // blob subtypes narrowing `mimeType`, emitted ahead of the class
+    let mut blob_classes = Vec::new();
+    // `check_union_type` calls for the invariant validator
+    let mut checks = Vec::new();
 
     for (name, prop) in fields {
         let field_name = to_field_name(name);
         let is_required = required.contains(name.as_str());
 
-        let mut py_type = property_to_python(prop, ctx);
-        if !is_required {
-            py_type = format!("{py_type} | None");
+        if config.emit_invariant_validators {
+            if let Some(types) = closed_union_types(prop, ctx) {
+                let types: Vec<String> = types
+                    .iter()
+                    .map(|t| format!("{}, ", python_string(t)))
+                    .collect();
+                let discriminator = match config.discriminator_field.as_str() {
+                    "$type" => String::new(),
+                    field => format!(", {}", python_string(field)),
+                };
+                checks.push(format!(
+                    "        check_union_type(data, {}, ({}){discriminator})",
+                    python_string(name),
+                    types.concat().trim_end()
+                ));
+            }
         }
 
-        let needs_alias = field_name != *name;
-        let needs_default = !is_required;
+        let mut py_type = match ctx.record_union {
+            Some(union) if is_record_slot(name, prop) => union.to_string(),
+            _ => property_to_python(prop, ctx),
+        };
+        if let Some(constraint) = blob_mime_constraint(prop) {
+            let blob_class = format!("{class_name}{}Blob", name.to_pascal_case());
+            blob_classes.push(blob_subclass(&blob_class, name, &constraint));
+            py_type = py_type.replace("BlobRef", &blob_class);
+        }
+        let literal = config
+            .small_int_ranges_as_literal
+            .then(|| integer_literal(prop, config.max_literal_range))
+            .flatten();
+        if let Some(bounded) = literal.or_else(|| bounded_integer(prop)) {
+            py_type = bounded;
+        }
+        if !is_required {
+            py_type = optional_type(&py_type);
+        }
+        if config.embeds_metadata() {
+            if let Some(meta) = property_meta(prop, ctx) {
+                py_type = format!("Annotated[{py_type}, {meta}]");
+            }
+        }
 
-        let field_def = match (needs_alias, needs_default) {
-            (false, false) => format!("    {field_name}: {py_type}"),
-            (true, false) => format!("    {field_name}: {py_type} = Field(alias=\"{name}\")"),
-            (false, true) => format!("    {field_name}: {py_type} = Field(default=None)"),
-            (true, true) => {
-                format!("    {field_name}: {py_type} = Field(default=None, alias=\"{name}\")")
+        let mut field_args = Vec::new();
+        if !is_required {
+            field_args.push("default=None".to_string());
+        }
+        if field_name != *name {
+            field_args.push(format!("alias={}", python_string(name)));
+        }
+        let raw = raw_properties.and_then(|props| props.get(name.as_str()));
+        if config.emit_titles {
+            let title = raw
+                .and_then(|raw| raw.get("title"))
+                .and_then(|title| title.as_str())
+                .map_or_else(|| humanize(name), str::to_string);
+            field_args.push(format!("title={}", python_string(&title)));
+            if let Some(examples) = raw
+                .and_then(|raw| raw.get("examples"))
+                .filter(|examples| examples.as_array().is_some_and(|e| !e.is_empty()))
+            {
+                field_args.push(format!("examples={}", python_literal(examples)));
             }
+        }
+        let mut schema_extra = Vec::new();
+        if is_params && matches!(prop, LexObjectProperty::Array(_)) {
+            schema_extra.push("\"explode\": True".to_string());
+        }
+        if config.constraints_in_schema {
+            schema_extra.extend(schema_constraints(prop));
+        }
+        if let Some(raw) = raw {
+            schema_extra.extend(field_metadata(raw, &config.field_metadata_keys));
+        }
+        if !schema_extra.is_empty() {
+            field_args.push(format!("json_schema_extra={{{}}}", schema_extra.join(", ")));
+        }
+        if config.mark_deprecated && is_deprecated(property_description(prop).as_deref()) {
+            field_args.push("deprecated=True".to_string());
+        }
+
+        let py_type = wrap_annotation(
+            &annotation(&py_type, config),
+            &format!("    {field_name}: "),
+            config,
+        );
+        let field_def = if field_args.is_empty() {
+            format!("    {field_name}: {py_type}")
+        } else {
+            format!(
+                "    {field_name}: {py_type} = Field({})",
+                field_args.join(", ")
+            )
         };
 
         lines.push(field_def);
     }
 
+    // `$type` is dropped once a member model is chosen, so check the raw input
+    if !checks.is_empty() {
+        lines.push(String::new());
+        lines.push("    @model_validator(mode=\"before\")".into());
+        lines.push("    @classmethod".into());
+        lines.push("    def _check_union_types(cls, data: Any) -> Any:".into());
+        lines.extend(checks);
+        lines.push("        return data".into());
+    }
+
+    blob_classes.push(lines.join("\n"));
+    blob_classes.join("\n\n\n")
+}
+
+/// a `BlobRef` subclass whose `mimeType` encodes a blob's `accept` list
+fn blob_subclass(class_name: &str, field: &str, constraint: &MimeConstraint) -> String {
+    let mime_type = match constraint {
+        MimeConstraint::Literal(types) => {
+            let types: Vec<String> = types.iter().map(|t| python_string(t)).collect();
+            format!(
+                "mime_type: Literal[{}] = Field(alias=\"mimeType\")  \
+                 # pyright: ignore[reportIncompatibleVariableOverride]",
+                types.join(", ")
+            )
+        }
+        MimeConstraint::Pattern(pattern) => {
+            format!(
+                "mime_type: str = Field(alias=\"mimeType\", pattern={})",
+                python_string(pattern)
+            )
+        }
+    };
+    format!("class {class_name}(BlobRef):\n    \"\"\"`{field}` blob\"\"\"\n\n    {mime_type}")
+}
+
+/// a class's docstring: its description and, with `config.docstring_style`,
+/// an `Attributes` section listing each described field
+fn class_docstring(description: Option<&str>, obj: &LexObject, config: &Config) -> Option<String> {
+    if config.docstring_style == DocstringStyle::None {
+        return description.map(docstring);
+    }
+    let required: HashSet<&str> = obj.required.iter().flatten().map(String::as_str).collect();
+    let mut fields: Vec<(&String, String)> = obj
+        .properties
+        .iter()
+        .filter_map(|(name, prop)| {
+            let desc = property_description(prop)?;
+            Some((name, desc.split_whitespace().collect::<Vec<_>>().join(" ")))
+        })
+        .collect();
+    // in the order the fields are declared
+    fields.sort_by_key(|(name, _)| (!required.contains(name.as_str()), *name));
+    if fields.is_empty() {
+        return description.map(docstring);
+    }
+
+    let mut text = description.map(|d| format!("{d}\n\n")).unwrap_or_default();
+    match config.docstring_style {
+        DocstringStyle::Napoleon => {
+            text.push_str("Attributes\n----------\n");
+            for (name, desc) in fields {
+                text.push_str(&format!("{}\n    {desc}\n", to_field_name(name)));
+            }
+        }
+        _ => {
+            text.push_str("Attributes:\n");
+            for (name, desc) in fields {
+                text.push_str(&format!("    {}: {desc}\n", to_field_name(name)));
+            }
+        }
+    }
+    // indented with the class body, closing quotes on their own line
+    let lines: Vec<String> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 || line.is_empty() {
+                line.to_string()
+            } else {
+                format!("    {line}")
+            }
+        })
+        .collect();
+    Some(docstring(&format!("{}\n    ", lines.join("\n"))))
+}
+
+/// generate a `typing.Protocol` with one read-only property per field
+///
+/// any object exposing attributes of the right types (e.g. an orm row)
+/// satisfies the protocol structurally.
+fn generate_protocol(
+    class_name: &str,
+    obj: &LexObject,
+    description: Option<&str>,
+    ctx: &RefContext,
+    config: &Config,
+) -> String {
+    let mut lines = vec![format!("class {class_name}(Protocol):")];
+
+    if let Some(doc) = class_docstring(description, obj, config) {
+        lines.push(format!("    {doc}"));
+    }
+
+    if obj.properties.is_empty() {
+        lines.push("    pass".into());
+        return lines.join("\n");
+    }
+
+    let required: HashSet<_> = obj
+        .required
+        .as_ref()
+        .map(|r| r.iter().map(String::as_str).collect())
+        .unwrap_or_default();
+
+    let mut fields: Vec<_> = obj.properties.iter().collect();
+    fields.sort_by_key(|(name, _)| (!required.contains(name.as_str()), *name));
+
+    for (name, prop) in fields {
+        let mut py_type = property_to_python(prop, ctx);
+        if config.small_int_ranges_as_literal {
+            if let Some(literal) = integer_literal(prop, config.max_literal_range) {
+                py_type = literal;
+            }
+        }
+        if !required.contains(name.as_str()) {
+            py_type = optional_type(&py_type);
+        }
+        lines.push(String::new());
+        lines.push("    @property".into());
+        lines.push(format!(
+            "    def {}(self) -> {}: ...",
+            to_field_name(name),
+            annotation(&py_type, config)
+        ));
+    }
+
     lines.join("\n")
 }
 
+/// a type annotation spelled the way `config.syntax` and
+/// `config.target_python` ask
+fn annotation(py_type: &str, config: &Config) -> String {
+    let py_type = match config.syntax {
+        Syntax::Pep604 => py_type.to_string(),
+        Syntax::Typing => typing_syntax(py_type),
+    };
+    if config.target_python.typing_generics() {
+        typing_generics(&py_type)
+    } else {
+        py_type
+    }
+}
+
+/// `py_type` following `prefix` on a line, broken across lines when the line
+/// would be wider than `config.max_annotation_width`
+///
+/// a union is parenthesized with one member per line and a subscript
+/// (`Union[...]`, `Annotated[...]`) gets one argument per line, as black
+/// writes them; a part still too wide is broken the same way, a level deeper.
+fn wrap_annotation(py_type: &str, prefix: &str, config: &Config) -> String {
+    match config.max_annotation_width {
+        Some(width) => {
+            let indent = prefix.len() - prefix.trim_start().len();
+            wrap_type(py_type, indent, prefix.len(), width)
+        }
+        None => py_type.to_string(),
+    }
+}
+
+/// `wrap_annotation` for a type starting at column `column` of a line
+/// indented by `indent`
+fn wrap_type(py_type: &str, indent: usize, column: usize, width: usize) -> String {
+    if column + py_type.len() <= width {
+        return py_type.to_string();
+    }
+    let inner = indent + 4;
+    let members = split_top_level(py_type, '|');
+    if members.len() > 1 {
+        let mut wrapped = String::from("(\n");
+        for (i, member) in members.iter().enumerate() {
+            let bar = if i == 0 { "" } else { "| " };
+            let member = wrap_type(member, inner, inner + bar.len(), width);
+            wrapped.push_str(&format!("{}{bar}{member}\n", " ".repeat(inner)));
+        }
+        wrapped.push_str(&format!("{})", " ".repeat(indent)));
+        return wrapped;
+    }
+    // a subscript, `Head[...]` with the brackets closing at the end
+    let subscript = first_group(py_type).filter(|&(open, close)| {
+        open > 0 && close == py_type.len() - 1 && py_type[open..].starts_with('[')
+    });
+    if let Some((open, close)) = subscript {
+        let head = &py_type[..open];
+        // a lone argument takes no trailing comma, which would make it a tuple
+        let args = split_top_level(&py_type[open + 1..close], ',');
+        let comma = if args.len() > 1 { "," } else { "" };
+        let mut wrapped = format!("{head}[\n");
+        for arg in args {
+            let arg = wrap_type(arg, inner, inner, width.saturating_sub(comma.len()));
+            wrapped.push_str(&format!("{}{arg}{comma}\n", " ".repeat(inner)));
+        }
+        wrapped.push_str(&format!("{}]", " ".repeat(indent)));
+        return wrapped;
+    }
+    py_type.to_string()
+}
+
+/// a json object as written in a lexicon
+type RawObject = serde_json::Map<String, serde_json::Value>;
+
+/// the raw `properties` of an object-like schema (object, record body, params)
+fn raw_properties(schema: Option<&serde_json::Value>) -> Option<&RawObject> {
+    schema?.get("properties")?.as_object()
+}
+
+/// `json_schema_extra` entries for the `field_metadata_keys` a raw property
+/// carries, with values passed through verbatim
+fn field_metadata(raw: &serde_json::Value, keys: &[String]) -> Vec<String> {
+    keys.iter()
+        .filter_map(|key| {
+            let value = raw.get(key)?;
+            Some(format!("{}: {}", python_string(key), python_literal(value)))
+        })
+        .collect()
+}
+
+/// a field title from a lexicon property name: `durationMs` is "Duration ms"
+fn humanize(name: &str) -> String {
+    let words = name.to_snake_case().replace('_', " ");
+    let mut chars = words.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => words,
+    }
+}
+
+/// a python literal equal to a json value
+fn python_literal(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "None".into(),
+        serde_json::Value::Bool(true) => "True".into(),
+        serde_json::Value::Bool(false) => "False".into(),
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::String(s) => python_string(s),
+        serde_json::Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(python_literal).collect();
+            format!("[{}]", items.join(", "))
+        }
+        serde_json::Value::Object(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("{}: {}", python_string(k), python_literal(v)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+/// a single-line python string literal for `s`
+///
+/// every lexicon string that ends up in generated source goes through here
+/// or `docstring`. double-quoted, unless `s` has double quotes and no single
+/// ones; backslashes, the quote and control characters are escaped.
+pub(crate) fn python_string(s: &str) -> String {
+    let quote = if s.contains('"') && !s.contains('\'') {
+        '\''
+    } else {
+        '"'
+    };
+    let mut literal = String::with_capacity(s.len() + 2);
+    literal.push(quote);
+    for c in s.chars() {
+        match c {
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c if c == '\\' || c == quote => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            c => push_char(&mut literal, c),
+        }
+    }
+    literal.push(quote);
+    literal
+}
+
+/// a triple-quoted docstring for a lexicon description
+///
+/// newlines and tabs stay literal so the docstring reads as written. a `"`
+/// is escaped only where it could close the string: before another `"` or
+/// at the very end.
+fn docstring(desc: &str) -> String {
+    let mut literal = String::with_capacity(desc.len() + 6);
+    literal.push_str("\"\"\"");
+    let mut chars = desc.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' | '\t' => literal.push(c),
+            '\\' => literal.push_str("\\\\"),
+            '"' if matches!(chars.peek(), None | Some('"')) => literal.push_str("\\\""),
+            c => push_char(&mut literal, c),
+        }
+    }
+    literal.push_str("\"\"\"");
+    literal
+}
+
+/// push `c` into a python string literal, escaping control characters
+///
+/// a raw `\r` would be read back as a newline and a raw nul is a syntax
+/// error, so these never appear in source as themselves.
+fn push_char(literal: &mut String, c: char) {
+    if c == '\r' {
+        literal.push_str("\\r");
+    } else if c.is_control() {
+        literal.push_str(&format!("\\x{:02x}", c as u32));
+    } else {
+        literal.push(c);
+    }
+}
+
 /// convert property name to valid python field name
 fn to_field_name(name: &str) -> String {
     let snake = name.to_snake_case();
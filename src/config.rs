@@ -0,0 +1,662 @@
+//! codegen configuration
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::cache::ParseCache;
+use crate::parser::Limits;
+
+/// how generated code is laid out on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// everything in one `models.py` (or `{prefix}.py`)
+    #[default]
+    Single,
+    /// a python package with one module per nsid
+    Package,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "single" => Ok(Self::Single),
+            "package" => Ok(Self::Package),
+            other => Err(format!(
+                "unknown layout: {other} (expected single or package)"
+            )),
+        }
+    }
+}
+
+/// what kind of python classes are emitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// pydantic `BaseModel` subclasses
+    #[default]
+    Pydantic,
+    /// `typing.Protocol` classes exposing each field as a read-only property
+    Protocol,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pydantic" => Ok(Self::Pydantic),
+            "protocol" => Ok(Self::Protocol),
+            other => Err(format!(
+                "unknown target: {other} (expected pydantic or protocol)"
+            )),
+        }
+    }
+}
+
+/// how a def that isn't an object (a top-level array, union or primitive)
+/// is emitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TopLevel {
+    /// a type alias, plus a `TypeAdapter` validating it (pydantic target)
+    #[default]
+    Adapter,
+    /// a `RootModel` subclass, a class like any other model (pydantic
+    /// target; the protocol target keeps the alias)
+    RootModel,
+}
+
+impl FromStr for TopLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "adapter" => Ok(Self::Adapter),
+            "rootmodel" => Ok(Self::RootModel),
+            other => Err(format!(
+                "unknown top_level_as: {other} (expected adapter or rootmodel)"
+            )),
+        }
+    }
+}
+
+/// what to do with a property whose name can't be a python identifier
+/// (empty, whitespace, or starting with a digit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnInvalidField {
+    /// leave the property out, with a warning
+    #[default]
+    Skip,
+    /// fail with `CodegenError::InvalidFieldName`
+    Error,
+}
+
+impl FromStr for OnInvalidField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "error" => Ok(Self::Error),
+            other => Err(format!(
+                "unknown on_invalid_field: {other} (expected skip or error)"
+            )),
+        }
+    }
+}
+
+/// what to do with an integer `default` its own `enum`, `const`, `minimum`
+/// or `maximum` rules out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnInvalidDefault {
+    /// fail with `CodegenError::InvalidDefault`
+    #[default]
+    Error,
+    /// generate anyway, with a warning
+    Warn,
+}
+
+impl FromStr for OnInvalidDefault {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            other => Err(format!(
+                "unknown on_invalid_default: {other} (expected error or warn)"
+            )),
+        }
+    }
+}
+
+/// how class docstrings document fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DocstringStyle {
+    /// the def's description only
+    #[default]
+    None,
+    /// a numpy-style `Attributes` section, as sphinx's napoleon reads it
+    Napoleon,
+    /// a google-style `Attributes:` section, which napoleon also reads
+    Google,
+}
+
+impl FromStr for DocstringStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "napoleon" => Ok(Self::Napoleon),
+            "google" => Ok(Self::Google),
+            other => Err(format!(
+                "unknown docstring_style: {other} (expected none, napoleon or google)"
+            )),
+        }
+    }
+}
+
+/// how emitted python is indented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Indent {
+    /// 4 spaces per level, as black and pep 8 have it
+    #[default]
+    Spaces,
+    /// a tab per level
+    Tabs,
+}
+
+impl FromStr for Indent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spaces" => Ok(Self::Spaces),
+            "tabs" => Ok(Self::Tabs),
+            other => Err(format!("unknown indent: {other} (expected spaces or tabs)")),
+        }
+    }
+}
+
+/// which quotes emitted string literals use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuoteStyle {
+    #[default]
+    Double,
+    /// single quotes, except for docstrings and strings holding a single
+    /// quote or a backslash
+    Single,
+}
+
+impl FromStr for QuoteStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "double" => Ok(Self::Double),
+            "single" => Ok(Self::Single),
+            other => Err(format!(
+                "unknown quote_style: {other} (expected double or single)"
+            )),
+        }
+    }
+}
+
+/// how union and optional annotations are spelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// `X | None` and `A | B` (pep 604)
+    #[default]
+    Pep604,
+    /// `Optional[X]` and `Union[A, B]` from `typing`, which pydantic can
+    /// evaluate before python 3.10
+    Typing,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pep604" => Ok(Self::Pep604),
+            "typing" => Ok(Self::Typing),
+            other => Err(format!(
+                "unknown syntax: {other} (expected pep604 or typing)"
+            )),
+        }
+    }
+}
+
+/// oldest python the generated code has to run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum PythonVersion {
+    /// as 3.9, with `Annotated` from `typing_extensions` and no builtin
+    /// generic (`list[X]`) anywhere pydantic evaluates it
+    Py38,
+    /// `Optional`/`Union` and `List`/`Dict` from `typing`
+    Py39,
+    /// pep 604 unions and builtin generics
+    #[default]
+    Py310,
+    /// as 3.10; nothing emitted needs more
+    Py312,
+}
+
+impl PythonVersion {
+    /// the union spelling this version can evaluate
+    pub fn syntax(self) -> Syntax {
+        match self {
+            Self::Py38 | Self::Py39 => Syntax::Typing,
+            Self::Py310 | Self::Py312 => Syntax::Pep604,
+        }
+    }
+
+    /// whether `list[X]`/`dict[K, V]` are spelled `List[X]`/`Dict[K, V]`
+    pub fn typing_generics(self) -> bool {
+        self < Self::Py310
+    }
+
+    /// whether builtin generics (pep 585) and `typing.Annotated` exist,
+    /// which helper definitions otherwise have to do without
+    pub fn runtime_generics(self) -> bool {
+        self >= Self::Py39
+    }
+}
+
+impl FromStr for PythonVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3.8" => Ok(Self::Py38),
+            "3.9" => Ok(Self::Py39),
+            "3.10" => Ok(Self::Py310),
+            "3.12" => Ok(Self::Py312),
+            other => Err(format!(
+                "unsupported target python: {other} (expected 3.8, 3.9, 3.10 or 3.12)"
+            )),
+        }
+    }
+}
+
+/// caller-supplied class naming: `(nsid, def_name)` to a class name
+///
+/// `Ok(None)` keeps the default name; an `Err` aborts generation.
+#[derive(Clone)]
+pub struct NameHook(Arc<NameHookFn>);
+
+type NameHookFn = dyn Fn(&str, &str) -> Result<Option<String>, String> + Send + Sync;
+
+impl NameHook {
+    pub fn new(
+        hook: impl Fn(&str, &str) -> Result<Option<String>, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub fn call(&self, nsid: &str, def_name: &str) -> Result<Option<String>, String> {
+        (self.0)(nsid, def_name)
+    }
+}
+
+impl fmt::Debug for NameHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NameHook(..)")
+    }
+}
+
+/// caller-supplied post-processing: `(path, content)` of each generated
+/// python file to the content written in its place
+///
+/// returning the content unchanged is a no-op; an `Err` aborts generation.
+#[derive(Clone)]
+pub struct FileHook(Arc<FileHookFn>);
+
+type FileHookFn = dyn Fn(&Path, &str) -> Result<String, String> + Send + Sync;
+
+impl FileHook {
+    pub fn new(
+        hook: impl Fn(&Path, &str) -> Result<String, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub fn call(&self, path: &Path, content: &str) -> Result<String, String> {
+        (self.0)(path, content)
+    }
+}
+
+impl fmt::Debug for FileHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FileHook(..)")
+    }
+}
+
+/// options controlling model generation
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// only emit documents whose nsid starts with this prefix, compared by
+    /// whole segments (`fm.plyr` takes `fm.plyr.track`, not `fm.plyrx.track`)
+    pub namespace_prefix: Option<String>,
+    /// resolve refs against the bundled com.atproto lexicons
+    pub use_builtins: bool,
+    /// replace the bundled lexicons with the ones parsed from this directory
+    pub builtins_dir: Option<PathBuf>,
+    /// fail on refs to unknown lexicons instead of recording a warning
+    pub strict_refs: bool,
+    /// wrap field types in `Annotated[..., LexiconMeta(...)]` recording the
+    /// source ref or string format
+    pub embed_metadata: bool,
+    /// type integers bounded by `minimum`/`maximum` as a `Literal[...]` of
+    /// every value in the range
+    pub small_int_ranges_as_literal: bool,
+    /// largest range (number of values) enumerated by
+    /// `small_int_ranges_as_literal`; wider ranges stay `int`
+    pub max_literal_range: usize,
+    /// pascal-case nsid segments and def names by upper-casing each word's
+    /// first letter only, so acronyms survive (`URLPreview` rather than
+    /// `UrlPreview`)
+    pub keep_acronyms: bool,
+    /// emit `Field(deprecated=True)` for fields whose description starts with
+    /// "DEPRECATED". off by default: the argument needs pydantic 2.7, and
+    /// older 2.x releases drop it with a warning
+    pub mark_deprecated: bool,
+    /// what kind of classes to emit (`embed_metadata` only applies to pydantic)
+    pub target: Target,
+    /// how defs that aren't objects are emitted
+    pub top_level_as: TopLevel,
+    /// how generated modules are laid out on disk
+    pub layout: Layout,
+    /// in package layout, write each def to its own module under a per-nsid
+    /// package (implies `Layout::Package`)
+    pub split_defs: bool,
+    /// emit only record defs and the defs they reference
+    pub records_only: bool,
+    /// defs (`nsid` for main, or `nsid#defName`) to emit along with every
+    /// def they reach through refs and unions, and nothing else; takes the
+    /// place of `records_only` when set
+    pub seeds: Vec<String>,
+    /// also write `client.py` with an async method stub per xrpc endpoint
+    pub emit_client: bool,
+    /// name of the transport base class the generated client inherits from
+    pub transport_base: String,
+    /// nsids with hand-written models: still used for ref resolution, but
+    /// imported from where package layout would write them instead of
+    /// generated
+    pub skip_nsids: Vec<String>,
+    /// nsids generated into another package, mapped to the module their
+    /// models are imported from (`atproto_models`); see `generate_targets`
+    pub imported_nsids: HashMap<String, String>,
+    /// add a `model_validator` enforcing lexicon invariants pydantic can't
+    /// express in field types: objects in closed unions must carry one of
+    /// the union's `$type`s
+    pub emit_invariant_validators: bool,
+    /// give record models a compact `__repr__`/`__str__` with the nsid and
+    /// any `uri`, `cid` and `rkey` fields instead of pydantic's full repr
+    pub compact_repr: bool,
+    /// give record models an `__eq__`/`__hash__` over whichever of
+    /// `identity_fields` they have, instead of comparing every field
+    /// (pydantic target only)
+    pub identity_equality: bool,
+    /// the fields `identity_equality` compares records by
+    pub identity_fields: Vec<String>,
+    /// also write `test_examples.py`, a pytest smoke test validating each
+    /// def-level `"examples"` entry against its generated model
+    pub emit_example_tests: bool,
+    /// overrides class names, for definitions and refs alike. it's called
+    /// for the defs generated or referenced, and must return identifiers
+    /// no other class is named
+    pub name_hook: Option<NameHook>,
+    /// a json file mapping defs (`nsid` for main, or `nsid#defName`) to the
+    /// `{"module": ..., "class": ...}` an earlier generator gave them, so
+    /// existing imports keep working. the class name wins over `name_hook`;
+    /// the dotted module path, within the package, only applies to package
+    /// layout. either may be left out to keep the default
+    pub compat_map: Option<PathBuf>,
+    /// rewrites each generated python file before it's written, after
+    /// restyling; called on the generating thread, never concurrently
+    pub file_hook: Option<FileHook>,
+    /// also emit a module-level `TypeAdapter` per closed-union field that
+    /// picks the member model by `$type` (pydantic target only)
+    pub emit_union_adapters: bool,
+    /// record string constraints pydantic can't enforce (byte and grapheme
+    /// lengths) in each field's `json_schema_extra`
+    pub constraints_in_schema: bool,
+    /// raw-input key naming a union member's type, read by union adapters
+    /// and invariant validators
+    pub discriminator_field: String,
+    /// extra lexicon property keys (e.g. custom `"searchable"` hints) copied
+    /// verbatim into each field's `json_schema_extra`
+    pub field_metadata_keys: Vec<String>,
+    /// give record models an `NSID` class constant holding their nsid
+    /// (pydantic target only)
+    pub emit_nsid_constant: bool,
+    /// give record models an `RKEY_TYPE` class constant holding their
+    /// lexicon `key` (`tid`, `any`, `literal:self`, ...), and for a
+    /// `literal:` key an `RKEY` constant with the one record key allowed
+    /// (pydantic target only)
+    pub emit_rkey_info: bool,
+    /// expose `__lexicon_hash__` (as `hash_lexicons` computes it) and
+    /// `__pmgfal_version__` from the package root; only `generate` and
+    /// `generate_targets`, which read the files, know the hash
+    pub emit_lexicon_hash: bool,
+    /// delete previously generated files in the output directory that this
+    /// run no longer writes (only files carrying the pmgfal banner, within
+    /// what `namespace_prefix` could have produced)
+    pub clean: bool,
+    /// report the files written (and removed) relative to the output
+    /// directory, rather than joined onto it as given
+    pub relative_paths: bool,
+    /// write the warnings, with the nsid, file and field each concerns, as
+    /// a json array to this path (relative to the output directory); only
+    /// `generate` and `generate_targets` write it, since parsing warns too
+    pub warnings_file: Option<PathBuf>,
+    /// most files package layout writes at once; 1 writes them one after
+    /// another. the files and their order are the same either way
+    pub write_threads: usize,
+    /// in package layout, have `__init__` modules import re-exported names
+    /// on first access instead of eagerly
+    pub lazy_imports: bool,
+    /// how union and optional annotations are spelled; match it to
+    /// `target_python` with `PythonVersion::syntax`
+    pub syntax: Syntax,
+    /// oldest python the generated code runs on; 3.9 spells generics with
+    /// `typing.List`/`typing.Dict`
+    pub target_python: PythonVersion,
+    /// indentation of emitted python
+    pub indent: Indent,
+    /// quotes of emitted string literals
+    pub quote_style: QuoteStyle,
+    /// document each described field in its class docstring, for autodoc
+    pub docstring_style: DocstringStyle,
+    /// widest line a field annotation or type alias may make before it's
+    /// broken across lines, one union member (or subscript argument) per line
+    pub max_annotation_width: Option<usize>,
+    /// emit `RECORD_TYPES` (record `$type` to model) and a `parse_record`
+    /// function validating raw records through it (pydantic target only)
+    pub emit_parse_record: bool,
+    /// emit `COLLECTIONS` (collection nsid to record model) and a
+    /// `validate_collection` function validating a list of a collection's
+    /// raw records (pydantic target only)
+    pub emit_collections: bool,
+    /// write `fixtures.py`, with a `make_<record>()` factory per record
+    /// model building a minimal valid instance (pydantic target only)
+    pub emit_fixtures: bool,
+    /// defs (`nsid` for main, or `nsid#defName`) emitted as frozen models,
+    /// which are immutable and hashable, e.g. `com.atproto.repo.strongRef`
+    pub value_object_defs: Vec<String>,
+    /// defs (`nsid` for main, or `nsid#defName`) mapped to python source
+    /// appended to their generated class body, such as `@computed_field`
+    /// properties deriving values from the fields
+    pub class_members: HashMap<String, String>,
+    /// nsid or `nsid#defName` patterns (`*` matches any run of characters)
+    /// for defs left out entirely, along with union members naming them
+    pub exclude: Vec<String>,
+    /// emit a union shape (set of member classes) used by more than one
+    /// field once, as a named alias the fields refer to
+    pub alias_repeated_unions: bool,
+    /// type `unknown` fields named `record` or `value`, which hold a record
+    /// in `com.atproto.repo` and its kin, as a discriminated union of every
+    /// generated record model instead of `Any`. pydantic target only
+    pub record_union_for_unknown: bool,
+    /// what to do with properties whose names can't be python identifiers
+    pub on_invalid_field: OnInvalidField,
+    /// what to do with integer defaults their own constraints rule out
+    pub on_invalid_default: OnInvalidDefault,
+    /// give each pydantic field a `title` (the property's `"title"`, or its
+    /// name humanized) and its property-level `"examples"`, for json schema
+    pub emit_titles: bool,
+    /// set each record and object model's json schema `$id` to its lexicon
+    /// ref (`nsid`, or `nsid#defName` for other defs than `main`)
+    pub schema_ids: bool,
+    /// emit an object def with no properties but an `additionalProperties`
+    /// schema as `dict[str, V]` instead of an empty model
+    pub open_maps: bool,
+    /// type `datetime`-format strings as `datetime`, dumped to json in
+    /// atproto's form (`Z` for utc, millisecond precision or finer)
+    pub typed_datetimes: bool,
+    /// a manifest listing the lexicon files to read, relative to the lexicon
+    /// directory, instead of every json file under it
+    pub manifest_path: Option<PathBuf>,
+    /// reuse the parses of unchanged lexicon files from earlier calls sharing
+    /// this cache; see `ParseCache`
+    pub parse_cache: Option<ParseCache>,
+    /// reject lexicons holding keys the lexicon language doesn't define
+    /// (a misspelt `defss` or `maxLenght`) instead of ignoring them.
+    /// pmgfal's extensions and `field_metadata_keys` are still accepted
+    pub strict_keys: bool,
+    /// merge files declaring the same nsid (a record's `main` in one, its
+    /// supporting defs in siblings) into one lexicon instead of rejecting
+    /// them as duplicates. a def declared in more than one is still an error
+    pub merge_split_nsids: bool,
+    /// largest lexicon file (or archive entry) read, in bytes
+    pub max_file_size: u64,
+    /// deepest json nesting accepted in a lexicon file
+    pub max_depth: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            namespace_prefix: None,
+            use_builtins: true,
+            builtins_dir: None,
+            strict_refs: false,
+            embed_metadata: false,
+            small_int_ranges_as_literal: false,
+            max_literal_range: 16,
+            keep_acronyms: false,
+            mark_deprecated: false,
+            target: Target::Pydantic,
+            top_level_as: TopLevel::Adapter,
+            layout: Layout::Single,
+            split_defs: false,
+            records_only: false,
+            seeds: Vec::new(),
+            emit_client: false,
+            transport_base: "XrpcTransport".into(),
+            skip_nsids: Vec::new(),
+            imported_nsids: HashMap::new(),
+            emit_invariant_validators: false,
+            compact_repr: false,
+            identity_equality: false,
+            identity_fields: vec!["uri".into(), "cid".into()],
+            emit_example_tests: false,
+            name_hook: None,
+            compat_map: None,
+            file_hook: None,
+            emit_union_adapters: false,
+            constraints_in_schema: false,
+            discriminator_field: "$type".into(),
+            field_metadata_keys: Vec::new(),
+            emit_nsid_constant: false,
+            emit_rkey_info: false,
+            emit_lexicon_hash: false,
+            clean: false,
+            relative_paths: false,
+            warnings_file: None,
+            write_threads: 8,
+            lazy_imports: false,
+            syntax: Syntax::Pep604,
+            target_python: PythonVersion::Py310,
+            indent: Indent::Spaces,
+            quote_style: QuoteStyle::Double,
+            docstring_style: DocstringStyle::None,
+            max_annotation_width: None,
+            emit_parse_record: false,
+            emit_collections: false,
+            emit_fixtures: false,
+            value_object_defs: Vec::new(),
+            class_members: HashMap::new(),
+            exclude: Vec::new(),
+            alias_repeated_unions: false,
+            record_union_for_unknown: false,
+            on_invalid_field: OnInvalidField::Skip,
+            on_invalid_default: OnInvalidDefault::Error,
+            emit_titles: false,
+            schema_ids: false,
+            open_maps: false,
+            typed_datetimes: false,
+            manifest_path: None,
+            parse_cache: None,
+            strict_keys: false,
+            merge_split_nsids: false,
+            max_file_size: Limits::default().max_file_size,
+            max_depth: Limits::default().max_depth,
+        }
+    }
+}
+
+impl Config {
+    /// the parsing limits these options set
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_file_size: self.max_file_size,
+            max_depth: self.max_depth,
+        }
+    }
+
+    /// keys strict parsing accepts beyond the lexicon language's, or `None`
+    /// when parsing isn't strict
+    pub(crate) fn extra_keys(&self) -> Option<&[String]> {
+        self.strict_keys
+            .then_some(self.field_metadata_keys.as_slice())
+    }
+
+    /// whether `nsid`'s models are defined outside the generated code, by
+    /// hand or by another package, so nothing emitted here may rename them
+    pub(crate) fn defined_elsewhere(&self, nsid: &str) -> bool {
+        self.skip_nsids.iter().any(|n| n == nsid) || self.imported_nsids.contains_key(nsid)
+    }
+
+    /// the `class_members` source for def `def_name` of `nsid`, if any
+    pub(crate) fn class_members(&self, nsid: &str, def_name: &str) -> Option<&str> {
+        let members = match self.class_members.get(&format!("{nsid}#{def_name}")) {
+            Some(members) => Some(members),
+            None if def_name == "main" => self.class_members.get(nsid),
+            None => None,
+        };
+        members.map(String::as_str)
+    }
+
+    /// whether fields are wrapped in `Annotated[..., LexiconMeta(...)]`
+    pub(crate) fn embeds_metadata(&self) -> bool {
+        self.embed_metadata && self.target == Target::Pydantic
+    }
+}
+
+/// whether `nsid` falls under the nsid prefix `prefix`
+///
+/// the prefix matches whole dot-separated segments, so a dotted prefix like
+/// `mycompany.generated` selects a subtree; a trailing dot is ignored.
+pub(crate) fn in_namespace(nsid: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('.');
+    match nsid.strip_prefix(prefix) {
+        Some(rest) => prefix.is_empty() || rest.is_empty() || rest.starts_with('.'),
+        None => false,
+    }
+}
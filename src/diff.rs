@@ -0,0 +1,191 @@
+//! model-level comparison of two lexicon sets
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use atrium_lex::lexicon::{LexObject, LexRecord, LexUserType};
+use atrium_lex::LexiconDoc;
+
+use crate::types::{optional_type, property_to_python, RefContext};
+
+/// what happened to a property between two lexicon versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+        }
+    }
+}
+
+/// a property-level change within a def present on both sides
+#[derive(Debug, Clone)]
+pub struct PropertyChange {
+    /// absolute def ref (`nsid#defName`)
+    pub def: String,
+    /// property name as spelled in the lexicon
+    pub property: String,
+    pub kind: ChangeKind,
+    /// rendered python type before the change (`None` when added)
+    pub old_type: Option<String>,
+    /// rendered python type after the change (`None` when removed)
+    pub new_type: Option<String>,
+    /// whether data or code written against the old models can break
+    pub breaking: bool,
+}
+
+/// differences between two lexicon sets at the generated-model level
+#[derive(Debug, Clone, Default)]
+pub struct LexiconDiff {
+    pub added_nsids: Vec<String>,
+    pub removed_nsids: Vec<String>,
+    /// defs added to nsids present on both sides (`nsid#defName`)
+    pub added_defs: Vec<String>,
+    /// defs removed from nsids present on both sides (`nsid#defName`)
+    pub removed_defs: Vec<String>,
+    /// property changes in record and object defs present on both sides
+    pub changes: Vec<PropertyChange>,
+}
+
+impl LexiconDiff {
+    /// whether any change removes or narrows something
+    ///
+    /// removed nsids, removed defs, removed required properties, new required
+    /// properties and narrowed types are breaking; everything else is additive.
+    pub fn is_breaking(&self) -> bool {
+        !self.removed_nsids.is_empty()
+            || !self.removed_defs.is_empty()
+            || self.changes.iter().any(|c| c.breaking)
+    }
+}
+
+/// compare two lexicon sets
+///
+/// property types are compared by their rendered python annotation (including
+/// `| None` for optional properties), so a type that gains union members or
+/// becomes optional is widened, and one that loses them is narrowed.
+pub fn diff_lexicons(old: &[LexiconDoc], new: &[LexiconDoc]) -> LexiconDiff {
+    let old: BTreeMap<&str, &LexiconDoc> = old.iter().map(|d| (d.id.as_str(), d)).collect();
+    let new: BTreeMap<&str, &LexiconDoc> = new.iter().map(|d| (d.id.as_str(), d)).collect();
+
+    let mut diff = LexiconDiff {
+        added_nsids: new
+            .keys()
+            .filter(|id| !old.contains_key(*id))
+            .map(|id| id.to_string())
+            .collect(),
+        removed_nsids: old
+            .keys()
+            .filter(|id| !new.contains_key(*id))
+            .map(|id| id.to_string())
+            .collect(),
+        ..Default::default()
+    };
+
+    for (nsid, old_doc) in &old {
+        let Some(new_doc) = new.get(nsid) else {
+            continue;
+        };
+        let old_defs: BTreeSet<&String> = old_doc.defs.keys().collect();
+        let new_defs: BTreeSet<&String> = new_doc.defs.keys().collect();
+
+        for def_name in new_defs.difference(&old_defs) {
+            diff.added_defs.push(format!("{nsid}#{def_name}"));
+        }
+        for def_name in old_defs.difference(&new_defs) {
+            diff.removed_defs.push(format!("{nsid}#{def_name}"));
+        }
+
+        let ctx = RefContext::new(nsid);
+        for def_name in old_defs.intersection(&new_defs) {
+            let (Some(old_obj), Some(new_obj)) = (
+                def_object(&old_doc.defs[*def_name]),
+                def_object(&new_doc.defs[*def_name]),
+            ) else {
+                continue;
+            };
+            diff_object(
+                &format!("{nsid}#{def_name}"),
+                old_obj,
+                new_obj,
+                &ctx,
+                &mut diff.changes,
+            );
+        }
+    }
+
+    diff
+}
+
+/// the object carrying a def's properties, for records and objects
+fn def_object(def: &LexUserType) -> Option<&LexObject> {
+    match def {
+        LexUserType::Record(LexRecord { record, .. }) => {
+            let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+            Some(obj)
+        }
+        LexUserType::Object(obj) => Some(obj),
+        _ => None,
+    }
+}
+
+/// rendered python types of an object's properties
+fn rendered_types(obj: &LexObject, ctx: &RefContext) -> BTreeMap<String, (String, bool)> {
+    let required: BTreeSet<&str> = obj.required.iter().flatten().map(String::as_str).collect();
+    obj.properties
+        .iter()
+        .map(|(name, prop)| {
+            let is_required = required.contains(name.as_str());
+            let mut py_type = property_to_python(prop, ctx);
+            if !is_required {
+                py_type = optional_type(&py_type);
+            }
+            (name.clone(), (py_type, is_required))
+        })
+        .collect()
+}
+
+fn diff_object(
+    def: &str,
+    old: &LexObject,
+    new: &LexObject,
+    ctx: &RefContext,
+    changes: &mut Vec<PropertyChange>,
+) {
+    let old_props = rendered_types(old, ctx);
+    let new_props = rendered_types(new, ctx);
+    let names: BTreeSet<&String> = old_props.keys().chain(new_props.keys()).collect();
+
+    for name in names {
+        let (kind, breaking) = match (old_props.get(name), new_props.get(name)) {
+            (None, Some((_, required))) => (ChangeKind::Added, *required),
+            (Some((_, required)), None) => (ChangeKind::Removed, *required),
+            (Some((old_type, _)), Some((new_type, _))) if old_type != new_type => {
+                (ChangeKind::Changed, !is_widening(old_type, new_type))
+            }
+            _ => continue,
+        };
+        changes.push(PropertyChange {
+            def: def.to_string(),
+            property: name.clone(),
+            kind,
+            old_type: old_props.get(name).map(|(t, _)| t.clone()),
+            new_type: new_props.get(name).map(|(t, _)| t.clone()),
+            breaking,
+        });
+    }
+}
+
+/// whether `new` accepts everything `old` did (its union members are a superset)
+fn is_widening(old: &str, new: &str) -> bool {
+    let old: BTreeSet<&str> = old.split(" | ").collect();
+    let new: BTreeSet<&str> = new.split(" | ").collect();
+    new.is_superset(&old)
+}
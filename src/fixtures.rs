@@ -0,0 +1,208 @@
+//! minimal valid values of lexicon types, for `emit_fixtures`
+//!
+//! like json schema conversion, this works on the serialized lexicon shape.
+//! only required properties are filled in, each with the simplest value its
+//! constraints allow, and a union takes its first member.
+
+use std::collections::{HashMap, HashSet};
+
+use atrium_lex::LexiconDoc;
+use serde_json::{json, Map, Value};
+
+use crate::schema::{field, str_field};
+use crate::types::RefContext;
+
+/// a valid cidv1, for `cid` strings, `cid-link`s and blob refs
+const CID: &str = "bafyreie5737gdxlw5i64vzichcalba3z2v5n6icifvx5xytvske7mr3hpm";
+
+/// placeholder data for the record def `def_name` of `doc`, as json
+///
+/// refs resolve against `docs`; one that doesn't resolve, or that would
+/// recurse into a def still being filled in, is an empty object.
+pub(crate) fn record_placeholder(
+    doc: &LexiconDoc,
+    def_name: &str,
+    docs: &HashMap<&str, &LexiconDoc>,
+) -> Value {
+    let mut placeholders = Placeholders {
+        docs,
+        filling: HashSet::from([format!("{}#{def_name}", doc.id)]),
+    };
+    let def = doc
+        .defs
+        .get(def_name)
+        .and_then(|def| serde_json::to_value(def).ok())
+        .unwrap_or_default();
+    match field(&def, "record") {
+        Some(record) => placeholders.value(record, &doc.id),
+        None => Value::Object(Map::new()),
+    }
+}
+
+struct Placeholders<'d> {
+    docs: &'d HashMap<&'d str, &'d LexiconDoc>,
+    /// `nsid#defName` of the defs being filled in, so a cycle of required
+    /// refs ends
+    filling: HashSet<String>,
+}
+
+impl Placeholders<'_> {
+    /// placeholder for a lexicon type written in document `nsid`
+    fn value(&mut self, lex: &Value, nsid: &str) -> Value {
+        if let Some(value) = field(lex, "const").or_else(|| field(lex, "default")) {
+            return value.clone();
+        }
+        let first = field(lex, "enum")
+            .or_else(|| field(lex, "knownValues"))
+            .and_then(Value::as_array)
+            .and_then(|values| values.first());
+        if let Some(value) = first {
+            return value.clone();
+        }
+        match str_field(lex, "type").unwrap_or_default() {
+            "boolean" => false.into(),
+            "integer" => integer(lex),
+            "string" => string(lex),
+            "bytes" => "x".repeat(min_length(lex)).into(),
+            "cid-link" => CID.into(),
+            "blob" => blob(lex),
+            "array" => {
+                let item = match field(lex, "items") {
+                    Some(items) => self.value(items, nsid),
+                    None => Value::Null,
+                };
+                vec![item; min_length(lex)].into()
+            }
+            "object" => self.object(lex, nsid),
+            "ref" => match str_field(lex, "ref") {
+                Some(r) => self.def_ref(r, nsid),
+                None => Value::Object(Map::new()),
+            },
+            "union" => {
+                let member = field(lex, "refs")
+                    .and_then(Value::as_array)
+                    .and_then(|refs| refs.first())
+                    .and_then(Value::as_str);
+                let Some(member) = member else {
+                    return Value::Object(Map::new());
+                };
+                let mut value = self.def_ref(member, nsid);
+                if let Value::Object(map) = &mut value {
+                    map.insert("$type".into(), union_type(member, nsid).into());
+                }
+                value
+            }
+            _ => Value::Object(Map::new()),
+        }
+    }
+
+    /// an object with a placeholder for each required property
+    fn object(&mut self, lex: &Value, nsid: &str) -> Value {
+        let properties = field(lex, "properties").and_then(Value::as_object);
+        let required = field(lex, "required").and_then(Value::as_array);
+        let mut object = Map::new();
+        for name in required.into_iter().flatten().filter_map(Value::as_str) {
+            if let Some(prop) = properties.and_then(|p| p.get(name)) {
+                let value = self.value(prop, nsid);
+                object.insert(name.to_string(), value);
+            }
+        }
+        object.into()
+    }
+
+    /// placeholder for the def a ref names
+    fn def_ref(&mut self, r: &str, nsid: &str) -> Value {
+        let absolute = RefContext::new(nsid).qualify_ref(r);
+        let (target_nsid, def_name) = absolute
+            .split_once('#')
+            .unwrap_or((absolute.as_str(), "main"));
+        let def = self
+            .docs
+            .get(target_nsid)
+            .and_then(|doc| doc.defs.get(def_name))
+            .and_then(|def| serde_json::to_value(def).ok());
+        let key = format!("{target_nsid}#{def_name}");
+        let Some(def) = def.filter(|_| !self.filling.contains(&key)) else {
+            return Value::Object(Map::new());
+        };
+        self.filling.insert(key.clone());
+        let value = match str_field(&def, "type") {
+            // a token's value is its own name
+            Some("token") => union_type(r, nsid).into(),
+            Some("record") => match field(&def, "record") {
+                Some(record) => self.value(record, target_nsid),
+                None => Value::Object(Map::new()),
+            },
+            _ => self.value(&def, target_nsid),
+        };
+        self.filling.remove(&key);
+        value
+    }
+}
+
+/// the `$type` of a union member, as records carry it
+fn union_type(r: &str, nsid: &str) -> String {
+    let absolute = RefContext::new(nsid).qualify_ref(r);
+    match absolute.strip_suffix("#main") {
+        Some(nsid) => nsid.to_string(),
+        None => absolute,
+    }
+}
+
+/// `minLength`, or 0
+fn min_length(lex: &Value) -> usize {
+    field(lex, "minLength").and_then(Value::as_u64).unwrap_or(0) as usize
+}
+
+/// 0, or the bound that excludes it
+fn integer(lex: &Value) -> Value {
+    let minimum = field(lex, "minimum").and_then(Value::as_i64);
+    let maximum = field(lex, "maximum").and_then(Value::as_i64);
+    match (minimum, maximum) {
+        (Some(min), _) if min > 0 => min.into(),
+        (_, Some(max)) if max < 0 => max.into(),
+        _ => 0.into(),
+    }
+}
+
+/// a string of the lexicon's `format`, or an empty one
+fn string(lex: &Value) -> Value {
+    let value = match str_field(lex, "format").unwrap_or_default() {
+        "at-identifier" | "handle" => "alice.test",
+        "at-uri" => "at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.post/3jzfcijpj2z2a",
+        "cid" => CID,
+        "datetime" => "2024-01-01T00:00:00.000Z",
+        "did" => "did:plc:z72i7hdynmk6r22z27h6tvur",
+        "language" => "en",
+        "nsid" => "com.example.record",
+        "record-key" => "self",
+        "tid" => "3jzfcijpj2z2a",
+        "uri" => "https://example.com",
+        _ => "",
+    };
+    value.into()
+}
+
+/// a blob ref of the first mime type `accept` allows
+fn blob(lex: &Value) -> Value {
+    let accept = field(lex, "accept")
+        .and_then(Value::as_array)
+        .and_then(|accept| accept.first())
+        .and_then(Value::as_str)
+        .unwrap_or("*/*");
+    let mime_type = match accept.split_once('/') {
+        Some(("*", _)) | None => "application/octet-stream".to_string(),
+        Some(("image", "*")) => "image/png".to_string(),
+        Some(("audio", "*")) => "audio/mpeg".to_string(),
+        Some(("video", "*")) => "video/mp4".to_string(),
+        Some(("text", "*")) => "text/plain".to_string(),
+        Some((family, "*")) => format!("{family}/octet-stream"),
+        Some(_) => accept.to_string(),
+    };
+    json!({
+        "$type": "blob",
+        "ref": {"$link": CID},
+        "mimeType": mime_type,
+        "size": 0,
+    })
+}
@@ -0,0 +1,249 @@
+//! graphql sdl export for record and object defs (experimental)
+//!
+//! like the openapi export, this works on the serialized lexicon shape and
+//! names types like the generated classes. records and objects become object
+//! types, unions become union types, and the string formats with a wire form
+//! of their own, like cids and datetimes, become custom scalars.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use atrium_lex::LexiconDoc;
+use heck::ToPascalCase;
+use serde_json::Value;
+
+use crate::schema::{field, reachable, str_field, Reachable};
+use crate::types::RefContext;
+
+/// custom scalars for string formats, by lexicon format
+const FORMAT_SCALARS: &[(&str, &str)] = &[
+    ("at-uri", "AtUri"),
+    ("cid", "Cid"),
+    ("datetime", "Datetime"),
+];
+
+/// build a graphql schema with a type per record and object def
+///
+/// every def reachable from the selected documents (including builtins) is
+/// covered. xrpc methods are left out; there is no `Query` type, so the
+/// schema is for sharing types rather than serving as is.
+pub fn to_graphql(
+    docs: &[LexiconDoc],
+    builtins: &[LexiconDoc],
+    namespace_prefix: Option<&str>,
+) -> String {
+    let reachable = reachable(docs, builtins, namespace_prefix);
+    let mut sdl = Sdl {
+        reachable: &reachable,
+        scalars: BTreeSet::new(),
+        unions: BTreeMap::new(),
+        resolving: HashSet::new(),
+    };
+
+    let mut types = Vec::new();
+    for nsid in &reachable.included {
+        let ctx = RefContext::with_class_names(nsid, &reachable.class_names);
+        let Some(defs) = field(&reachable.lookup[nsid.as_str()], "defs").and_then(Value::as_object)
+        else {
+            continue;
+        };
+        let mut names: Vec<_> = defs.keys().collect();
+        names.sort();
+        for def_name in names {
+            let def = &defs[def_name];
+            let object = match str_field(def, "type") {
+                Some("record") => field(def, "record"),
+                Some("object") => Some(def),
+                _ => None,
+            };
+            if let Some(object) = object {
+                let name = ctx.class_name(nsid, def_name);
+                types.push(sdl.object_type(&name, def, object, &ctx));
+            }
+        }
+    }
+
+    let mut blocks: Vec<String> = sdl
+        .scalars
+        .iter()
+        .map(|s| format!("scalar {s}\n"))
+        .collect();
+    blocks.extend(types);
+    blocks.extend(
+        sdl.unions
+            .iter()
+            .map(|(name, members)| format!("union {name} = {}\n", members.join(" | "))),
+    );
+    blocks.join("\n")
+}
+
+struct Sdl<'r> {
+    reachable: &'r Reachable<'r>,
+    /// custom scalars the schema uses
+    scalars: BTreeSet<&'static str>,
+    /// union types by name, with their member types
+    unions: BTreeMap<String, Vec<String>>,
+    /// `nsid#defName` of the non-object defs being resolved, so a def whose
+    /// type refers back to itself ends
+    resolving: HashSet<String>,
+}
+
+impl Sdl<'_> {
+    /// an object type `name` for the object schema `object` of `def`
+    ///
+    /// an object without properties, which graphql can't express as a type,
+    /// is a scalar instead.
+    fn object_type(&mut self, name: &str, def: &Value, object: &Value, ctx: &RefContext) -> String {
+        let mut output = description(def, "");
+        let properties = field(object, "properties").and_then(Value::as_object);
+        let Some(properties) = properties.filter(|p| !p.is_empty()) else {
+            output.push_str(&format!("scalar {name}\n"));
+            return output;
+        };
+        let required = names_in(object, "required");
+        let nullable = names_in(object, "nullable");
+
+        output.push_str(&format!("type {name} {{\n"));
+        for (field_name, prop) in properties {
+            if !is_graphql_name(field_name) {
+                continue;
+            }
+            let mut graphql_type =
+                self.type_of(prop, ctx, &format!("{name}{}", field_name.to_pascal_case()));
+            if required.contains(field_name.as_str()) && !nullable.contains(field_name.as_str()) {
+                graphql_type.push('!');
+            }
+            output.push_str(&description(prop, "  "));
+            output.push_str(&format!("  {field_name}: {graphql_type}\n"));
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    /// the graphql type of a lexicon type, without non-null marking
+    ///
+    /// `union_name` names a union type it needs.
+    fn type_of(&mut self, lex: &Value, ctx: &RefContext, union_name: &str) -> String {
+        match str_field(lex, "type").unwrap_or_default() {
+            "boolean" => "Boolean".into(),
+            "integer" => "Int".into(),
+            "string" => {
+                let format = str_field(lex, "format").unwrap_or_default();
+                match FORMAT_SCALARS.iter().find(|(f, _)| *f == format) {
+                    Some((_, scalar)) => self.scalar(scalar),
+                    None => "String".into(),
+                }
+            }
+            "bytes" => self.scalar("Bytes"),
+            "cid-link" => self.scalar("CidLink"),
+            "blob" => self.scalar("Blob"),
+            "array" => {
+                let item = match field(lex, "items") {
+                    Some(items) => self.type_of(items, ctx, union_name),
+                    None => self.scalar("Unknown"),
+                };
+                format!("[{item}!]")
+            }
+            "ref" => match str_field(lex, "ref") {
+                Some(r) => self.ref_type(r, ctx),
+                None => self.scalar("Unknown"),
+            },
+            "union" => {
+                let members: Vec<String> = field(lex, "refs")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .filter(|r| self.is_object_type(&ctx.qualify_ref(r)))
+                    .map(|r| ctx.resolve_ref(r))
+                    .collect();
+                if members.is_empty() {
+                    return self.scalar("Unknown");
+                }
+                self.unions.insert(union_name.to_string(), members);
+                union_name.to_string()
+            }
+            _ => self.scalar("Unknown"),
+        }
+    }
+
+    /// the graphql type a ref names: the target's type for a record or an
+    /// object, otherwise the type of the def itself
+    fn ref_type(&mut self, r: &str, ctx: &RefContext) -> String {
+        let absolute = ctx.qualify_ref(r);
+        if self.is_object_type(&absolute) {
+            return ctx.resolve_ref(r);
+        }
+        let (nsid, def_name) = absolute
+            .split_once('#')
+            .unwrap_or((absolute.as_str(), "main"));
+        let key = format!("{nsid}#{def_name}");
+        let reachable = self.reachable;
+        let def = reachable
+            .lookup
+            .get(nsid)
+            .and_then(|doc| field(doc, "defs"))
+            .and_then(|defs| field(defs, def_name));
+        let Some(def) = def.filter(|_| !self.resolving.contains(&key)) else {
+            return self.scalar("Unknown");
+        };
+        if str_field(def, "type") == Some("token") {
+            return "String".into();
+        }
+        let target = RefContext::with_class_names(nsid, &reachable.class_names);
+        let union_name = target.class_name(nsid, def_name);
+        self.resolving.insert(key.clone());
+        let graphql_type = self.type_of(def, &target, &union_name);
+        self.resolving.remove(&key);
+        graphql_type
+    }
+
+    /// whether the def at `nsid#defName` (or `nsid`, for main) is emitted as
+    /// a graphql type: a record or an object
+    fn is_object_type(&self, def_ref: &str) -> bool {
+        let (nsid, def_name) = def_ref.split_once('#').unwrap_or((def_ref, "main"));
+        let def = self
+            .reachable
+            .lookup
+            .get(nsid)
+            .and_then(|doc| field(doc, "defs"))
+            .and_then(|defs| field(defs, def_name));
+        matches!(
+            def.and_then(|def| str_field(def, "type")),
+            Some("record" | "object")
+        )
+    }
+
+    /// the custom scalar `name`, recording that the schema uses it
+    fn scalar(&mut self, name: &'static str) -> String {
+        self.scalars.insert(name);
+        name.to_string()
+    }
+}
+
+/// the string entries of `key` in a serialized object schema
+fn names_in<'v>(object: &'v Value, key: &str) -> HashSet<&'v str> {
+    field(object, key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect()
+}
+
+/// whether `name` is a valid graphql field name
+fn is_graphql_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && !name.starts_with("__")
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// a lexicon value's description as a block string at `indent`, or nothing
+fn description(lex: &Value, indent: &str) -> String {
+    match str_field(lex, "description") {
+        Some(desc) => {
+            let desc = desc.replace("\"\"\"", "\\\"\"\"");
+            format!("{indent}\"\"\"\n{indent}{desc}\n{indent}\"\"\"\n")
+        }
+        None => String::new(),
+    }
+}
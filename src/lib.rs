@@ -1,79 +1,378 @@
 //! pmgfal - pydantic model generator for atproto lexicons
+//!
+//! the codegen pipeline is usable directly from rust (e.g. a build script):
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! let config = pmgfal::Config {
+//!     namespace_prefix: Some("fm.plyr".into()),
+//!     ..Default::default()
+//! };
+//! let result = pmgfal::generate(Path::new("lexicons"), Path::new("src/models"), &config)?;
+//! println!("wrote {:?}", result.files);
+//! # Ok::<(), pmgfal::Error>(())
+//! ```
+//!
+//! with the `jsonc` feature, lexicon files may contain `//` and `/* */`
+//! comments, which are stripped before parsing. the experimental `graphql`
+//! feature adds `to_graphql`, a graphql sdl export of record and object defs.
+//!
+//! the python extension module is a thin layer over this api, compiled only
+//! with the `python` feature (on by default). `--no-default-features` builds a
+//! pure-rust library that doesn't link against cpython.
 
-mod builtin;
-mod codegen;
-mod parser;
-mod types;
+#[macro_use]
+mod trace;
 
-use std::fs;
-use std::path::Path;
+mod archive;
+pub mod builtin;
+mod cache;
+pub mod check;
+pub mod codegen;
+pub mod config;
+pub mod diff;
+mod fixtures;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod openapi;
+pub mod parser;
+#[cfg(feature = "python")]
+mod python;
+mod schema;
+mod style;
+pub mod types;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use pyo3::prelude::*;
 use sha2::{Digest, Sha256};
+use thiserror::Error;
 
-/// compute a hash of all lexicon files in a directory
-#[pyfunction]
-#[pyo3(signature = (lexicon_dir, namespace_prefix=None))]
-fn hash_lexicons(lexicon_dir: &str, namespace_prefix: Option<&str>) -> PyResult<String> {
-    let lexicon_path = Path::new(lexicon_dir);
+pub use crate::cache::ParseCache;
+pub use crate::check::{check_lexicons, LexiconProblem};
+pub use crate::codegen::{
+    generate_models, generate_models_with_examples, generate_models_with_extras, CodegenError,
+    GenerateResult, GenerateWarning,
+};
+pub use crate::config::{
+    Config, DocstringStyle, FileHook, Indent, Layout, NameHook, OnInvalidDefault, OnInvalidField,
+    PythonVersion, QuoteStyle, Syntax, Target, TopLevel,
+};
+pub use crate::diff::{diff_lexicons, LexiconDiff};
+#[cfg(feature = "graphql")]
+pub use crate::graphql::to_graphql;
+pub use crate::openapi::to_openapi;
+pub use crate::parser::{
+    parse_lexicons, parse_lexicons_with_examples, parse_lexicons_with_extras,
+    parse_lexicons_with_limits, Examples, Extras, Limits, ParseError, RawDefs,
+};
 
-    let mut hasher = Sha256::new();
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
 
-    // include version in hash so cache invalidates on upgrades
-    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    #[error(transparent)]
+    Codegen(#[from] CodegenError),
+}
 
-    // include prefix in hash
-    if let Some(prefix) = namespace_prefix {
-        hasher.update(prefix.as_bytes());
+/// parse the lexicons in `lexicon_dir` and write models to `output_dir`
+///
+/// refs are resolved against the builtin set selected by `config`.
+pub fn generate(
+    lexicon_dir: &Path,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<GenerateResult, Error> {
+    let files = read_files(lexicon_dir, config)?;
+    let hash = hash_files(&files, config.namespace_prefix.as_deref());
+    generate_files(&files, hash, output_dir, config)
+}
+
+/// `generate`, also returning the `hash_lexicons` hash of the same files
+///
+/// the lexicon files are read once for both, so a caller caching on the hash
+/// doesn't walk the tree twice.
+pub fn generate_with_hash(
+    lexicon_dir: &Path,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<(String, GenerateResult), Error> {
+    let files = read_files(lexicon_dir, config)?;
+    let hash = hash_files(&files, config.namespace_prefix.as_deref());
+    Ok((
+        hash.clone(),
+        generate_files(&files, hash, output_dir, config)?,
+    ))
+}
+
+/// parse the lexicons in `lexicon_dir` once and write each target's models
+///
+/// a target pairs an nsid prefix with the directory its models go to, as one
+/// `generate` call with that `namespace_prefix` would; `config`'s own prefix
+/// is ignored. all targets resolve refs against the same parse, and a ref
+/// into an nsid another target generates imports that model from the other
+/// package, named after its directory (`from atproto_models import ...`),
+/// rather than emitting a copy. the directories' parent must be importable.
+/// results are in the order of `targets`.
+pub fn generate_targets(
+    lexicon_dir: &Path,
+    targets: &[(String, PathBuf)],
+    config: &Config,
+) -> Result<Vec<GenerateResult>, Error> {
+    let results = generate_targets_with_hash(lexicon_dir, targets, config)?;
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// `generate_targets`, with each target's `hash_lexicons` hash
+fn generate_targets_with_hash(
+    lexicon_dir: &Path,
+    targets: &[(String, PathBuf)],
+    config: &Config,
+) -> Result<Vec<(String, GenerateResult)>, Error> {
+    let files = read_files(lexicon_dir, config)?;
+    let lexicons = parse_files(&files, config)?;
+    let builtins = builtin::select_builtins(config)?;
+
+    // shorter prefixes first, so the most specific target claims an nsid
+    let mut packages: Vec<(&str, String)> = targets
+        .iter()
+        .map(|(prefix, output_dir)| (prefix.as_str(), target_module(prefix, output_dir, config)))
+        .collect();
+    packages.sort_by_key(|(prefix, _)| prefix.len());
+
+    let mut results = targets
+        .iter()
+        .map(|(prefix, output_dir)| {
+            let mut imported_nsids = HashMap::new();
+            for (other, module) in &packages {
+                for doc in &lexicons.docs {
+                    if config::in_namespace(&doc.id, other)
+                        && !config::in_namespace(&doc.id, prefix)
+                    {
+                        imported_nsids.insert(doc.id.clone(), module.clone());
+                    }
+                }
+            }
+            // stale files are removed once every target is written
+            let config = Config {
+                namespace_prefix: Some(prefix.clone()),
+                imported_nsids,
+                clean: false,
+                ..config.clone()
+            };
+            let hash = hash_files(&files, Some(prefix));
+            let mut result = codegen::generate_models_with_hash(
+                &lexicons.docs,
+                &builtins,
+                &lexicons.extras,
+                Some(hash.clone()),
+                output_dir,
+                &config,
+            )?;
+            result
+                .warnings
+                .splice(0..0, lexicons.warnings.iter().cloned());
+            report_warnings(&mut result, &lexicons.paths, output_dir, &config)?;
+            Ok((hash, result))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    if config.clean {
+        clean_targets(targets, &mut results, config)?;
     }
+    Ok(results)
+}
 
-    // collect and sort json files for deterministic hashing
-    let mut json_files: Vec<_> = walkdir::WalkDir::new(lexicon_path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+/// `clean` for `generate_targets`: one pass per distinct output directory,
+/// keeping the files every target wrote
+///
+/// targets may share a directory or nest one inside another's, so a pass
+/// over one target's directory must not take another target's files for
+/// stale ones. each directory's removals are reported by the first target
+/// writing there.
+fn clean_targets(
+    targets: &[(String, PathBuf)],
+    results: &mut [(String, GenerateResult)],
+    config: &Config,
+) -> Result<(), Error> {
+    // compared as absolute paths, so `out` and `./out` are the same directory
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let written: Vec<String> = targets
+        .iter()
+        .zip(results.iter())
+        .flat_map(|((_, output_dir), (_, result))| {
+            result.files.iter().map(move |file| {
+                let file = if config.relative_paths {
+                    output_dir.join(file)
+                } else {
+                    PathBuf::from(file)
+                };
+                absolute(&file).to_string_lossy().into_owned()
+            })
+        })
         .collect();
 
-    json_files.sort_by(|a, b| a.path().cmp(b.path()));
+    // what any target could have written, wherever it sits
+    let scope: Vec<PathBuf> = targets
+        .iter()
+        .flat_map(|(prefix, output_dir)| {
+            codegen::clean_scope(&absolute(output_dir), Some(prefix), config)
+        })
+        .collect();
 
-    for entry in json_files {
-        let path = entry.path();
-        if let Some(name) = path.file_name() {
-            hasher.update(name.as_encoded_bytes());
-        }
-        if let Ok(content) = fs::read(path) {
-            hasher.update(&content);
+    let mut cleaned = Vec::new();
+    for (i, (_, output_dir)) in targets.iter().enumerate() {
+        let dir = absolute(output_dir);
+        if cleaned.contains(&dir) {
+            continue;
         }
+        let removed = codegen::remove_stale_files(&dir, &scope, &written, config)
+            .map_err(CodegenError::Io)?;
+        results[i].1.removed = removed
+            .into_iter()
+            .map(|path| {
+                let relative = Path::new(&path)
+                    .strip_prefix(&dir)
+                    .unwrap_or(Path::new(&path));
+                let path = if config.relative_paths {
+                    relative.to_path_buf()
+                } else {
+                    output_dir.join(relative)
+                };
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        cleaned.push(dir);
     }
-
-    let result = hasher.finalize();
-    Ok(hex::encode(&result[..8])) // 16 hex chars
+    Ok(())
 }
 
-/// generate pydantic models from lexicon files
-#[pyfunction]
-#[pyo3(signature = (lexicon_dir, output_dir, namespace_prefix=None))]
-fn generate(
-    lexicon_dir: &str,
-    output_dir: &str,
-    namespace_prefix: Option<&str>,
-) -> PyResult<Vec<String>> {
-    let lexicon_path = Path::new(lexicon_dir);
-    let output_path = Path::new(output_dir);
+/// the module a target's models are imported from: its package, or in
+/// single layout the module inside its directory
+fn target_module(prefix: &str, output_dir: &Path, config: &Config) -> String {
+    let package = std::path::absolute(output_dir)
+        .ok()
+        .and_then(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    if config.layout == Layout::Package || config.split_defs {
+        package
+    } else {
+        format!(
+            "{package}.{}",
+            prefix.trim_end_matches('.').replace('.', "_")
+        )
+    }
+}
 
-    let docs = parser::parse_lexicons(lexicon_path)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+/// the lexicon files under `lexicon_dir`, or just those its manifest lists
+fn read_files(lexicon_dir: &Path, config: &Config) -> Result<Vec<archive::Entry>, ParseError> {
+    match (&config.manifest_path, &config.parse_cache) {
+        (Some(manifest), _) => parser::read_manifest_files(lexicon_dir, manifest, &config.limits()),
+        (None, Some(cache)) => cache.read_json_files(lexicon_dir, &config.limits()),
+        (None, None) => parser::read_json_files(lexicon_dir, &config.limits()),
+    }
+}
 
-    let files = codegen::generate_models(&docs, output_path, namespace_prefix)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+/// parse files read by `read_files`, through `config.parse_cache` if set
+fn parse_files(files: &[archive::Entry], config: &Config) -> Result<parser::Lexicons, ParseError> {
+    match &config.parse_cache {
+        Some(cache) => cache.parse_files(
+            files,
+            &config.limits(),
+            config.extra_keys(),
+            config.merge_split_nsids,
+        ),
+        None => parser::parse_files(
+            files,
+            &config.limits(),
+            config.extra_keys(),
+            config.merge_split_nsids,
+        ),
+    }
+}
 
-    Ok(files)
+/// generate from files already read, whose `hash_files` hash is `hash`
+fn generate_files(
+    files: &[archive::Entry],
+    hash: String,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<GenerateResult, Error> {
+    let lexicons = parse_files(files, config)?;
+    let builtins = builtin::select_builtins(config)?;
+    let mut result = codegen::generate_models_with_hash(
+        &lexicons.docs,
+        &builtins,
+        &lexicons.extras,
+        Some(hash),
+        output_dir,
+        config,
+    )?;
+    result.warnings.splice(0..0, lexicons.warnings);
+    report_warnings(&mut result, &lexicons.paths, output_dir, config)?;
+    Ok(result)
 }
 
-#[pymodule]
-fn _pmgfal(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(generate, m)?)?;
-    m.add_function(wrap_pyfunction!(hash_lexicons, m)?)?;
-    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+/// fill in the file each warning's document came from, then write them to
+/// `config.warnings_file`
+fn report_warnings(
+    result: &mut GenerateResult,
+    paths: &HashMap<String, String>,
+    output_dir: &Path,
+    config: &Config,
+) -> Result<(), Error> {
+    for warning in &mut result.warnings {
+        if warning.path.is_none() {
+            warning.path = warning
+                .nsid
+                .as_ref()
+                .and_then(|nsid| paths.get(nsid))
+                .cloned();
+        }
+    }
+    codegen::write_warnings_file(&result.warnings, output_dir, config).map_err(CodegenError::Io)?;
     Ok(())
 }
+
+/// compute a hash of all lexicon files in a directory (or archive)
+///
+/// json files that aren't lexicons are left out, as `parse_lexicons` skips
+/// them too. files are hashed as raw bytes, so with the `jsonc` feature an
+/// edit to a comment also changes the hash; caching stays conservative
+/// rather than second-guessing what a comment can't affect.
+pub fn hash_lexicons(lexicon_dir: &Path, namespace_prefix: Option<&str>) -> String {
+    let files =
+        parser::read_json_files(lexicon_dir, &parser::Limits::default()).unwrap_or_default();
+    hash_files(&files, namespace_prefix)
+}
+
+/// hash of the lexicons among files read by `parser::read_json_files`
+fn hash_files(files: &[archive::Entry], namespace_prefix: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+
+    // include version in hash so cache invalidates on upgrades
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+
+    // include prefix in hash
+    if let Some(prefix) = namespace_prefix {
+        hasher.update(prefix.as_bytes());
+    }
+
+    // files are sorted by path, so the hash is deterministic
+    for file in files {
+        if !parser::is_lexicon(&file.content) {
+            continue;
+        }
+        if let Some(name) = Path::new(&file.path).file_name() {
+            hasher.update(name.as_encoded_bytes());
+        }
+        hasher.update(&file.content);
+    }
+
+    let result = hasher.finalize();
+    hex::encode(&result[..8]) // 16 hex chars
+}
@@ -0,0 +1,165 @@
+//! openapi 3.1 export for xrpc methods
+
+use atrium_lex::LexiconDoc;
+use serde_json::{json, Map, Value};
+
+use crate::schema::{field, reachable, str_field, to_json_schema};
+use crate::types::RefContext;
+
+/// build an openapi document with one `/xrpc/{nsid}` path per query/procedure
+///
+/// schemas for every def reachable from the selected documents (including
+/// builtins) are emitted as components named like the generated classes.
+pub fn to_openapi(
+    docs: &[LexiconDoc],
+    builtins: &[LexiconDoc],
+    namespace_prefix: Option<&str>,
+) -> Value {
+    let reachable = reachable(docs, builtins, namespace_prefix);
+    let (lookup, class_names) = (&reachable.lookup, &reachable.class_names);
+
+    let mut paths = Map::new();
+    for doc in &reachable.selected {
+        let Some(main) = field(&lookup[doc.id.as_str()], "defs").and_then(|d| field(d, "main"))
+        else {
+            continue;
+        };
+        let ctx = RefContext::with_class_names(&doc.id, class_names);
+        let method = match str_field(main, "type") {
+            Some("query") => "get",
+            Some("procedure") => "post",
+            _ => continue,
+        };
+        paths.insert(
+            format!("/xrpc/{}", doc.id),
+            json!({ method: operation(&doc.id, main, &ctx) }),
+        );
+    }
+
+    let mut schemas = Map::new();
+    for nsid in &reachable.included {
+        let ctx = RefContext::with_class_names(nsid, class_names);
+        let Some(defs) = field(&lookup[nsid.as_str()], "defs").and_then(Value::as_object) else {
+            continue;
+        };
+        let mut names: Vec<_> = defs.keys().collect();
+        names.sort();
+        for def_name in names {
+            let def = &defs[def_name];
+            let schema = match str_field(def, "type") {
+                Some("query" | "procedure" | "subscription") => continue,
+                Some("token") => json!({"type": "string", "const": format!("{nsid}#{def_name}")}),
+                _ => to_json_schema(def, &ctx),
+            };
+            schemas.insert(ctx.class_name(nsid, def_name), schema);
+        }
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {"title": "xrpc", "version": env!("CARGO_PKG_VERSION")},
+        "paths": paths,
+        "components": {"schemas": schemas},
+    })
+}
+
+/// build the operation object for a query or procedure def
+fn operation(nsid: &str, def: &Value, ctx: &RefContext) -> Value {
+    let mut op = Map::new();
+    op.insert("operationId".into(), nsid.into());
+    if let Some(desc) = field(def, "description") {
+        op.insert("description".into(), desc.clone());
+    }
+
+    if let Some(params) = field(def, "parameters") {
+        let required: Vec<&str> = field(params, "required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let mut parameters = Vec::new();
+        if let Some(props) = field(params, "properties").and_then(Value::as_object) {
+            let mut names: Vec<_> = props.keys().collect();
+            names.sort();
+            for name in names {
+                let prop = &props[name];
+                let mut param = json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&name.as_str()),
+                    "schema": to_json_schema(prop, ctx),
+                });
+                // arrays repeat the key: ?uri=a&uri=b
+                if str_field(prop, "type") == Some("array") {
+                    param["style"] = "form".into();
+                    param["explode"] = true.into();
+                }
+                parameters.push(param);
+            }
+        }
+        op.insert("parameters".into(), Value::Array(parameters));
+    }
+
+    if let Some(input) = field(def, "input") {
+        op.insert(
+            "requestBody".into(),
+            json!({"required": true, "content": body_content(input, ctx)}),
+        );
+    }
+
+    let mut responses = Map::new();
+    let success = match field(def, "output") {
+        Some(output) => json!({
+            "description": str_field(output, "description").unwrap_or("success"),
+            "content": body_content(output, ctx),
+        }),
+        None => json!({"description": "success"}),
+    };
+    responses.insert("200".into(), success);
+    responses.insert("400".into(), error_response(def));
+    op.insert("responses".into(), Value::Object(responses));
+
+    Value::Object(op)
+}
+
+/// media type map for an xrpc input/output body
+fn body_content(body: &Value, ctx: &RefContext) -> Value {
+    let encoding = str_field(body, "encoding").unwrap_or("application/json");
+    let schema = match field(body, "schema") {
+        Some(schema) => to_json_schema(schema, ctx),
+        None if encoding == "application/json" => json!({}),
+        None => json!({"type": "string", "format": "binary"}),
+    };
+    json!({ encoding: {"schema": schema} })
+}
+
+/// xrpc errors are returned as `{"error": name, "message": ...}` with status 400
+fn error_response(def: &Value) -> Value {
+    let errors: Vec<&Value> = field(def, "errors")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let mut error = json!({"type": "string"});
+    let mut description = String::from("xrpc error");
+    if !errors.is_empty() {
+        let names: Vec<&str> = errors.iter().filter_map(|e| str_field(e, "name")).collect();
+        error["enum"] = json!(names);
+        description = format!("xrpc error: {}", names.join(", "));
+    }
+
+    json!({
+        "description": description,
+        "content": {
+            "application/json": {
+                "schema": {
+                    "type": "object",
+                    "required": ["error"],
+                    "properties": {"error": error, "message": {"type": "string"}},
+                },
+            },
+        },
+    })
+}
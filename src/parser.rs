@@ -1,48 +1,978 @@
 //! lexicon file parsing
 
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use atrium_lex::LexiconDoc;
 use thiserror::Error;
 
+use crate::archive;
+use crate::codegen::GenerateWarning;
+
 #[derive(Error, Debug)]
 pub enum ParseError {
     #[error("not a directory: {0}")]
     NotADirectory(String),
 
+    #[error("reading archives requires the `archive` feature: {0}")]
+    ArchiveUnsupported(String),
+
+    #[error("invalid lexicon {path}: {source}")]
+    InvalidLexicon {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("ref aliases form a cycle: {}", .chain.join(" -> "))]
+    AliasCycle { chain: Vec<String> },
+
+    #[error("lexicon file {path} has unknown keys: {}", .keys.join(", "))]
+    UnknownKeys { path: String, keys: Vec<String> },
+
+    #[error("lexicon file {path} is {size} bytes, over the {limit} byte limit")]
+    TooLarge { path: String, size: u64, limit: u64 },
+
+    #[error("lexicon file {path} nests deeper than the limit of {limit}")]
+    TooDeep { path: String, limit: usize },
+
+    #[error("invalid manifest {path}: {reason}")]
+    InvalidManifest { path: String, reason: String },
+
+    #[error("manifest {manifest} lists {path}, which doesn't exist")]
+    MissingManifestEntry { manifest: String, path: String },
+
+    #[error("duplicate nsid {nsid} in {first} and {second}")]
+    DuplicateNsid {
+        nsid: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("def {def_name} of {nsid} declared in both {first} and {second}")]
+    DuplicateDef {
+        nsid: String,
+        def_name: String,
+        first: String,
+        second: String,
+    },
+
     #[error("io error: {0}")]
     Io(#[from] io::Error),
+}
+
+/// example values of record and object defs, keyed by `nsid#defName`
+///
+/// read from a def-level `"examples"` array, which atproto lexicons don't
+/// define (and atrium-lex drops) but which pmgfal accepts as an extension.
+pub type Examples = HashMap<String, Vec<serde_json::Value>>;
+
+/// each def's json as written, keyed by `nsid#defName`
+///
+/// keeps keys atrium-lex drops, such as the custom property hints read
+/// through `field_metadata_keys`.
+pub type RawDefs = HashMap<String, serde_json::Value>;
+
+/// lexicon content atrium-lex doesn't keep, read alongside the documents
+#[derive(Debug, Clone, Default)]
+pub struct Extras {
+    pub examples: Examples,
+    pub raw_defs: RawDefs,
+}
+
+/// bounds on the lexicon files read, so hostile input fails cleanly instead
+/// of exhausting memory
+///
+/// real lexicons are a few kilobytes and nest a dozen levels deep, so the
+/// defaults leave plenty of room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// largest file (or archive entry) read, in bytes
+    pub max_file_size: u64,
+    /// deepest nesting of json objects and arrays
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_file_size: 16 * 1024 * 1024,
+            max_depth: 64,
+        }
+    }
+}
+
+/// everything read from a lexicon directory
+#[derive(Default)]
+pub(crate) struct Parsed {
+    /// documents by nsid, with the file each came from
+    docs: BTreeMap<String, (String, LexiconDoc)>,
+    extras: Extras,
+    warnings: Vec<GenerateWarning>,
+    /// merge the defs of documents sharing an nsid instead of rejecting them
+    merge_split: bool,
+}
+
+/// one lexicon deserialized by `parse_document`, before it's checked
+/// against the others
+#[derive(Clone)]
+pub(crate) struct ParsedDocument {
+    doc: LexiconDoc,
+    unknown_formats: Vec<String>,
+    examples: Vec<(String, Vec<serde_json::Value>)>,
+    raw_defs: serde_json::Map<String, serde_json::Value>,
+}
 
+/// lexicons parsed by `parse_files`
+pub(crate) struct Lexicons {
+    /// documents sorted by nsid
+    pub docs: Vec<LexiconDoc>,
+    pub extras: Extras,
+    /// non-fatal problems, e.g. string formats this crate doesn't know
+    pub warnings: Vec<GenerateWarning>,
+    /// the file each document came from, by nsid
+    pub paths: HashMap<String, String>,
 }
 
 /// parse all lexicon files from a directory recursively
+///
+/// `dir` may also be a `.zip`, `.tar.gz` or `.tgz` archive, whose json
+/// entries are parsed in place.
 pub fn parse_lexicons(dir: &Path) -> Result<Vec<LexiconDoc>, ParseError> {
+    parse_lexicons_with_examples(dir).map(|(docs, _)| docs)
+}
+
+/// parse lexicon files like `parse_lexicons`, also returning their examples
+pub fn parse_lexicons_with_examples(dir: &Path) -> Result<(Vec<LexiconDoc>, Examples), ParseError> {
+    let (docs, extras) = parse_lexicons_with_extras(dir)?;
+    Ok((docs, extras.examples))
+}
+
+/// parse lexicon files like `parse_lexicons`, also returning what atrium-lex
+/// drops (examples and raw def json)
+pub fn parse_lexicons_with_extras(dir: &Path) -> Result<(Vec<LexiconDoc>, Extras), ParseError> {
+    parse_lexicons_with_limits(dir, &Limits::default())
+}
+
+/// parse lexicon files like `parse_lexicons_with_extras`, within `limits`
+pub fn parse_lexicons_with_limits(
+    dir: &Path,
+    limits: &Limits,
+) -> Result<(Vec<LexiconDoc>, Extras), ParseError> {
+    let lexicons = parse_files(&read_json_files(dir, limits)?, limits, None, false)?;
+    Ok((lexicons.docs, lexicons.extras))
+}
+
+/// every `.json` file of a lexicon directory (recursively) or archive, read
+/// into memory and sorted by path
+///
+/// parsing and hashing both work from these, so one pass over the tree
+/// serves both. a file over `limits.max_file_size` is an error, found before
+/// it's read.
+pub(crate) fn read_json_files(
+    dir: &Path,
+    limits: &Limits,
+) -> Result<Vec<archive::Entry>, ParseError> {
+    if archive::is_archive(dir) {
+        return archive::json_entries(dir, limits.max_file_size);
+    }
     if !dir.is_dir() {
         return Err(ParseError::NotADirectory(dir.display().to_string()));
     }
+    let mut paths = Vec::new();
+    visit_dir(dir, &mut paths)?;
+    paths.sort();
+    paths.iter().map(|path| read_file(path, limits)).collect()
+}
+
+/// the files a manifest lists, read like `read_json_files`
+///
+/// the manifest is a json array of paths (or an object with an `"imports"`
+/// array of them), or else text with one path per line, where blank lines
+/// and lines starting with `#` are skipped. paths are relative to `dir`, or
+/// to the root of an archive, and each one must exist; nothing else under
+/// `dir` is read.
+pub(crate) fn read_manifest_files(
+    dir: &Path,
+    manifest: &Path,
+    limits: &Limits,
+) -> Result<Vec<archive::Entry>, ParseError> {
+    let listed = manifest_paths(manifest)?;
+    let missing = |path: &str| ParseError::MissingManifestEntry {
+        manifest: manifest.display().to_string(),
+        path: path.to_string(),
+    };
+
+    if archive::is_archive(dir) {
+        let mut entries = archive::json_entries(dir, limits.max_file_size)?;
+        if let Some(path) = listed
+            .iter()
+            .find(|path| !entries.iter().any(|e| e.path == **path))
+        {
+            return Err(missing(path));
+        }
+        entries.retain(|e| listed.contains(&e.path));
+        return Ok(entries);
+    }
+    if !dir.is_dir() {
+        return Err(ParseError::NotADirectory(dir.display().to_string()));
+    }
+    let mut paths = Vec::new();
+    for path in &listed {
+        let full = dir.join(path);
+        if !full.is_file() {
+            return Err(missing(path));
+        }
+        paths.push(full);
+    }
+    paths.sort();
+    paths.dedup();
+    paths.iter().map(|path| read_file(path, limits)).collect()
+}
 
-    let mut docs = Vec::new();
-    visit_dir(dir, &mut docs)?;
-    docs.sort_by(|a, b| a.id.cmp(&b.id));
-    Ok(docs)
+/// the relative paths a manifest lists, without any leading `./`
+fn manifest_paths(manifest: &Path) -> Result<Vec<String>, ParseError> {
+    let content = fs::read_to_string(manifest)?;
+    let invalid = |reason: &str| ParseError::InvalidManifest {
+        path: manifest.display().to_string(),
+        reason: reason.to_string(),
+    };
+
+    let paths: Vec<&str> = match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(value) => value
+            .get("imports")
+            .unwrap_or(&value)
+            .as_array()
+            .ok_or_else(|| invalid("expected an array of paths, or an object with \"imports\""))?
+            .iter()
+            .map(|path| {
+                path.as_str()
+                    .ok_or_else(|| invalid("paths must be strings"))
+            })
+            .collect::<Result<_, _>>()?,
+        Err(_) => content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect(),
+    };
+    Ok(paths
+        .into_iter()
+        .map(|path| path.trim_start_matches("./").to_string())
+        .collect())
+}
+
+/// a lexicon file read into memory, unless it's over `limits.max_file_size`
+pub(crate) fn read_file(path: &Path, limits: &Limits) -> Result<archive::Entry, ParseError> {
+    let size = fs::metadata(path)?.len();
+    if size > limits.max_file_size {
+        return Err(ParseError::TooLarge {
+            path: path.display().to_string(),
+            size,
+            limit: limits.max_file_size,
+        });
+    }
+    Ok(archive::Entry {
+        content: fs::read(path)?,
+        path: path.display().to_string(),
+    })
 }
 
-fn visit_dir(dir: &Path, docs: &mut Vec<LexiconDoc>) -> Result<(), ParseError> {
+pub(crate) fn visit_dir(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
     for entry in fs::read_dir(dir)? {
         let path = entry?.path();
 
         if path.is_dir() {
-            visit_dir(&path, docs)?;
+            visit_dir(&path, paths)?;
         } else if path.extension().is_some_and(|e| e == "json") {
-            let content = fs::read_to_string(&path)?;
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// parse the lexicons among files read by `read_json_files`
+///
+/// with `strict_keys`, a lexicon holding a key the lexicon language doesn't
+/// define, other than pmgfal's extensions and the keys listed, is an error
+/// rather than ignored. with `merge_split`, files declaring the same nsid
+/// are merged into one document (see `Parsed::add`).
+pub(crate) fn parse_files(
+    files: &[archive::Entry],
+    limits: &Limits,
+    strict_keys: Option<&[String]>,
+    merge_split: bool,
+) -> Result<Lexicons, ParseError> {
+    let mut documents = Vec::new();
+    for file in files {
+        check_depth(file, limits)?;
+        if let Some(value) = read_document(&file.content, &file.path)? {
+            if let Some(allowed) = strict_keys {
+                check_unknown_keys(&value, &file.path, allowed)?;
+            }
+            documents.push((file.path.as_str(), value));
+        }
+    }
+    let resolved = resolve_ref_aliases(documents.iter().map(|(_, value)| value))?;
 
-            // skip non-lexicon json files silently
-            if let Ok(doc) = serde_json::from_str::<LexiconDoc>(&content) {
-                docs.push(doc);
+    let mut parsed = Parsed::new(merge_split);
+    for (origin, mut value) in documents {
+        drop_ref_aliases(&mut value, &resolved);
+        parsed.add(origin, parse_document(value, origin)?)?;
+    }
+    Ok(parsed.into_lexicons())
+}
+
+impl Parsed {
+    pub(crate) fn new(merge_split: bool) -> Self {
+        Self {
+            merge_split,
+            ..Self::default()
+        }
+    }
+
+    /// add a document read from `origin`; a second document with the same
+    /// nsid is an error
+    ///
+    /// when merging split documents, its defs are added to the first's
+    /// instead, and only a def both declare is an error. the merged document
+    /// keeps the first description given, and is attributed to the file
+    /// declaring `main`.
+    pub(crate) fn add(&mut self, origin: &str, document: ParsedDocument) -> Result<(), ParseError> {
+        let ParsedDocument {
+            doc,
+            unknown_formats,
+            examples,
+            raw_defs,
+        } = document;
+        if let Some((first, existing)) = self.docs.get(&doc.id) {
+            if !self.merge_split {
+                return Err(ParseError::DuplicateNsid {
+                    nsid: doc.id.clone(),
+                    first: first.clone(),
+                    second: origin.to_string(),
+                });
+            }
+            let mut shared: Vec<&String> = doc
+                .defs
+                .keys()
+                .filter(|name| existing.defs.contains_key(*name))
+                .collect();
+            shared.sort();
+            if let Some(def_name) = shared.first() {
+                return Err(ParseError::DuplicateDef {
+                    nsid: doc.id.clone(),
+                    def_name: def_name.to_string(),
+                    first: first.clone(),
+                    second: origin.to_string(),
+                });
+            }
+        }
+        for format in unknown_formats {
+            warn!("{}: unknown string format {format}, typed as str", doc.id);
+            let message = format!(
+                "unknown string format: {format} in {} (typed as str)",
+                doc.id
+            );
+            self.warnings.push(GenerateWarning {
+                path: Some(origin.to_string()),
+                ..GenerateWarning::new("unknown_format", &doc.id, message)
+            });
+        }
+        for (def_name, values) in examples {
+            self.extras
+                .examples
+                .insert(format!("{}#{def_name}", doc.id), values);
+        }
+        for (def_name, def) in raw_defs {
+            self.extras
+                .raw_defs
+                .insert(format!("{}#{def_name}", doc.id), def);
+        }
+        match self.docs.entry(doc.id.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert((origin.to_string(), doc));
+            }
+            Entry::Occupied(mut entry) => {
+                let (path, merged) = entry.get_mut();
+                if doc.defs.contains_key("main") {
+                    *path = origin.to_string();
+                }
+                if merged.description.is_none() {
+                    merged.description = doc.description;
+                }
+                merged.defs.extend(doc.defs);
             }
         }
+        Ok(())
+    }
+
+    pub(crate) fn into_lexicons(self) -> Lexicons {
+        let mut paths = HashMap::new();
+        let mut docs = Vec::new();
+        for (nsid, (path, doc)) in self.docs {
+            paths.insert(nsid, path);
+            docs.push(doc);
+        }
+        Lexicons {
+            docs,
+            extras: self.extras,
+            warnings: self.warnings,
+            paths,
+        }
+    }
+}
+
+/// a file nesting deeper than `limits.max_depth` is an error
+pub(crate) fn check_depth(file: &archive::Entry, limits: &Limits) -> Result<(), ParseError> {
+    if json_depth(&file.content) > limits.max_depth {
+        return Err(ParseError::TooDeep {
+            path: file.path.clone(),
+            limit: limits.max_depth,
+        });
     }
     Ok(())
 }
+
+/// whether `content` is json with a top-level `lexicon` key
+///
+/// this is the test `parse_lexicons` applies before deserializing, so other
+/// json in the tree (`package.json`, `tsconfig.json`) never affects output.
+pub fn is_lexicon(content: &[u8]) -> bool {
+    read_json(content).is_ok_and(|value| has_lexicon_key(&value))
+}
+
+/// `content` as json, after `strip_comments`
+pub(crate) fn read_json(content: &[u8]) -> Result<serde_json::Value, serde_json::Error> {
+    serde_json::from_slice(&strip_comments(content))
+}
+
+/// `content` with `//` line and `/* */` block comments blanked out
+///
+/// comments become spaces (newlines are kept), so serde's line and column
+/// numbers in errors still point into the original file. text inside json
+/// strings is left alone.
+#[cfg(feature = "jsonc")]
+fn strip_comments(content: &[u8]) -> Cow<'_, [u8]> {
+    if !content.contains(&b'/') {
+        return Cow::Borrowed(content);
+    }
+    let mut out = content.to_vec();
+    let mut i = 0;
+    let mut in_string = false;
+    while i < out.len() {
+        match (in_string, out[i], out.get(i + 1).copied()) {
+            (true, b'\\', _) => i += 1,
+            (_, b'"', _) => in_string = !in_string,
+            (false, b'/', Some(b'/')) => {
+                while i < out.len() && out[i] != b'\n' {
+                    out[i] = b' ';
+                    i += 1;
+                }
+                continue;
+            }
+            (false, b'/', Some(b'*')) => {
+                let end = out[i + 2..]
+                    .windows(2)
+                    .position(|w| w == b"*/")
+                    .map_or(out.len(), |p| i + 2 + p + 2);
+                for byte in &mut out[i..end] {
+                    if *byte != b'\n' {
+                        *byte = b' ';
+                    }
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(not(feature = "jsonc"))]
+fn strip_comments(content: &[u8]) -> Cow<'_, [u8]> {
+    Cow::Borrowed(content)
+}
+
+/// deepest nesting of `{`/`[` in json text, ignoring brackets in strings
+///
+/// a linear scan without building values, so deep input is rejected before
+/// serde recurses into it. comments are scanned too, which only errs on the
+/// side of a deeper count.
+fn json_depth(content: &[u8]) -> usize {
+    let mut depth = 0usize;
+    let mut deepest = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in content {
+        match (in_string, byte) {
+            (true, _) if escaped => escaped = false,
+            (true, b'\\') => escaped = true,
+            (_, b'"') => in_string = !in_string,
+            (false, b'{' | b'[') => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            (false, b'}' | b']') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    deepest
+}
+
+fn has_lexicon_key(value: &serde_json::Value) -> bool {
+    value.get("lexicon").is_some()
+}
+
+/// keys of a legacy single-def document that belong to the document itself
+const DOCUMENT_KEYS: &[&str] = &["lexicon", "id", "revision", "description"];
+
+/// move a legacy root-level schema (`"type"` beside `"id"`, no `"defs"`)
+/// into `defs.main`
+///
+/// early lexicons declared their one def at the document root. the root
+/// `description` is kept on the document and copied to the def.
+fn normalize_legacy(value: &mut serde_json::Value) {
+    let Some(root) = value.as_object_mut() else {
+        return;
+    };
+    if root.contains_key("defs") || !root.contains_key("type") {
+        return;
+    }
+    let mut main = serde_json::Map::new();
+    let keys: Vec<String> = root.keys().cloned().collect();
+    for key in keys {
+        if key == "description" {
+            main.insert(key.clone(), root[&key].clone());
+        } else if !DOCUMENT_KEYS.contains(&key.as_str()) {
+            main.insert(key.clone(), root.remove(&key).unwrap_or_default());
+        }
+    }
+    debug!("synthesized main def for legacy document");
+    root.insert("defs".into(), serde_json::json!({ "main": main }));
+}
+
+/// string formats atrium-lex deserializes
+const KNOWN_FORMATS: &[&str] = &[
+    "at-identifier",
+    "at-uri",
+    "cid",
+    "datetime",
+    "did",
+    "handle",
+    "language",
+    "nsid",
+    "record-key",
+    "tid",
+    "uri",
+];
+
+/// drop string `format`s this crate doesn't know, returning them
+///
+/// atproto adds formats over time; deserializing an unknown one would fail
+/// the whole document, while the field is still a plain string either way.
+fn strip_unknown_formats(value: &mut serde_json::Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    match value {
+        serde_json::Value::Object(map) => {
+            if map.get("type").and_then(|t| t.as_str()) == Some("string") {
+                if let Some(serde_json::Value::String(format)) = map.get("format") {
+                    if !KNOWN_FORMATS.contains(&format.as_str()) {
+                        unknown.push(format.clone());
+                        map.remove("format");
+                    }
+                }
+            }
+            for child in map.values_mut() {
+                unknown.extend(strip_unknown_formats(child));
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for child in items {
+                unknown.extend(strip_unknown_formats(child));
+            }
+        }
+        _ => {}
+    }
+    unknown
+}
+
+/// keys the lexicon language defines for each schema `type`, besides `type`
+/// and `description`
+const SCHEMA_KEYS: &[(&str, &[&str])] = &[
+    ("array", &["items", "minLength", "maxLength"]),
+    ("blob", &["accept", "maxSize"]),
+    ("boolean", &["default", "const"]),
+    ("bytes", &["minLength", "maxLength"]),
+    ("cid-link", &[]),
+    (
+        "integer",
+        &["minimum", "maximum", "enum", "default", "const"],
+    ),
+    ("object", &["required", "nullable", "properties"]),
+    ("params", &["required", "properties"]),
+    ("procedure", &["parameters", "input", "output", "errors"]),
+    ("query", &["parameters", "output", "errors"]),
+    ("record", &["key", "record"]),
+    ("ref", &["ref"]),
+    (
+        "string",
+        &[
+            "format",
+            "minLength",
+            "maxLength",
+            "minGraphemes",
+            "maxGraphemes",
+            "knownValues",
+            "enum",
+            "default",
+            "const",
+        ],
+    ),
+    ("subscription", &["parameters", "message", "errors"]),
+    ("token", &[]),
+    ("union", &["refs", "closed"]),
+    ("unknown", &[]),
+];
+
+/// keys pmgfal reads on any schema although lexicons don't define them
+const EXTENSION_KEYS: &[&str] = &["examples", "title", "additionalProperties"];
+
+/// keys of a lexicon the lexicon language doesn't define, as dotted paths
+/// (`defss`, `defs.main.record.properties.title.maxLenght`)
+///
+/// pmgfal's extensions and the keys in `allowed` are accepted on any
+/// schema. a schema of a `type` this crate doesn't know isn't looked into;
+/// it fails to deserialize on its own.
+fn unknown_keys(value: &serde_json::Value, allowed: &[String]) -> Vec<String> {
+    let mut unknown = Vec::new();
+    check_keys(
+        value,
+        "",
+        &["lexicon", "id", "revision", "description", "defs"],
+        &mut unknown,
+    );
+    let defs = value.get("defs").and_then(|d| d.as_object());
+    for (name, def) in defs.into_iter().flatten() {
+        schema_keys(def, &format!("defs.{name}"), allowed, &mut unknown);
+    }
+    unknown
+}
+
+/// `unknown_keys` of a lexicon file, as `parse_files` checks it
+pub(crate) fn file_unknown_keys(content: &[u8], allowed: &[String]) -> Vec<String> {
+    let Ok(mut value) = read_json(content) else {
+        return Vec::new();
+    };
+    normalize_legacy(&mut value);
+    unknown_keys(&value, allowed)
+}
+
+/// collect the unknown keys of one schema and the schemas nested in it
+fn schema_keys(
+    schema: &serde_json::Value,
+    path: &str,
+    allowed: &[String],
+    unknown: &mut Vec<String>,
+) {
+    let Some(map) = schema.as_object() else {
+        return;
+    };
+    let type_ = map.get("type").and_then(|t| t.as_str());
+    let Some((_, keys)) = SCHEMA_KEYS.iter().find(|(name, _)| Some(*name) == type_) else {
+        return;
+    };
+    for key in map.keys() {
+        let known = ["type", "description"].contains(&key.as_str())
+            || keys.contains(&key.as_str())
+            || EXTENSION_KEYS.contains(&key.as_str())
+            || allowed.contains(key);
+        if !known {
+            unknown.push(join_path(path, key));
+        }
+    }
+    let nested = |key: &str| map.get(key).map(|child| (child, join_path(path, key)));
+    for (child, child_path) in ["record", "items", "parameters", "additionalProperties"]
+        .into_iter()
+        .filter_map(nested)
+    {
+        schema_keys(child, &child_path, allowed, unknown);
+    }
+    if let Some((properties, properties_path)) = nested("properties") {
+        for (name, prop) in properties.as_object().into_iter().flatten() {
+            schema_keys(prop, &join_path(&properties_path, name), allowed, unknown);
+        }
+    }
+    for (body, body_path) in ["input", "output", "message"]
+        .into_iter()
+        .filter_map(nested)
+    {
+        check_keys(
+            body,
+            &body_path,
+            &["description", "encoding", "schema"],
+            unknown,
+        );
+        if let Some(body_schema) = body.get("schema") {
+            schema_keys(
+                body_schema,
+                &join_path(&body_path, "schema"),
+                allowed,
+                unknown,
+            );
+        }
+    }
+    if let Some((errors, errors_path)) = nested("errors") {
+        for (i, error) in errors.as_array().into_iter().flatten().enumerate() {
+            let error_path = join_path(&errors_path, &i.to_string());
+            check_keys(error, &error_path, &["name", "description"], unknown);
+        }
+    }
+}
+
+/// collect the keys of `value`, if an object, that aren't in `known`
+fn check_keys(value: &serde_json::Value, path: &str, known: &[&str], unknown: &mut Vec<String>) {
+    for key in value.as_object().into_iter().flat_map(|map| map.keys()) {
+        if !known.contains(&key.as_str()) {
+            unknown.push(join_path(path, key));
+        }
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// one json file's lexicon, or `None` if it isn't one
+///
+/// json without a top-level `lexicon` key (e.g. `package.json`) is skipped.
+pub(crate) fn read_document(
+    content: &[u8],
+    origin: &str,
+) -> Result<Option<serde_json::Value>, ParseError> {
+    debug!("reading {origin}");
+    let mut value = match read_json(content) {
+        Ok(value) if has_lexicon_key(&value) => value,
+        Ok(_) => {
+            info!("skipping non-lexicon json {origin}: no `lexicon` key");
+            return Ok(None);
+        }
+        Err(e) => {
+            info!("skipping non-lexicon json {origin}: {e}");
+            return Ok(None);
+        }
+    };
+    normalize_legacy(&mut value);
+    Ok(Some(value))
+}
+
+/// a lexicon holding a key the lexicon language doesn't define, other than
+/// pmgfal's extensions and `allowed`, is an error
+pub(crate) fn check_unknown_keys(
+    value: &serde_json::Value,
+    origin: &str,
+    allowed: &[String],
+) -> Result<(), ParseError> {
+    let keys = unknown_keys(value, allowed);
+    if !keys.is_empty() {
+        return Err(ParseError::UnknownKeys {
+            path: origin.to_string(),
+            keys,
+        });
+    }
+    Ok(())
+}
+
+/// the `nsid#defName` of each alias def (a def that is just a `ref`) in
+/// `documents`, with the ref it names, made absolute
+fn ref_aliases<'v>(
+    documents: impl IntoIterator<Item = &'v serde_json::Value>,
+) -> BTreeMap<String, String> {
+    let mut aliases = BTreeMap::new();
+    for value in documents {
+        let Some(nsid) = value.get("id").and_then(|id| id.as_str()) else {
+            continue;
+        };
+        let defs = value.get("defs").and_then(|d| d.as_object());
+        for (def_name, def) in defs.into_iter().flatten() {
+            if def.get("type").and_then(|t| t.as_str()) != Some("ref") {
+                continue;
+            }
+            if let Some(target) = def.get("ref").and_then(|r| r.as_str()) {
+                aliases.insert(format!("{nsid}#{def_name}"), absolute_ref(nsid, target));
+            }
+        }
+    }
+    aliases
+}
+
+/// the alias defs a lexicon file declares, as `nsid#defName`
+pub(crate) fn file_ref_aliases(content: &[u8]) -> Vec<String> {
+    let Ok(mut value) = read_json(content) else {
+        return Vec::new();
+    };
+    normalize_legacy(&mut value);
+    ref_aliases([&value]).into_keys().collect()
+}
+
+/// the def each alias def's chain ends in, by the alias's `nsid#defName`
+///
+/// lexicons have no inheritance, but a def may be nothing but a ref to
+/// another def. atrium-lex can't read such a def, so the chain (`#a` ->
+/// `#b` -> `#c`, across documents too) is followed to its end here, and
+/// `drop_ref_aliases` points refs past it, so fields typed by an alias hold
+/// the concrete model. a chain that comes back on itself is an error.
+pub(crate) fn resolve_ref_aliases<'v>(
+    documents: impl IntoIterator<Item = &'v serde_json::Value>,
+) -> Result<HashMap<String, String>, ParseError> {
+    let aliases = ref_aliases(documents);
+    let mut resolved = HashMap::new();
+    for (alias, first) in &aliases {
+        let mut chain = vec![alias.clone()];
+        let mut target = first;
+        while let Some(next) = aliases.get(target) {
+            let cycle = chain.contains(target);
+            chain.push(target.clone());
+            if cycle {
+                return Err(ParseError::AliasCycle { chain });
+            }
+            target = next;
+        }
+        debug!("resolved ref alias {alias} -> {target}");
+        resolved.insert(alias.clone(), target.clone());
+    }
+    Ok(resolved)
+}
+
+/// point a document's refs to alias defs at the def their chain ends in
+/// (`resolve_ref_aliases`), and drop its alias defs
+pub(crate) fn drop_ref_aliases(value: &mut serde_json::Value, resolved: &HashMap<String, String>) {
+    if resolved.is_empty() {
+        return;
+    }
+    let Some(nsid) = value
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(str::to_string)
+    else {
+        return;
+    };
+    if let Some(defs) = value.get_mut("defs").and_then(|d| d.as_object_mut()) {
+        let names: Vec<String> = defs
+            .keys()
+            .filter(|def_name| resolved.contains_key(&format!("{nsid}#{def_name}")))
+            .cloned()
+            .collect();
+        for def_name in names {
+            defs.remove(&def_name);
+        }
+    }
+    rewrite_refs(value, &nsid, resolved);
+}
+
+/// point the refs within `value`, part of document `nsid`, past `resolved`
+/// aliases
+fn rewrite_refs(value: &mut serde_json::Value, nsid: &str, resolved: &HashMap<String, String>) {
+    let rewrite = |r: &mut serde_json::Value| {
+        let target = r
+            .as_str()
+            .and_then(|r| resolved.get(&absolute_ref(nsid, r)));
+        if let Some(target) = target.map(|target| relative_ref(nsid, target)) {
+            *r = serde_json::Value::String(target);
+        }
+    };
+    match value {
+        serde_json::Value::Object(map) => {
+            let type_ = map.get("type").and_then(|t| t.as_str()).map(str::to_string);
+            match type_.as_deref() {
+                Some("ref") => map.get_mut("ref").into_iter().for_each(rewrite),
+                Some("union") => map
+                    .get_mut("refs")
+                    .and_then(|refs| refs.as_array_mut())
+                    .into_iter()
+                    .flatten()
+                    .for_each(rewrite),
+                _ => {}
+            }
+            for child in map.values_mut() {
+                rewrite_refs(child, nsid, resolved);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for child in items {
+                rewrite_refs(child, nsid, resolved);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// a ref as `nsid#defName`, whatever document it's written in
+fn absolute_ref(nsid: &str, r: &str) -> String {
+    match r.split_once('#') {
+        Some(("", def_name)) => format!("{nsid}#{def_name}"),
+        Some(_) => r.to_string(),
+        None => format!("{r}#main"),
+    }
+}
+
+/// an absolute ref as document `nsid` would write it
+fn relative_ref(nsid: &str, target: &str) -> String {
+    match target.split_once('#') {
+        Some((target_nsid, def_name)) if target_nsid == nsid => format!("#{def_name}"),
+        Some((target_nsid, "main")) => target_nsid.to_string(),
+        _ => target.to_string(),
+    }
+}
+
+/// deserialize one lexicon read by `read_document`, its ref aliases dropped
+///
+/// a file that doesn't deserialize is an error.
+pub(crate) fn parse_document(
+    mut value: serde_json::Value,
+    origin: &str,
+) -> Result<ParsedDocument, ParseError> {
+    debug!("parsing {origin}");
+    let unknown_formats = strip_unknown_formats(&mut value);
+    let examples = def_examples(&value);
+    let raw_defs = value
+        .get("defs")
+        .and_then(|d| d.as_object())
+        .cloned()
+        .unwrap_or_default();
+    let doc: LexiconDoc =
+        serde_json::from_value(value).map_err(|source| ParseError::InvalidLexicon {
+            path: origin.to_string(),
+            source,
+        })?;
+    debug!("parsed {} from {origin}", doc.id);
+    Ok(ParsedDocument {
+        doc,
+        unknown_formats,
+        examples,
+        raw_defs,
+    })
+}
+
+/// the `"examples"` arrays of a document's defs, by def name
+fn def_examples(value: &serde_json::Value) -> Vec<(String, Vec<serde_json::Value>)> {
+    let Some(defs) = value.get("defs").and_then(|d| d.as_object()) else {
+        return Vec::new();
+    };
+    defs.iter()
+        .filter_map(|(name, def)| {
+            let examples = def.get("examples")?.as_array()?;
+            Some((name.clone(), examples.clone()))
+        })
+        .collect()
+}
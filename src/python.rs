@@ -0,0 +1,845 @@
+//! pyo3 bindings over the rust api
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use pyo3::exceptions::{
+    PyIOError, PyIndexError, PyNotADirectoryError, PyRuntimeError, PyValueError,
+};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyIterator, PyList};
+
+use crate::codegen::{self, CodegenError};
+use crate::config::{
+    Config, DocstringStyle, FileHook, Indent, Layout, NameHook, OnInvalidDefault, OnInvalidField,
+    PythonVersion, QuoteStyle, Syntax, Target, TopLevel,
+};
+use crate::parser::{Limits, ParseError};
+use crate::{builtin, check, diff, parser, trace, Error, ParseCache};
+
+pyo3::create_exception!(
+    pmgfal,
+    LexiconError,
+    PyValueError,
+    "a lexicon directory or file could not be read"
+);
+pyo3::create_exception!(
+    pmgfal,
+    InvalidLexiconError,
+    LexiconError,
+    "a file declares a lexicon but isn't a valid one"
+);
+pyo3::create_exception!(
+    pmgfal,
+    DuplicateNsidError,
+    LexiconError,
+    "two lexicon files declare the same nsid"
+);
+
+/// compute a hash of all lexicon files in a directory
+#[pyfunction]
+#[pyo3(signature = (lexicon_dir, namespace_prefix=None))]
+fn hash_lexicons(lexicon_dir: &str, namespace_prefix: Option<&str>) -> PyResult<String> {
+    Ok(crate::hash_lexicons(
+        Path::new(lexicon_dir),
+        namespace_prefix,
+    ))
+}
+
+/// list the nsids of the bundled lexicons, sorted
+#[pyfunction]
+fn builtin_nsids() -> Vec<String> {
+    builtin::builtin_lexicons()
+        .iter()
+        .map(|doc| doc.id.clone())
+        .collect()
+}
+
+/// version and optional cargo features this build was compiled with
+#[pyfunction]
+fn build_info(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let features: Vec<&str> = [
+        ("archive", cfg!(feature = "archive")),
+        ("graphql", cfg!(feature = "graphql")),
+        ("jsonc", cfg!(feature = "jsonc")),
+        ("tracing", cfg!(feature = "tracing")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+
+    let info = PyDict::new(py);
+    info.set_item("version", env!("CARGO_PKG_VERSION"))?;
+    info.set_item("features", features)?;
+    info.set_item("builtin_lexicon_count", builtin::builtin_lexicons().len())?;
+    Ok(info)
+}
+
+/// declares `generate`, the functions taking its arguments and `Generator`'s
+/// methods from one list of keyword arguments, so their signatures can't drift
+macro_rules! generate_entry_points {
+    ($($name:ident: $ty:ty = $default:tt,)*) => {
+        /// `generate`'s keyword arguments
+        struct GenerateArgs<'a> {
+            $($name: $ty,)*
+        }
+
+        /// generate pydantic models from lexicon files
+        ///
+        /// when `log_level` is set, codegen diagnostics are forwarded to the python
+        /// `pmgfal` logger for the duration of the call.
+        #[pyfunction]
+        #[pyo3(signature = (lexicon_dir, output_dir, $($name = $default),*))]
+        #[allow(clippy::too_many_arguments)]
+        fn generate<'a>(
+            py: Python<'_>,
+            lexicon_dir: &str,
+            output_dir: Output,
+            $($name: $ty),*
+        ) -> PyResult<Py<PyAny>> {
+            let args = GenerateArgs { $($name),* };
+            let results = run_generate(None, lexicon_dir, &output_dir, args)?;
+            results_to_py(py, &output_dir, results)
+        }
+
+        /// `generate`, also returning the `hash_lexicons` hash of the same files
+        ///
+        /// takes the same arguments as `generate`. the lexicon files are read once
+        /// for both, so a build step caching on the hash doesn't walk the tree twice.
+        #[pyfunction]
+        #[pyo3(signature = (lexicon_dir, output_dir, $($name = $default),*))]
+        #[allow(clippy::too_many_arguments)]
+        fn generate_with_hash<'a>(
+            py: Python<'_>,
+            lexicon_dir: &str,
+            output_dir: Output,
+            $($name: $ty),*
+        ) -> PyResult<(String, Py<GenerateResult>)> {
+            if matches!(output_dir, Output::Targets(_)) {
+                return Err(PyErr::new::<PyValueError, _>(
+                    "generate_with_hash takes one output_dir; use generate_targets for targets",
+                ));
+            }
+            let args = GenerateArgs { $($name),* };
+            let result = run_generate(None, lexicon_dir, &output_dir, args)?
+                .pop()
+                .expect("one result per output directory");
+            Ok((result.lexicon_hash.clone(), Py::new(py, result)?))
+        }
+
+        /// `generate` for several nsid prefixes from one parse of the lexicons
+        ///
+        /// takes `generate`'s keyword arguments except `namespace_prefix`; each
+        /// `(prefix, output_dir)` target stands in for a call with that prefix, and
+        /// refs from one target into another's nsids import across packages.
+        #[pyfunction]
+        #[pyo3(signature = (lexicon_dir, targets, $($name = $default),*))]
+        #[allow(clippy::too_many_arguments)]
+        fn generate_targets<'a>(
+            py: Python<'_>,
+            lexicon_dir: &str,
+            targets: Vec<(String, PathBuf)>,
+            $($name: $ty),*
+        ) -> PyResult<Py<PyList>> {
+            let output_dir = Output::Targets(targets);
+            let args = GenerateArgs { $($name),* };
+            let results = run_generate(None, lexicon_dir, &output_dir, args)?;
+            Ok(PyList::new(py, results)?.unbind())
+        }
+
+        #[pymethods]
+        impl Generator {
+            #[new]
+            fn new() -> Self {
+                Self {
+                    cache: ParseCache::new(),
+                }
+            }
+
+            /// `generate`, reusing the parses of unchanged files
+            ///
+            /// `output_dir` may also be `generate_targets`' list of targets.
+            #[pyo3(signature = (lexicon_dir, output_dir, $($name = $default),*))]
+            #[allow(clippy::too_many_arguments)]
+            fn generate<'a>(
+                &self,
+                py: Python<'_>,
+                lexicon_dir: &str,
+                output_dir: Output,
+                $($name: $ty),*
+            ) -> PyResult<Py<PyAny>> {
+                let args = GenerateArgs { $($name),* };
+                let results = run_generate(Some(&self.cache), lexicon_dir, &output_dir, args)?;
+                results_to_py(py, &output_dir, results)
+            }
+
+            /// how many lexicon files were parsed so far
+            #[getter]
+            fn parses(&self) -> usize {
+                self.cache.parses()
+            }
+        }
+    };
+}
+
+generate_entry_points! {
+    namespace_prefix: Option<&'a str> = None,
+    log_level: Option<&'a str> = None,
+    use_builtins: bool = true,
+    strict_refs: bool = false,
+    builtins_dir: Option<PathBuf> = None,
+    embed_metadata: bool = false,
+    layout: &'a str = "single",
+    split_defs: bool = false,
+    small_int_ranges_as_literal: bool = false,
+    max_literal_range: usize = 16,
+    keep_acronyms: bool = false,
+    mark_deprecated: bool = false,
+    target: &'a str = "pydantic",
+    records_only: bool = false,
+    seeds: Option<Vec<String>> = None,
+    emit_client: bool = false,
+    transport_base: &'a str = "XrpcTransport",
+    skip_nsids: Option<Vec<String>> = None,
+    emit_invariant_validators: bool = false,
+    compact_repr: bool = false,
+    identity_equality: bool = false,
+    identity_fields: Option<Vec<String>> = None,
+    emit_example_tests: bool = false,
+    name_hook: Option<Py<PyAny>> = None,
+    compat_map: Option<PathBuf> = None,
+    file_hook: Option<Py<PyAny>> = None,
+    emit_union_adapters: bool = false,
+    constraints_in_schema: bool = false,
+    discriminator_field: &'a str = "$type",
+    field_metadata_keys: Option<Vec<String>> = None,
+    emit_nsid_constant: bool = false,
+    emit_rkey_info: bool = false,
+    emit_lexicon_hash: bool = false,
+    clean: bool = false,
+    relative_paths: bool = false,
+    warnings_file: Option<PathBuf> = None,
+    write_threads: usize = 8,
+    lazy_imports: bool = false,
+    syntax: Option<&'a str> = None,
+    target_python: &'a str = "3.10",
+    indent: &'a str = "spaces",
+    quote_style: &'a str = "double",
+    docstring_style: &'a str = "none",
+    max_annotation_width: Option<usize> = None,
+    emit_parse_record: bool = false,
+    emit_collections: bool = false,
+    emit_fixtures: bool = false,
+    value_object_defs: Option<Vec<String>> = None,
+    class_members: Option<HashMap<String, String>> = None,
+    exclude: Option<Vec<String>> = None,
+    alias_repeated_unions: bool = false,
+    record_union_for_unknown: bool = false,
+    top_level_as: &'a str = "adapter",
+    on_invalid_field: &'a str = "skip",
+    on_invalid_default: &'a str = "error",
+    emit_titles: bool = false,
+    schema_ids: bool = false,
+    open_maps: bool = false,
+    typed_datetimes: bool = false,
+    manifest_path: Option<PathBuf> = None,
+    strict_keys: bool = false,
+    merge_split_nsids: bool = false,
+    max_file_size: Option<u64> = None,
+    max_depth: Option<usize> = None,
+}
+
+/// runs `generate` into `output_dir`, through `parse_cache` if one is given
+///
+/// the cache is only ever passed here by `Generator`, so a hook calling
+/// `generate` again doesn't inherit it.
+fn run_generate(
+    parse_cache: Option<&ParseCache>,
+    lexicon_dir: &str,
+    output_dir: &Output,
+    args: GenerateArgs<'_>,
+) -> PyResult<Vec<GenerateResult>> {
+    let GenerateArgs {
+        namespace_prefix,
+        log_level,
+        use_builtins,
+        strict_refs,
+        builtins_dir,
+        embed_metadata,
+        layout,
+        split_defs,
+        small_int_ranges_as_literal,
+        max_literal_range,
+        keep_acronyms,
+        mark_deprecated,
+        target,
+        records_only,
+        seeds,
+        emit_client,
+        transport_base,
+        skip_nsids,
+        emit_invariant_validators,
+        compact_repr,
+        identity_equality,
+        identity_fields,
+        emit_example_tests,
+        name_hook,
+        compat_map,
+        file_hook,
+        emit_union_adapters,
+        constraints_in_schema,
+        discriminator_field,
+        field_metadata_keys,
+        emit_nsid_constant,
+        emit_rkey_info,
+        emit_lexicon_hash,
+        clean,
+        relative_paths,
+        warnings_file,
+        write_threads,
+        lazy_imports,
+        syntax,
+        target_python,
+        indent,
+        quote_style,
+        docstring_style,
+        max_annotation_width,
+        emit_parse_record,
+        emit_collections,
+        emit_fixtures,
+        value_object_defs,
+        class_members,
+        exclude,
+        alias_repeated_unions,
+        record_union_for_unknown,
+        top_level_as,
+        on_invalid_field,
+        on_invalid_default,
+        emit_titles,
+        schema_ids,
+        open_maps,
+        typed_datetimes,
+        manifest_path,
+        strict_keys,
+        merge_split_nsids,
+        max_file_size,
+        max_depth,
+    } = args;
+    if matches!(output_dir, Output::Targets(_)) && namespace_prefix.is_some() {
+        return Err(PyErr::new::<PyValueError, _>(
+            "namespace_prefix can't be combined with targets, which set their own",
+        ));
+    }
+    let layout = layout
+        .parse::<Layout>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let target = target
+        .parse::<Target>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let top_level_as = top_level_as
+        .parse::<TopLevel>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let on_invalid_field = on_invalid_field
+        .parse::<OnInvalidField>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let on_invalid_default = on_invalid_default
+        .parse::<OnInvalidDefault>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let target_python = target_python
+        .parse::<PythonVersion>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let indent = indent
+        .parse::<Indent>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let quote_style = quote_style
+        .parse::<QuoteStyle>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let docstring_style = docstring_style
+        .parse::<DocstringStyle>()
+        .map_err(PyErr::new::<PyValueError, _>)?;
+    let syntax = match syntax {
+        Some(syntax) => syntax
+            .parse::<Syntax>()
+            .map_err(PyErr::new::<PyValueError, _>)?,
+        None => target_python.syntax(),
+    };
+
+    // the hook runs with the gil held; its exception is kept to re-raise as is
+    let hook_error: Arc<Mutex<Option<PyErr>>> = Arc::default();
+    let name_hook = name_hook.map(|hook| {
+        let slot = Arc::clone(&hook_error);
+        NameHook::new(move |nsid, def_name| {
+            Python::attach(|py| {
+                hook.call1(py, (nsid, def_name))
+                    .and_then(|name| name.bind(py).extract::<Option<String>>())
+                    .map_err(|e| {
+                        let message = e.to_string();
+                        *slot.lock().unwrap() = Some(e);
+                        message
+                    })
+            })
+        })
+    });
+    // its exception is re-raised as the cause of one naming the file
+    let file_hook = file_hook.map(|hook| {
+        let slot = Arc::clone(&hook_error);
+        FileHook::new(move |path, content| {
+            Python::attach(|py| {
+                hook.call1(py, (path.display().to_string(), content))
+                    .and_then(|content| content.bind(py).extract::<String>())
+                    .map_err(|e| {
+                        let message = e.to_string();
+                        let error = PyRuntimeError::new_err(format!(
+                            "file_hook failed for {}: {message}",
+                            path.display()
+                        ));
+                        error.set_cause(py, Some(e));
+                        *slot.lock().unwrap() = Some(error);
+                        message
+                    })
+            })
+        })
+    });
+
+    let config = Config {
+        namespace_prefix: namespace_prefix.map(str::to_string),
+        use_builtins,
+        builtins_dir,
+        strict_refs,
+        embed_metadata,
+        small_int_ranges_as_literal,
+        max_literal_range,
+        keep_acronyms,
+        mark_deprecated,
+        target,
+        layout,
+        split_defs,
+        records_only,
+        seeds: seeds.unwrap_or_default(),
+        emit_client,
+        transport_base: transport_base.to_string(),
+        skip_nsids: skip_nsids.unwrap_or_default(),
+        emit_invariant_validators,
+        compact_repr,
+        identity_equality,
+        identity_fields: identity_fields.unwrap_or_else(|| Config::default().identity_fields),
+        emit_example_tests,
+        name_hook,
+        compat_map,
+        file_hook,
+        emit_union_adapters,
+        constraints_in_schema,
+        discriminator_field: discriminator_field.to_string(),
+        field_metadata_keys: field_metadata_keys.unwrap_or_default(),
+        emit_nsid_constant,
+        emit_rkey_info,
+        emit_lexicon_hash,
+        clean,
+        relative_paths,
+        warnings_file,
+        write_threads,
+        lazy_imports,
+        syntax,
+        target_python,
+        indent,
+        quote_style,
+        docstring_style,
+        max_annotation_width,
+        emit_parse_record,
+        emit_collections,
+        emit_fixtures,
+        value_object_defs: value_object_defs.unwrap_or_default(),
+        class_members: class_members.unwrap_or_default(),
+        exclude: exclude.unwrap_or_default(),
+        alias_repeated_unions,
+        record_union_for_unknown,
+        top_level_as,
+        on_invalid_field,
+        on_invalid_default,
+        emit_titles,
+        schema_ids,
+        open_maps,
+        typed_datetimes,
+        manifest_path,
+        parse_cache: parse_cache.cloned(),
+        strict_keys,
+        merge_split_nsids,
+        max_file_size: max_file_size.unwrap_or(Limits::default().max_file_size),
+        max_depth: max_depth.unwrap_or(Limits::default().max_depth),
+    };
+
+    // hashing the files already in memory is cheap, and gives
+    // `generate_with_hash` its hash without a second walk of the tree
+    let lexicon_dir = Path::new(lexicon_dir);
+    let run = || match output_dir {
+        Output::Dir(dir) => crate::generate_with_hash(lexicon_dir, dir, &config).map(|r| vec![r]),
+        Output::Targets(targets) => {
+            crate::generate_targets_with_hash(lexicon_dir, targets, &config)
+        }
+    };
+    let results = match log_level {
+        Some(level) => trace::with_python_logging(level, run)?,
+        None => run(),
+    }
+    .map_err(|e| match hook_error.lock().unwrap().take() {
+        Some(hook_error) => hook_error,
+        None => error_to_py(e),
+    })?;
+
+    Ok(results
+        .into_iter()
+        .map(|(lexicon_hash, inner)| GenerateResult {
+            inner,
+            lexicon_hash,
+        })
+        .collect())
+}
+
+/// `generate`'s return value: one result for a directory, a list for targets
+fn results_to_py(
+    py: Python<'_>,
+    output_dir: &Output,
+    mut results: Vec<GenerateResult>,
+) -> PyResult<Py<PyAny>> {
+    match output_dir {
+        Output::Dir(_) => {
+            let result = results.pop().expect("one result per output directory");
+            Ok(Py::new(py, result)?.into_any())
+        }
+        Output::Targets(_) => Ok(PyList::new(py, results)?.into_any().unbind()),
+    }
+}
+
+/// runs `generate` with lexicon parses kept across calls
+///
+/// only files whose modification time or size changed since an earlier call
+/// are read and parsed again, which suits a long-running process regenerating
+/// on every change.
+#[pyclass(frozen, module = "pmgfal._pmgfal")]
+struct Generator {
+    cache: ParseCache,
+}
+
+/// where `generate` writes: one directory, or one per `generate_targets`
+/// target
+#[derive(FromPyObject)]
+enum Output {
+    Dir(PathBuf),
+    Targets(Vec<(String, PathBuf)>),
+}
+
+fn error_to_py(e: Error) -> PyErr {
+    match e {
+        Error::Parse(e) => parse_error_to_py(e),
+        Error::Codegen(CodegenError::Io(_)) => PyErr::new::<PyIOError, _>(e.to_string()),
+        Error::Codegen(CodegenError::NotADirectory(_)) => {
+            PyErr::new::<PyNotADirectoryError, _>(e.to_string())
+        }
+        _ => PyErr::new::<PyValueError, _>(e.to_string()),
+    }
+}
+
+fn parse_error_to_py(e: ParseError) -> PyErr {
+    match e {
+        ParseError::InvalidLexicon { .. }
+        | ParseError::UnknownKeys { .. }
+        | ParseError::AliasCycle { .. } => InvalidLexiconError::new_err(e.to_string()),
+        ParseError::DuplicateNsid { .. } | ParseError::DuplicateDef { .. } => {
+            DuplicateNsidError::new_err(e.to_string())
+        }
+        ParseError::Io(_) => PyErr::new::<PyIOError, _>(e.to_string()),
+        _ => LexiconError::new_err(e.to_string()),
+    }
+}
+
+/// export the xrpc methods in lexicon files as an openapi 3.1 json document
+#[pyfunction]
+#[pyo3(signature = (lexicon_dir, namespace_prefix=None))]
+fn to_openapi(lexicon_dir: &str, namespace_prefix: Option<&str>) -> PyResult<String> {
+    let docs = parser::parse_lexicons(Path::new(lexicon_dir)).map_err(parse_error_to_py)?;
+
+    let spec = crate::to_openapi(&docs, builtin::builtin_lexicons(), namespace_prefix);
+    serde_json::to_string_pretty(&spec).map_err(|e| PyErr::new::<PyValueError, _>(e.to_string()))
+}
+
+/// export the record and object defs in lexicon files as a graphql schema
+#[cfg(feature = "graphql")]
+#[pyfunction]
+#[pyo3(signature = (lexicon_dir, namespace_prefix=None))]
+fn to_graphql(lexicon_dir: &str, namespace_prefix: Option<&str>) -> PyResult<String> {
+    let docs = parser::parse_lexicons(Path::new(lexicon_dir)).map_err(parse_error_to_py)?;
+
+    Ok(crate::to_graphql(
+        &docs,
+        builtin::builtin_lexicons(),
+        namespace_prefix,
+    ))
+}
+
+/// compare two lexicon directories at the generated-model level
+#[pyfunction]
+fn diff_lexicons(old_dir: &str, new_dir: &str) -> PyResult<LexiconDiff> {
+    let parse = |dir: &str| parser::parse_lexicons(Path::new(dir)).map_err(parse_error_to_py);
+    let inner = diff::diff_lexicons(&parse(old_dir)?, &parse(new_dir)?);
+    Ok(LexiconDiff { inner })
+}
+
+/// lint lexicon files without generating anything
+#[pyfunction]
+#[pyo3(signature = (
+    lexicon_dir,
+    use_builtins=true,
+    builtins_dir=None,
+    strict_keys=false,
+    strict_paths=false,
+))]
+fn check_lexicons(
+    lexicon_dir: &str,
+    use_builtins: bool,
+    builtins_dir: Option<PathBuf>,
+    strict_keys: bool,
+    strict_paths: bool,
+) -> PyResult<Vec<LexiconProblem>> {
+    let config = Config {
+        use_builtins,
+        builtins_dir,
+        ..Default::default()
+    };
+    let builtins = builtin::select_builtins(&config).map_err(parse_error_to_py)?;
+    let problems = check::check_lexicons(
+        Path::new(lexicon_dir),
+        &builtins,
+        &config.limits(),
+        strict_keys,
+        strict_paths,
+    )
+    .map_err(parse_error_to_py)?;
+    Ok(problems
+        .into_iter()
+        .map(|p| LexiconProblem {
+            path: p.path,
+            nsid: p.nsid,
+            message: p.message,
+        })
+        .collect())
+}
+
+/// result of a `generate` call
+///
+/// behaves like the list of generated file paths for backwards compatibility.
+#[pyclass(frozen, module = "pmgfal._pmgfal")]
+struct GenerateResult {
+    inner: codegen::GenerateResult,
+    /// `hash_lexicons` of the files generated from, for `generate_with_hash`
+    lexicon_hash: String,
+}
+
+#[pymethods]
+impl GenerateResult {
+    /// paths of the generated files
+    #[getter]
+    fn files(&self) -> Vec<String> {
+        self.inner.files.clone()
+    }
+
+    /// builtin nsids overridden by a user lexicon with the same id
+    #[getter]
+    fn shadowed_builtins(&self) -> Vec<String> {
+        self.inner.shadowed_builtins.clone()
+    }
+
+    /// non-fatal problems found during generation
+    #[getter]
+    fn warnings(&self) -> Vec<String> {
+        self.inner.warnings.iter().map(|w| w.to_string()).collect()
+    }
+
+    /// stale generated files deleted because of `clean=True`
+    #[getter]
+    fn removed(&self) -> Vec<String> {
+        self.inner.removed.clone()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.files.len()
+    }
+
+    fn __getitem__(&self, index: isize) -> PyResult<String> {
+        let len = self.inner.files.len() as isize;
+        let i = if index < 0 { index + len } else { index };
+        if i < 0 || i >= len {
+            return Err(PyErr::new::<PyIndexError, _>(
+                "GenerateResult index out of range",
+            ));
+        }
+        Ok(self.inner.files[i as usize].clone())
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        PyList::new(py, &self.inner.files)?.as_any().try_iter()
+    }
+
+    fn __eq__(&self, other: &Bound<'_, PyAny>) -> bool {
+        if let Ok(files) = other.extract::<Vec<String>>() {
+            return files == self.inner.files;
+        }
+        other
+            .downcast::<GenerateResult>()
+            .is_ok_and(|o| o.get().inner.files == self.inner.files)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "GenerateResult(files={:?}, shadowed_builtins={:?}, warnings={:?})",
+            self.inner.files,
+            self.inner.shadowed_builtins,
+            self.warnings()
+        )
+    }
+}
+
+/// summary of a `diff_lexicons` call
+#[pyclass(frozen, module = "pmgfal._pmgfal")]
+struct LexiconDiff {
+    inner: diff::LexiconDiff,
+}
+
+#[pymethods]
+impl LexiconDiff {
+    #[getter]
+    fn added_nsids(&self) -> Vec<String> {
+        self.inner.added_nsids.clone()
+    }
+
+    #[getter]
+    fn removed_nsids(&self) -> Vec<String> {
+        self.inner.removed_nsids.clone()
+    }
+
+    /// defs added to nsids present on both sides (`nsid#defName`)
+    #[getter]
+    fn added_defs(&self) -> Vec<String> {
+        self.inner.added_defs.clone()
+    }
+
+    /// defs removed from nsids present on both sides (`nsid#defName`)
+    #[getter]
+    fn removed_defs(&self) -> Vec<String> {
+        self.inner.removed_defs.clone()
+    }
+
+    /// property changes in defs present on both sides
+    #[getter]
+    fn changes(&self) -> Vec<PropertyChange> {
+        self.inner
+            .changes
+            .iter()
+            .map(|c| PropertyChange {
+                def_ref: c.def.clone(),
+                property: c.property.clone(),
+                kind: c.kind.as_str().to_string(),
+                old_type: c.old_type.clone(),
+                new_type: c.new_type.clone(),
+                breaking: c.breaking,
+            })
+            .collect()
+    }
+
+    /// whether any change removes or narrows something
+    #[getter]
+    fn breaking(&self) -> bool {
+        self.inner.is_breaking()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "LexiconDiff(added_nsids={:?}, removed_nsids={:?}, added_defs={:?}, \
+             removed_defs={:?}, changes={}, breaking={})",
+            self.inner.added_nsids,
+            self.inner.removed_nsids,
+            self.inner.added_defs,
+            self.inner.removed_defs,
+            self.inner.changes.len(),
+            if self.inner.is_breaking() {
+                "True"
+            } else {
+                "False"
+            },
+        )
+    }
+}
+
+/// a property added, removed or retyped between two lexicon versions
+#[pyclass(frozen, get_all, module = "pmgfal._pmgfal")]
+struct PropertyChange {
+    /// absolute def ref (`nsid#defName`)
+    def_ref: String,
+    property: String,
+    /// "added", "removed" or "changed"
+    kind: String,
+    old_type: Option<String>,
+    new_type: Option<String>,
+    breaking: bool,
+}
+
+#[pymethods]
+impl PropertyChange {
+    fn __repr__(&self) -> String {
+        format!(
+            "PropertyChange(def_ref={:?}, property={:?}, kind={:?}, old_type={:?}, \
+             new_type={:?}, breaking={})",
+            self.def_ref,
+            self.property,
+            self.kind,
+            self.old_type,
+            self.new_type,
+            if self.breaking { "True" } else { "False" },
+        )
+    }
+}
+
+/// a problem `check_lexicons` found in one lexicon file
+#[pyclass(frozen, get_all, module = "pmgfal._pmgfal")]
+struct LexiconProblem {
+    path: String,
+    /// nsid of the document, when the file got far enough to declare one
+    nsid: Option<String>,
+    message: String,
+}
+
+#[pymethods]
+impl LexiconProblem {
+    fn __repr__(&self) -> String {
+        format!(
+            "LexiconProblem(path={:?}, nsid={:?}, message={:?})",
+            self.path, self.nsid, self.message
+        )
+    }
+}
+
+#[pymodule]
+fn _pmgfal(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(generate, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_with_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_targets, m)?)?;
+    m.add_function(wrap_pyfunction!(hash_lexicons, m)?)?;
+    m.add_function(wrap_pyfunction!(builtin_nsids, m)?)?;
+    m.add_function(wrap_pyfunction!(build_info, m)?)?;
+    m.add_function(wrap_pyfunction!(to_openapi, m)?)?;
+    #[cfg(feature = "graphql")]
+    m.add_function(wrap_pyfunction!(to_graphql, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_lexicons, m)?)?;
+    m.add_function(wrap_pyfunction!(check_lexicons, m)?)?;
+    m.add_class::<GenerateResult>()?;
+    m.add_class::<Generator>()?;
+    m.add_class::<LexiconDiff>()?;
+    m.add_class::<PropertyChange>()?;
+    m.add_class::<LexiconProblem>()?;
+    let py = m.py();
+    m.add("LexiconError", py.get_type::<LexiconError>())?;
+    m.add("InvalidLexiconError", py.get_type::<InvalidLexiconError>())?;
+    m.add("DuplicateNsidError", py.get_type::<DuplicateNsidError>())?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
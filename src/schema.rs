@@ -0,0 +1,294 @@
+//! json schema conversion for lexicon types
+//!
+//! conversion works on the serialized lexicon shape, so keys match the
+//! lexicon spec (`minLength`, `knownValues`, ...) rather than atrium's structs.
+
+use std::collections::{BTreeSet, HashMap};
+
+use atrium_lex::LexiconDoc;
+use serde_json::{json, Map, Value};
+
+use crate::config::in_namespace;
+use crate::types::{disambiguate_class_names, ClassNames, RefContext};
+
+/// prefix for schema refs pointing into a document's components
+pub const COMPONENTS_PREFIX: &str = "#/components/schemas/";
+
+/// get a non-null field from a serialized lexicon value
+pub fn field<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value.get(key).filter(|v| !v.is_null())
+}
+
+/// get a non-null string field from a serialized lexicon value
+pub fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    field(value, key).and_then(Value::as_str)
+}
+
+/// convert a serialized lexicon type to a json schema
+pub fn to_json_schema(lex: &Value, ctx: &RefContext) -> Value {
+    let mut schema = Map::new();
+
+    match str_field(lex, "type").unwrap_or_default() {
+        "boolean" => {
+            schema.insert("type".into(), "boolean".into());
+            copy_fields(
+                lex,
+                &mut schema,
+                &[("default", "default"), ("const", "const")],
+            );
+        }
+        "integer" => {
+            schema.insert("type".into(), "integer".into());
+            copy_fields(
+                lex,
+                &mut schema,
+                &[
+                    ("default", "default"),
+                    ("const", "const"),
+                    ("enum", "enum"),
+                    ("minimum", "minimum"),
+                    ("maximum", "maximum"),
+                ],
+            );
+        }
+        "string" => {
+            schema.insert("type".into(), "string".into());
+            if let Some(format) = str_field(lex, "format") {
+                schema.insert("format".into(), string_format(format).into());
+            }
+            copy_fields(
+                lex,
+                &mut schema,
+                &[
+                    ("default", "default"),
+                    ("const", "const"),
+                    ("enum", "enum"),
+                    ("minLength", "minLength"),
+                    ("maxLength", "maxLength"),
+                    ("knownValues", "examples"),
+                ],
+            );
+        }
+        "bytes" => {
+            schema.insert("type".into(), "string".into());
+            schema.insert("contentEncoding".into(), "base64".into());
+            copy_fields(
+                lex,
+                &mut schema,
+                &[("minLength", "minLength"), ("maxLength", "maxLength")],
+            );
+        }
+        "cid-link" => return with_description(cid_link_schema(), lex),
+        "blob" => {
+            let blob = json!({
+                "type": "object",
+                "required": ["$type", "ref", "mimeType", "size"],
+                "properties": {
+                    "$type": {"const": "blob"},
+                    "ref": cid_link_schema(),
+                    "mimeType": {"type": "string"},
+                    "size": {"type": "integer"},
+                },
+            });
+            return with_description(blob, lex);
+        }
+        "ref" => {
+            let target = str_field(lex, "ref").unwrap_or_default();
+            schema.insert("$ref".into(), component_ref(&ctx.resolve_ref(target)));
+        }
+        "union" => {
+            let refs = field(lex, "refs").and_then(Value::as_array);
+            let members: Vec<Value> = refs
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(|r| json!({ "$ref": component_ref(&ctx.resolve_ref(r)) }))
+                .collect();
+            if !members.is_empty() {
+                schema.insert("oneOf".into(), Value::Array(members));
+            }
+        }
+        "array" => {
+            schema.insert("type".into(), "array".into());
+            if let Some(items) = field(lex, "items") {
+                schema.insert("items".into(), to_json_schema(items, ctx));
+            }
+            copy_fields(
+                lex,
+                &mut schema,
+                &[("minLength", "minItems"), ("maxLength", "maxItems")],
+            );
+        }
+        "object" | "params" => {
+            schema.insert("type".into(), "object".into());
+            let nullable: Vec<&str> = field(lex, "nullable")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .collect();
+
+            let mut properties = Map::new();
+            if let Some(props) = field(lex, "properties").and_then(Value::as_object) {
+                let mut names: Vec<_> = props.keys().collect();
+                names.sort();
+                for name in names {
+                    let mut prop = to_json_schema(&props[name], ctx);
+                    if nullable.contains(&name.as_str()) {
+                        prop = json!({ "anyOf": [prop, {"type": "null"}] });
+                    }
+                    properties.insert(name.clone(), prop);
+                }
+            }
+            schema.insert("properties".into(), Value::Object(properties));
+
+            if let Some(required) = field(lex, "required").and_then(Value::as_array) {
+                if !required.is_empty() {
+                    schema.insert("required".into(), Value::Array(required.clone()));
+                }
+            }
+        }
+        "record" => {
+            if let Some(record) = field(lex, "record") {
+                return with_description(to_json_schema(record, ctx), lex);
+            }
+        }
+        // unknown and anything unrecognized accept any value
+        _ => {}
+    }
+
+    with_description(Value::Object(schema), lex)
+}
+
+/// json schema for a dag-json cid link
+fn cid_link_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["$link"],
+        "properties": {"$link": {"type": "string"}},
+    })
+}
+
+/// map a lexicon string format to its json schema equivalent
+fn string_format(format: &str) -> &str {
+    match format {
+        "datetime" => "date-time",
+        other => other,
+    }
+}
+
+fn component_ref(class_name: &str) -> Value {
+    Value::String(format!("{COMPONENTS_PREFIX}{class_name}"))
+}
+
+/// copy lexicon fields to schema keys, skipping absent ones
+fn copy_fields(lex: &Value, schema: &mut Map<String, Value>, keys: &[(&str, &str)]) {
+    for (from, to) in keys {
+        if let Some(value) = field(lex, from) {
+            schema.insert((*to).into(), value.clone());
+        }
+    }
+}
+
+fn with_description(mut schema: Value, lex: &Value) -> Value {
+    if let (Some(obj), Some(desc)) = (schema.as_object_mut(), field(lex, "description")) {
+        obj.insert("description".into(), desc.clone());
+    }
+    schema
+}
+
+/// the serialized documents an export covers
+pub struct Reachable<'d> {
+    /// every document by nsid, user documents taking precedence over builtins
+    pub lookup: HashMap<&'d str, Value>,
+    /// the user documents under the namespace prefix
+    pub selected: Vec<&'d LexiconDoc>,
+    /// the selected documents' nsids, plus every nsid they reach through refs
+    pub included: BTreeSet<String>,
+    /// class names of the included defs, where they differ from the default
+    pub class_names: ClassNames,
+}
+
+/// the documents under `namespace_prefix` and every document they reach
+pub fn reachable<'d>(
+    docs: &'d [LexiconDoc],
+    builtins: &'d [LexiconDoc],
+    namespace_prefix: Option<&str>,
+) -> Reachable<'d> {
+    // user documents take precedence over builtins
+    let mut lookup: HashMap<&str, Value> = HashMap::new();
+    for doc in builtins.iter().chain(docs) {
+        lookup.insert(&doc.id, serde_json::to_value(doc).unwrap_or(Value::Null));
+    }
+
+    let selected: Vec<&LexiconDoc> = docs
+        .iter()
+        .filter(|doc| namespace_prefix.is_none_or(|p| in_namespace(&doc.id, p)))
+        .collect();
+
+    // collect every document reachable through refs
+    let mut included = BTreeSet::new();
+    let mut pending: Vec<String> = selected.iter().map(|d| d.id.clone()).collect();
+    while let Some(nsid) = pending.pop() {
+        let Some(doc) = lookup.get(nsid.as_str()) else {
+            continue;
+        };
+        if !included.insert(nsid) {
+            continue;
+        }
+        let mut refs = BTreeSet::new();
+        collect_ref_nsids(doc, &mut refs);
+        pending.extend(refs);
+    }
+
+    let mut class_names = ClassNames::new();
+    for nsid in &included {
+        if let Some(defs) = field(&lookup[nsid.as_str()], "defs").and_then(Value::as_object) {
+            class_names.extend(disambiguate_class_names(
+                nsid,
+                defs.keys().map(String::as_str),
+                false,
+            ));
+        }
+    }
+
+    Reachable {
+        lookup,
+        selected,
+        included,
+        class_names,
+    }
+}
+
+/// collect the nsids of all external refs in a serialized lexicon document
+fn collect_ref_nsids(value: &Value, refs: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(obj) => {
+            let targets: Vec<&str> = match str_field(value, "type") {
+                Some("ref") => str_field(value, "ref").into_iter().collect(),
+                Some("union") => field(value, "refs")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .collect(),
+                _ => vec![],
+            };
+            for target in targets {
+                if !target.starts_with('#') {
+                    let nsid = target.split_once('#').map_or(target, |(nsid, _)| nsid);
+                    refs.insert(nsid.to_string());
+                }
+            }
+            for child in obj.values() {
+                collect_ref_nsids(child, refs);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                collect_ref_nsids(item, refs);
+            }
+        }
+        _ => {}
+    }
+}
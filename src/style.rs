@@ -0,0 +1,92 @@
+//! code style of emitted python: indentation and string quotes
+//!
+//! codegen writes black's style (4-space indents, double quotes); a module's
+//! finished source is restyled in one pass before it's written, so every
+//! emitter honors the configured style without threading it through.
+
+use std::borrow::Cow;
+
+use crate::config::{Config, Indent, QuoteStyle};
+
+/// `source` in the indentation and quote style `config` asks for
+pub(crate) fn restyle<'s>(source: &'s str, config: &Config) -> Cow<'s, str> {
+    let mut source = Cow::Borrowed(source);
+    if config.indent == Indent::Tabs {
+        source = Cow::Owned(tab_indent(&source));
+    }
+    if config.quote_style == QuoteStyle::Single {
+        source = Cow::Owned(single_quote(&source));
+    }
+    source
+}
+
+/// each leading run of 4 spaces as a tab
+fn tab_indent(source: &str) -> String {
+    source
+        .split_inclusive('\n')
+        .map(|line| {
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            format!(
+                "{}{}{}",
+                "\t".repeat(spaces / 4),
+                " ".repeat(spaces % 4),
+                &line[spaces..]
+            )
+        })
+        .collect()
+}
+
+/// double-quoted single-line strings as single-quoted ones
+///
+/// a string holding a single quote or a backslash keeps its double quotes,
+/// so no escape has to change; so do triple-quoted strings (docstrings),
+/// which stay `"""` as pep 257 has them. comments are left alone.
+fn single_quote(source: &str) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let mut out = String::with_capacity(source.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    out.push(chars[i]);
+                    i += 1;
+                }
+            }
+            quote @ ('"' | '\'') => {
+                let triple = chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote);
+                let end = string_end(&chars, i, quote, triple);
+                let literal: String = chars[i..end].iter().collect();
+                match literal.strip_prefix('"').and_then(|l| l.strip_suffix('"')) {
+                    Some(body) if !triple && !body.contains(['\'', '\\']) => {
+                        out.push('\'');
+                        out.push_str(body);
+                        out.push('\'');
+                    }
+                    _ => out.push_str(&literal),
+                }
+                i = end;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// index just past the string literal opening at `start`
+fn string_end(chars: &[char], start: usize, quote: char, triple: bool) -> usize {
+    let mut i = start + if triple { 3 } else { 1 };
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            c if c == quote && !triple => return i + 1,
+            c if c == quote && chars[i..].starts_with(&[quote, quote, quote]) => return i + 3,
+            '\n' if !triple => return i,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
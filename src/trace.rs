@@ -0,0 +1,131 @@
+//! diagnostic events for the codegen pipeline
+//!
+//! the `debug!`/`info!`/`warn!` macros forward to `tracing` when the `tracing`
+//! feature is enabled and compile to nothing otherwise. with the `python`
+//! feature, events can also be forwarded to python's `logging`.
+
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg:tt)*) => { tracing::debug!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! info {
+    ($($arg:tt)*) => { tracing::info!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! warn {
+    ($($arg:tt)*) => { tracing::warn!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(all(feature = "tracing", feature = "python"))]
+mod bridge {
+    use std::fmt::{self, Write};
+
+    use pyo3::prelude::*;
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    /// forwards tracing events to python's `logging.getLogger("pmgfal")`
+    struct PyLoggingLayer {
+        max_level: Level,
+    }
+
+    impl<S: Subscriber> Layer<S> for PyLoggingLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let level = *event.metadata().level();
+            if level > self.max_level {
+                return;
+            }
+
+            let mut visitor = MessageVisitor::default();
+            event.record(&mut visitor);
+
+            Python::attach(|py| {
+                // logging failures must never abort codegen
+                let _ = py
+                    .import("logging")
+                    .and_then(|logging| logging.call_method1("getLogger", ("pmgfal",)))
+                    .and_then(|logger| {
+                        logger.call_method1("log", (python_level(level), visitor.message))
+                    });
+            });
+        }
+    }
+
+    #[derive(Default)]
+    struct MessageVisitor {
+        message: String,
+    }
+
+    impl Visit for MessageVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.message, "{value:?}");
+            } else {
+                let _ = write!(self.message, " {}={value:?}", field.name());
+            }
+        }
+    }
+
+    /// map a tracing level to the matching python logging level number
+    fn python_level(level: Level) -> u8 {
+        match level {
+            Level::ERROR => 40,
+            Level::WARN => 30,
+            Level::INFO => 20,
+            Level::DEBUG => 10,
+            Level::TRACE => 5,
+        }
+    }
+
+    /// run `f` with tracing events at or above `level` routed to python logging
+    pub fn with_python_logging<T>(level: &str, f: impl FnOnce() -> T) -> PyResult<T> {
+        let max_level: Level = level.parse().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("invalid log level: {level}"))
+        })?;
+
+        let subscriber = tracing_subscriber::registry().with(PyLoggingLayer { max_level });
+        Ok(tracing::subscriber::with_default(subscriber, f))
+    }
+}
+
+#[cfg(all(feature = "tracing", feature = "python"))]
+pub use bridge::with_python_logging;
+
+/// without the `tracing` feature there is nothing to forward
+#[cfg(all(not(feature = "tracing"), feature = "python"))]
+pub fn with_python_logging<T>(_level: &str, _f: impl FnOnce() -> T) -> pyo3::PyResult<T> {
+    Err(pyo3::PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(
+        "log_level requires pmgfal to be built with the `tracing` feature",
+    ))
+}
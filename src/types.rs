@@ -1,22 +1,101 @@
 //! type conversion from lexicon types to python type annotations
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use atrium_lex::lexicon::{
-    LexArrayItem, LexObject, LexObjectProperty, LexRecord, LexRef, LexRefUnion, LexUserType,
+    LexArrayItem, LexBlob, LexBytes, LexInteger, LexObject, LexObjectProperty, LexRecord, LexRef,
+    LexRefUnion, LexString, LexUserType,
 };
 use atrium_lex::LexiconDoc;
 use heck::ToPascalCase;
+use serde::Serialize;
+
+use crate::codegen::python_string;
+
+/// class names that differ from `to_class_name`, keyed by `nsid#defName`
+pub type ClassNames = HashMap<String, String>;
+
+/// names of union shapes shared by several fields, keyed by `union_shape`
+pub type UnionAliases = HashMap<String, String>;
 
 /// context for resolving refs within a document
+#[derive(Clone, Copy)]
 pub struct RefContext<'a> {
     /// nsid of the current document (e.g., "fm.plyr.track")
     pub nsid: &'a str,
+    /// disambiguated names for defs whose class names collide
+    pub class_names: Option<&'a ClassNames>,
+    /// aliases rendered in place of the unions they name
+    pub union_aliases: Option<&'a UnionAliases>,
+    /// render `cid-link` and `bytes` as the `CidLink`/`Bytes` helpers, which
+    /// also accept dag-json's `{"$link": ...}`/`{"$bytes": ...}` objects
+    pub dag_json: bool,
+    /// render `datetime`-format strings as datetimes rather than `str`: the
+    /// `Datetime` helper, which dumps back to atproto's form, when
+    /// `dag_json` is set too, otherwise plain `datetime`
+    pub datetimes: bool,
+    /// alias rendered for `unknown` fields that hold a record, see
+    /// `record_union_for_unknown`
+    pub record_union: Option<&'a str>,
 }
 
 impl<'a> RefContext<'a> {
     pub fn new(nsid: &'a str) -> Self {
-        Self { nsid }
+        Self {
+            nsid,
+            class_names: None,
+            union_aliases: None,
+            dag_json: false,
+            datetimes: false,
+            record_union: None,
+        }
+    }
+
+    pub fn with_class_names(nsid: &'a str, class_names: &'a ClassNames) -> Self {
+        Self {
+            nsid,
+            class_names: Some(class_names),
+            union_aliases: None,
+            dag_json: false,
+            datetimes: false,
+            record_union: None,
+        }
+    }
+
+    /// this context, rendering unions named in `aliases` by their alias
+    pub fn with_union_aliases(self, aliases: &'a UnionAliases) -> Self {
+        Self {
+            union_aliases: Some(aliases),
+            ..self
+        }
+    }
+
+    /// this context, rendering `cid-link` and `bytes` as dag-json aware
+    /// helpers when `dag_json` is set
+    pub fn with_dag_json(self, dag_json: bool) -> Self {
+        Self { dag_json, ..self }
+    }
+
+    /// this context, rendering `datetime`-format strings as datetimes when
+    /// `datetimes` is set
+    pub fn with_datetimes(self, datetimes: bool) -> Self {
+        Self { datetimes, ..self }
+    }
+
+    /// this context, typing `unknown` record slots as `record_union`
+    pub fn with_record_union(self, record_union: Option<&'a str>) -> Self {
+        Self {
+            record_union,
+            ..self
+        }
+    }
+
+    /// python class name for a def, honoring disambiguated names
+    pub fn class_name(&self, nsid: &str, def_name: &str) -> String {
+        self.class_names
+            .and_then(|names| names.get(&format!("{nsid}#{def_name}")))
+            .cloned()
+            .unwrap_or_else(|| to_class_name(nsid, def_name))
     }
 
     /// resolve a ref string to a python class name
@@ -25,28 +104,56 @@ impl<'a> RefContext<'a> {
     /// - `com.example.foo` -> external nsid main def
     /// - `com.example.foo#bar` -> external nsid specific def
     pub fn resolve_ref(&self, ref_str: &str) -> String {
-        if let Some(local_name) = ref_str.strip_prefix('#') {
+        let class_name = if let Some(local_name) = ref_str.strip_prefix('#') {
             // local ref within same document
-            to_class_name(self.nsid, local_name)
+            self.class_name(self.nsid, local_name)
         } else if let Some((nsid, def_name)) = ref_str.split_once('#') {
             // external ref with specific def
-            to_class_name(nsid, def_name)
+            self.class_name(nsid, def_name)
         } else {
             // external ref to main def
-            to_class_name(ref_str, "main")
+            self.class_name(ref_str, "main")
+        };
+        debug!("{}: resolved ref {ref_str} -> {class_name}", self.nsid);
+        class_name
+    }
+
+    /// make a ref absolute (`#localDef` -> `nsid#localDef`)
+    pub fn qualify_ref(&self, ref_str: &str) -> String {
+        match ref_str.strip_prefix('#') {
+            Some(local_name) => format!("{}#{local_name}", self.nsid),
+            None => ref_str.to_string(),
         }
     }
 }
 
+/// a non-model top-level def (string, array, ...) as the equivalent property
+///
+/// `None` for records, objects, xrpc methods and tokens.
+pub fn def_as_property(def: &LexUserType) -> Option<LexObjectProperty> {
+    let prop = match def {
+        LexUserType::Boolean(b) => LexObjectProperty::Boolean(b.clone()),
+        LexUserType::Integer(i) => LexObjectProperty::Integer(i.clone()),
+        LexUserType::String(s) => LexObjectProperty::String(s.clone()),
+        LexUserType::Bytes(b) => LexObjectProperty::Bytes(b.clone()),
+        LexUserType::CidLink(c) => LexObjectProperty::CidLink(c.clone()),
+        LexUserType::Blob(b) => LexObjectProperty::Blob(b.clone()),
+        LexUserType::Unknown(u) => LexObjectProperty::Unknown(u.clone()),
+        LexUserType::Array(a) => LexObjectProperty::Array(a.clone()),
+        _ => return None,
+    };
+    Some(prop)
+}
+
 /// convert lexicon property to python type annotation
 pub fn property_to_python(prop: &LexObjectProperty, ctx: &RefContext) -> String {
     match prop {
         LexObjectProperty::Boolean(_) => "bool".into(),
         LexObjectProperty::Integer(_) => "int".into(),
-        LexObjectProperty::String(_) => "str".into(),
-        LexObjectProperty::Bytes(_) => "bytes".into(),
-        LexObjectProperty::CidLink(_) => "str".into(),
-        LexObjectProperty::Blob(_) => "dict[str, Any]".into(),
+        LexObjectProperty::String(s) => string_to_python(s, ctx),
+        LexObjectProperty::Bytes(b) => bytes_to_python(b, ctx),
+        LexObjectProperty::CidLink(_) => cid_link_to_python(ctx),
+        LexObjectProperty::Blob(_) => "BlobRef".into(),
         LexObjectProperty::Unknown(_) => "Any".into(),
         LexObjectProperty::Ref(r) => ref_to_python(r, ctx),
         LexObjectProperty::Union(u) => union_to_python(u, ctx),
@@ -57,6 +164,42 @@ pub fn property_to_python(prop: &LexObjectProperty, ctx: &RefContext) -> String
     }
 }
 
+/// convert a string to python type, a datetime for the `datetime` format
+/// when the context asks for it
+fn string_to_python(s: &LexString, ctx: &RefContext) -> String {
+    let is_datetime = s.format.as_ref().and_then(serde_name).as_deref() == Some("datetime");
+    match (ctx.datetimes && is_datetime, ctx.dag_json) {
+        (true, true) => "Datetime".into(),
+        (true, false) => "datetime".into(),
+        (false, _) => "str".into(),
+    }
+}
+
+/// convert bytes to python type, constraining its length when bounded
+fn bytes_to_python(b: &LexBytes, ctx: &RefContext) -> String {
+    let bytes = if ctx.dag_json { "Bytes" } else { "bytes" };
+    let mut bounds = Vec::new();
+    if let Some(min) = b.min_length {
+        bounds.push(format!("min_length={min}"));
+    }
+    if let Some(max) = b.max_length {
+        bounds.push(format!("max_length={max}"));
+    }
+    if bounds.is_empty() {
+        return bytes.into();
+    }
+    format!("Annotated[{bytes}, Field({})]", bounds.join(", "))
+}
+
+/// convert a cid-link to python type
+fn cid_link_to_python(ctx: &RefContext) -> String {
+    if ctx.dag_json {
+        "CidLink".into()
+    } else {
+        "str".into()
+    }
+}
+
 /// convert a ref to python type
 fn ref_to_python(r: &LexRef, ctx: &RefContext) -> String {
     ctx.resolve_ref(&r.r#ref)
@@ -67,28 +210,474 @@ fn union_to_python(u: &LexRefUnion, ctx: &RefContext) -> String {
     if u.refs.is_empty() {
         return "Any".into();
     }
+    let types = union_members(u, ctx);
+    if let Some(alias) = ctx
+        .union_aliases
+        .and_then(|aliases| aliases.get(&union_shape(&types)))
+    {
+        return alias.clone();
+    }
+    types.join(" | ")
+}
+
+/// class names of a union's members, in lexicon order
+pub fn union_members(u: &LexRefUnion, ctx: &RefContext) -> Vec<String> {
+    // refs spelled differently (`#a`, `nsid#a`) can resolve to one class
+    let mut types: Vec<String> = Vec::new();
+    for class_name in u.refs.iter().map(|r| ctx.resolve_ref(r)) {
+        if !types.contains(&class_name) {
+            types.push(class_name);
+        }
+    }
+    types
+}
+
+/// key identifying a union by its member set, whatever their order
+pub fn union_shape(members: &[String]) -> String {
+    let mut members = members.to_vec();
+    members.sort();
+    members.join(" | ")
+}
+
+/// the type of a non-required field: `py_type` with `None` as a union member
+///
+/// members are flat (`A | B | None`, never `(A | B) | None`), and a type that
+/// already admits `None` is returned unchanged.
+pub fn optional_type(py_type: &str) -> String {
+    if top_level_members(py_type).contains(&"None") {
+        return py_type.to_string();
+    }
+    format!("{py_type} | None")
+}
 
-    let types: Vec<String> = u.refs.iter().map(|r| ctx.resolve_ref(r)).collect();
+/// the members of a union annotation, ignoring `|` nested in brackets
+fn top_level_members(py_type: &str) -> Vec<&str> {
+    split_top_level(py_type, '|')
+}
 
-    if types.len() == 1 {
-        types.into_iter().next().unwrap()
+/// rewrite a pep 604 annotation with `typing.Optional` and `typing.Union`
+///
+/// `A | B | None` becomes `Optional[Union[A, B]]`, at any depth
+/// (`list[A | None]` -> `list[Optional[A]]`). `|` inside string literals,
+/// as in `Literal["a|b"]`, is left alone, as is any part without a union.
+pub fn typing_syntax(py_type: &str) -> String {
+    if !unquoted_chars(py_type).iter().any(|&(_, c, _)| c == '|') {
+        return py_type.to_string();
+    }
+    let mut members: Vec<String> = top_level_members(py_type)
+        .into_iter()
+        .map(typing_member)
+        .collect();
+    if members.len() == 1 {
+        return members.remove(0);
+    }
+    let optional = members.iter().any(|m| m == "None");
+    members.retain(|m| m != "None");
+    let core = match members.as_slice() {
+        [single] => single.clone(),
+        _ => format!("Union[{}]", members.join(", ")),
+    };
+    if optional {
+        format!("Optional[{core}]")
     } else {
-        types.join(" | ")
+        core
+    }
+}
+
+/// spell builtin generics with their `typing` aliases (`list[X]` ->
+/// `List[X]`, `dict[K, V]` -> `Dict[K, V]`, `tuple[...]` -> `Tuple[...]`),
+/// leaving string literals alone
+pub fn typing_generics(py_type: &str) -> String {
+    let mut output = String::with_capacity(py_type.len());
+    let mut last = 0;
+    let mut previous = None;
+    for (i, c, _) in unquoted_chars(py_type) {
+        let word_start = !previous.is_some_and(|p: char| p.is_alphanumeric() || p == '_');
+        previous = Some(c);
+        if !word_start {
+            continue;
+        }
+        for (builtin, alias) in [("list[", "List["), ("dict[", "Dict["), ("tuple[", "Tuple[")] {
+            if py_type[i..].starts_with(builtin) {
+                output.push_str(&py_type[last..i]);
+                output.push_str(alias);
+                last = i + builtin.len();
+            }
+        }
+    }
+    output.push_str(&py_type[last..]);
+    output
+}
+
+/// `typing_syntax` applied inside each bracketed or called group of a member
+fn typing_member(member: &str) -> String {
+    let mut output = String::new();
+    let mut rest = member;
+    while let Some((open, close)) = first_group(rest) {
+        output.push_str(&rest[..=open]);
+        let items: Vec<String> = split_top_level(&rest[open + 1..close], ',')
+            .into_iter()
+            .map(typing_syntax)
+            .collect();
+        output.push_str(&items.join(", "));
+        output.push_str(&rest[close..=close]);
+        rest = &rest[close + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// split on `sep` outside brackets and string literals, trimming each part
+pub fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c, depth) in unquoted_chars(s) {
+        if c == sep && depth == 0 {
+            parts.push(s[start..i].trim());
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(s[start..].trim());
+    parts
+}
+
+/// byte offsets of the first outermost bracket pair in `s`
+pub fn first_group(s: &str) -> Option<(usize, usize)> {
+    let mut open = None;
+    for (i, c, depth) in unquoted_chars(s) {
+        match c {
+            '[' | '(' | '{' if depth == 0 && open.is_none() => open = Some(i),
+            ']' | ')' | '}' if depth == 0 => return open.map(|o| (o, i)),
+            _ => {}
+        }
     }
+    None
+}
+
+/// characters of a python expression outside string literals, each with
+/// the bracket depth it sits at (a bracket counts as outside its own pair)
+pub(crate) fn unquoted_chars(s: &str) -> Vec<(usize, char, usize)> {
+    let mut chars = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '[' | '(' | '{' => {
+                chars.push((i, c, depth));
+                depth += 1;
+            }
+            ']' | ')' | '}' => {
+                depth = depth.saturating_sub(1);
+                chars.push((i, c, depth));
+            }
+            _ => chars.push((i, c, depth)),
+        }
+    }
+    chars
 }
 
 /// convert array item type to python
-fn array_item_to_python(item: &LexArrayItem, ctx: &RefContext) -> String {
+///
+/// items are typed as the same property on its own would be, so an array of
+/// blobs or cid-links gets the `BlobRef` and `CidLink` helpers too.
+pub fn array_item_to_python(item: &LexArrayItem, ctx: &RefContext) -> String {
+    property_to_python(&item_as_property(item), ctx)
+}
+
+/// an array item as the equivalent property
+fn item_as_property(item: &LexArrayItem) -> LexObjectProperty {
     match item {
-        LexArrayItem::Boolean(_) => "bool".into(),
-        LexArrayItem::Integer(_) => "int".into(),
-        LexArrayItem::String(_) => "str".into(),
-        LexArrayItem::Bytes(_) => "bytes".into(),
-        LexArrayItem::CidLink(_) => "str".into(),
-        LexArrayItem::Blob(_) => "dict[str, Any]".into(),
-        LexArrayItem::Unknown(_) => "Any".into(),
-        LexArrayItem::Ref(r) => ref_to_python(r, ctx),
-        LexArrayItem::Union(u) => union_to_python(u, ctx),
+        LexArrayItem::Boolean(b) => LexObjectProperty::Boolean(b.clone()),
+        LexArrayItem::Integer(i) => LexObjectProperty::Integer(i.clone()),
+        LexArrayItem::String(s) => LexObjectProperty::String(s.clone()),
+        LexArrayItem::Bytes(b) => LexObjectProperty::Bytes(b.clone()),
+        LexArrayItem::CidLink(c) => LexObjectProperty::CidLink(c.clone()),
+        LexArrayItem::Blob(b) => LexObjectProperty::Blob(b.clone()),
+        LexArrayItem::Unknown(u) => LexObjectProperty::Unknown(u.clone()),
+        LexArrayItem::Ref(r) => LexObjectProperty::Ref(r.clone()),
+        LexArrayItem::Union(u) => LexObjectProperty::Union(u.clone()),
+    }
+}
+
+/// enumerate a bounded integer property as a `Literal[...]` type
+///
+/// applies to integers (and arrays of integers) with both `minimum` and
+/// `maximum` set, as long as the range holds at most `max_range` values.
+pub fn integer_literal(prop: &LexObjectProperty, max_range: usize) -> Option<String> {
+    match prop {
+        LexObjectProperty::Integer(i) => int_range_literal(i, max_range),
+        LexObjectProperty::Array(arr) => match &arr.items {
+            LexArrayItem::Integer(i) => {
+                int_range_literal(i, max_range).map(|lit| format!("list[{lit}]"))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn int_range_literal(i: &LexInteger, max_range: usize) -> Option<String> {
+    let (min, max) = (i.minimum?, i.maximum?);
+    let span = max.checked_sub(min)?;
+    if span < 0 || span as u64 >= max_range as u64 {
+        return None;
+    }
+    let values: Vec<String> = (min..=max).map(|v| v.to_string()).collect();
+    Some(format!("Literal[{}]", values.join(", ")))
+}
+
+/// an integer property's `minimum`/`maximum` as pydantic bounds
+///
+/// `Annotated[int, Field(ge=0)]` for an integer with `minimum: 0`, or a list
+/// of those for an array of integers. lexicon integers are 64-bit, which
+/// python's `int` holds anyway, so the bounds are what's left to enforce;
+/// either one is emitted on its own, so unsigned counters and sizes
+/// (`minimum: 0`, no maximum) still reject negatives.
+pub fn bounded_integer(prop: &LexObjectProperty) -> Option<String> {
+    match prop {
+        LexObjectProperty::Integer(i) => int_bounds(i),
+        LexObjectProperty::Array(arr) => match &arr.items {
+            LexArrayItem::Integer(i) => int_bounds(i).map(|ty| format!("list[{ty}]")),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn int_bounds(i: &LexInteger) -> Option<String> {
+    let bounds: Vec<String> = [("ge", i.minimum), ("le", i.maximum)]
+        .into_iter()
+        .filter_map(|(arg, value)| Some(format!("{arg}={}", value?)))
+        .collect();
+    if bounds.is_empty() {
+        return None;
+    }
+    Some(format!("Annotated[int, Field({})]", bounds.join(", ")))
+}
+
+/// why an integer property's (or array of integers') `default` breaks its
+/// own `enum`, `const`, `minimum` or `maximum`, if it does
+pub fn integer_default_problem(prop: &LexObjectProperty) -> Option<String> {
+    let i = match prop {
+        LexObjectProperty::Integer(i) => i,
+        LexObjectProperty::Array(arr) => match &arr.items {
+            LexArrayItem::Integer(i) => i,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let default = i.default?;
+    if let Some(values) = i
+        .r#enum
+        .as_ref()
+        .filter(|values| !values.contains(&default))
+    {
+        return Some(format!("default {default} is not in its enum {values:?}"));
+    }
+    if let Some(value) = i.r#const.filter(|value| *value != default) {
+        return Some(format!("default {default} is not its const {value}"));
+    }
+    if let Some(min) = i.minimum.filter(|min| default < *min) {
+        return Some(format!("default {default} is below its minimum {min}"));
+    }
+    if let Some(max) = i.maximum.filter(|max| default > *max) {
+        return Some(format!("default {default} is above its maximum {max}"));
+    }
+    None
+}
+
+/// how a blob's `accept` list narrows its `mimeType`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MimeConstraint {
+    /// only these exact mime types
+    Literal(Vec<String>),
+    /// a regex, for lists containing wildcard families like `image/*`
+    Pattern(String),
+}
+
+/// the `mimeType` constraint of a blob property (or array of blobs)
+///
+/// `None` when there is no `accept` list or it admits any type (`*/*`).
+pub fn blob_mime_constraint(prop: &LexObjectProperty) -> Option<MimeConstraint> {
+    let blob: &LexBlob = match prop {
+        LexObjectProperty::Blob(b) => b,
+        LexObjectProperty::Array(arr) => match &arr.items {
+            LexArrayItem::Blob(b) => b,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let accept = blob.accept.as_ref().filter(|a| !a.is_empty())?;
+    if accept.iter().any(|m| m == "*/*" || m == "*") {
+        return None;
+    }
+    if accept.iter().all(|m| !m.ends_with("/*")) {
+        return Some(MimeConstraint::Literal(accept.clone()));
+    }
+
+    let alternatives: Vec<String> = accept
+        .iter()
+        .map(|m| match m.strip_suffix("/*") {
+            Some(family) => format!("{}/[^/]+", escape_regex(family)),
+            None => escape_regex(m),
+        })
+        .collect();
+    Some(MimeConstraint::Pattern(format!(
+        "^(?:{})$",
+        alternatives.join("|")
+    )))
+}
+
+fn escape_regex(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '.' | '+' | '*' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '^' | '$' | '\\' => {
+                vec!['\\', c]
+            }
+            _ => vec![c],
+        })
+        .collect()
+}
+
+/// describe where a property's type comes from in its source lexicon
+///
+/// returns a `LexiconMeta(...)` expression for refs, unions and formatted
+/// strings, looking through arrays to their items.
+pub fn property_meta(prop: &LexObjectProperty, ctx: &RefContext) -> Option<String> {
+    match prop {
+        LexObjectProperty::Ref(r) => Some(ref_meta(r, ctx)),
+        LexObjectProperty::Union(u) => Some(union_meta(u, ctx)),
+        LexObjectProperty::String(s) => string_meta(s),
+        LexObjectProperty::Array(arr) => match &arr.items {
+            LexArrayItem::Ref(r) => Some(ref_meta(r, ctx)),
+            LexArrayItem::Union(u) => Some(union_meta(u, ctx)),
+            LexArrayItem::String(s) => string_meta(s),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn ref_meta(r: &LexRef, ctx: &RefContext) -> String {
+    format!(
+        "LexiconMeta(ref={})",
+        python_string(&ctx.qualify_ref(&r.r#ref))
+    )
+}
+
+fn union_meta(u: &LexRefUnion, ctx: &RefContext) -> String {
+    let refs: Vec<String> = u
+        .refs
+        .iter()
+        .map(|r| format!("{}, ", python_string(&ctx.qualify_ref(r))))
+        .collect();
+    format!("LexiconMeta(refs=({}))", refs.concat().trim_end())
+}
+
+fn string_meta(s: &LexString) -> Option<String> {
+    let format = serde_name(s.format.as_ref()?)?;
+    Some(format!("LexiconMeta(format=\"{format}\")"))
+}
+
+/// lexicon constraints pydantic can't enforce, as `json_schema_extra` entries
+///
+/// string `minLength`/`maxLength` count utf-8 bytes and the grapheme bounds
+/// count user-perceived characters, neither of which matches pydantic's
+/// `max_length`.
+pub fn schema_constraints(prop: &LexObjectProperty) -> Vec<String> {
+    let LexObjectProperty::String(s) = prop else {
+        return Vec::new();
+    };
+    [
+        ("minLength", s.min_length),
+        ("maxLength", s.max_length),
+        ("minGraphemes", s.min_graphemes),
+        ("maxGraphemes", s.max_graphemes),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some(format!("\"{key}\": {}", value?)))
+    .collect()
+}
+
+/// accepted `$type` values of a closed union property (or array of one)
+///
+/// `None` for open unions and other types. `#main` refs are spelled as the
+/// bare nsid, the way records carry them.
+pub fn closed_union_types(prop: &LexObjectProperty, ctx: &RefContext) -> Option<Vec<String>> {
+    let members = closed_union_members(prop, ctx)?;
+    Some(members.into_iter().map(|(type_, _)| type_).collect())
+}
+
+/// `($type, class name)` of each member of a closed union property (or array
+/// of one), deduplicated and sorted by `$type`
+///
+/// the sort pins the order of adapter members and invariant checks whatever
+/// order the lexicon lists refs in; the field annotation keeps that order.
+pub fn closed_union_members(
+    prop: &LexObjectProperty,
+    ctx: &RefContext,
+) -> Option<Vec<(String, String)>> {
+    let union = match prop {
+        LexObjectProperty::Union(u) => u,
+        LexObjectProperty::Array(arr) => match &arr.items {
+            LexArrayItem::Union(u) => u,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if union.closed != Some(true) {
+        return None;
+    }
+    let mut members: Vec<(String, String)> = Vec::new();
+    for r in &union.refs {
+        let absolute = ctx.qualify_ref(r);
+        let type_ = match absolute.strip_suffix("#main") {
+            Some(nsid) => nsid.to_string(),
+            None => absolute,
+        };
+        if !members.iter().any(|(t, _)| *t == type_) {
+            members.push((type_, ctx.resolve_ref(r)));
+        }
+    }
+    members.sort();
+    Some(members)
+}
+
+/// the description of a property, whatever its type
+pub fn property_description(prop: &LexObjectProperty) -> Option<String> {
+    let value = serde_json::to_value(prop).ok()?;
+    value.get("description")?.as_str().map(str::to_string)
+}
+
+/// whether a description marks its field as deprecated
+///
+/// atproto lexicons have no deprecation flag; by convention the description
+/// starts with "DEPRECATED" (e.g. `"DEPRECATED: use #identity instead"`).
+pub fn is_deprecated(description: Option<&str>) -> bool {
+    description.is_some_and(|d| {
+        d.trim_start()
+            .get(..10)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("deprecated"))
+    })
+}
+
+/// the lexicon spelling of a serde-named enum value (e.g. `"at-uri"`)
+pub fn serde_name<T: Serialize>(value: &T) -> Option<String> {
+    match serde_json::to_value(value).ok()? {
+        serde_json::Value::String(name) => Some(name),
+        _ => None,
     }
 }
 
@@ -101,21 +690,73 @@ pub fn to_class_name(nsid: &str, def_name: &str) -> String {
     parts.iter().map(|p| p.to_pascal_case()).collect()
 }
 
+/// `to_class_name`, keeping the case of every letter but each word's first
+///
+/// words are only split at `_` and `-`, so acronyms survive: `URLPreview`
+/// stays `URLPreview` where `to_class_name` makes it `UrlPreview`.
+pub fn to_class_name_keeping_case(nsid: &str, def_name: &str) -> String {
+    let mut parts: Vec<&str> = nsid.split('.').collect();
+    if def_name != "main" {
+        parts.push(def_name);
+    }
+    parts
+        .iter()
+        .flat_map(|p| p.split(['_', '-']))
+        .map(capitalize)
+        .collect()
+}
+
+/// `word` with its first letter upper-cased
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// class names for the defs of one document, where they differ from
+/// `to_class_name`
+///
+/// with `keep_case`, every def is named by `to_class_name_keeping_case`.
+/// defs whose names still pascal-case alike (`myDef` and `my_def` both
+/// become `MyDef`) are disambiguated: the first def in key order keeps the
+/// plain class name and the rest get their raw def key appended
+/// (`FooMyDef_my_def`).
+pub fn disambiguate_class_names<'d>(
+    nsid: &str,
+    def_names: impl IntoIterator<Item = &'d str>,
+    keep_case: bool,
+) -> ClassNames {
+    let mut def_names: Vec<&str> = def_names.into_iter().collect();
+    def_names.sort_unstable();
+
+    let mut taken = HashSet::new();
+    let mut renamed = ClassNames::new();
+    for def_name in def_names {
+        let default = to_class_name(nsid, def_name);
+        let class_name = if keep_case {
+            to_class_name_keeping_case(nsid, def_name)
+        } else {
+            default.clone()
+        };
+        if !taken.insert(class_name.clone()) {
+            let unique = format!("{class_name}_{def_name}");
+            info!("{nsid}: def {def_name} collides with {class_name}, using {unique}");
+            renamed.insert(format!("{nsid}#{def_name}"), unique);
+        } else if class_name != default {
+            renamed.insert(format!("{nsid}#{def_name}"), class_name);
+        }
+    }
+    renamed
+}
+
 /// collect all external ref nsids from a document
 pub fn collect_external_refs(doc: &LexiconDoc) -> HashSet<String> {
     let mut refs = HashSet::new();
 
     for def in doc.defs.values() {
-        match def {
-            LexUserType::Record(LexRecord { record, .. }) => {
-                let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
-                collect_refs_from_object(obj, &mut refs);
-            }
-            LexUserType::Object(obj) => {
-                collect_refs_from_object(obj, &mut refs);
-            }
-            _ => {}
-        }
+        refs.extend(collect_def_refs(def));
     }
 
     // filter to only external refs (not starting with #)
@@ -128,6 +769,27 @@ pub fn collect_external_refs(doc: &LexiconDoc) -> HashSet<String> {
         .collect()
 }
 
+/// collect all refs (local and external) used by a single def
+pub fn collect_def_refs(def: &LexUserType) -> HashSet<String> {
+    let mut refs = HashSet::new();
+
+    match def {
+        LexUserType::Record(LexRecord { record, .. }) => {
+            let atrium_lex::lexicon::LexRecordRecord::Object(obj) = record;
+            collect_refs_from_object(obj, &mut refs);
+        }
+        LexUserType::Object(obj) => {
+            collect_refs_from_object(obj, &mut refs);
+        }
+        LexUserType::Array(arr) => {
+            collect_refs_from_array_item(&arr.items, &mut refs);
+        }
+        _ => {}
+    }
+
+    refs
+}
+
 fn collect_refs_from_object(obj: &LexObject, refs: &mut HashSet<String>) {
     for prop in obj.properties.values() {
         collect_refs_from_property(prop, refs);
@@ -0,0 +1,147 @@
+"""black-box tests for lexicon linting."""
+
+import json
+import tempfile
+from pathlib import Path
+
+import pytest
+
+TRACK = {
+    "lexicon": 1,
+    "id": "fm.plyr.track",
+    "defs": {
+        "main": {
+            "type": "record",
+            "record": {
+                "type": "object",
+                "properties": {
+                    "subject": {"type": "ref", "ref": "com.atproto.repo.strongRef"},
+                    "artist": {"type": "ref", "ref": "#artist"},
+                },
+            },
+        },
+        "artist": {"type": "object", "properties": {"name": {"type": "string"}}},
+    },
+}
+
+
+class TestCheck:
+    """test linting a lexicon directory."""
+
+    def test_clean_directory(self):
+        """valid lexicons whose refs resolve have no problems."""
+        from pmgfal import check_lexicons
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            (Path(tmpdir) / "track.json").write_text(json.dumps(TRACK))
+            (Path(tmpdir) / "package.json").write_text(json.dumps({"name": "x"}))
+
+            assert check_lexicons(tmpdir) == []
+            assert not list(Path(tmpdir).glob("*.py"))
+
+    def test_every_problem_is_reported(self):
+        """one bad file doesn't stop the others from being checked."""
+        from pmgfal import check_lexicons
+
+        track = json.loads(json.dumps(TRACK))
+        properties = track["defs"]["main"]["record"]["properties"]
+        properties["album"] = {"type": "ref", "ref": "fm.plyr.album"}
+        properties["cover"] = {"type": "ref", "ref": "#cover"}
+        properties["embed"] = {"type": "union", "refs": []}
+        properties["plays"] = {"type": "integer", "minimum": 0, "default": -1}
+
+        files = {
+            "a_track.json": json.dumps(track),
+            "b_dup.json": json.dumps(TRACK),
+            "bad_id.json": json.dumps({"lexicon": 1, "id": "fm.plyr", "defs": {}}),
+            "broken.json": '{"lexicon": 1,',
+            "invalid.json": json.dumps(
+                {"lexicon": 1, "id": "fm.plyr.invalid", "defs": 3}
+            ),
+        }
+        with tempfile.TemporaryDirectory() as tmpdir:
+            for name, content in files.items():
+                (Path(tmpdir) / name).write_text(content)
+
+            problems = check_lexicons(tmpdir)
+
+        found = {}
+        for problem in problems:
+            found.setdefault(Path(problem.path).name, []).append(problem)
+        assert sorted(found) == sorted(files)
+
+        assert [p.message for p in found["a_track.json"]] == [
+            "fm.plyr.track#main.embed: union has no members",
+            "fm.plyr.track#main: unresolved ref #cover",
+            "fm.plyr.track#main: unresolved ref fm.plyr.album",
+            "fm.plyr.track#main.plays: default -1 is below its minimum 0",
+        ]
+        assert {p.nsid for p in found["a_track.json"]} == {"fm.plyr.track"}
+
+        [dup] = found["b_dup.json"]
+        assert dup.nsid == "fm.plyr.track"
+        assert "duplicate nsid" in dup.message
+        assert "a_track.json" in dup.message
+
+        [bad_id] = found["bad_id.json"]
+        assert bad_id.message == 'invalid nsid "fm.plyr"'
+
+        [broken] = found["broken.json"]
+        assert broken.nsid is None
+        assert broken.message.startswith("malformed json")
+
+        [invalid] = found["invalid.json"]
+        assert invalid.nsid == "fm.plyr.invalid"
+        assert invalid.message.startswith("invalid lexicon")
+
+    def test_builtins_resolve_refs(self):
+        """refs into com.atproto fail the check without builtins."""
+        from pmgfal import check_lexicons
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            (Path(tmpdir) / "track.json").write_text(json.dumps(TRACK))
+
+            [problem] = check_lexicons(tmpdir, use_builtins=False)
+            assert problem.message == (
+                "fm.plyr.track#main: unresolved ref com.atproto.repo.strongRef"
+            )
+
+    def test_strict_keys(self):
+        """keys the lexicon language doesn't define are reported by path."""
+        from pmgfal import check_lexicons
+
+        track = json.loads(json.dumps(TRACK))
+        track["lexicoin"] = 1
+        artist = track["defs"]["artist"]
+        artist["examples"] = [{"name": "nate"}]
+        artist["properties"]["name"].update({"title": "Name", "maxLenght": 64})
+        with tempfile.TemporaryDirectory() as tmpdir:
+            (Path(tmpdir) / "track.json").write_text(json.dumps(track))
+
+            assert check_lexicons(tmpdir) == []
+            problems = check_lexicons(tmpdir, strict_keys=True)
+
+        assert [p.message for p in problems] == [
+            "unknown key lexicoin",
+            "unknown key defs.artist.properties.name.maxLenght",
+        ]
+        assert {p.nsid for p in problems} == {"fm.plyr.track"}
+
+    def test_strict_paths(self):
+        """a file whose path doesn't spell its declared id is reported."""
+        from pmgfal import check_lexicons
+
+        fixtures = Path(__file__).parent / "fixtures" / "mismatched_paths"
+        assert check_lexicons(str(fixtures)) == []
+        for lexicon_dir in (fixtures, fixtures / "fm" / "plyr"):
+            [problem] = check_lexicons(str(lexicon_dir), strict_paths=True)
+            assert problem.nsid == "fm.plyr.repost"
+            assert problem.message.startswith("id fm.plyr.repost doesn't match its")
+            assert problem.message.endswith("like.json")
+
+    def test_missing_directory_raises(self):
+        """a tree that can't be read at all is an error, not a problem."""
+        from pmgfal import LexiconError, check_lexicons
+
+        with pytest.raises(LexiconError, match="not a directory"):
+            check_lexicons("/nonexistent/lexicons")
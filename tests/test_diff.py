@@ -0,0 +1,94 @@
+"""black-box tests for lexicon diffing."""
+
+import json
+import tempfile
+from pathlib import Path
+
+
+def track(properties: dict, required: list[str], extra_defs: dict | None = None):
+    """a fm.plyr.track lexicon with the given record properties."""
+    return {
+        "lexicon": 1,
+        "id": "fm.plyr.track",
+        "defs": {
+            "main": {
+                "type": "record",
+                "record": {
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                },
+            },
+            **(extra_defs or {}),
+        },
+    }
+
+
+def write_lexicons(root: Path, *lexicons: dict) -> Path:
+    root.mkdir()
+    for lex in lexicons:
+        (root / f"{lex['id']}.json").write_text(json.dumps(lex))
+    return root
+
+
+class TestDiff:
+    """test comparing two lexicon directories."""
+
+    def test_additive_changes(self):
+        """new optional fields, defs and nsids are not breaking."""
+        from pmgfal import diff_lexicons
+
+        old = track({"title": {"type": "string"}}, ["title"])
+        new = track(
+            {"title": {"type": "string"}, "genre": {"type": "string"}},
+            ["title"],
+            {"artist": {"type": "object", "properties": {}}},
+        )
+        like = {"lexicon": 1, "id": "fm.plyr.like", "defs": {}}
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            old_dir = write_lexicons(Path(tmpdir) / "old", old)
+            new_dir = write_lexicons(Path(tmpdir) / "new", new, like)
+
+            diff = diff_lexicons(str(old_dir), str(new_dir))
+
+            assert diff.added_nsids == ["fm.plyr.like"]
+            assert diff.added_defs == ["fm.plyr.track#artist"]
+            [change] = diff.changes
+            assert change.def_ref == "fm.plyr.track#main"
+            assert change.property == "genre"
+            assert change.kind == "added"
+            assert change.new_type == "str | None"
+            assert not diff.breaking
+
+    def test_breaking_changes(self):
+        """removed required fields and narrowed types are breaking."""
+        from pmgfal import diff_lexicons
+
+        old = track(
+            {
+                "title": {"type": "string"},
+                "plays": {"type": "integer"},
+                "genre": {"type": "string"},
+            },
+            ["title"],
+        )
+        new = track(
+            {"plays": {"type": "integer"}, "genre": {"type": "string"}},
+            ["plays", "genre"],
+        )
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            old_dir = write_lexicons(Path(tmpdir) / "old", old)
+            new_dir = write_lexicons(Path(tmpdir) / "new", new)
+
+            diff = diff_lexicons(str(old_dir), str(new_dir))
+
+            changes = {c.property: c for c in diff.changes}
+            assert changes["title"].kind == "removed"
+            assert changes["title"].breaking
+            assert changes["plays"].kind == "changed"
+            assert changes["plays"].old_type == "int | None"
+            assert changes["plays"].new_type == "int"
+            assert changes["plays"].breaking
+            assert diff.breaking
@@ -1,9 +1,51 @@
 """black-box tests for model generation."""
 
+import importlib.util
 import json
+import os
+import re
+import shutil
+import sys
 import tempfile
 from pathlib import Path
 
+import pytest
+
+
+def load_module(path: str, name: str = "generated_models"):
+    """import a generated file as a real module so pydantic can resolve refs."""
+    spec = importlib.util.spec_from_file_location(name, path)
+    assert spec is not None and spec.loader is not None
+    module = importlib.util.module_from_spec(spec)
+    sys.modules[name] = module
+    spec.loader.exec_module(module)
+    return module
+
+
+def import_package(root: Path, name: str):
+    """import a generated package rooted at `root / name`."""
+    import importlib
+
+    sys.path.insert(0, str(root))
+    try:
+        return importlib.import_module(name)
+    finally:
+        sys.path.remove(str(root))
+
+
+def object_def(properties: dict, *required: str) -> dict:
+    """an object def with `properties`, of which `required` are required."""
+    obj = {"type": "object", "properties": properties}
+    if required:
+        obj["required"] = list(required)
+    return obj
+
+
+def record(nsid: str, properties: dict, *required: str, **defs: dict) -> dict:
+    """a lexicon whose main def is a record of `properties`, next to `defs`."""
+    main = {"type": "record", "record": object_def(properties, *required)}
+    return {"lexicon": 1, "id": nsid, "defs": {"main": main, **defs}}
+
 
 class TestGenerate:
     """test the generate function end-to-end."""
@@ -210,6 +252,16 @@ class TestGenerate:
             # the referenced class should be generated
             assert "class FmPlyrTrackFeaturedArtist(BaseModel):" in content
 
+            # the record leads the file, ahead of the defs it references
+            assert content.index("class FmPlyrTrack(") < content.index(
+                "class FmPlyrTrackFeaturedArtist("
+            )
+            module = load_module(files[0])
+            track = module.FmPlyrTrack(
+                title="song", features=[{"did": "did:x", "handle": "x"}]
+            )
+            assert track.features[0].handle == "x"
+
     def test_external_ref_resolution(self):
         """external refs should resolve to class names."""
         from pmgfal import generate
@@ -252,77 +304,4689 @@ class TestGenerate:
             assert "uri: str" in content
             assert "cid: str" in content
 
+    def test_user_lexicon_shadows_builtin(self):
+        """a vendored com.atproto lexicon should win over the bundled one."""
+        from pmgfal import generate
 
-class TestCaching:
-    """test caching behavior."""
+        strong_ref = {
+            "lexicon": 1,
+            "id": "com.atproto.repo.strongRef",
+            "defs": {
+                "main": {
+                    "type": "object",
+                    "required": ["uri", "cid"],
+                    "properties": {
+                        "uri": {"type": "string", "format": "at-uri"},
+                        "cid": {"type": "string", "format": "cid"},
+                        "vendoredNote": {"type": "string"},
+                    },
+                }
+            },
+        }
+        like = {
+            "lexicon": 1,
+            "id": "fm.plyr.like",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "subject": {
+                                "type": "ref",
+                                "ref": "com.atproto.repo.strongRef",
+                            },
+                        },
+                        "required": ["subject"],
+                    },
+                },
+            },
+        }
 
-    def test_cache_hit(self):
-        """second run should hit cache."""
-        from pmgfal import main
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            (lexicon_dir / "com" / "atproto" / "repo").mkdir(parents=True)
+            (lexicon_dir / "com" / "atproto" / "repo" / "strongRef.json").write_text(
+                json.dumps(strong_ref)
+            )
+            (lexicon_dir / "like.json").write_text(json.dumps(like))
+
+            output_dir = Path(tmpdir) / "generated"
+            result = generate(str(lexicon_dir), str(output_dir), "fm.plyr")
+
+            assert result.shadowed_builtins == ["com.atproto.repo.strongRef"]
+            content = Path(result.files[0]).read_text()
+            assert "subject: ComAtprotoRepoStrongRef" in content
+            assert "vendored_note" in content
+            assert "# com.atproto.repo.strongRef (external)" in content
+
+    def test_disable_builtins(self):
+        """use_builtins=False should leave com.atproto refs unresolved."""
+        from pmgfal import generate
 
         lexicon = {
             "lexicon": 1,
-            "id": "test.cache",
+            "id": "fm.plyr.like",
             "defs": {
                 "main": {
                     "type": "record",
                     "record": {
                         "type": "object",
-                        "properties": {"x": {"type": "string"}},
+                        "properties": {
+                            "subject": {
+                                "type": "ref",
+                                "ref": "com.atproto.repo.strongRef",
+                            },
+                        },
+                        "required": ["subject"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "like.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            result = generate(str(lexicon_dir), str(output_dir), use_builtins=False)
+
+            content = Path(result.files[0]).read_text()
+            assert "class ComAtprotoRepoStrongRef" not in content
+            assert result.warnings == ["unresolved ref: com.atproto.repo.strongRef"]
+
+            with pytest.raises(ValueError, match="com.atproto.repo.strongRef"):
+                generate(
+                    str(lexicon_dir),
+                    str(output_dir),
+                    use_builtins=False,
+                    strict_refs=True,
+                )
+
+    def test_builtins_dir_replaces_bundled(self):
+        """builtins_dir should replace the bundled lexicons for the call."""
+        from pmgfal import generate
+
+        strong_ref = {
+            "lexicon": 1,
+            "id": "com.atproto.repo.strongRef",
+            "defs": {
+                "main": {
+                    "type": "object",
+                    "required": ["uri", "cid"],
+                    "properties": {
+                        "uri": {"type": "string"},
+                        "cid": {"type": "string"},
+                        "upstreamField": {"type": "string"},
                     },
                 }
             },
         }
+        like = {
+            "lexicon": 1,
+            "id": "fm.plyr.like",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "subject": {
+                                "type": "ref",
+                                "ref": "com.atproto.repo.strongRef",
+                            },
+                            "label": {
+                                "type": "ref",
+                                "ref": "com.atproto.label.defs#label",
+                            },
+                        },
+                        "required": ["subject"],
+                    },
+                },
+            },
+        }
 
         with tempfile.TemporaryDirectory() as tmpdir:
             lexicon_dir = Path(tmpdir) / "lexicons"
             lexicon_dir.mkdir()
-            (lexicon_dir / "cache.json").write_text(json.dumps(lexicon))
+            (lexicon_dir / "like.json").write_text(json.dumps(like))
+            builtins_dir = Path(tmpdir) / "upstream"
+            builtins_dir.mkdir()
+            (builtins_dir / "strongRef.json").write_text(json.dumps(strong_ref))
 
             output_dir = Path(tmpdir) / "generated"
+            result = generate(
+                str(lexicon_dir), str(output_dir), builtins_dir=str(builtins_dir)
+            )
 
-            # first run - cache miss
-            result = main([str(lexicon_dir), "-o", str(output_dir)])
-            assert result == 0
-            assert (output_dir / "models.py").exists()
+            content = Path(result.files[0]).read_text()
+            assert "upstream_field" in content
+            assert result.shadowed_builtins == []
+            # the bundled snapshot is not consulted at all
+            assert result.warnings == ["unresolved ref: com.atproto.label.defs"]
 
-            # delete output to prove cache works
-            (output_dir / "models.py").unlink()
+    def test_query_params_with_array(self):
+        """array params become list fields marked as repeated query keys."""
+        from pmgfal import generate
 
-            # second run - cache hit
-            result = main([str(lexicon_dir), "-o", str(output_dir)])
-            assert result == 0
-            assert (output_dir / "models.py").exists()
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.getTracks",
+            "defs": {
+                "main": {
+                    "type": "query",
+                    "parameters": {
+                        "type": "params",
+                        "required": ["uris"],
+                        "properties": {
+                            "uris": {
+                                "type": "array",
+                                "items": {"type": "string", "format": "at-uri"},
+                                "maxLength": 25,
+                            },
+                            "limit": {"type": "integer"},
+                        },
+                    },
+                }
+            },
+        }
 
-    def test_no_cache_flag(self):
-        """--no-cache should force regeneration."""
-        from pmgfal import main
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "getTracks.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrGetTracksParams(BaseModel):" in content
+            assert (
+                'uris: list[str] = Field(json_schema_extra={"explode": True})'
+                in content
+            )
+
+            namespace = {}
+            exec(content, namespace)
+            params = namespace["FmPlyrGetTracksParams"](uris=["at://a", "at://b"])
+            assert params.uris == ["at://a", "at://b"]
+            assert params.limit is None
+
+    def test_embed_metadata(self):
+        """embed_metadata attaches the source ref/format to field types."""
+        import typing
+
+        from pmgfal import generate
 
         lexicon = {
             "lexicon": 1,
-            "id": "test.nocache",
+            "id": "fm.plyr.like",
             "defs": {
                 "main": {
                     "type": "record",
                     "record": {
                         "type": "object",
-                        "properties": {"y": {"type": "string"}},
+                        "properties": {
+                            "subject": {
+                                "type": "ref",
+                                "ref": "com.atproto.repo.strongRef",
+                            },
+                            "createdAt": {"type": "string", "format": "datetime"},
+                        },
+                        "required": ["subject"],
                     },
-                }
+                },
             },
         }
 
         with tempfile.TemporaryDirectory() as tmpdir:
             lexicon_dir = Path(tmpdir) / "lexicons"
             lexicon_dir.mkdir()
-            (lexicon_dir / "nocache.json").write_text(json.dumps(lexicon))
+            (lexicon_dir / "like.json").write_text(json.dumps(lexicon))
 
             output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir), embed_metadata=True)
 
-            # first run
-            result = main([str(lexicon_dir), "-o", str(output_dir)])
-            assert result == 0
+            module = load_module(files[0])
+            hints = typing.get_type_hints(module.FmPlyrLike, include_extras=True)
+            subject_meta = hints["subject"].__metadata__[0]
+            assert subject_meta == module.LexiconMeta(ref="com.atproto.repo.strongRef")
+            created_meta = hints["created_at"].__metadata__[0]
+            assert created_meta.format == "datetime"
 
-            # second run with --no-cache
-            result = main([str(lexicon_dir), "-o", str(output_dir), "--no-cache"])
-            assert result == 0
+    def test_small_int_ranges_as_literal(self):
+        """bounded integers become literals when the range is small enough."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.rating",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "stars": {"type": "integer", "minimum": 0, "maximum": 3},
+                            "plays": {"type": "integer", "minimum": 0, "maximum": 999},
+                        },
+                        "required": ["stars", "plays"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "rating.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(
+                str(lexicon_dir), str(output_dir), small_int_ranges_as_literal=True
+            )
+
+            content = Path(files[0]).read_text()
+            assert "stars: Literal[0, 1, 2, 3]" in content
+            assert "plays: Annotated[int, Field(ge=0, le=999)]" in content
+
+            module = load_module(files[0])
+            module.FmPlyrRating(stars=2, plays=500)
+            with pytest.raises(ValueError):
+                module.FmPlyrRating(stars=4, plays=500)
+
+    def test_integer_bounds(self):
+        """integer minimum/maximum become ge/le, each on its own."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.stats",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "plays": {"type": "integer", "minimum": 0},
+                            "offset": {"type": "integer", "maximum": 10},
+                            "delta": {"type": "integer"},
+                            "sizes": {
+                                "type": "array",
+                                "items": {"type": "integer", "minimum": 0},
+                            },
+                        },
+                        "required": ["plays", "sizes"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "stats.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "plays: Annotated[int, Field(ge=0)]" in content
+            assert "delta: int | None" in content
+
+            module = load_module(files[0])
+            stats = module.FmPlyrStats(plays=2**62, sizes=[0, 1], offset=-5)
+            assert stats.plays == 2**62
+            for bad in [
+                {"plays": -1, "sizes": []},
+                {"plays": 0, "sizes": [-1]},
+                {"plays": 0, "sizes": [], "offset": 11},
+            ]:
+                with pytest.raises(ValueError):
+                    module.FmPlyrStats(**bad)
+
+    def test_colliding_def_names(self):
+        """defs that pascal-case alike get distinct, consistently-referenced names."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.test",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "a": {"type": "ref", "ref": "#myDef"},
+                            "b": {"type": "ref", "ref": "#my_def"},
+                        },
+                        "required": ["a", "b"],
+                    },
+                },
+                "myDef": {
+                    "type": "object",
+                    "properties": {"x": {"type": "string"}},
+                    "required": ["x"],
+                },
+                "my_def": {
+                    "type": "object",
+                    "properties": {"y": {"type": "integer"}},
+                    "required": ["y"],
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "test.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrTestMyDef(BaseModel):" in content
+            assert "class FmPlyrTestMyDef_my_def(BaseModel):" in content
+            assert "b: FmPlyrTestMyDef_my_def" in content
+
+            module = load_module(files[0])
+            record = module.FmPlyrTest(a={"x": "hi"}, b={"y": 1})
+            assert record.b.y == 1
+
+    def test_deprecated_fields(self):
+        """mark_deprecated makes fields described as DEPRECATED warn on access."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string"},
+                            "artistName": {
+                                "type": "string",
+                                "description": "DEPRECATED: use #artist instead",
+                            },
+                        },
+                        "required": ["title"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir), mark_deprecated=True)
+
+            content = Path(files[0]).read_text()
+            assert 'alias="artistName", deprecated=True)' in content
+
+            module = load_module(files[0])
+            track = module.FmPlyrTrack(title="song", artistName="someone")
+            with pytest.warns(DeprecationWarning):
+                assert track.artist_name == "someone"
+
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "deprecated=True" not in Path(files[0]).read_text()
+
+    def test_blob_accept_narrows_mime_type(self):
+        """blobs with `accept` get a BlobRef subtype constraining mimeType."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "audio": {"type": "blob", "accept": ["audio/*"]},
+                            "meta": {"type": "blob", "accept": ["application/json"]},
+                            "cover": {"type": "blob"},
+                        },
+                        "required": ["audio"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrTrackAudioBlob(BlobRef):" in content
+            assert 'mime_type: Literal["application/json"]' in content
+            assert "cover: BlobRef | None" in content
+
+            module = load_module(files[0])
+            blob = {
+                "$type": "blob",
+                "ref": {"$link": "bafkrei"},
+                "mimeType": "audio/mpeg",
+                "size": 1024,
+            }
+            track = module.FmPlyrTrack(
+                audio=blob, meta={**blob, "mimeType": "application/json"}
+            )
+            assert track.audio.mime_type == "audio/mpeg"
+            with pytest.raises(ValueError):
+                module.FmPlyrTrack(audio={**blob, "mimeType": "image/png"})
+            with pytest.raises(ValueError):
+                module.FmPlyrTrack(audio=blob, meta=blob)
+
+            schema = module.FmPlyrTrack.model_json_schema()
+            meta_schema = schema["$defs"]["FmPlyrTrackMetaBlob"]
+            assert meta_schema["properties"]["mimeType"]["const"] == "application/json"
+
+    def test_blob_arrays(self):
+        """arrays of blobs are typed and narrowed like a single blob."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.album",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "covers": {
+                                "type": "array",
+                                "items": {"type": "blob", "accept": ["image/*"]},
+                            },
+                            "attachments": {
+                                "type": "array",
+                                "items": {"type": "blob"},
+                            },
+                        },
+                        "required": ["covers"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "album.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "covers: list[FmPlyrAlbumCoversBlob]" in content
+            assert "attachments: list[BlobRef] | None" in content
+
+            module = load_module(files[0])
+            blob = {
+                "$type": "blob",
+                "ref": {"$link": "bafkrei"},
+                "mimeType": "image/png",
+                "size": 1024,
+            }
+            album = module.FmPlyrAlbum(
+                covers=[blob], attachments=[{**blob, "mimeType": "text/plain"}]
+            )
+            assert isinstance(album.covers[0], module.BlobRef)
+            assert album.covers[0].mime_type == "image/png"
+            assert album.attachments[0].mime_type == "text/plain"
+            with pytest.raises(ValueError):
+                module.FmPlyrAlbum(covers=[blob, {**blob, "mimeType": "audio/mpeg"}])
+
+    def test_bytes_length_bounds(self):
+        """bytes min/maxLength become pydantic length constraints."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.key",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "key": {
+                                "type": "bytes",
+                                "minLength": 32,
+                                "maxLength": 32,
+                            },
+                            "sigs": {
+                                "type": "array",
+                                "items": {"type": "bytes", "maxLength": 4},
+                            },
+                            "raw": {"type": "bytes"},
+                        },
+                        "required": ["key"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "key.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            bounded = "Annotated[Bytes, Field(min_length=32, max_length=32)]"
+            assert f"key: {bounded}" in content
+            assert "sigs: list[Annotated[Bytes, Field(max_length=4)]] | None" in content
+            assert "raw: Bytes | None" in content
+
+            module = load_module(files[0])
+            assert module.FmPlyrKey(key=b"k" * 32).key == b"k" * 32
+            with pytest.raises(ValueError):
+                module.FmPlyrKey(key=b"short")
+            with pytest.raises(ValueError):
+                module.FmPlyrKey(key=b"k" * 32, sigs=[b"toolong"])
+
+    def test_dag_json_links_and_bytes(self):
+        """cid-link and bytes accept dag-json wrapper objects and plain values."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.commit",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "data": {"type": "cid-link"},
+                            "prev": {"type": "array", "items": {"type": "cid-link"}},
+                            "sig": {"type": "bytes", "maxLength": 4},
+                        },
+                        "required": ["data"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "commit.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            content = Path(files[0]).read_text()
+            assert "data: CidLink" in content
+            assert "prev: list[CidLink] | None" in content
+
+            module = load_module(files[0])
+            cid = "bafyreib2rxk3rh6kzwq"
+            dag_json = module.FmPlyrCommit.model_validate(
+                {
+                    "data": {"$link": cid},
+                    "prev": [{"$link": cid}, cid],
+                    # dag-json base64 has no padding
+                    "sig": {"$bytes": "AQI"},
+                }
+            )
+            assert dag_json.data == cid
+            assert dag_json.prev == [cid, cid]
+            assert dag_json.sig == b"\x01\x02"
+
+            plain = module.FmPlyrCommit(data=cid, sig=b"\x01\x02")
+            assert plain.model_dump() == dag_json.model_dump() | {"prev": None}
+
+            with pytest.raises(ValueError):
+                module.FmPlyrCommit(data=cid, sig={"$bytes": "AQIDBAU"})
+            with pytest.raises(ValueError):
+                module.FmPlyrCommit(data={"$link": cid, "extra": 1})
+
+            files = generate(str(lexicon_dir), str(output_dir), target="protocol")
+            content = Path(files[0]).read_text()
+            assert "def data(self) -> str: ..." in content
+            assert "pydantic" not in content
+
+    def test_invariant_validators(self):
+        """closed-union objects must carry a listed $type when opted in."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.test",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "closed": {
+                                "type": "union",
+                                "refs": ["#a", "#b"],
+                                "closed": True,
+                            },
+                            "open": {"type": "union", "refs": ["#a", "#b"]},
+                        },
+                        "required": ["closed"],
+                    },
+                },
+                "a": {
+                    "type": "object",
+                    "properties": {"x": {"type": "string"}},
+                    "required": ["x"],
+                },
+                "b": {
+                    "type": "object",
+                    "properties": {"y": {"type": "integer"}},
+                    "required": ["y"],
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "test.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "model_validator" not in Path(files[0]).read_text()
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), emit_invariant_validators=True
+            )
+            content = Path(files[0]).read_text()
+            allowed = '("fm.plyr.test#a", "fm.plyr.test#b",)'
+            assert f'check_union_type(data, "closed", {allowed})' in content
+            assert '"open"' not in content
+
+            module = load_module(files[0])
+            record = module.FmPlyrTest(closed={"$type": "fm.plyr.test#b", "y": 1})
+            assert record.closed.y == 1
+            with pytest.raises(ValueError, match=r"\$type"):
+                module.FmPlyrTest(closed={"y": 1})
+            with pytest.raises(ValueError, match=r"\$type"):
+                module.FmPlyrTest(closed={"$type": "fm.plyr.other", "y": 1})
+
+    def test_non_object_defs_become_aliases(self):
+        """top-level string/array defs get a type alias and a TypeAdapter."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.defs",
+            "defs": {
+                "trackUri": {"type": "string", "format": "at-uri"},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "track": {
+                    "type": "object",
+                    "properties": {"uri": {"type": "ref", "ref": "#trackUri"}},
+                    "required": ["uri"],
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "defs.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "FmPlyrDefsTrackUri = str\n" in content
+            assert "FmPlyrDefsTags = list[str]\n" in content
+            # aliases follow the classes they may reference
+            assert content.index("class FmPlyrDefsTrack(") < content.index(
+                "FmPlyrDefsTags ="
+            )
+
+            module = load_module(files[0])
+            assert module.FmPlyrDefsTrack(uri="at://x").uri == "at://x"
+            adapter = module.FmPlyrDefsTrackUriAdapter
+            assert adapter.validate_python("at://x") == "at://x"
+            with pytest.raises(ValueError):
+                adapter.validate_python(3)
+            assert module.FmPlyrDefsTagsAdapter.validate_python(["a"]) == ["a"]
+
+    def test_top_level_as_rootmodel(self):
+        """top_level_as picks a TypeAdapter alias or a RootModel subclass."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.defs",
+            "defs": {
+                "tracks": {"type": "array", "items": {"type": "ref", "ref": "#track"}},
+                "track": {
+                    "type": "object",
+                    "properties": {"title": {"type": "string"}},
+                    "required": ["title"],
+                },
+                "playlist": {
+                    "type": "object",
+                    "properties": {"tracks": {"type": "ref", "ref": "#tracks"}},
+                    "required": ["tracks"],
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "defs.json").write_text(json.dumps(lexicon))
+            raw = [{"title": "a"}]
+
+            output_dir = Path(tmpdir) / "adapter"
+            files = generate(str(lexicon_dir), str(output_dir), top_level_as="adapter")
+            content = Path(files[0]).read_text()
+            assert "FmPlyrDefsTracks = list[FmPlyrDefsTrack]\n" in content
+            assert "RootModel" not in content
+            module = load_module(files[0])
+            tracks = module.FmPlyrDefsTracksAdapter.validate_python(raw)
+            assert tracks[0].title == "a"
+            assert module.FmPlyrDefsPlaylist(tracks=raw).tracks[0].title == "a"
+
+            output_dir = Path(tmpdir) / "rootmodel"
+            files = generate(
+                str(lexicon_dir), str(output_dir), top_level_as="rootmodel"
+            )
+            content = Path(files[0]).read_text()
+            assert (
+                "class FmPlyrDefsTracks(RootModel):\n"
+                "    root: list[FmPlyrDefsTrack]\n"
+            ) in content
+            assert "FmPlyrDefsTracksAdapter" not in content
+            module = load_module(files[0])
+            tracks = module.FmPlyrDefsTracks.model_validate(raw)
+            assert tracks.root[0].title == "a"
+            assert tracks.model_dump() == raw
+            playlist = module.FmPlyrDefsPlaylist(tracks=raw)
+            assert isinstance(playlist.tracks, module.FmPlyrDefsTracks)
+
+            with pytest.raises(ValueError, match="top_level_as"):
+                generate(str(lexicon_dir), str(output_dir), top_level_as="alias")
+
+    def test_single_member_unions_compose_with_optional(self):
+        """collapsed unions and optionality yield one flat annotation."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.test",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "one": {"type": "union", "refs": ["#a"]},
+                            "same": {
+                                "type": "union",
+                                "refs": ["#a", "fm.plyr.test#a"],
+                            },
+                            "required_one": {"type": "union", "refs": ["#a"]},
+                            "many": {"type": "union", "refs": ["#a", "#b"]},
+                        },
+                        "required": ["required_one"],
+                    },
+                },
+                "a": {"type": "object", "properties": {"x": {"type": "string"}}},
+                "b": {"type": "object", "properties": {"y": {"type": "string"}}},
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "test.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "    required_one: FmPlyrTestA\n" in content
+            assert "    one: FmPlyrTestA | None = Field(default=None)" in content
+            assert "    same: FmPlyrTestA | None = Field(default=None)" in content
+            assert (
+                "    many: FmPlyrTestA | FmPlyrTestB | None = Field(default=None)"
+                in content
+            )
+
+            module = load_module(files[0])
+            assert module.FmPlyrTest(required_one={}).one is None
+
+    def test_compact_repr(self):
+        """compact_repr shows the nsid and identifying fields of records."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "uri": {"type": "string"},
+                            "cid": {"type": "string"},
+                            "title": {"type": "string"},
+                        },
+                        "required": ["uri", "title"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "__repr__" not in Path(files[0]).read_text()
+
+            files = generate(str(lexicon_dir), str(output_dir), compact_repr=True)
+            module = load_module(files[0])
+            track = module.FmPlyrTrack(uri="at://x", title="song")
+            assert repr(track) == "fm.plyr.track(uri='at://x', cid=None)"
+            assert str(track) == repr(track)
+
+    def test_identity_equality(self):
+        """identity_equality compares records by uri and cid only."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "uri": {"type": "string"},
+                            "cid": {"type": "string"},
+                            "likeCount": {"type": "integer"},
+                        },
+                        "required": ["uri"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            files = generate(str(lexicon_dir), str(output_dir))
+            module = load_module(files[0])
+            a = module.FmPlyrTrack(uri="at://x", cid="bafy", likeCount=1)
+            b = module.FmPlyrTrack(uri="at://x", cid="bafy", likeCount=2)
+            assert a != b
+
+            files = generate(str(lexicon_dir), str(output_dir), identity_equality=True)
+            module = load_module(files[0])
+            a = module.FmPlyrTrack(uri="at://x", cid="bafy", likeCount=1)
+            b = module.FmPlyrTrack(uri="at://x", cid="bafy", likeCount=2)
+            assert a == b
+            assert len({a, b}) == 1
+            assert a != module.FmPlyrTrack(uri="at://x", cid="bafz", likeCount=1)
+
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                identity_equality=True,
+                identity_fields=["uri"],
+            )
+            module = load_module(files[0])
+            a = module.FmPlyrTrack(uri="at://x", cid="bafy")
+            assert a == module.FmPlyrTrack(uri="at://x", cid="bafz")
+
+    def test_record_union_for_unknown(self):
+        """record_union_for_unknown types record slots as the record union."""
+        from pmgfal import generate
+
+        listing = {
+            "lexicon": 1,
+            "id": "fm.plyr.listRecords",
+            "defs": {
+                "record": {
+                    "type": "object",
+                    "required": ["uri", "value"],
+                    "properties": {
+                        "uri": {"type": "string"},
+                        "value": {"type": "unknown"},
+                        "extra": {"type": "unknown"},
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for name in ("track", "album"):
+                properties = {"name": {"type": "string"}}
+                lexicon = record(f"fm.plyr.{name}", properties, "name")
+                (lexicon_dir / f"{name}.json").write_text(json.dumps(lexicon))
+            (lexicon_dir / "listRecords.json").write_text(json.dumps(listing))
+            output_dir = Path(tmpdir) / "generated"
+
+            [path] = generate(str(lexicon_dir), str(output_dir))
+            assert "AnyRecord" not in Path(path).read_text()
+
+            [path] = generate(
+                str(lexicon_dir), str(output_dir), record_union_for_unknown=True
+            )
+            content = Path(path).read_text()
+            assert "value: AnyRecord" in content
+            assert "extra: Any | None" in content
+
+            module = load_module(path)
+            listed = module.FmPlyrListRecordsRecord.model_validate(
+                {"uri": "at://x", "value": {"$type": "fm.plyr.track", "name": "a"}}
+            )
+            assert isinstance(listed.value, module.FmPlyrTrack)
+            listed = module.FmPlyrListRecordsRecord.model_validate(
+                {"uri": "at://x", "value": {"$type": "fm.plyr.album", "name": "b"}}
+            )
+            assert isinstance(listed.value, module.FmPlyrAlbum)
+
+            with pytest.raises(ValueError):
+                module.FmPlyrListRecordsRecord.model_validate(
+                    {"uri": "at://x", "value": {"name": "untyped"}}
+                )
+
+    def test_nsid_constant(self):
+        """emit_nsid_constant gives records a class-level NSID, not a field."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"title": {"type": "string"}},
+                        "required": ["title"],
+                    },
+                },
+                "artist": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "NSID" not in Path(files[0]).read_text()
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), emit_nsid_constant=True
+            )
+            module = load_module(files[0])
+            assert module.FmPlyrTrack.NSID == "fm.plyr.track"
+            assert "NSID" not in module.FmPlyrTrack.model_fields
+            assert "NSID" not in module.FmPlyrTrack(title="song").model_dump()
+            assert not hasattr(module.FmPlyrTrackArtist, "NSID")
+
+    def test_rkey_info(self):
+        """emit_rkey_info exposes each record's key as class constants."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            keys = [("fm.plyr.profile", "literal:self"), ("fm.plyr.track", "tid")]
+            for nsid, key in keys:
+                lex = record(nsid, {"name": {"type": "string"}})
+                lex["defs"]["main"]["key"] = key
+                (lexicon_dir / f"{nsid}.json").write_text(json.dumps(lex))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "RKEY" not in Path(files[0]).read_text()
+
+            files = generate(str(lexicon_dir), str(output_dir), emit_rkey_info=True)
+            module = load_module(files[0], "rkey_models")
+            assert module.FmPlyrProfile.RKEY_TYPE == "literal:self"
+            assert module.FmPlyrProfile.RKEY == "self"
+            assert module.FmPlyrTrack.RKEY_TYPE == "tid"
+            assert not hasattr(module.FmPlyrTrack, "RKEY")
+            assert "RKEY_TYPE" not in module.FmPlyrProfile.model_fields
+            assert module.FmPlyrProfile(name="x").model_dump() == {"name": "x"}
+
+    def test_parse_record(self):
+        """emit_parse_record validates raw records by their $type."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"title": {"type": "string"}},
+                        "required": ["title"],
+                    },
+                },
+                "artist": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "parse_record" not in Path(files[0]).read_text()
+
+            files = generate(str(lexicon_dir), str(output_dir), emit_parse_record=True)
+            module = load_module(files[0])
+            assert module.RECORD_TYPES == {"fm.plyr.track": module.FmPlyrTrack}
+
+            track = module.parse_record({"$type": "fm.plyr.track", "title": "song"})
+            assert isinstance(track, module.FmPlyrTrack)
+            assert track.title == "song"
+
+            for data in [{"$type": "fm.plyr.track#artist"}, {"title": "song"}]:
+                with pytest.raises(ValueError, match="unknown record \\$type"):
+                    module.parse_record(data)
+
+    def test_validate_collection(self):
+        """emit_collections validates a list of one collection's records."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"title": {"type": "string"}},
+                        "required": ["title"],
+                    },
+                },
+                "draft": {
+                    "type": "record",
+                    "record": {"type": "object", "properties": {}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir), emit_collections=True)
+            module = load_module(files[0])
+            assert module.COLLECTIONS == {"fm.plyr.track": module.FmPlyrTrack}
+            assert not hasattr(module, "parse_record")
+
+            records = [
+                {"$type": "fm.plyr.track", "title": "song"},
+                {"$type": "fm.plyr.track", "title": "another song"},
+            ]
+            tracks = module.validate_collection("fm.plyr.track", records)
+            assert [type(track) for track in tracks] == [module.FmPlyrTrack] * 2
+            assert [track.title for track in tracks] == ["song", "another song"]
+
+            with pytest.raises(ValueError, match="unknown collection"):
+                module.validate_collection("fm.plyr.track#draft", records)
+            with pytest.raises(ValueError):
+                module.validate_collection("fm.plyr.track", [{"$type": "x"}])
+
+    def test_legacy_root_level_schema(self):
+        """a schema at the document root (no defs) is read as defs.main."""
+        from pmgfal import generate
+
+        fixtures = Path(__file__).parent / "fixtures" / "legacy"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(fixtures), str(output_dir))
+
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrTrack(BaseModel):" in content
+            assert "legacy single-def form" in content
+
+            module = load_module(files[0])
+            track = module.FmPlyrTrack(title="song", durationMs=1000)
+            assert track.duration_ms == 1000
+
+    def test_invalid_field_names(self):
+        """properties that can't be python identifiers are skipped or fatal."""
+        from pmgfal import generate
+
+        fixtures = Path(__file__).parent / "fixtures" / "invalid_fields"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            output_dir = Path(tmpdir) / "generated"
+            result = generate(str(fixtures), str(output_dir))
+            assert result.warnings == [
+                f"fm.plyr.track#main: property {name} is not a valid python "
+                "identifier, skipped"
+                for name in ['""', '"   "', '"2fa"']
+            ]
+
+            module = load_module(result.files[0])
+            assert list(module.FmPlyrTrack.model_fields) == ["title"]
+            assert module.FmPlyrTrack(title="song").title == "song"
+
+            with pytest.raises(ValueError, match="not a valid python identifier"):
+                generate(str(fixtures), str(output_dir), on_invalid_field="error")
+
+    def test_invalid_integer_default(self):
+        """an integer default outside its own enum is an error, or a warning."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "rating": {"type": "integer", "enum": [1, 2], "default": 3},
+                            "plays": {"type": "integer", "minimum": 0, "default": 0},
+                        },
+                    },
+                }
+            },
+        }
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            message = r"fm\.plyr\.track#main\.rating: default 3 is not in its enum"
+            with pytest.raises(ValueError, match=message):
+                generate(str(lexicon_dir), str(output_dir))
+
+            result = generate(
+                str(lexicon_dir), str(output_dir), on_invalid_default="warn"
+            )
+            assert result.warnings == [
+                "fm.plyr.track#main.rating: default 3 is not in its enum [1, 2]"
+            ]
+            module = load_module(result.files[0])
+            assert module.FmPlyrTrack(rating=1).rating == 1
+
+            # only generated defs are checked
+            album = {
+                "lexicon": 1,
+                "id": "fm.other.album",
+                "defs": {"main": {"type": "object", "properties": {}}},
+            }
+            (lexicon_dir / "album.json").write_text(json.dumps(album))
+            result = generate(
+                str(lexicon_dir), str(output_dir), namespace_prefix="fm.other"
+            )
+            assert result.warnings == []
+            result = generate(
+                str(lexicon_dir), str(output_dir), skip_nsids=["fm.plyr.track"]
+            )
+            assert "class FmOtherAlbum(BaseModel):" in Path(result.files[0]).read_text()
+
+    def test_strict_keys(self):
+        """strict_keys rejects a lexicon with keys the language doesn't define."""
+        from pmgfal import InvalidLexiconError, generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "propertiess": {"title": {"type": "string"}},
+                        "properties": {
+                            "title": {"type": "string", "x-sensitive": True},
+                        },
+                    },
+                }
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+            generate(str(lexicon_dir), str(output_dir))
+
+            kwargs = {"strict_keys": True, "field_metadata_keys": ["x-sensitive"]}
+            with pytest.raises(InvalidLexiconError) as excinfo:
+                generate(str(lexicon_dir), str(output_dir), **kwargs)
+            message = str(excinfo.value)
+            assert "track.json" in message
+            assert message.endswith("unknown keys: defs.main.record.propertiess")
+
+            del lexicon["defs"]["main"]["record"]["propertiess"]
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            generate(str(lexicon_dir), str(output_dir), **kwargs)
+            with pytest.raises(InvalidLexiconError, match="title.x-sensitive"):
+                generate(str(lexicon_dir), str(output_dir), strict_keys=True)
+
+    def test_typed_datetimes_round_trip(self):
+        """datetime fields dump back to the timestamps they were read from."""
+        from datetime import datetime, timedelta
+
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "required": ["createdAt"],
+                        "properties": {
+                            "createdAt": {"type": "string", "format": "datetime"},
+                            "playedAt": {
+                                "type": "array",
+                                "items": {"type": "string", "format": "datetime"},
+                            },
+                        },
+                    },
+                }
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            [default] = generate(str(lexicon_dir), str(output_dir))
+            assert "created_at: str" in Path(default).read_text()
+
+            [path] = generate(str(lexicon_dir), str(output_dir), typed_datetimes=True)
+            module = load_module(path)
+            data = {
+                "createdAt": "2024-05-01T12:00:00.000Z",
+                "playedAt": [
+                    "2024-05-01T12:30:00.123456Z",
+                    "2024-05-01T14:00:00.250+02:00",
+                ],
+            }
+            track = module.FmPlyrTrack.model_validate(data)
+            assert isinstance(track.created_at, datetime)
+            assert track.played_at[1].utcoffset() == timedelta(hours=2)
+
+            assert track.model_dump(mode="json", by_alias=True) == data
+            assert json.loads(track.model_dump_json(by_alias=True)) == data
+
+    def test_ref_aliases(self):
+        """refs through defs that are only refs resolve to the concrete model."""
+        from pmgfal import InvalidLexiconError, check_lexicons, generate
+
+        fixtures = Path(__file__).parent / "fixtures" / "ref_aliases"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            output_dir = Path(tmpdir) / "generated"
+            [path] = generate(str(fixtures), str(output_dir))
+            content = Path(path).read_text()
+            assert "artist: FmPlyrTrackC" in content
+            assert "list[FmPlyrTrackC]" in content
+            assert "label: FmPlyrLabelLabel | None" in content
+            for alias in ["FmPlyrTrackA", "FmPlyrTrackB", "FmPlyrLabelCurrent"]:
+                assert alias not in content
+
+            module = load_module(path)
+            track = module.FmPlyrTrack.model_validate(
+                {"artist": {"name": "nate"}, "label": {"name": "plyr"}}
+            )
+            assert isinstance(track.artist, module.FmPlyrTrackC)
+            assert check_lexicons(str(fixtures)) == []
+
+            lexicon = {
+                "lexicon": 1,
+                "id": "fm.plyr.loop",
+                "defs": {
+                    "x": {"type": "ref", "ref": "#y"},
+                    "y": {"type": "ref", "ref": "fm.plyr.loop#x"},
+                },
+            }
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "loop.json").write_text(json.dumps(lexicon))
+            with pytest.raises(InvalidLexiconError, match="cycle: fm.plyr.loop#x -> "):
+                generate(str(lexicon_dir), str(output_dir))
+
+    def test_open_maps(self):
+        """objects with additionalProperties become dicts when asked."""
+        from pmgfal import generate
+
+        fixtures = Path(__file__).parent / "fixtures" / "open_maps"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            output_dir = Path(tmpdir) / "generated"
+            [default] = generate(str(fixtures), str(output_dir))
+            assert "class FmPlyrLabelsValueMap(BaseModel):" in Path(default).read_text()
+
+            result = generate(str(fixtures), str(output_dir), open_maps=True)
+            assert result.warnings == []
+            content = Path(result.files[0]).read_text()
+            assert "FmPlyrLabelsValueMap = dict[str, FmPlyrLabelsLabelValue]" in content
+            assert "class FmPlyrLabelsValueMap" not in content
+
+            module = load_module(result.files[0])
+            record = module.FmPlyrLabels.model_validate(
+                {"values": {"spam": {"severity": "alert"}}, "counts": {"a": 1}}
+            )
+            assert record.values["spam"].severity == "alert"
+            assert record.counts == {"a": 1}
+            with pytest.raises(ValueError):
+                module.FmPlyrLabels.model_validate(
+                    {"values": {}, "counts": {"a": "many"}}
+                )
+
+    def test_jsonc_comments(self):
+        """line and block comments are stripped; the hash still sees them."""
+        import shutil
+
+        from pmgfal import build_info, generate, hash_lexicons
+
+        if "jsonc" not in build_info()["features"]:
+            pytest.skip("built without the jsonc feature")
+
+        fixtures = Path(__file__).parent / "fixtures" / "jsonc"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            shutil.copytree(fixtures, lexicon_dir)
+            files = generate(str(lexicon_dir), str(Path(tmpdir) / "generated"))
+
+            content = Path(files[0]).read_text()
+            assert "a track // not a comment inside a string" in content
+            module = load_module(files[0])
+            track = module.FmPlyrTrack(title="song", durationMs=1000)
+            assert track.duration_ms == 1000
+
+            before = hash_lexicons(str(lexicon_dir))
+            track_file = lexicon_dir / "fm" / "plyr" / "track.json"
+            track_file.write_text(track_file.read_text().replace("display", "shown"))
+            assert hash_lexicons(str(lexicon_dir)) != before
+
+    def test_examples(self):
+        """def examples appear in docstrings and back generated smoke tests."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "description": "a track",
+                    "examples": [{"title": "song", "durationMs": 1000}],
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string"},
+                            "durationMs": {"type": "integer"},
+                        },
+                        "required": ["title"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert not (output_dir / "test_examples.py").exists()
+
+            files = generate(str(lexicon_dir), str(output_dir), emit_example_tests=True)
+            module = load_module(files[0])
+            assert '{"title":"song","durationMs":1000}' in module.FmPlyrTrack.__doc__
+
+            tests = import_package(output_dir, "test_examples")
+            tests.test_fm_plyr_track_example_0()
+
+    def test_name_hook(self):
+        """name_hook renames classes and the refs pointing at them."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"artist": {"type": "ref", "ref": "#artist"}},
+                        "required": ["artist"],
+                    },
+                },
+                "artist": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+            },
+        }
+
+        seen = []
+
+        def name_hook(nsid: str, def_name: str) -> str | None:
+            seen.append(f"{nsid}#{def_name}")
+            if def_name == "artist":
+                return "Artist"
+            return None
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir), name_hook=name_hook)
+
+            content = Path(files[0]).read_text()
+            assert "class Artist(BaseModel):" in content
+            assert "artist: Artist" in content
+            assert "FmPlyrTrackArtist" not in content
+
+            module = load_module(files[0])
+            assert module.FmPlyrTrack(artist={"name": "x"}).artist.name == "x"
+            # builtins nothing refs aren't asked about
+            assert sorted(seen) == ["fm.plyr.track#artist", "fm.plyr.track#main"]
+
+            for bad in ["1Artist", "class", "Artist Name"]:
+                with pytest.raises(ValueError, match="not a valid class name"):
+                    generate(
+                        str(lexicon_dir),
+                        str(output_dir),
+                        name_hook=lambda nsid, def_name, bad=bad: bad,
+                    )
+            with pytest.raises(ValueError, match="both named FmPlyrTrack"):
+                generate(
+                    str(lexicon_dir),
+                    str(output_dir),
+                    name_hook=lambda nsid, def_name: "FmPlyrTrack",
+                )
+
+            class HookFailed(Exception):
+                pass
+
+            def failing_hook(nsid: str, def_name: str) -> str | None:
+                raise HookFailed(nsid)
+
+            with pytest.raises(HookFailed, match="fm.plyr.track"):
+                generate(str(lexicon_dir), str(output_dir), name_hook=failing_hook)
+
+    def test_compat_map(self):
+        """compat_map moves and renames mapped defs, leaving the rest alone."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"artist": {"type": "ref", "ref": "#artist"}},
+                        "required": ["artist"],
+                    },
+                },
+                "artist": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+                "label": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+            },
+        }
+        compat = {
+            "fm.plyr.track": {"module": "music.tracks", "class": "Track"},
+            "fm.plyr.track#artist": {"class": "Artist"},
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            compat_map = Path(tmpdir) / "compat.json"
+            compat_map.write_text(json.dumps(compat))
+
+            output_dir = Path(tmpdir) / "pkg_compat"
+            generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                compat_map=str(compat_map),
+            )
+            tracks = (output_dir / "music" / "tracks.py").read_text()
+            assert "class Track(BaseModel):" in tracks
+            assert "artist: Artist" in tracks
+            track = (output_dir / "fm" / "plyr" / "track.py").read_text()
+            assert "class Artist(BaseModel):" in track
+            assert "class FmPlyrTrackLabel(BaseModel):" in track
+            assert "class Track(" not in track
+
+            pkg = import_package(Path(tmpdir), "pkg_compat")
+            assert pkg.Track(artist={"name": "x"}).artist.name == "x"
+
+            compat_map.write_text(json.dumps({"fm.plyr.track": {"module": "a..b"}}))
+            with pytest.raises(ValueError, match="not a module path"):
+                generate(
+                    str(lexicon_dir), str(output_dir), compat_map=str(compat_map)
+                )
+
+    def test_file_hook(self):
+        """file_hook rewrites every generated file before it's written."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"title": {"type": "string"}},
+                    },
+                },
+            },
+        }
+
+        seen = []
+
+        def file_hook(path: str, content: str) -> str:
+            seen.append(path)
+            return "# license\n" + content
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir), file_hook=file_hook)
+            assert seen == files
+            assert Path(files[0]).read_text().startswith("# license\n")
+            assert load_module(files[0]).FmPlyrTrack(title="x").title == "x"
+
+            seen.clear()
+            output_dir = Path(tmpdir) / "pkg_hooked"
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                file_hook=file_hook,
+            )
+            python_files = sorted(str(p) for p in output_dir.rglob("*.py"))
+            assert sorted(seen) == python_files
+            for path in python_files:
+                assert Path(path).read_text().startswith("# license\n")
+            assert not (output_dir / "py.typed").read_text()
+            pkg = import_package(Path(tmpdir), "pkg_hooked")
+            assert pkg.FmPlyrTrack(title="x").title == "x"
+
+            # hooked files are still recognised as generated on the next run
+            init = (output_dir / "__init__.py").read_text()
+            generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                file_hook=file_hook,
+            )
+            assert (output_dir / "__init__.py").read_text() == init
+            assert "# BEGIN pmgfal" not in init
+
+            album = {**lexicon, "id": "fm.plyr.album"}
+            (lexicon_dir / "album.json").write_text(json.dumps(album))
+            options = {"layout": "package", "file_hook": file_hook, "clean": True}
+            generate(str(lexicon_dir), str(output_dir), **options)
+            assert (output_dir / "fm" / "plyr" / "album.py").exists()
+            (lexicon_dir / "album.json").unlink()
+            result = generate(str(lexicon_dir), str(output_dir), **options)
+            assert not (output_dir / "fm" / "plyr" / "album.py").exists()
+            assert result.removed == [str(output_dir / "fm" / "plyr" / "album.py")]
+
+            class HookFailed(Exception):
+                pass
+
+            def failing_hook(path: str, content: str) -> str:
+                raise HookFailed("no license")
+
+            with pytest.raises(RuntimeError, match="models.py") as excinfo:
+                generate(str(lexicon_dir), str(output_dir), file_hook=failing_hook)
+            assert isinstance(excinfo.value.__cause__, HookFailed)
+
+    def test_acronym_def_names(self):
+        """acronym def names get the same class name at definitions and refs."""
+        from pmgfal import generate
+
+        embed = {
+            "lexicon": 1,
+            "id": "fm.plyr.embed",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "required": ["preview", "client"],
+                        "properties": {
+                            "preview": {"type": "ref", "ref": "#URLPreview"},
+                            "client": {"type": "ref", "ref": "#OAuthClient"},
+                            "items": {
+                                "type": "array",
+                                "items": {"type": "ref", "ref": "#feedItem"},
+                            },
+                        },
+                    },
+                },
+                "URLPreview": {
+                    "type": "object",
+                    "properties": {"url": {"type": "string"}},
+                },
+                "OAuthClient": {
+                    "type": "object",
+                    "properties": {"id": {"type": "string"}},
+                },
+                "feedItem": {
+                    "type": "object",
+                    "properties": {"uri": {"type": "string"}},
+                },
+            },
+        }
+        post = {
+            "lexicon": 1,
+            "id": "fm.plyr.post",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "required": ["preview"],
+                        "properties": {
+                            "preview": {
+                                "type": "ref",
+                                "ref": "fm.plyr.embed#URLPreview",
+                            },
+                        },
+                    },
+                },
+            },
+        }
+        data = {"preview": {"url": "https://x"}, "client": {"id": "c"}}
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "embed.json").write_text(json.dumps(embed))
+            (lexicon_dir / "post.json").write_text(json.dumps(post))
+
+            files = generate(str(lexicon_dir), str(Path(tmpdir) / "default"))
+            module = load_module(files[0], "acronym_default")
+            record = module.FmPlyrEmbed.model_validate(data)
+            assert isinstance(record.preview, module.FmPlyrEmbedUrlPreview)
+            assert isinstance(record.client, module.FmPlyrEmbedOAuthClient)
+
+            files = generate(
+                str(lexicon_dir), str(Path(tmpdir) / "kept"), keep_acronyms=True
+            )
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrEmbedURLPreview(BaseModel):" in content
+            assert "preview: FmPlyrEmbedURLPreview" in content
+            assert "FmPlyrEmbedUrlPreview" not in content
+            module = load_module(files[0], "acronym_kept")
+            record = module.FmPlyrEmbed.model_validate(data)
+            assert isinstance(record.preview, module.FmPlyrEmbedURLPreview)
+            assert isinstance(record.client, module.FmPlyrEmbedOAuthClient)
+            assert hasattr(module, "FmPlyrEmbedFeedItem")
+            post_record = module.FmPlyrPost.model_validate({"preview": {"url": "y"}})
+            assert isinstance(post_record.preview, module.FmPlyrEmbedURLPreview)
+
+            generate(
+                str(lexicon_dir),
+                str(Path(tmpdir) / "pkg_acronyms"),
+                layout="package",
+                keep_acronyms=True,
+            )
+            pkg = import_package(Path(tmpdir), "pkg_acronyms")
+            post_record = pkg.FmPlyrPost.model_validate({"preview": {"url": "y"}})
+            assert isinstance(post_record.preview, pkg.FmPlyrEmbedURLPreview)
+
+    def test_pathological_descriptions(self):
+        """quotes and backslashes in lexicon strings still produce valid python."""
+        from pmgfal import generate
+
+        descriptions = {
+            "main": 'say """hi""" to C:\\path\\',
+            "quoted": "ends in a quote\"",
+            "mixed": "it's \"both\"\n\\n is not a newline",
+        }
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.note",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "description": descriptions["main"],
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "text": {"type": "string"},
+                            "extra": {"type": "ref", "ref": "#quoted"},
+                        },
+                        "required": ["text"],
+                    },
+                },
+                "quoted": {
+                    "type": "object",
+                    "description": descriptions["quoted"],
+                    "properties": {"inner": {"type": "ref", "ref": "#mixed"}},
+                },
+                "mixed": {
+                    "type": "object",
+                    "description": descriptions["mixed"],
+                    "properties": {},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "note.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            module = load_module(files[0])
+
+            assert module.FmPlyrNote.__doc__ == descriptions["main"]
+            assert module.FmPlyrNoteQuoted.__doc__ == descriptions["quoted"]
+            assert module.FmPlyrNoteMixed.__doc__ == descriptions["mixed"]
+
+    def test_populate_by_name(self):
+        """aliased models accept wire names and python names alike."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.profile",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "displayName": {"type": "string"},
+                            "from": {"type": "string"},
+                            "avatar": {"type": "blob"},
+                        },
+                        "required": ["displayName"],
+                    },
+                },
+            },
+        }
+        blob = {"ref": {"$link": "bafy"}, "size": 1}
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "profile.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            module = load_module(files[0])
+
+            by_alias = module.FmPlyrProfile.model_validate(
+                {
+                    "displayName": "nate",
+                    "from": "here",
+                    "avatar": {"$type": "blob", "mimeType": "image/png", **blob},
+                }
+            )
+            by_name = module.FmPlyrProfile(
+                display_name="nate",
+                from_="here",
+                avatar=module.BlobRef(type_="blob", mime_type="image/png", **blob),
+            )
+            assert by_alias == by_name
+            assert by_name.model_dump(by_alias=True)["displayName"] == "nate"
+
+    def test_constraints_in_schema(self):
+        """byte and grapheme lengths show up in the json schema."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.comment",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "text": {
+                                "type": "string",
+                                "maxLength": 3000,
+                                "maxGraphemes": 300,
+                            },
+                            "lang": {"type": "string"},
+                        },
+                        "required": ["text"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "comment.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "json_schema_extra" not in Path(files[0]).read_text()
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), constraints_in_schema=True
+            )
+            module = load_module(files[0])
+            properties = module.FmPlyrComment.model_json_schema()["properties"]
+            assert properties["text"]["maxLength"] == 3000
+            assert properties["text"]["maxGraphemes"] == 300
+            assert "maxGraphemes" not in properties["lang"]
+
+    def test_field_metadata_keys(self):
+        """listed custom property keys are copied into the json schema."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "title": {
+                                "type": "string",
+                                "maxLength": 256,
+                                "searchable": True,
+                                "facets": {"weight": 2, "tags": ["a", None]},
+                            },
+                            "plays": {"type": "integer", "searchable": False},
+                        },
+                        "required": ["title"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "json_schema_extra" not in Path(files[0]).read_text()
+
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                constraints_in_schema=True,
+                field_metadata_keys=["searchable", "facets"],
+            )
+            module = load_module(files[0])
+            properties = module.FmPlyrTrack.model_json_schema()["properties"]
+            assert properties["title"]["searchable"] is True
+            assert properties["title"]["maxLength"] == 256
+            assert properties["title"]["facets"] == {"weight": 2, "tags": ["a", None]}
+            assert properties["plays"]["searchable"] is False
+
+    def test_emit_titles(self):
+        """fields get a title and property-level examples for json schema."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "durationMs": {
+                                "type": "integer",
+                                "examples": [180000, 240000],
+                            },
+                            "isrc": {"type": "string", "title": "ISRC code"},
+                        },
+                        "required": ["durationMs"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "title=" not in Path(files[0]).read_text()
+
+            files = generate(str(lexicon_dir), str(output_dir), emit_titles=True)
+            assert (
+                'Field(alias="durationMs", title="Duration ms", '
+                "examples=[180000, 240000])"
+            ) in Path(files[0]).read_text()
+
+            module = load_module(files[0])
+            properties = module.FmPlyrTrack.model_json_schema()["properties"]
+            assert properties["durationMs"]["title"] == "Duration ms"
+            assert properties["durationMs"]["examples"] == [180000, 240000]
+            assert properties["isrc"]["title"] == "ISRC code"
+            assert "examples" not in properties["isrc"]
+
+    def test_names_in_strings_are_not_imported(self):
+        """a name appearing only inside a string literal isn't imported."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "createdAt": {"type": "string", "format": "datetime"},
+                            "kind": {"type": "string", "title": "Any"},
+                        },
+                        "required": ["createdAt"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                embed_metadata=True,
+                emit_titles=True,
+            )
+            content = Path(files[0]).read_text()
+            assert 'format="datetime"' in content
+            assert 'title="Any"' in content
+
+            imports = [
+                line
+                for line in content.splitlines()
+                if line.startswith(("import ", "from "))
+            ]
+            assert not any("datetime" in line for line in imports), imports
+            assert not any(re.search(r"\bAny\b", line) for line in imports), imports
+            assert load_module(files[0]).FmPlyrTrack(createdAt="x").kind is None
+
+    def test_schema_ids(self):
+        """schema_ids sets each model's json schema $id to its lexicon ref."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"artist": {"type": "ref", "ref": "#artist"}},
+                    },
+                },
+                "artist": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+                "empty": {"type": "object", "properties": {}},
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            [path] = generate(str(lexicon_dir), str(output_dir))
+            module = load_module(path)
+            assert "$id" not in module.FmPlyrTrack.model_json_schema()
+
+            [path] = generate(str(lexicon_dir), str(output_dir), schema_ids=True)
+            module = load_module(path)
+            schema = module.FmPlyrTrack.model_json_schema()
+            assert schema["$id"] == "fm.plyr.track"
+            assert schema["$defs"]["FmPlyrTrackArtist"]["$id"] == (
+                "fm.plyr.track#artist"
+            )
+            empty = module.FmPlyrTrackEmpty.model_json_schema()
+            assert empty["$id"] == "fm.plyr.track#empty"
+
+    def test_unknown_string_format(self):
+        """an unrecognized string format is typed as str, with a warning."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "thing": {"type": "string", "format": "future-thing"},
+                        },
+                        "required": ["thing"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            result = generate(str(lexicon_dir), str(Path(tmpdir) / "generated"))
+            assert "    thing: str" in Path(result[0]).read_text()
+            assert result.warnings == [
+                "unknown string format: future-thing in fm.plyr.track (typed as str)"
+            ]
+
+    def test_warnings_file(self):
+        """warnings_file writes each warning with its nsid, file and field."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "2fa": {"type": "string"},
+                            "when": {"type": "string", "format": "future-thing"},
+                            "label": {"type": "ref", "ref": "fm.plyr.missing"},
+                        },
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            report = output_dir / "reports" / "warnings.json"
+            result = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                warnings_file="reports/warnings.json",
+            )
+            entries = json.loads(report.read_text())
+            assert [entry["message"] for entry in entries] == result.warnings
+            assert entries == [
+                {
+                    "kind": "unknown_format",
+                    "nsid": "fm.plyr.track",
+                    "path": str(lexicon_dir / "track.json"),
+                    "field": None,
+                    "message": "unknown string format: future-thing in "
+                    "fm.plyr.track (typed as str)",
+                },
+                {
+                    "kind": "invalid_field",
+                    "nsid": "fm.plyr.track",
+                    "path": str(lexicon_dir / "track.json"),
+                    "field": "fm.plyr.track#main.2fa",
+                    "message": 'fm.plyr.track#main: property "2fa" is not a '
+                    "valid python identifier, skipped",
+                },
+                {
+                    "kind": "unresolved_ref",
+                    "nsid": "fm.plyr.missing",
+                    "path": None,
+                    "field": None,
+                    "message": "unresolved ref: fm.plyr.missing",
+                },
+            ]
+            assert str(report) not in result.files
+
+            del lexicon["defs"]["main"]["record"]["properties"]["2fa"]
+            del lexicon["defs"]["main"]["record"]["properties"]["when"]
+            lexicon["defs"]["main"]["record"]["properties"]["label"] = {
+                "type": "string"
+            }
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            result = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                warnings_file="reports/warnings.json",
+            )
+            assert result.warnings == []
+            assert not report.exists()
+
+    def test_single_file_collisions(self):
+        """one file resolves cross-nsid name clashes and rebuilds every model."""
+        from pmgfal import generate
+
+        track = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "item": {"type": "ref", "ref": "#item"},
+                            "other": {"type": "ref", "ref": "fm.plyr.trackItem"},
+                        },
+                    },
+                },
+                "item": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+            },
+        }
+        track_item = {
+            "lexicon": 1,
+            "id": "fm.plyr.trackItem",
+            "defs": {
+                "main": {
+                    "type": "object",
+                    "properties": {"count": {"type": "integer"}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(track))
+            (lexicon_dir / "trackItem.json").write_text(json.dumps(track_item))
+
+            files = generate(str(lexicon_dir), str(Path(tmpdir) / "generated"))
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrTrackItem(BaseModel):" in content
+            assert "class FmPlyrTrackItem_fm_plyr_track_item(BaseModel):" in content
+            assert "FmPlyrTrack.model_rebuild()" in content
+            assert content.count("from pydantic import") == 1
+
+            module = load_module(files[0])
+            record = module.FmPlyrTrack(item={"name": "x"}, other={"count": 1})
+            assert record.item.name == "x"
+            assert record.other.count == 1
+
+    def test_ref_to_later_nsid(self):
+        """a ref to an nsid sorting later is defined or imported before use."""
+        from pmgfal import generate
+
+        first = {
+            "lexicon": 1,
+            "id": "a.b.c",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"item": {"type": "ref", "ref": "z.y.x#item"}},
+                    },
+                },
+                "items": {
+                    "type": "array",
+                    "items": {"type": "ref", "ref": "z.y.x#item"},
+                },
+            },
+        }
+        last = {
+            "lexicon": 1,
+            "id": "z.y.x",
+            "defs": {
+                "item": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "a.json").write_text(json.dumps(first))
+            (lexicon_dir / "z.json").write_text(json.dumps(last))
+
+            files = generate(str(lexicon_dir), str(Path(tmpdir) / "generated"))
+            module = load_module(files[0])
+            items = module.ABCItemsAdapter.validate_python([{"name": "x"}])
+            assert isinstance(items[0], module.ZYXItem)
+
+            output_dir = Path(tmpdir) / "pkg_order"
+            generate(str(lexicon_dir), str(output_dir), layout="package")
+            content = (output_dir / "a" / "b" / "c.py").read_text()
+            assert "from ...z.y.x import ZYXItem" in content
+            pkg = import_package(Path(tmpdir), "pkg_order")
+            assert pkg.ABC(item={"name": "x"}).item.name == "x"
+
+    def test_union_adapters(self):
+        """closed unions get a reusable adapter discriminated by $type."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.post",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "embed": {
+                                "type": "union",
+                                "refs": ["#images", "#external"],
+                                "closed": True,
+                            },
+                            "extra": {
+                                "type": "union",
+                                "refs": ["#images", "#external"],
+                            },
+                        },
+                    },
+                },
+                "images": {
+                    "type": "object",
+                    "properties": {"alt": {"type": "string"}},
+                },
+                "external": {
+                    "type": "object",
+                    "properties": {"uri": {"type": "string"}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "post.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir))
+            assert "Adapter" not in Path(files[0]).read_text()
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), emit_union_adapters=True
+            )
+            content = Path(files[0]).read_text()
+            assert "FmPlyrPostExtraAdapter" not in content
+
+            module = load_module(files[0])
+            adapter = module.FmPlyrPostEmbedAdapter
+            embed = adapter.validate_python({"$type": "fm.plyr.post#external"})
+            assert isinstance(embed, module.FmPlyrPostExternal)
+            with pytest.raises(ValueError, match="fm.plyr.other"):
+                adapter.validate_python({"$type": "fm.plyr.other"})
+
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                emit_union_adapters=True,
+                emit_invariant_validators=True,
+                discriminator_field="kind",
+            )
+            module = load_module(files[0])
+            adapter = module.FmPlyrPostEmbedAdapter
+            embed = adapter.validate_python({"kind": "fm.plyr.post#images"})
+            assert isinstance(embed, module.FmPlyrPostImages)
+            with pytest.raises(ValueError, match="kind must be one of"):
+                module.FmPlyrPost(embed={"kind": "fm.plyr.other"})
+
+    def test_typing_syntax(self):
+        """syntax="typing" spells unions with Optional/Union from typing."""
+        import ast
+
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.post",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "text": {"type": "string"},
+                            "plays": {"type": "integer", "minimum": 0},
+                            "embed": {
+                                "type": "union",
+                                "refs": ["#images", "#external"],
+                                "closed": True,
+                            },
+                            "embeds": {
+                                "type": "array",
+                                "items": {
+                                    "type": "union",
+                                    "refs": ["#images", "#external"],
+                                },
+                            },
+                        },
+                        "required": ["text"],
+                    },
+                },
+                "images": {
+                    "type": "object",
+                    "properties": {"alt": {"type": "string"}},
+                },
+                "external": {
+                    "type": "object",
+                    "properties": {"uri": {"type": "string"}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "post.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            for syntax in ["pep604", "typing"]:
+                files = generate(
+                    str(lexicon_dir),
+                    str(output_dir),
+                    syntax=syntax,
+                    emit_union_adapters=True,
+                )
+                content = Path(files[0]).read_text()
+                ast.parse(content, feature_version=(3, 9))
+                module = load_module(files[0])
+                embed = module.FmPlyrPostImages(alt="cover")
+                post = module.FmPlyrPost(text="hi", embed=embed, plays=3)
+                assert post.embed == embed
+
+            typing_import = next(
+                line for line in content.splitlines() if line.startswith("from typing")
+            )
+            assert "Optional" in typing_import and "Union" in typing_import
+            annotations = module.FmPlyrPost.__annotations__
+            assert annotations["plays"] == "Optional[Annotated[int, Field(ge=0)]]"
+            assert annotations["embed"] == (
+                "Optional[Union[FmPlyrPostImages, FmPlyrPostExternal]]"
+            )
+            assert annotations["embeds"] == (
+                "Optional[list[Union[FmPlyrPostImages, FmPlyrPostExternal]]]"
+            )
+            assert module.FmPlyrPostEmbedAdapter.validate_python(
+                {"$type": "fm.plyr.post#external", "uri": "https://x"}
+            ) == module.FmPlyrPostExternal(uri="https://x")
+
+            with pytest.raises(ValueError, match="unknown syntax"):
+                generate(str(lexicon_dir), str(output_dir), syntax="pep484")
+
+    def test_target_python(self):
+        """target_python picks union and generic spellings for its version."""
+        import ast
+
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.playlist",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "name": {"type": "string"},
+                            "tracks": {"type": "array", "items": {"type": "string"}},
+                        },
+                        "required": ["name"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "playlist.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            expected = {
+                "3.8": "Optional[List[str]]",
+                "3.9": "Optional[List[str]]",
+                "3.10": "list[str] | None",
+                "3.12": "list[str] | None",
+            }
+            for version, tracks_type in expected.items():
+                files = generate(
+                    str(lexicon_dir), str(output_dir), target_python=version
+                )
+                content = Path(files[0]).read_text()
+                major, minor = map(int, version.split("."))
+                ast.parse(content, feature_version=(major, minor))
+                assert "from __future__ import annotations" in content
+
+                module = load_module(files[0])
+                annotations = module.FmPlyrPlaylist.__annotations__
+                assert annotations["tracks"] == tracks_type
+                playlist = module.FmPlyrPlaylist(name="mix", tracks=["a"])
+                assert playlist.tracks == ["a"]
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), target_python="3.9", syntax="pep604"
+            )
+            module = load_module(files[0])
+            assert module.FmPlyrPlaylist.__annotations__["tracks"] == (
+                "List[str] | None"
+            )
+
+            with pytest.raises(ValueError, match="unsupported target python"):
+                generate(str(lexicon_dir), str(output_dir), target_python="3.7")
+
+    def test_target_python_38(self):
+        """3.8 output never subscripts a builtin generic where it's evaluated."""
+        import ast
+        import shutil
+        import subprocess
+
+        from pmgfal import generate
+
+        builtin_generics = {"list", "dict", "tuple", "type", "set", "frozenset"}
+
+        def evaluated_generics(tree: ast.AST) -> list[str]:
+            """builtin generics outside functions and module-level annotations,
+            which python 3.8 can't subscript at runtime."""
+            lazy = set()
+            for node in ast.walk(tree):
+                if isinstance(node, (ast.FunctionDef, ast.AsyncFunctionDef)):
+                    lazy.update(map(id, ast.walk(node)))
+            for node in getattr(tree, "body", []):
+                if isinstance(node, ast.AnnAssign):
+                    lazy.update(map(id, ast.walk(node.annotation)))
+            return [
+                ast.unparse(node)
+                for node in ast.walk(tree)
+                if isinstance(node, ast.Subscript)
+                and isinstance(node.value, ast.Name)
+                and node.value.id in builtin_generics
+                and id(node) not in lazy
+            ]
+
+        fixtures = Path(__file__).parent / "fixtures" / "strict"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            output_dir = Path(tmpdir) / "generated38"
+            generate(
+                str(fixtures),
+                str(output_dir),
+                target_python="3.8",
+                layout="package",
+                embed_metadata=True,
+                emit_union_adapters=True,
+                emit_invariant_validators=True,
+                typed_datetimes=True,
+            )
+            for path in output_dir.rglob("*.py"):
+                content = path.read_text()
+                tree = ast.parse(content, feature_version=(3, 8))
+                assert evaluated_generics(tree) == [], path
+                assert "removesuffix" not in content
+                for node in ast.walk(tree):
+                    if isinstance(node, ast.ImportFrom) and node.module == "typing":
+                        assert "Annotated" not in {a.name for a in node.names}
+
+            pkg = import_package(Path(tmpdir), "generated38")
+            assert pkg.__all__
+
+            # import under a real 3.8 where one with pydantic is around
+            python38 = shutil.which("python3.8")
+            has_pydantic = python38 is not None and (
+                subprocess.run(
+                    [python38, "-c", "import pydantic"], capture_output=True
+                ).returncode
+                == 0
+            )
+            if has_pydantic:
+                result = subprocess.run(
+                    [python38, "-c", "import generated38"],
+                    cwd=tmpdir,
+                    capture_output=True,
+                    text=True,
+                )
+                assert result.returncode == 0, result.stderr
+
+    def test_wide_unions_wrap(self):
+        """annotations wider than max_annotation_width break per member."""
+        from pmgfal import generate
+
+        kinds = [
+            "alpha", "bravo", "charlie", "delta", "echo", "foxtrot",
+            "golf", "hotel", "india", "juliett", "kilo", "lima",
+        ]  # fmt: skip
+        defs = {
+            kind: {"type": "object", "properties": {"n": {"type": "integer"}}}
+            for kind in kinds
+        }
+        refs = [f"#{kind}" for kind in kinds]
+        defs["main"] = {
+            "type": "record",
+            "record": {
+                "type": "object",
+                "properties": {"entry": {"type": "union", "refs": refs}},
+            },
+        }
+        defs["items"] = {
+            "type": "array",
+            "items": {"type": "union", "refs": refs},
+        }
+        lexicon = {"lexicon": 1, "id": "fm.plyr.feed", "defs": defs}
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "feed.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            files = generate(str(lexicon_dir), str(output_dir))
+            lines = Path(files[0]).read_text().splitlines()
+            assert max(len(line) for line in lines) > 88
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), max_annotation_width=88
+            )
+            content = Path(files[0]).read_text()
+            assert all(len(line) <= 88 for line in content.splitlines())
+            assert "    entry: (\n        FmPlyrFeedAlpha\n" in content
+            assert "        | FmPlyrFeedBravo\n" in content
+            assert "FmPlyrFeedItems = list[\n" in content
+
+            module = load_module(files[0])
+            feed = module.FmPlyrFeed.model_validate({"entry": {"n": 1}})
+            assert feed.entry.n == 1
+            adapter = module.FmPlyrFeedItemsAdapter
+            assert adapter.validate_python([{"n": 2}])[0].n == 2
+
+    def test_indent_and_quote_style(self):
+        """tabs and single quotes restyle the output without changing it."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "description": "a track's record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string"},
+                            "durationMs": {"type": "integer"},
+                        },
+                        "required": ["title"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), indent="tabs", quote_style="single"
+            )
+            content = Path(files[0]).read_text()
+            indented = [line for line in content.splitlines() if line[:1].isspace()]
+            assert indented
+            assert all(line.startswith("\t") for line in indented)
+            assert "    " not in content
+            assert "alias='durationMs'" in content
+            assert '"""a track\'s record"""' in content
+
+            module = load_module(files[0])
+            track = module.FmPlyrTrack.model_validate({"title": "a", "durationMs": 5})
+            assert track.duration_ms == 5
+
+            with pytest.raises(ValueError, match="unknown indent"):
+                generate(str(lexicon_dir), str(output_dir), indent="2")
+
+    def test_docstring_style(self):
+        """docstring_style documents described fields in the class docstring."""
+        import inspect
+
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "description": "a track",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string", "description": "the title"},
+                            "durationMs": {
+                                "type": "integer",
+                                "description": "length in\nmilliseconds",
+                            },
+                            "genre": {"type": "string"},
+                        },
+                        "required": ["title"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            files = generate(str(lexicon_dir), str(output_dir))
+            module = load_module(files[0])
+            assert inspect.getdoc(module.FmPlyrTrack) == "a track"
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), docstring_style="google"
+            )
+            module = load_module(files[0])
+            assert inspect.getdoc(module.FmPlyrTrack) == (
+                "a track\n\n"
+                "Attributes:\n"
+                "    title: the title\n"
+                "    duration_ms: length in milliseconds"
+            )
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), docstring_style="napoleon"
+            )
+            module = load_module(files[0])
+            assert inspect.getdoc(module.FmPlyrTrack) == (
+                "a track\n\n"
+                "Attributes\n"
+                "----------\n"
+                "title\n"
+                "    the title\n"
+                "duration_ms\n"
+                "    length in milliseconds"
+            )
+
+            with pytest.raises(ValueError, match="unknown docstring_style"):
+                generate(str(lexicon_dir), str(output_dir), docstring_style="rst")
+
+    def test_union_of_external_records(self):
+        """a union member that is another nsid's record is tagged by bare nsid."""
+        from pmgfal import generate
+
+        title = {"title": {"type": "string"}}
+        pin = {
+            "lexicon": 1,
+            "id": "fm.plyr.pin",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "subject": {
+                                "type": "union",
+                                "refs": ["fm.plyr.track", "fm.plyr.album#main"],
+                                "closed": True,
+                            },
+                        },
+                        "required": ["subject"],
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            lexicons = [
+                record("fm.plyr.track", title, "title"),
+                record("fm.plyr.album", title, "title"),
+                pin,
+            ]
+            for lex in lexicons:
+                (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                emit_union_adapters=True,
+                emit_invariant_validators=True,
+            )
+            content = Path(files[0]).read_text()
+            assert "subject: FmPlyrTrack | FmPlyrAlbum" in content
+            assert 'Tag("fm.plyr.track")' in content
+            assert 'Tag("fm.plyr.album")' in content
+            assert "#main" not in content
+
+            # adapter members and checks are sorted by $type, not lexicon order
+            assert content.index('Tag("fm.plyr.album")') < content.index(
+                'Tag("fm.plyr.track")'
+            )
+            assert '("fm.plyr.album", "fm.plyr.track",)' in content
+
+            module = load_module(files[0])
+            adapter = module.FmPlyrPinSubjectAdapter
+            album = adapter.validate_python({"$type": "fm.plyr.album", "title": "a"})
+            assert isinstance(album, module.FmPlyrAlbum)
+            pin_model = module.FmPlyrPin(
+                subject={"$type": "fm.plyr.track", "title": "t"}
+            )
+            assert isinstance(pin_model.subject, module.FmPlyrTrack)
+            with pytest.raises(ValueError, match="must be one of"):
+                module.FmPlyrPin(subject={"$type": "fm.plyr.album#main", "title": "a"})
+
+    def test_exclude_collapses_unions(self):
+        """excluding union members leaves the bare type, or Any if none remain."""
+        from pmgfal import generate
+
+        lexicons = [
+            {
+                "lexicon": 1,
+                "id": "fm.plyr.embed",
+                "defs": {
+                    "images": object_def({"alt": {"type": "string"}}),
+                    "video": object_def({"captions": {"type": "string"}}),
+                    "external": object_def({"uri": {"type": "string"}}),
+                },
+            },
+            {
+                "lexicon": 1,
+                "id": "fm.plyr.post",
+                "defs": {
+                    "main": {
+                        "type": "record",
+                        "record": {
+                            "type": "object",
+                            "properties": {
+                                "embed": {
+                                    "type": "union",
+                                    "refs": [
+                                        "fm.plyr.embed#images",
+                                        "fm.plyr.embed#video",
+                                        "fm.plyr.embed#external",
+                                    ],
+                                    "closed": True,
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+        ]
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for lex in lexicons:
+                (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+
+            output_dir = Path(tmpdir) / "generated"
+            result = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                emit_union_adapters=True,
+                exclude=["fm.plyr.embed#video", "fm.plyr.embed#ext*"],
+            )
+            content = Path(result.files[0]).read_text()
+            assert "embed: FmPlyrEmbedImages | None" in content
+            assert "FmPlyrEmbedVideo" not in content
+            assert "FmPlyrEmbedExternal" not in content
+            assert "Adapter" not in content
+            assert result.warnings == []
+
+            module = load_module(result.files[0])
+            post = module.FmPlyrPost(embed={"alt": "cover"})
+            assert isinstance(post.embed, module.FmPlyrEmbedImages)
+
+            result = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                emit_invariant_validators=True,
+                exclude=["fm.plyr.embed"],
+            )
+            content = Path(result.files[0]).read_text()
+            assert "embed: Any | None" in content
+            assert "check_union_type" not in content
+            assert result.warnings == [
+                "fm.plyr.post#main.embed: every union member is excluded, typed as Any"
+            ]
+
+    def test_alias_repeated_unions(self):
+        """a union shared by several fields is emitted once and reused."""
+        from pmgfal import generate
+
+        images, video = "fm.plyr.embed#images", "fm.plyr.embed#video"
+        lexicons = [
+            {
+                "lexicon": 1,
+                "id": "fm.plyr.embed",
+                "defs": {
+                    "images": object_def({"alt": {"type": "string"}}, "alt"),
+                    "video": object_def({"captions": {"type": "string"}}, "captions"),
+                },
+            },
+            record(
+                "fm.plyr.reply", {"media": {"type": "union", "refs": [video, images]}}
+            ),
+            record(
+                "fm.plyr.post", {"embed": {"type": "union", "refs": [images, video]}}
+            ),
+        ]
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for lex in lexicons:
+                (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+
+            output_dir = Path(tmpdir) / "generated"
+            result = generate(
+                str(lexicon_dir), str(output_dir), alias_repeated_unions=True
+            )
+            content = Path(result.files[0]).read_text()
+            # named after fm.plyr.post, which sorts first, in its member order
+            assert content.count("FmPlyrPostEmbedUnion = ") == 1
+            assert (
+                "FmPlyrPostEmbedUnion = FmPlyrEmbedImages | FmPlyrEmbedVideo"
+                in content
+            )
+            assert "embed: FmPlyrPostEmbedUnion | None" in content
+            assert "media: FmPlyrPostEmbedUnion | None" in content
+
+            module = load_module(result.files[0])
+            reply = module.FmPlyrReply(media={"captions": "en"})
+            assert isinstance(reply.media, module.FmPlyrEmbedVideo)
+
+            generate(
+                str(lexicon_dir),
+                str(Path(tmpdir) / "pkg_unions"),
+                layout="package",
+                alias_repeated_unions=True,
+            )
+            pkg = import_package(Path(tmpdir), "pkg_unions")
+            assert "FmPlyrPostEmbedUnion" in pkg.__all__
+            post = pkg.FmPlyrPost(embed={"alt": "cover"})
+            assert isinstance(post.embed, pkg.FmPlyrEmbedImages)
+
+    def test_records_only(self):
+        """records_only keeps records and their dependencies, nothing else."""
+        from pmgfal import generate
+
+        track = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "artist": {"type": "ref", "ref": "#artist"},
+                        },
+                        "required": ["artist"],
+                    },
+                },
+                "artist": {
+                    "type": "object",
+                    "properties": {"label": {"type": "ref", "ref": "#label"}},
+                },
+                "label": {
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                },
+                "unused": {
+                    "type": "object",
+                    "properties": {"x": {"type": "string"}},
+                },
+            },
+        }
+        get_tracks = {
+            "lexicon": 1,
+            "id": "fm.plyr.getTracks",
+            "defs": {
+                "main": {
+                    "type": "query",
+                    "parameters": {
+                        "type": "params",
+                        "properties": {"limit": {"type": "integer"}},
+                    },
+                }
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(track))
+            (lexicon_dir / "getTracks.json").write_text(json.dumps(get_tracks))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir), records_only=True)
+
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrTrack(BaseModel):" in content
+            assert "class FmPlyrTrackArtist(BaseModel):" in content
+            assert "class FmPlyrTrackLabel(BaseModel):" in content
+            assert "FmPlyrTrackUnused" not in content
+            assert "FmPlyrGetTracksParams" not in content
+            assert "# fm.plyr.getTracks" not in content
+
+            module = load_module(files[0])
+            record = module.FmPlyrTrack(artist={"label": {"name": "x"}})
+            assert record.artist.label.name == "x"
+
+    def test_seeds(self):
+        """seeds generate exactly the defs they reach, across documents."""
+        from pmgfal import generate
+
+        lexicons = [
+            record(
+                "fm.plyr.post",
+                {
+                    "embed": {
+                        "type": "union",
+                        "refs": ["fm.plyr.embed#image", "fm.plyr.embed#quote"],
+                    },
+                },
+            ),
+            {
+                "lexicon": 1,
+                "id": "fm.plyr.embed",
+                "defs": {
+                    "image": {
+                        "type": "object",
+                        "properties": {
+                            "media": {"type": "ref", "ref": "fm.plyr.media"},
+                        },
+                    },
+                    "quote": {
+                        "type": "object",
+                        "properties": {
+                            "subject": {
+                                "type": "ref",
+                                "ref": "com.atproto.repo.strongRef",
+                            },
+                        },
+                    },
+                    "unused": {
+                        "type": "object",
+                        "properties": {"x": {"type": "string"}},
+                    },
+                },
+            },
+            {
+                "lexicon": 1,
+                "id": "fm.plyr.media",
+                "defs": {
+                    "main": {
+                        "type": "object",
+                        "properties": {"alt": {"type": "string"}},
+                    },
+                },
+            },
+            record("fm.plyr.unrelated", {"x": {"type": "string"}}),
+        ]
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for lex in lexicons:
+                (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(lexicon_dir), str(output_dir), seeds=["fm.plyr.post"])
+
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrPost(BaseModel):" in content
+            assert "class FmPlyrEmbedImage(BaseModel):" in content
+            assert "class FmPlyrMedia(BaseModel):" in content
+            assert "class ComAtprotoRepoStrongRef(BaseModel):" in content
+            assert "FmPlyrEmbedUnused" not in content
+            assert "FmPlyrUnrelated" not in content
+
+            module = load_module(files[0], "seeded_models")
+            post = module.FmPlyrPost(
+                embed={"$type": "fm.plyr.embed#image", "media": {"alt": "a"}}
+            )
+            assert post.embed.media.alt == "a"
+
+            files = generate(
+                str(lexicon_dir), str(output_dir), seeds=["fm.plyr.embed#image"]
+            )
+            content = Path(files[0]).read_text()
+            assert "class FmPlyrMedia(BaseModel):" in content
+            assert "FmPlyrPost" not in content
+            assert "FmPlyrEmbedQuote" not in content
+
+            with pytest.raises(ValueError, match="fm.plyr.nope#main"):
+                generate(str(lexicon_dir), str(output_dir), seeds=["fm.plyr.nope"])
+
+    def test_invalid_lexicon_raises(self):
+        """a file declaring a lexicon that doesn't deserialize is an error."""
+        from pmgfal import InvalidLexiconError, LexiconError, generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "broken.json").write_text(
+                json.dumps({"lexicon": 1, "id": "fm.plyr.broken", "defs": 3})
+            )
+            (lexicon_dir / "package.json").write_text(json.dumps({"name": "x"}))
+
+            with pytest.raises(InvalidLexiconError, match="broken.json"):
+                generate(str(lexicon_dir), str(Path(tmpdir) / "generated"))
+            assert issubclass(InvalidLexiconError, LexiconError)
+            assert issubclass(LexiconError, ValueError)
+
+    def test_manifest_selects_files(self):
+        """only the files a manifest lists are parsed."""
+        from pmgfal import LexiconError, generate, generate_with_hash
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            (lexicon_dir / "fm" / "plyr").mkdir(parents=True)
+            for name in ["track", "album", "draft"]:
+                path = lexicon_dir / "fm" / "plyr" / f"{name}.json"
+                lexicon = record(f"fm.plyr.{name}", {"title": {"type": "string"}})
+                path.write_text(json.dumps(lexicon))
+            # a stray file that would fail the build if it were read
+            (lexicon_dir / "broken.json").write_text(
+                json.dumps({"lexicon": 1, "id": "fm.plyr.broken", "defs": 3})
+            )
+            output_dir = Path(tmpdir) / "generated"
+
+            manifest = Path(tmpdir) / "lexicons.txt"
+            manifest.write_text(
+                "# lexicons in this build\n./fm/plyr/track.json\n\nfm/plyr/album.json\n"
+            )
+            result = generate(
+                str(lexicon_dir), str(output_dir), manifest_path=str(manifest)
+            )
+            content = Path(result.files[0]).read_text()
+            assert "class FmPlyrTrack(" in content
+            assert "class FmPlyrAlbum(" in content
+            assert "FmPlyrDraft" not in content
+
+            manifest = Path(tmpdir) / "manifest.json"
+            manifest.write_text(json.dumps({"imports": ["fm/plyr/track.json"]}))
+            hash_track, result = generate_with_hash(
+                str(lexicon_dir), str(output_dir), manifest_path=str(manifest)
+            )
+            content = Path(result.files[0]).read_text()
+            assert "class FmPlyrTrack(" in content
+            assert "FmPlyrAlbum" not in content
+
+            listed = ["fm/plyr/track.json", "fm/plyr/album.json"]
+            manifest.write_text(json.dumps(listed))
+            hash_both, _ = generate_with_hash(
+                str(lexicon_dir), str(output_dir), manifest_path=str(manifest)
+            )
+            assert hash_both != hash_track
+
+            manifest.write_text(json.dumps(["fm/plyr/missing.json"]))
+            with pytest.raises(LexiconError, match="missing.json"):
+                generate(str(lexicon_dir), str(output_dir), manifest_path=str(manifest))
+
+    def test_duplicate_nsid_raises(self):
+        """two files declaring the same nsid are an error."""
+        from pmgfal import DuplicateNsidError, generate
+
+        lexicon = {"lexicon": 1, "id": "fm.plyr.dup", "defs": {}}
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            (lexicon_dir / "nested").mkdir(parents=True)
+            (lexicon_dir / "a.json").write_text(json.dumps(lexicon))
+            (lexicon_dir / "nested" / "b.json").write_text(json.dumps(lexicon))
+
+            with pytest.raises(DuplicateNsidError, match="fm.plyr.dup"):
+                generate(str(lexicon_dir), str(Path(tmpdir) / "generated"))
+
+    def test_merge_split_nsids(self):
+        """merge_split_nsids joins the defs of files sharing an nsid."""
+        from pmgfal import DuplicateNsidError, generate
+
+        fixtures = Path(__file__).parent / "fixtures" / "split_nsid"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            output_dir = Path(tmpdir) / "generated"
+            with pytest.raises(DuplicateNsidError, match="fm.plyr.track"):
+                generate(str(fixtures), str(output_dir))
+
+            files = generate(str(fixtures), str(output_dir), merge_split_nsids=True)
+            content = Path(files[0]).read_text()
+            classes = re.findall(r"^class (\w+)\(BaseModel\):", content, re.M)
+            assert sorted(classes) == [
+                "FmPlyrTrack",
+                "FmPlyrTrackAlbum",
+                "FmPlyrTrackArtist",
+            ]
+
+            module = load_module(files[0])
+            track = module.FmPlyrTrack(title="song", artist={"name": "x"})
+            assert track.artist.name == "x"
+
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            shutil.copytree(fixtures, lexicon_dir)
+            conflicting = {
+                "lexicon": 1,
+                "id": "fm.plyr.track",
+                "defs": {"album": {"type": "object", "properties": {}}},
+            }
+            (lexicon_dir / "fm" / "plyr" / "album.json").write_text(
+                json.dumps(conflicting)
+            )
+            with pytest.raises(DuplicateNsidError, match="def album of fm.plyr.track"):
+                generate(str(lexicon_dir), str(output_dir), merge_split_nsids=True)
+
+    def test_oversized_file_raises(self):
+        """a file over max_file_size is rejected before it's parsed."""
+        from pmgfal import LexiconError, generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.big",
+            "description": "x" * 4096,
+            "defs": {"main": {"type": "object", "properties": {}}},
+        }
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "big.json").write_text(json.dumps(lexicon))
+            output_dir = Path(tmpdir) / "generated"
+
+            with pytest.raises(LexiconError, match="big.json.*over the 1024 byte"):
+                generate(str(lexicon_dir), str(output_dir), max_file_size=1024)
+            assert generate(str(lexicon_dir), str(output_dir))
+
+    def test_deeply_nested_file_raises(self):
+        """json nested past max_depth is rejected before it's deserialized."""
+        from pmgfal import LexiconError, generate
+
+        depth = 10_000
+        nested = '{"a": ' * depth + "1" + "}" * depth
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "deep.json").write_text(
+                '{"lexicon": 1, "id": "fm.plyr.deep", "defs": {}, "x": ' + nested + "}"
+            )
+            output_dir = Path(tmpdir) / "generated"
+
+            with pytest.raises(LexiconError, match="deep.json.*limit of 64"):
+                generate(str(lexicon_dir), str(output_dir))
+            with pytest.raises(LexiconError, match="limit of 8"):
+                generate(str(lexicon_dir), str(output_dir), max_depth=8)
+
+    def test_output_is_stable(self):
+        """regenerating yields byte-identical lf files ending in one newline."""
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "description": "a track\r\nwith windows line endings",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "zeta": {"type": "string"},
+                            "alpha": {"type": "string"},
+                            "mid": {"type": "integer"},
+                        },
+                        "required": ["zeta"],
+                    },
+                },
+                "other": {"type": "object", "properties": {}},
+                "another": {"type": "object", "properties": {}},
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+
+            first = Path(tmpdir) / "first"
+            second = Path(tmpdir) / "second"
+            first_bytes = Path(generate(str(lexicon_dir), str(first))[0]).read_bytes()
+            second_bytes = Path(generate(str(lexicon_dir), str(second))[0]).read_bytes()
+
+            assert first_bytes == second_bytes
+            assert first_bytes.endswith(b"\n")
+            assert not first_bytes.endswith(b"\n\n")
+            assert b"\r" not in first_bytes
+            assert not first_bytes.startswith(b"\xef\xbb\xbf")
+
+
+class TestPackageLayout:
+    """test generating a python package instead of a single module."""
+
+    LEXICONS = [
+        {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string"},
+                            "features": {
+                                "type": "array",
+                                "items": {"type": "ref", "ref": "#featuredArtist"},
+                            },
+                        },
+                        "required": ["title"],
+                    },
+                },
+                "featuredArtist": {
+                    "type": "object",
+                    "properties": {"did": {"type": "string"}},
+                    "required": ["did"],
+                },
+            },
+        },
+        {
+            "lexicon": 1,
+            "id": "fm.plyr.like",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {
+                            "subject": {
+                                "type": "ref",
+                                "ref": "com.atproto.repo.strongRef",
+                            },
+                        },
+                        "required": ["subject"],
+                    },
+                },
+            },
+        },
+    ]
+
+    def _write_lexicons(self, tmpdir: str) -> Path:
+        lexicon_dir = Path(tmpdir) / "lexicons"
+        lexicon_dir.mkdir()
+        for lex in self.LEXICONS:
+            (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+        return lexicon_dir
+
+    def test_one_module_per_nsid(self):
+        """each nsid gets a module and cross-nsid refs become imports."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_layout"
+            generate(str(lexicon_dir), str(output_dir), layout="package")
+
+            like = output_dir / "fm" / "plyr" / "like.py"
+            assert like.exists()
+            assert (output_dir / "com" / "atproto" / "repo" / "strong_ref.py").exists()
+            assert (
+                "from ...com.atproto.repo.strong_ref import ComAtprotoRepoStrongRef"
+                in like.read_text()
+            )
+
+            py_typed = output_dir / "py.typed"
+            assert py_typed.read_text() == ""
+
+            pkg = import_package(Path(tmpdir), "pkg_layout")
+            like_model = pkg.FmPlyrLike(subject={"uri": "at://x", "cid": "bafy"})
+            assert like_model.subject.cid == "bafy"
+
+    def test_hand_written_init_survives(self):
+        """regenerating keeps hand-written __init__.py content around the region."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_existing"
+            output_dir.mkdir()
+            init = output_dir / "__init__.py"
+            init.write_text('"""my package"""\n\nVERSION = "1.0"\n')
+
+            generate(str(lexicon_dir), str(output_dir), layout="package")
+            content = init.read_text()
+            assert content.startswith('"""my package"""\n\nVERSION = "1.0"\n')
+            assert "# BEGIN pmgfal\n" in content
+            assert content.endswith("# END pmgfal\n")
+            assert "from __future__" not in content
+
+            init.write_text(content + "\n\ndef helper() -> str:\n    return VERSION\n")
+            generate(str(lexicon_dir), str(output_dir), layout="package")
+            regenerated = init.read_text()
+            assert regenerated.count("# BEGIN pmgfal") == 1
+            assert regenerated.startswith(content)
+            assert regenerated.endswith("def helper() -> str:\n    return VERSION\n")
+
+            pkg = import_package(Path(tmpdir), "pkg_existing")
+            assert pkg.helper() == "1.0"
+            like_model = pkg.FmPlyrLike(subject={"uri": "at://x", "cid": "bafy"})
+            assert like_model.subject.cid == "bafy"
+
+    def test_missing_output_dir(self):
+        """a missing output directory is created, parents included."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            not_a_dir = Path(tmpdir) / "models.py"
+            not_a_dir.write_text("")
+
+            for layout in ["single", "package"]:
+                output_dir = Path(tmpdir) / layout / "does" / "not" / "exist"
+                files = generate(str(lexicon_dir), str(output_dir), layout=layout)
+                assert files
+                assert all(Path(f).is_file() for f in files)
+
+                with pytest.raises(NotADirectoryError, match="not a directory"):
+                    generate(str(lexicon_dir), str(not_a_dir), layout=layout)
+                assert not_a_dir.read_text() == ""
+
+    def test_lazy_imports(self):
+        """lazy_imports defers importing a module until its names are used."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_lazy"
+            generate(
+                str(lexicon_dir), str(output_dir), layout="package", lazy_imports=True
+            )
+
+            pkg = import_package(Path(tmpdir), "pkg_lazy")
+            assert "pkg_lazy.fm.plyr.like" not in sys.modules
+            assert "FmPlyrLike" in dir(pkg)
+
+            like_model = pkg.FmPlyrLike(subject={"uri": "at://x", "cid": "bafy"})
+            assert "pkg_lazy.fm.plyr.like" in sys.modules
+            assert like_model.subject.cid == "bafy"
+            assert "pkg_lazy.fm.plyr.track" not in sys.modules
+
+            with pytest.raises(AttributeError):
+                pkg.NotAModel  # noqa: B018
+
+    def test_parse_record_from_package_root(self):
+        """parse_record is importable from the package root."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_records"
+            generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                emit_parse_record=True,
+            )
+
+            pkg = import_package(Path(tmpdir), "pkg_records")
+            like_model = pkg.parse_record(
+                {
+                    "$type": "fm.plyr.like",
+                    "subject": {"uri": "at://x", "cid": "bafy"},
+                }
+            )
+            assert isinstance(like_model, pkg.FmPlyrLike)
+            assert set(pkg.RECORD_TYPES) == {"fm.plyr.like", "fm.plyr.track"}
+
+    def test_cross_module_cycle(self):
+        """modules that ref each other import without a circular import error."""
+        from pmgfal import generate
+
+        lexicons = [
+            {
+                "lexicon": 1,
+                "id": "fm.plyr.playlist",
+                "defs": {
+                    "main": {
+                        "type": "object",
+                        "properties": {
+                            "tracks": {
+                                "type": "array",
+                                "items": {"type": "ref", "ref": "fm.plyr.entry"},
+                            },
+                        },
+                    }
+                },
+            },
+            {
+                "lexicon": 1,
+                "id": "fm.plyr.entry",
+                "defs": {
+                    "main": {
+                        "type": "object",
+                        "properties": {
+                            "title": {"type": "string"},
+                            "playlist": {"type": "ref", "ref": "fm.plyr.playlist"},
+                        },
+                    }
+                },
+            },
+        ]
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for lex in lexicons:
+                (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+            output_dir = Path(tmpdir) / "pkg_cycle"
+            generate(str(lexicon_dir), str(output_dir), layout="package")
+
+            entry = (output_dir / "fm" / "plyr" / "entry.py").read_text()
+            deferred = "if TYPE_CHECKING:\n    from .playlist import FmPlyrPlaylist"
+            assert deferred in entry
+
+            pkg = import_package(Path(tmpdir), "pkg_cycle")
+            playlist = pkg.FmPlyrPlaylist.model_validate(
+                {"tracks": [{"title": "a", "playlist": {"tracks": []}}]}
+            )
+            assert isinstance(playlist.tracks[0], pkg.FmPlyrEntry)
+            assert isinstance(playlist.tracks[0].playlist, pkg.FmPlyrPlaylist)
+
+    def test_write_threads(self):
+        """concurrent writes produce the files sequential writes do, in order."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for i in range(40):
+                nsid = f"fm.plyr.group{i % 4}.item{i}"
+                lexicon = {
+                    "lexicon": 1,
+                    "id": nsid,
+                    "defs": {
+                        "main": {
+                            "type": "record",
+                            "record": {
+                                "type": "object",
+                                "properties": {
+                                    "other": {
+                                        "type": "ref",
+                                        "ref": f"fm.plyr.group{(i + 1) % 4}"
+                                        f".item{(i + 1) % 40}",
+                                    },
+                                },
+                            },
+                        },
+                    },
+                }
+                (lexicon_dir / f"{nsid}.json").write_text(json.dumps(lexicon))
+
+            outputs = []
+            for threads in (1, 4, 64):
+                output_dir = Path(tmpdir) / f"threads_{threads}"
+                result = generate(
+                    str(lexicon_dir),
+                    str(output_dir),
+                    layout="package",
+                    write_threads=threads,
+                    relative_paths=True,
+                )
+                contents = [(output_dir / path).read_text() for path in result.files]
+                outputs.append((result.files, contents))
+
+            sequential = outputs[0]
+            assert len(sequential[0]) > 40
+            assert all(output == sequential for output in outputs[1:])
+
+    def test_generator_reuses_parses(self):
+        """a Generator parses a file again only once it changes."""
+        from pmgfal import Generator
+
+        def lexicon(nsid: str, *fields: str) -> str:
+            properties = {name: {"type": "string"} for name in fields}
+            return json.dumps(record(nsid, properties))
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(lexicon("fm.plyr.track", "title"))
+            (lexicon_dir / "album.json").write_text(lexicon("fm.plyr.album", "name"))
+            output_dir = Path(tmpdir) / "generated"
+
+            generator = Generator()
+            generator.generate(str(lexicon_dir), str(output_dir))
+            assert generator.parses == 2
+
+            # nothing changed, so nothing is parsed again
+            result = generator.generate(str(lexicon_dir), str(output_dir))
+            assert generator.parses == 2
+            assert "class FmPlyrAlbum(BaseModel):" in Path(result.files[0]).read_text()
+
+            (lexicon_dir / "album.json").write_text(
+                lexicon("fm.plyr.album", "name", "artist")
+            )
+            result = generator.generate(str(lexicon_dir), str(output_dir))
+            assert generator.parses == 3
+            module = load_module(result.files[0])
+            assert set(module.FmPlyrAlbum.model_fields) == {"artist", "name"}
+
+            # a fresh generator starts without parses
+            assert Generator().parses == 0
+
+    def test_generator_cache_stays_explicit(self):
+        """a Generator's cache serves its own calls only, targets included."""
+        from pmgfal import Generator, generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "fm.plyr.track",
+            "defs": {"main": {"type": "object", "properties": {}}},
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "track.json").write_text(json.dumps(lexicon))
+            other_dir = Path(tmpdir) / "other"
+            other_dir.mkdir()
+            other = {**lexicon, "id": "fm.plyr.album"}
+            (other_dir / "album.json").write_text(json.dumps(other))
+
+            # a hook's own generate call doesn't go through the cache
+            def name_hook(nsid: str, def_name: str) -> None:
+                generate(str(other_dir), str(Path(tmpdir) / "inner"))
+                return None
+
+            generator = Generator()
+            assert "name_hook=None" in Generator.generate.__text_signature__
+            generator.generate(
+                str(lexicon_dir), str(Path(tmpdir) / "outer"), name_hook=name_hook
+            )
+            assert generator.parses == 1
+
+            targets = [("fm.plyr", str(Path(tmpdir) / "targets_plyr"))]
+            results = generator.generate(str(lexicon_dir), targets)
+            assert [len(result.files) for result in results] == [1]
+            assert generator.parses == 1
+
+    def test_value_object_defs(self):
+        """value_object_defs emits frozen, hashable models."""
+        import pydantic
+
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_frozen"
+            generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                value_object_defs=["com.atproto.repo.strongRef"],
+            )
+
+            pkg = import_package(Path(tmpdir), "pkg_frozen")
+            ref = pkg.ComAtprotoRepoStrongRef(uri="at://x", cid="bafy")
+            same = pkg.ComAtprotoRepoStrongRef(uri="at://x", cid="bafy")
+            other = pkg.ComAtprotoRepoStrongRef(uri="at://y", cid="bafy")
+            assert len({ref, same, other}) == 2
+            assert ref in {same}
+
+            with pytest.raises(pydantic.ValidationError):
+                ref.uri = "at://z"
+
+            like_model = pkg.FmPlyrLike(subject=ref)
+            with pytest.raises(TypeError):
+                hash(like_model)
+
+    def test_class_members(self):
+        """class_members appends user source, like a computed field, to a class."""
+        from pmgfal import generate
+
+        members = {
+            "fm.plyr.like": """
+                @computed_field
+                @property
+                def subject_uri(self) -> str:
+                    return self.subject.uri
+            """,
+            "fm.plyr.track#featuredArtist": (
+                "def shout(self) -> str:\n    return self.did.upper()\n"
+            ),
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_members"
+            generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                class_members=members,
+            )
+
+            pkg = import_package(Path(tmpdir), "pkg_members")
+            like = pkg.FmPlyrLike(
+                subject=pkg.ComAtprotoRepoStrongRef(uri="at://x", cid="bafy")
+            )
+            assert like.subject_uri == "at://x"
+            assert like.model_dump()["subject_uri"] == "at://x"
+
+            artist = pkg.FmPlyrTrackFeaturedArtist(did="did:plc:abc")
+            assert artist.shout() == "DID:PLC:ABC"
+            assert not hasattr(pkg.FmPlyrTrack, "shout")
+
+            single = generate(str(lexicon_dir), str(tmpdir), class_members=members)
+            module = load_module(single.files[0], "class_members_models")
+            assert hasattr(module.FmPlyrLike, "subject_uri")
+
+    def test_clean_removes_stale_files(self):
+        """clean deletes orphaned generated modules but never user files."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_clean"
+            generate(str(lexicon_dir), str(output_dir), layout="package")
+
+            like = output_dir / "fm" / "plyr" / "like.py"
+            assert like.exists()
+            user_file = output_dir / "fm" / "plyr" / "custom.py"
+            user_file.write_text("CUSTOM = True\n")
+
+            (lexicon_dir / "fm.plyr.like.json").unlink()
+            result = generate(str(lexicon_dir), str(output_dir), layout="package")
+            assert like.exists()
+            assert result.removed == []
+
+            result = generate(
+                str(lexicon_dir), str(output_dir), layout="package", clean=True
+            )
+            assert not like.exists()
+            assert str(like) in result.removed
+            assert not (output_dir / "com").exists()
+            assert (output_dir / "fm" / "plyr" / "track.py").exists()
+            assert user_file.read_text() == "CUSTOM = True\n"
+
+            pkg = import_package(Path(tmpdir), "pkg_clean")
+            assert not hasattr(pkg, "FmPlyrLike")
+            assert pkg.FmPlyrTrack(title="song").title == "song"
+
+    def test_clean_keeps_other_prefixes(self):
+        """clean leaves the modules of other prefixes sharing the directory."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for name, nsid in [("ref", "fm.core.ref"), ("like", "fm.plyr.like")]:
+                (lexicon_dir / f"{name}.json").write_text(json.dumps(record(nsid, {})))
+            output_dir = Path(tmpdir) / "shared"
+
+            args = (str(lexicon_dir), str(output_dir))
+            for layout, core in [
+                ("single", output_dir / "fm_core.py"),
+                ("package", output_dir / "fm" / "core" / "ref.py"),
+            ]:
+                options = {"layout": layout, "clean": True}
+                generate(*args, namespace_prefix="fm.core", **options)
+                result = generate(*args, namespace_prefix="fm.plyr", **options)
+                assert core.exists()
+                assert result.removed == []
+
+    def test_relative_paths(self):
+        """relative_paths reports written and removed files under output_dir."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_relative"
+            kwargs = {"layout": "package", "relative_paths": True}
+            result = generate(str(lexicon_dir), str(output_dir), **kwargs)
+
+            assert "fm/plyr/track.py" in result.files
+            for path in result.files:
+                assert not Path(path).is_absolute()
+                assert (output_dir / path).exists()
+
+            (lexicon_dir / "fm.plyr.like.json").unlink()
+            result = generate(str(lexicon_dir), str(output_dir), clean=True, **kwargs)
+            assert "fm/plyr/like.py" in result.removed
+            assert not any(Path(path).is_absolute() for path in result.removed)
+
+            absolute = generate(str(lexicon_dir), str(output_dir), layout="package")
+            assert str(output_dir / "fm" / "plyr" / "track.py") in absolute.files
+
+    def test_dotted_namespace_prefix(self):
+        """a two-segment prefix nests its modules and matches whole segments."""
+        from pmgfal import generate
+
+        lexicons = [
+            record(
+                "mycompany.generated.track",
+                {"artist": {"type": "ref", "ref": "mycompany.generated.defs#artist"}},
+            ),
+            {
+                "lexicon": 1,
+                "id": "mycompany.generated.defs",
+                "defs": {
+                    "artist": {
+                        "type": "object",
+                        "required": ["name"],
+                        "properties": {"name": {"type": "string"}},
+                    },
+                },
+            },
+            record("mycompany.generatedextra.post", {"text": {"type": "string"}}),
+        ]
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for lex in lexicons:
+                (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+
+            output_dir = Path(tmpdir) / "pkg_dotted"
+            result = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                namespace_prefix="mycompany.generated",
+                layout="package",
+            )
+            nested = output_dir / "mycompany" / "generated"
+            assert str(nested / "track.py") in result.files
+            assert str(nested / "defs.py") in result.files
+            assert not (output_dir / "mycompany" / "generatedextra").exists()
+
+            pkg = import_package(Path(tmpdir), "pkg_dotted")
+            track = importlib.import_module("pkg_dotted.mycompany.generated.track")
+            value = track.MycompanyGeneratedTrack.model_validate(
+                {"artist": {"name": "alice"}}
+            )
+            assert isinstance(value.artist, pkg.MycompanyGeneratedDefsArtist)
+            assert not hasattr(pkg, "MycompanyGeneratedextraPost")
+
+            single_dir = Path(tmpdir) / "single"
+            result = generate(
+                str(lexicon_dir),
+                str(single_dir),
+                namespace_prefix="mycompany.generated.",
+            )
+            assert result.files == [str(single_dir / "mycompany_generated.py")]
+            module = load_module(result.files[0], "dotted_prefix_models")
+            assert hasattr(module, "MycompanyGeneratedTrack")
+            assert not hasattr(module, "MycompanyGeneratedextraPost")
+
+    def test_fixtures(self):
+        """every emitted factory builds a validating instance of its record."""
+        from pydantic import BaseModel
+
+        from pmgfal import generate
+
+        album = {
+            "lexicon": 1,
+            "id": "fm.plyr.album",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "key": "tid",
+                    "record": {
+                        "type": "object",
+                        "required": ["tracks", "cover", "year", "kind", "embed"],
+                        "properties": {
+                            "tracks": {
+                                "type": "array",
+                                "minLength": 1,
+                                "items": {"type": "ref", "ref": "#entry"},
+                            },
+                            "cover": {"type": "blob", "accept": ["image/*"]},
+                            "year": {"type": "integer", "minimum": 1900},
+                            "kind": {"type": "string", "enum": ["lp", "ep"]},
+                            "embed": {"type": "union", "refs": ["#entry", "#note"]},
+                            "notes": {"type": "ref", "ref": "#note"},
+                        },
+                    },
+                },
+                "entry": {
+                    "type": "object",
+                    "required": ["track", "addedAt"],
+                    "properties": {
+                        "track": {"type": "ref", "ref": "com.atproto.repo.strongRef"},
+                        "addedAt": {"type": "string", "format": "datetime"},
+                    },
+                },
+                "note": {
+                    "type": "object",
+                    "required": ["text"],
+                    "properties": {"text": {"type": "string"}},
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            (lexicon_dir / "fm.plyr.album.json").write_text(json.dumps(album))
+            output_dir = Path(tmpdir) / "pkg_fixtures"
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                emit_fixtures=True,
+                typed_datetimes=True,
+            )
+            assert str(output_dir / "fixtures.py") in files
+
+            pkg = import_package(Path(tmpdir), "pkg_fixtures")
+            fixtures = importlib.import_module("pkg_fixtures.fixtures")
+            factories = sorted(n for n in dir(fixtures) if n.startswith("make_"))
+            assert factories == [
+                "make_fm_plyr_album",
+                "make_fm_plyr_like",
+                "make_fm_plyr_track",
+            ]
+            for name in factories:
+                instance = getattr(fixtures, name)()
+                assert isinstance(instance, BaseModel)
+
+            album_model = fixtures.make_fm_plyr_album()
+            assert isinstance(album_model, pkg.FmPlyrAlbum)
+            assert album_model.year == 1900
+            assert album_model.kind == "lp"
+            assert album_model.cover.mime_type == "image/png"
+            assert len(album_model.tracks) == 1
+            assert isinstance(album_model.embed, pkg.FmPlyrAlbumEntry)
+            assert album_model.notes is None
+
+            track = fixtures.make_fm_plyr_track(title="song")
+            assert track.title == "song"
+
+    def test_skip_nsids(self):
+        """skipped nsids aren't written but are imported from their module."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_skip"
+            strong_ref = output_dir / "com" / "atproto" / "repo" / "strong_ref.py"
+            strong_ref.parent.mkdir(parents=True)
+            strong_ref.write_text(
+                "from pydantic import BaseModel\n\n\n"
+                "class ComAtprotoRepoStrongRef(BaseModel):\n"
+                "    uri: str\n"
+                "    cid: str\n"
+                "    hand_written: bool = True\n"
+            )
+            generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                skip_nsids=["com.atproto.repo.strongRef"],
+            )
+
+            assert "hand_written" in strong_ref.read_text()
+            pkg = import_package(Path(tmpdir), "pkg_skip")
+            like_model = pkg.FmPlyrLike(subject={"uri": "at://x", "cid": "bafy"})
+            assert like_model.subject.hand_written
+
+    def test_skip_nsids_single_layout(self):
+        """in single layout, a skipped nsid becomes a relative import."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(
+                str(lexicon_dir), str(output_dir), skip_nsids=["fm.plyr.track"]
+            )
+
+            content = Path(files[0]).read_text()
+            assert (
+                "from .fm.plyr.track import FmPlyrTrack, FmPlyrTrackFeaturedArtist"
+                in content
+            )
+            assert "class FmPlyrTrack(" not in content
+            assert "class FmPlyrLike(BaseModel):" in content
+
+    def test_split_defs(self):
+        """split_defs writes one module per def under a per-nsid package."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_split"
+            generate(str(lexicon_dir), str(output_dir), split_defs=True)
+
+            track_dir = output_dir / "fm" / "plyr" / "track"
+            main = (track_dir / "main.py").read_text()
+            assert "from .featured_artist import FmPlyrTrackFeaturedArtist" in main
+            assert (track_dir / "featured_artist.py").exists()
+            init = (track_dir / "__init__.py").read_text()
+            assert "from .main import FmPlyrTrack" in init
+
+            import_package(Path(tmpdir), "pkg_split")
+            track_pkg = import_package(Path(tmpdir), "pkg_split.fm.plyr.track")
+            track = track_pkg.FmPlyrTrack(title="song", features=[{"did": "did:x"}])
+            assert track.features[0].did == "did:x"
+
+    def test_lexicon_hash_constants(self):
+        """the package root exposes the hash `hash_lexicons` computes."""
+        from pmgfal import __version__, generate, hash_lexicons
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "pkg_hashed"
+            generate(
+                str(lexicon_dir),
+                str(output_dir),
+                layout="package",
+                emit_lexicon_hash=True,
+            )
+
+            pkg = import_package(Path(tmpdir), "pkg_hashed")
+            assert pkg.__lexicon_hash__ == hash_lexicons(str(lexicon_dir))
+            assert pkg.__pmgfal_version__ == __version__
+            track = (output_dir / "fm" / "plyr" / "track.py").read_text()
+            assert "__lexicon_hash__" not in track
+
+            files = generate(
+                str(lexicon_dir),
+                str(Path(tmpdir) / "single"),
+                namespace_prefix="fm.plyr",
+                emit_lexicon_hash=True,
+            )
+            module = load_module(files[0], "single_hashed")
+            assert module.__lexicon_hash__ == hash_lexicons(str(lexicon_dir), "fm.plyr")
+
+            files = generate(str(lexicon_dir), str(Path(tmpdir) / "plain"))
+            assert "__lexicon_hash__" not in Path(files[0]).read_text()
+
+    def test_generate_targets(self):
+        """targets share one parse and import each other's models."""
+        from pmgfal import generate_targets
+
+        ref = {
+            "lexicon": 1,
+            "id": "fm.core.ref",
+            "defs": {
+                "main": {
+                    "type": "object",
+                    "required": ["uri"],
+                    "properties": {"uri": {"type": "string"}},
+                },
+            },
+        }
+        like = {
+            "lexicon": 1,
+            "id": "fm.plyr.like",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "required": ["subject"],
+                        "properties": {
+                            "subject": {"type": "ref", "ref": "fm.core.ref"},
+                        },
+                    },
+                },
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "ref.json").write_text(json.dumps(ref))
+            (lexicon_dir / "like.json").write_text(json.dumps(like))
+            core_dir = Path(tmpdir) / "targets_core"
+            plyr_dir = Path(tmpdir) / "targets_plyr"
+
+            core, plyr = generate_targets(
+                str(lexicon_dir),
+                [("fm.core", str(core_dir)), ("fm.plyr", str(plyr_dir))],
+                layout="package",
+            )
+            assert all(f.startswith(str(core_dir)) for f in core.files)
+            assert all(f.startswith(str(plyr_dir)) for f in plyr.files)
+            assert not (plyr_dir / "fm" / "core").exists()
+            source = (plyr_dir / "fm" / "plyr" / "like.py").read_text()
+            assert "from targets_core import FmCoreRef" in source
+
+            core_pkg = import_package(Path(tmpdir), "targets_core")
+            plyr_pkg = import_package(Path(tmpdir), "targets_plyr")
+            record = plyr_pkg.FmPlyrLike.model_validate({"subject": {"uri": "at://x"}})
+            assert isinstance(record.subject, core_pkg.FmCoreRef)
+
+            generate_targets(
+                str(lexicon_dir),
+                [("fm.core", str(core_dir)), ("fm.plyr", str(plyr_dir))],
+            )
+            single = (plyr_dir / "fm_plyr.py").read_text()
+            assert "from targets_core.fm_core import FmCoreRef" in single
+            assert "class FmCoreRef(" not in single
+
+            with pytest.raises(ValueError, match="namespace_prefix"):
+                generate_targets(
+                    str(lexicon_dir),
+                    [("fm.core", str(core_dir))],
+                    namespace_prefix="fm",
+                )
+
+    def test_generate_targets_clean(self):
+        """clean keeps every target's files when targets share a directory."""
+        from pmgfal import generate_targets
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            for name, nsid in [("ref", "fm.core.ref"), ("like", "fm.plyr.like")]:
+                (lexicon_dir / f"{name}.json").write_text(json.dumps(record(nsid, {})))
+            output_dir = Path(tmpdir) / "shared"
+            output_dir.mkdir()
+
+            targets = [("fm.core", str(output_dir)), ("fm.plyr", str(output_dir))]
+            core, plyr = generate_targets(str(lexicon_dir), targets, clean=True)
+            assert (output_dir / "fm_core.py").exists()
+            assert (output_dir / "fm_plyr.py").exists()
+            assert core.removed == plyr.removed == []
+
+            gone = lexicon_dir / "gone.json"
+            gone.write_text(json.dumps(record("fm.plyr.gone", {})))
+            options = {"layout": "package", "clean": True}
+            generate_targets(str(lexicon_dir), targets, **options)
+            gone.unlink()
+            core, plyr = generate_targets(str(lexicon_dir), targets, **options)
+            assert (output_dir / "fm" / "core" / "ref.py").exists()
+            assert (output_dir / "fm" / "plyr" / "like.py").exists()
+            assert not (output_dir / "fm" / "plyr" / "gone.py").exists()
+            assert core.removed == [str(output_dir / "fm" / "plyr" / "gone.py")]
+            assert plyr.removed == []
+
+            nested = [
+                ("fm.core", str(output_dir)),
+                ("fm.plyr", str(output_dir / "plyr")),
+            ]
+            generate_targets(str(lexicon_dir), nested, **options)
+            assert (output_dir / "fm" / "core" / "ref.py").exists()
+            assert (output_dir / "plyr" / "fm" / "plyr" / "like.py").exists()
+
+
+class TestArchive:
+    """test reading lexicons from archives."""
+
+    LEXICON = {
+        "lexicon": 1,
+        "id": "fm.plyr.track",
+        "defs": {
+            "main": {
+                "type": "record",
+                "record": {
+                    "type": "object",
+                    "properties": {"title": {"type": "string"}},
+                    "required": ["title"],
+                },
+            },
+        },
+    }
+
+    def test_zip(self):
+        """lexicons inside a zip are parsed without extraction."""
+        import zipfile
+
+        from pmgfal import generate, hash_lexicons
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            archive = Path(tmpdir) / "lexicons.zip"
+            with zipfile.ZipFile(archive, "w") as zf:
+                zf.writestr("lexicons/fm/plyr/track.json", json.dumps(self.LEXICON))
+                zf.writestr("README.md", "not a lexicon")
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(archive), str(output_dir))
+
+            assert "class FmPlyrTrack(BaseModel):" in Path(files[0]).read_text()
+            assert hash_lexicons(str(archive)) == hash_lexicons(str(archive))
+
+    def test_tarball(self):
+        """lexicons inside a .tar.gz are parsed without extraction."""
+        import io
+        import tarfile
+
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            archive = Path(tmpdir) / "lexicons.tar.gz"
+            data = json.dumps(self.LEXICON).encode()
+            with tarfile.open(archive, "w:gz") as tf:
+                info = tarfile.TarInfo("lexicons/fm/plyr/track.json")
+                info.size = len(data)
+                tf.addfile(info, io.BytesIO(data))
+
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(str(archive), str(output_dir))
+
+            assert "class FmPlyrTrack(BaseModel):" in Path(files[0]).read_text()
+
+class TestProtocolTarget:
+    """test emitting typing.Protocol classes instead of pydantic models."""
+
+    LEXICON = {
+        "lexicon": 1,
+        "id": "fm.plyr.track",
+        "defs": {
+            "main": {
+                "type": "record",
+                "record": {
+                    "type": "object",
+                    "properties": {
+                        "title": {"type": "string"},
+                        "durationMs": {"type": "integer"},
+                    },
+                    "required": ["title"],
+                },
+            },
+        },
+    }
+
+    def _generate(self, tmpdir: str) -> Path:
+        from pmgfal import generate
+
+        lexicon_dir = Path(tmpdir) / "lexicons"
+        lexicon_dir.mkdir()
+        (lexicon_dir / "track.json").write_text(json.dumps(self.LEXICON))
+        files = generate(str(lexicon_dir), tmpdir, target="protocol")
+        return Path(files[0])
+
+    def test_protocol_output(self):
+        """fields become read-only properties on a Protocol."""
+        with tempfile.TemporaryDirectory() as tmpdir:
+            path = self._generate(tmpdir)
+            content = path.read_text()
+
+            assert "class FmPlyrTrack(Protocol):" in content
+            assert "    @property\n    def title(self) -> str: ..." in content
+            assert "def duration_ms(self) -> int | None: ..." in content
+            assert "pydantic" not in content
+            load_module(str(path))
+
+    def test_conforming_object_type_checks(self):
+        """an unrelated class with the same shape satisfies the protocol."""
+        import shutil
+        import subprocess
+
+        pyright = shutil.which("pyright")
+        if pyright is None:
+            pytest.skip("pyright not installed")
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            self._generate(tmpdir)
+            check = Path(tmpdir) / "check.py"
+
+            check.write_text(
+                "from dataclasses import dataclass\n\n"
+                "from models import FmPlyrTrack\n\n\n"
+                "@dataclass\n"
+                "class Row:\n"
+                "    title: str\n"
+                "    duration_ms: int | None\n\n\n"
+                'track: FmPlyrTrack = Row(title="song", duration_ms=None)\n'
+            )
+            result = subprocess.run([pyright, str(check)], cwd=tmpdir)
+            assert result.returncode == 0
+
+            check.write_text(
+                "from dataclasses import dataclass\n\n"
+                "from models import FmPlyrTrack\n\n\n"
+                "@dataclass\n"
+                "class Row:\n"
+                "    title: int\n\n\n"
+                "track: FmPlyrTrack = Row(title=1)\n"
+            )
+            result = subprocess.run([pyright, str(check)], cwd=tmpdir)
+            assert result.returncode != 0
+
+class TestClient:
+    """test emitting an async client for xrpc endpoints."""
+
+    LEXICONS = [
+        {
+            "lexicon": 1,
+            "id": "fm.plyr.getTracks",
+            "defs": {
+                "main": {
+                    "type": "query",
+                    "description": "list tracks by uri",
+                    "parameters": {
+                        "type": "params",
+                        "properties": {
+                            "uris": {"type": "array", "items": {"type": "string"}},
+                        },
+                        "required": ["uris"],
+                    },
+                    "output": {
+                        "encoding": "application/json",
+                        "schema": {"type": "ref", "ref": "#trackList"},
+                    },
+                },
+                "trackList": {
+                    "type": "object",
+                    "properties": {
+                        "titles": {"type": "array", "items": {"type": "string"}},
+                    },
+                    "required": ["titles"],
+                },
+            },
+        },
+        {
+            "lexicon": 1,
+            "id": "fm.plyr.deleteTrack",
+            "defs": {
+                "main": {
+                    "type": "procedure",
+                    "input": {
+                        "encoding": "application/json",
+                        "schema": {
+                            "type": "object",
+                            "properties": {"uri": {"type": "string"}},
+                        },
+                    },
+                },
+            },
+        },
+    ]
+
+    def _write_lexicons(self, tmpdir: str) -> Path:
+        lexicon_dir = Path(tmpdir) / "lexicons"
+        lexicon_dir.mkdir()
+        for lex in self.LEXICONS:
+            (lexicon_dir / f"{lex['id']}.json").write_text(json.dumps(lex))
+        return lexicon_dir
+
+    def test_client_methods(self):
+        """each endpoint gets a typed async method forwarding to `_call`."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "generated"
+            files = generate(
+                str(lexicon_dir),
+                str(output_dir),
+                emit_client=True,
+                transport_base="HttpTransport",
+            )
+
+            client = output_dir / "client.py"
+            assert str(client) in files
+            content = client.read_text()
+            assert "from .models import FmPlyrGetTracksParams" in content
+            assert "class HttpTransport:" in content
+            assert "class XrpcClient(HttpTransport):" in content
+            assert (
+                "async def fm_plyr_get_tracks(self, params: FmPlyrGetTracksParams)"
+                " -> FmPlyrGetTracksTrackList:"
+            ) in content
+            assert (
+                "async def fm_plyr_delete_track(self, input: dict[str, Any]) -> None:"
+                in content
+            )
+
+    def test_client_calls_transport(self):
+        """a transport subclass receives the call and outputs are validated."""
+        import asyncio
+
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "client_pkg"
+            generate(
+                str(lexicon_dir), str(output_dir), layout="package", emit_client=True
+            )
+
+            pkg = import_package(Path(tmpdir), "client_pkg")
+            client_module = import_package(Path(tmpdir), "client_pkg.client")
+            calls = []
+
+            class FakeClient(client_module.XrpcClient):
+                async def _call(self, nsid, params, input):
+                    calls.append((nsid, params, input))
+                    return {"titles": ["a"]}
+
+            params = pkg.FmPlyrGetTracksParams(uris=["at://a"])
+            result = asyncio.run(FakeClient().fm_plyr_get_tracks(params))
+            assert result.titles == ["a"]
+            assert calls == [("fm.plyr.getTracks", params, None)]
+
+            with pytest.raises(NotImplementedError):
+                asyncio.run(client_module.XrpcClient().fm_plyr_delete_track({}))
+
+    def test_no_client_by_default(self):
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = self._write_lexicons(tmpdir)
+            output_dir = Path(tmpdir) / "generated"
+            generate(str(lexicon_dir), str(output_dir))
+            assert not (output_dir / "client.py").exists()
+
+
+class TestBuiltins:
+    """test introspection of the bundled lexicons."""
+
+    def test_build_info(self):
+        """build_info reports the version, wheel features and builtin count."""
+        from pmgfal import __version__, build_info, builtin_nsids
+
+        info = build_info()
+        assert info["version"] == __version__
+        assert "archive" in info["features"]
+        assert info["builtin_lexicon_count"] == len(builtin_nsids())
+
+    def test_builtin_nsids(self):
+        """builtin_nsids should list the bundled com.atproto ids in order."""
+        from pmgfal import builtin_nsids
+
+        nsids = builtin_nsids()
+        assert "com.atproto.repo.strongRef" in nsids
+        assert nsids == sorted(nsids)
+        assert all(nsid.startswith("com.atproto.") for nsid in nsids)
+
+    def test_bundled_lexicons_golden(self):
+        """the bundled lexicons generate byte-for-byte the checked-in output.
+
+        after an intended change to generated code, refresh the golden file
+        with `PMGFAL_UPDATE_GOLDEN=1` (`just update-golden`) and review the diff.
+        """
+        from pmgfal import generate
+
+        root = Path(__file__).parent.parent
+        golden = Path(__file__).parent / "fixtures" / "golden" / "bundled.py"
+        with tempfile.TemporaryDirectory() as tmpdir:
+            files = generate(
+                str(root / "lexicons"),
+                tmpdir,
+                use_builtins=False,
+                emit_union_adapters=True,
+                emit_invariant_validators=True,
+                emit_parse_record=True,
+            )
+            output = Path(files[0]).read_text()
+
+        if os.environ.get("PMGFAL_UPDATE_GOLDEN"):
+            golden.parent.mkdir(exist_ok=True)
+            golden.write_text(output)
+        if not golden.exists():
+            pytest.fail(f"{golden} is missing; rerun with PMGFAL_UPDATE_GOLDEN=1")
+        assert output == golden.read_text()
+
+    def test_bundled_lexicons_reproducible(self):
+        """repeat runs over the bundled lexicons write identical bytes."""
+        from pmgfal import generate
+
+        root = Path(__file__).parent.parent
+        with tempfile.TemporaryDirectory() as tmpdir:
+            for layout in ["single", "package"]:
+                outputs = []
+                for run in ["first", "second"]:
+                    output_dir = Path(tmpdir) / layout / run
+                    generate(
+                        str(root / "lexicons"),
+                        str(output_dir),
+                        use_builtins=False,
+                        layout=layout,
+                        emit_union_adapters=True,
+                        emit_parse_record=True,
+                    )
+                    outputs.append(
+                        {
+                            path.relative_to(output_dir): path.read_bytes()
+                            for path in sorted(output_dir.rglob("*.py"))
+                        }
+                    )
+                assert outputs[0] == outputs[1]
+
+class TestCaching:
+    """test caching behavior."""
+
+    def test_cache_hit(self):
+        """second run should hit cache."""
+        from pmgfal import main
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "test.cache",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"x": {"type": "string"}},
+                    },
+                }
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "cache.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+
+            # first run - cache miss
+            result = main([str(lexicon_dir), "-o", str(output_dir)])
+            assert result == 0
+            assert (output_dir / "models.py").exists()
+
+            # delete output to prove cache works
+            (output_dir / "models.py").unlink()
+
+            # second run - cache hit
+            result = main([str(lexicon_dir), "-o", str(output_dir)])
+            assert result == 0
+            assert (output_dir / "models.py").exists()
+
+    def test_hash_ignores_non_lexicon_json(self):
+        """json files that aren't lexicons don't change the hash."""
+        from pmgfal import hash_lexicons
+
+        lexicon = {"lexicon": 1, "id": "test.hash", "defs": {}}
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "hash.json").write_text(json.dumps(lexicon))
+            before = hash_lexicons(str(lexicon_dir))
+
+            (lexicon_dir / "package.json").write_text(json.dumps({"name": "x"}))
+            (lexicon_dir / "broken.json").write_text("{not json")
+            assert hash_lexicons(str(lexicon_dir)) == before
+
+            lexicon["description"] = "changed"
+            (lexicon_dir / "hash.json").write_text(json.dumps(lexicon))
+            assert hash_lexicons(str(lexicon_dir)) != before
+
+    def test_generate_with_hash(self):
+        """one call yields the generated files and the lexicon hash."""
+        from pmgfal import generate_with_hash, hash_lexicons
+
+        lexicon = {"lexicon": 1, "id": "test.hash", "defs": {}}
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "hash.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+            lexicon_hash, files = generate_with_hash(
+                str(lexicon_dir), str(output_dir), namespace_prefix="test"
+            )
+            assert lexicon_hash == hash_lexicons(str(lexicon_dir), "test")
+            assert files.files == [str(output_dir / "test.py")]
+
+            with pytest.raises(ValueError, match="generate_targets"):
+                generate_with_hash(str(lexicon_dir), [("test", str(output_dir))])
+
+    def test_no_cache_flag(self):
+        """--no-cache should force regeneration."""
+        from pmgfal import main
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "test.nocache",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {
+                        "type": "object",
+                        "properties": {"y": {"type": "string"}},
+                    },
+                }
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "nocache.json").write_text(json.dumps(lexicon))
+
+            output_dir = Path(tmpdir) / "generated"
+
+            # first run
+            result = main([str(lexicon_dir), "-o", str(output_dir)])
+            assert result == 0
+
+            # second run with --no-cache
+            result = main([str(lexicon_dir), "-o", str(output_dir), "--no-cache"])
+            assert result == 0
+
+
+class TestLogging:
+    """test forwarding codegen diagnostics to python logging."""
+
+    def test_log_level_forwards_events(self, caplog):
+        """log_level should surface parse and skip events on the pmgfal logger."""
+        import logging
+
+        from pmgfal import generate
+
+        lexicon = {
+            "lexicon": 1,
+            "id": "test.logging",
+            "defs": {
+                "main": {
+                    "type": "record",
+                    "record": {"type": "object", "properties": {}},
+                }
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir) / "lexicons"
+            lexicon_dir.mkdir()
+            (lexicon_dir / "logging.json").write_text(json.dumps(lexicon))
+            (lexicon_dir / "package.json").write_text(json.dumps({"name": "x"}))
+
+            output_dir = Path(tmpdir) / "generated"
+            with caplog.at_level(logging.DEBUG, logger="pmgfal"):
+                generate(str(lexicon_dir), str(output_dir), log_level="debug")
+
+            messages = [r.getMessage() for r in caplog.records if r.name == "pmgfal"]
+            assert any("parsed test.logging" in m for m in messages)
+            assert any("skipping non-lexicon json" in m for m in messages)
+
+    def test_invalid_log_level(self):
+        """an unknown level name should raise."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            with pytest.raises(ValueError):
+                generate(tmpdir, tmpdir, log_level="loud")
@@ -0,0 +1,110 @@
+"""black-box tests for the graphql export."""
+
+import json
+import tempfile
+from pathlib import Path
+
+import pytest
+
+from pmgfal import build_info
+
+pytestmark = pytest.mark.skipif(
+    "graphql" not in build_info()["features"],
+    reason="built without the graphql feature",
+)
+
+TRACK = {
+    "lexicon": 1,
+    "id": "fm.plyr.track",
+    "defs": {
+        "main": {
+            "type": "record",
+            "key": "tid",
+            "record": {
+                "type": "object",
+                "required": ["title", "createdAt"],
+                "properties": {
+                    "title": {"type": "string", "description": "the title"},
+                    "createdAt": {"type": "string", "format": "datetime"},
+                    "artists": {
+                        "type": "array",
+                        "items": {"type": "ref", "ref": "#artist"},
+                    },
+                    "embed": {"type": "union", "refs": ["#image", "#link"]},
+                    "genre": {"type": "ref", "ref": "#genre"},
+                    "subject": {
+                        "type": "ref",
+                        "ref": "com.atproto.repo.strongRef",
+                    },
+                },
+            },
+        },
+        "artist": {
+            "type": "object",
+            "required": ["name"],
+            "properties": {"name": {"type": "string"}},
+        },
+        "image": {
+            "type": "object",
+            "required": ["image"],
+            "properties": {"image": {"type": "blob", "accept": ["image/*"]}},
+        },
+        "link": {
+            "type": "object",
+            "properties": {"uri": {"type": "string", "format": "uri"}},
+        },
+        "genre": {"type": "string", "knownValues": ["rock", "jazz"]},
+    },
+}
+
+
+def _to_graphql(*lexicons: dict) -> str:
+    from pmgfal import to_graphql
+
+    with tempfile.TemporaryDirectory() as tmpdir:
+        lexicon_dir = Path(tmpdir)
+        for lexicon in lexicons:
+            (lexicon_dir / f"{lexicon['id']}.json").write_text(json.dumps(lexicon))
+        return to_graphql(str(lexicon_dir))
+
+
+class TestGraphql:
+    """test exporting record and object defs as graphql sdl."""
+
+    def test_types_unions_and_scalars(self):
+        """records and objects become types, unions union types, formats scalars."""
+        sdl = _to_graphql(TRACK)
+
+        assert "type FmPlyrTrack {" in sdl
+        assert '  """\n  the title\n  """\n  title: String!\n' in sdl
+        assert "  createdAt: Datetime!\n" in sdl
+        assert "  artists: [FmPlyrTrackArtist!]\n" in sdl
+        assert "  embed: FmPlyrTrackEmbed\n" in sdl
+        assert "union FmPlyrTrackEmbed = FmPlyrTrackImage | FmPlyrTrackLink\n" in sdl
+        # a ref to a non-object def takes that def's type
+        assert "  genre: String\n" in sdl
+        assert "type FmPlyrTrackArtist {\n  name: String!\n}\n" in sdl
+        assert "  image: Blob!\n" in sdl
+
+        # custom scalars are declared once, and only when used
+        assert sdl.count("scalar Datetime\n") == 1
+        assert "scalar Blob\n" in sdl
+        assert "scalar Bytes\n" not in sdl
+
+        # refs into builtins are pulled in as types
+        assert "  subject: ComAtprotoRepoStrongRef\n" in sdl
+        assert "type ComAtprotoRepoStrongRef {" in sdl
+        assert "  cid: Cid!\n" in sdl
+        assert "  uri: AtUri!\n" in sdl
+
+    def test_valid_schema(self):
+        """the sdl builds as a graphql schema."""
+        graphql = pytest.importorskip("graphql")
+
+        schema = graphql.build_schema(_to_graphql(TRACK))
+
+        track = schema.get_type("FmPlyrTrack")
+        assert str(track.fields["createdAt"].type) == "Datetime!"
+        assert str(track.fields["artists"].type) == "[FmPlyrTrackArtist!]"
+        embed = schema.get_type("FmPlyrTrackEmbed")
+        assert [t.name for t in embed.types] == ["FmPlyrTrackImage", "FmPlyrTrackLink"]
@@ -0,0 +1,102 @@
+"""black-box tests for the openapi export."""
+
+import json
+import tempfile
+from pathlib import Path
+
+
+class TestOpenApi:
+    """test exporting xrpc methods as openapi."""
+
+    def test_query_and_procedure_paths(self):
+        """queries map to GET and procedures to POST with typed bodies."""
+        from pmgfal import to_openapi
+
+        query = {
+            "lexicon": 1,
+            "id": "fm.plyr.getTracks",
+            "defs": {
+                "main": {
+                    "type": "query",
+                    "parameters": {
+                        "type": "params",
+                        "required": ["uris"],
+                        "properties": {
+                            "uris": {
+                                "type": "array",
+                                "items": {"type": "string", "format": "at-uri"},
+                            },
+                            "limit": {"type": "integer", "minimum": 1},
+                        },
+                    },
+                    "output": {
+                        "encoding": "application/json",
+                        "schema": {
+                            "type": "object",
+                            "required": ["tracks"],
+                            "properties": {
+                                "tracks": {
+                                    "type": "array",
+                                    "items": {"type": "ref", "ref": "#track"},
+                                }
+                            },
+                        },
+                    },
+                    "errors": [{"name": "TrackNotFound"}],
+                },
+                "track": {
+                    "type": "object",
+                    "required": ["ref"],
+                    "properties": {
+                        "ref": {"type": "ref", "ref": "com.atproto.repo.strongRef"}
+                    },
+                },
+            },
+        }
+        procedure = {
+            "lexicon": 1,
+            "id": "fm.plyr.createTrack",
+            "defs": {
+                "main": {
+                    "type": "procedure",
+                    "input": {
+                        "encoding": "application/json",
+                        "schema": {
+                            "type": "object",
+                            "required": ["title"],
+                            "properties": {"title": {"type": "string"}},
+                        },
+                    },
+                }
+            },
+        }
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            lexicon_dir = Path(tmpdir)
+            (lexicon_dir / "getTracks.json").write_text(json.dumps(query))
+            (lexicon_dir / "createTrack.json").write_text(json.dumps(procedure))
+
+            spec = json.loads(to_openapi(str(lexicon_dir)))
+
+        assert spec["openapi"] == "3.1.0"
+
+        get = spec["paths"]["/xrpc/fm.plyr.getTracks"]["get"]
+        uris = next(p for p in get["parameters"] if p["name"] == "uris")
+        assert uris["required"] is True
+        assert uris["explode"] is True
+        assert uris["schema"]["type"] == "array"
+        ok = get["responses"]["200"]["content"]["application/json"]["schema"]
+        assert ok["properties"]["tracks"]["items"] == {
+            "$ref": "#/components/schemas/FmPlyrGetTracksTrack"
+        }
+        error = get["responses"]["400"]["content"]["application/json"]["schema"]
+        assert error["properties"]["error"]["enum"] == ["TrackNotFound"]
+
+        post = spec["paths"]["/xrpc/fm.plyr.createTrack"]["post"]
+        body = post["requestBody"]["content"]["application/json"]["schema"]
+        assert body["required"] == ["title"]
+
+        # refs into builtins are pulled in as components
+        schemas = spec["components"]["schemas"]
+        assert "FmPlyrGetTracksTrack" in schemas
+        assert "ComAtprotoRepoStrongRef" in schemas
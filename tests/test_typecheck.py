@@ -0,0 +1,79 @@
+"""run pyright --strict over generated code.
+
+the fixtures in `tests/fixtures/strict` cover records, objects, local and
+external refs, unions, arrays, blobs, unknowns, tokens and query params; the
+code generated from them must type-check cleanly in strict mode.
+"""
+
+import json
+import os
+import shutil
+import subprocess
+import tempfile
+from pathlib import Path
+
+import pytest
+
+FIXTURES = Path(__file__).parent / "fixtures" / "strict"
+
+PYRIGHT_CONFIG = {"typeCheckingMode": "strict", "pythonVersion": "3.10"}
+
+
+def pyright_strict(
+    root: Path, python_version: str = "3.10"
+) -> subprocess.CompletedProcess[str]:
+    """type-check every python file under `root` in strict mode."""
+    pyright = shutil.which("pyright")
+    if pyright is None:
+        # ci installs the dev group, so a missing pyright there is a failure
+        if os.environ.get("CI"):
+            pytest.fail("pyright not installed")
+        pytest.skip("pyright not installed")
+    config = {**PYRIGHT_CONFIG, "pythonVersion": python_version}
+    (root / "pyrightconfig.json").write_text(json.dumps(config))
+    return subprocess.run(
+        [pyright, str(root)], cwd=root, capture_output=True, text=True
+    )
+
+
+class TestStrictTyping:
+    """test that generated code is clean under pyright --strict."""
+
+    @pytest.mark.parametrize(
+        "options",
+        [
+            {},
+            {"embed_metadata": True},
+            {"small_int_ranges_as_literal": True, "max_literal_range": 100},
+            {"layout": "package"},
+            {"split_defs": True, "embed_metadata": True},
+            {"target": "protocol"},
+        ],
+        ids=["default", "metadata", "literals", "package", "split", "protocol"],
+    )
+    def test_generated_code_is_strict_clean(self, options):
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            root = Path(tmpdir)
+            generate(str(FIXTURES), str(root / "generated"), **options)
+
+            result = pyright_strict(root)
+            assert result.returncode == 0, result.stdout
+
+    @pytest.mark.parametrize("target_python", ["3.9", "3.12"])
+    def test_target_python_is_strict_clean(self, target_python):
+        """code generated for a python version type-checks as that version."""
+        from pmgfal import generate
+
+        with tempfile.TemporaryDirectory() as tmpdir:
+            root = Path(tmpdir)
+            generate(
+                str(FIXTURES),
+                str(root / "generated"),
+                target_python=target_python,
+                emit_union_adapters=True,
+            )
+
+            result = pyright_strict(root, python_version=target_python)
+            assert result.returncode == 0, result.stdout